@@ -1,4 +1,5 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use sinteflake::id::{Encoding, SINTEFlakeId, MAX_ENCODED_LEN};
 use sinteflake::sinteflake::SINTEFlake;
 
 fn sinteflake_bench(c: &mut Criterion) {
@@ -52,5 +53,31 @@ fn sinteflake_bench(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, sinteflake_bench);
+fn format_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("format");
+    let id = SINTEFlakeId::new(1234567890123);
+
+    group.bench_function("to_base62", |b| {
+        b.iter(|| black_box(id).to_base62());
+    });
+
+    group.bench_function("format_into_base62", |b| {
+        let mut buf = [0; MAX_ENCODED_LEN];
+        b.iter(|| black_box(black_box(id).format_into(Encoding::Base62, &mut buf)).len());
+    });
+
+    group.bench_function("format_into_hex", |b| {
+        let mut buf = [0; MAX_ENCODED_LEN];
+        b.iter(|| black_box(black_box(id).format_into(Encoding::Hex, &mut buf)).len());
+    });
+
+    group.bench_function("format_into_base32", |b| {
+        let mut buf = [0; MAX_ENCODED_LEN];
+        b.iter(|| black_box(black_box(id).format_into(Encoding::Base32, &mut buf)).len());
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, sinteflake_bench, format_bench);
 criterion_main!(benches);
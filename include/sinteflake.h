@@ -0,0 +1,92 @@
+/* Generated with cbindgen from src/ffi.rs, do not edit by hand. */
+
+#ifndef SINTEFLAKE_H
+#define SINTEFLAKE_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The call succeeded.
+ */
+#define SINTEFLAKE_OK 0
+
+/**
+ * The call failed, but may succeed later, after updating the time.
+ */
+#define SINTEFLAKE_RETRY 1
+
+/**
+ * A pointer argument is null.
+ */
+#define SINTEFLAKE_NULL_POINTER -1
+
+/**
+ * The call failed.
+ */
+#define SINTEFLAKE_ERROR -2
+
+/**
+ * SINTEFlake is a 64-bit ID generator inspired by Twitter's Snowflake and Sony's Sonyflake.
+ * It generates unique identifiers that start with a hash or a pseudo-random number instead of a timestamp.
+ */
+typedef struct SINTEFlake SINTEFlake;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates an instance with the default settings and an instance ID.
+ *
+ * Returns null if the instance ID is too high, above 1023, or if the instance
+ * can't be created. The instance must be freed with [`sinteflake_free`].
+ */
+SINTEFlake *sinteflake_new(uint16_t instance_id);
+
+/**
+ * Updates the time of an instance, see [`SINTEFlake::update_time`].
+ *
+ * # Safety
+ * `instance` must be null or come from [`sinteflake_new`] and not be freed.
+ */
+int32_t sinteflake_update_time(SINTEFlake *instance);
+
+/**
+ * Generates the next ID and writes it to `id`.
+ *
+ * # Safety
+ * `instance` must be null or come from [`sinteflake_new`] and not be freed,
+ * and `id` must be null or valid for writes.
+ */
+int32_t sinteflake_next_id(SINTEFlake *instance, uint64_t *id);
+
+/**
+ * Generates the next ID with the hash of `length` bytes at `data`, and writes it to `id`.
+ *
+ * # Safety
+ * `instance` must be null or come from [`sinteflake_new`] and not be freed,
+ * `data` must be valid for `length` bytes, or may be null if `length` is 0,
+ * and `id` must be null or valid for writes.
+ */
+int32_t sinteflake_next_id_with_hash(SINTEFlake *instance,
+                                     const uint8_t *data,
+                                     uintptr_t length,
+                                     uint64_t *id);
+
+/**
+ * Frees an instance. Does nothing if `instance` is null.
+ *
+ * # Safety
+ * `instance` must be null or come from [`sinteflake_new`], and not be freed already.
+ */
+void sinteflake_free(SINTEFlake *instance);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SINTEFLAKE_H */
@@ -0,0 +1,213 @@
+//! Actix-web middleware giving a SINTEFlake ID to every request.
+//!
+//! Handlers get the ID with the `web::ReqData<SINTEFlakeId>` extractor,
+//! and the response gets it in the `x-request-id` header:
+//!
+//! ```rust
+//! use actix_web::{web, App, HttpResponse};
+//! use sinteflake::actix::RequestIdMiddleware;
+//! use sinteflake::id::SINTEFlakeId;
+//!
+//! async fn index(id: web::ReqData<SINTEFlakeId>) -> HttpResponse {
+//!     HttpResponse::Ok().body(id.to_string())
+//! }
+//!
+//! let app = App::new()
+//!     .wrap(RequestIdMiddleware::new())
+//!     .route("/", web::get().to(index));
+//! ```
+//!
+//! The middleware behaves like the Tower layer of the `tower` feature.
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{Error, HttpMessage};
+
+use crate::id::SINTEFlakeId;
+use crate::singleton;
+
+/// Middleware generating a [`SINTEFlakeId`] per request, with the global SINTEFlake instance.
+///
+/// The ID is added to the extensions of the request, and to the `x-request-id` header
+/// of both the request and the response. A request already having the header keeps it,
+/// and the response gets it back; its value is in the extensions if it is a decimal ID.
+///
+/// The ID is generated without waiting for the rate limit or the clock, so the async
+/// runtime isn't blocked. Requests are not refused if no ID can be generated, they just
+/// don't get one.
+#[derive(Debug, Clone)]
+pub struct RequestIdMiddleware {
+    header: HeaderName,
+}
+
+impl RequestIdMiddleware {
+    /// Creates a middleware using the `x-request-id` header.
+    pub fn new() -> Self {
+        RequestIdMiddleware {
+            header: HeaderName::from_static("x-request-id"),
+        }
+    }
+
+    /// Sets the name of the header.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+}
+
+impl Default for RequestIdMiddleware {
+    fn default() -> Self {
+        RequestIdMiddleware::new()
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequestIdMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestIdService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdService {
+            service,
+            header: self.header.clone(),
+        }))
+    }
+}
+
+/// Service added by [`RequestIdMiddleware`].
+#[derive(Debug)]
+pub struct RequestIdService<S> {
+    service: S,
+    header: HeaderName,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<ServiceResponse<B>, Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut request: ServiceRequest) -> Self::Future {
+        let value = match request.headers().get(&self.header).cloned() {
+            Some(value) => {
+                if let Some(id) = value.to_str().ok().and_then(|value| value.parse().ok()) {
+                    request.extensions_mut().insert::<SINTEFlakeId>(id);
+                }
+                Some(value)
+            }
+            None => singleton::next_id_without_waiting().ok().map(|id| {
+                let value = HeaderValue::from(id);
+                request.extensions_mut().insert(SINTEFlakeId::new(id));
+                request
+                    .headers_mut()
+                    .insert(self.header.clone(), value.clone());
+                value
+            }),
+        };
+
+        let response = self.service.call(request);
+        let header = self.header.clone();
+        Box::pin(async move {
+            let mut response = response.await?;
+            if let Some(value) = value {
+                if !response.headers().contains_key(&header) {
+                    response.headers_mut().insert(header, value);
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, web, App, HttpResponse};
+
+    async fn index(id: web::ReqData<SINTEFlakeId>) -> HttpResponse {
+        HttpResponse::Ok().body(id.to_string())
+    }
+
+    #[actix_web::test]
+    async fn test_request_id_middleware() {
+        let app = test::init_service(
+            App::new()
+                .wrap(RequestIdMiddleware::new())
+                .route("/", web::get().to(index)),
+        )
+        .await;
+
+        let response = test::call_service(&app, test::TestRequest::get().to_request()).await;
+        let header = response
+            .headers()
+            .get("x-request-id")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = test::read_body(response).await;
+        assert_eq!(header.as_bytes(), body);
+
+        let response = test::call_service(&app, test::TestRequest::get().to_request()).await;
+        assert_ne!(test::read_body(response).await, body);
+    }
+
+    #[actix_web::test]
+    async fn test_custom_header() {
+        let app = test::init_service(
+            App::new()
+                .wrap(RequestIdMiddleware::new().header(HeaderName::from_static("x-trace-id")))
+                .route("/", web::get().to(index)),
+        )
+        .await;
+
+        let response = test::call_service(&app, test::TestRequest::get().to_request()).await;
+        assert!(response.headers().contains_key("x-trace-id"));
+        assert!(!response.headers().contains_key("x-request-id"));
+    }
+
+    #[actix_web::test]
+    async fn test_incoming_header() {
+        async fn header(request: actix_web::HttpRequest) -> HttpResponse {
+            let header = request.headers().get("x-request-id").unwrap();
+            HttpResponse::Ok().body(header.to_str().unwrap().to_string())
+        }
+
+        let app = test::init_service(
+            App::new()
+                .wrap(RequestIdMiddleware::new())
+                .route("/", web::get().to(index))
+                .route("/header", web::get().to(header)),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .insert_header(("x-request-id", "1234"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.headers().get("x-request-id").unwrap(), "1234");
+        assert_eq!(test::read_body(response).await, "1234");
+
+        // the request gets the generated header too
+        let request = test::TestRequest::get().uri("/header").to_request();
+        let response = test::call_service(&app, request).await;
+        let generated = response.headers().get("x-request-id").unwrap().clone();
+        assert_eq!(test::read_body(response).await, generated.as_bytes());
+    }
+}
@@ -0,0 +1,182 @@
+//! A generator task owning a SINTEFlake instance, with the `async` feature.
+//!
+//! Instead of sharing an instance behind a mutex, [`spawn`] moves it to a dedicated task
+//! and returns a [`Handle`] sending requests over a channel. Under a very high fan-in,
+//! the clients wait in the channel instead of convoying on a lock, and the task answers
+//! the pending requests in batches.
+//!
+//! ```rust
+//! use sinteflake::actor;
+//! use sinteflake::sinteflake::SINTEFlake;
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> Result<(), sinteflake::error::SINTEFlakeError> {
+//! let handle = actor::spawn(SINTEFlake::new()?);
+//!
+//! let id = handle.next_id().await?;
+//! let id = handle.next_id_with_hash(&[1, 2, 3]).await?;
+//! handle.update_time().await?;
+//! # Ok(())
+//! # }
+//! ```
+use tokio::sync::{mpsc, oneshot};
+
+use crate::error::SINTEFlakeError;
+use crate::sinteflake::SINTEFlake;
+
+/// Number of requests waiting in the channel before the clients wait to send.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Maximum number of requests answered in a batch.
+const BATCH_SIZE: usize = 64;
+
+type Reply<T> = oneshot::Sender<Result<T, SINTEFlakeError>>;
+
+enum Request {
+    NextId(Reply<u64>),
+    NextIdWithHash(Vec<u8>, Reply<u64>),
+    UpdateTime(Reply<()>),
+}
+
+/// Sends requests to a generator task started with [`spawn`].
+///
+/// Cloning a handle is cheap, the clones send to the same task.
+/// The task stops when all the handles are dropped.
+#[derive(Debug, Clone)]
+pub struct Handle {
+    sender: mpsc::Sender<Request>,
+}
+
+/// Moves the instance to a new task on the current tokio runtime, and returns its handle.
+///
+/// # Panics
+/// Panics if called outside of a tokio runtime.
+pub fn spawn(instance: SINTEFlake) -> Handle {
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    tokio::spawn(run(instance, receiver));
+    Handle { sender }
+}
+
+impl Handle {
+    /// Generates the next unique ID.
+    ///
+    /// # Errors
+    /// Returns `ActorStopped` if the task has stopped, or the error of the instance.
+    pub async fn next_id(&self) -> Result<u64, SINTEFlakeError> {
+        self.request(Request::NextId).await
+    }
+
+    /// Generates the next unique ID using the provided data for hashing.
+    ///
+    /// # Errors
+    /// Returns `ActorStopped` if the task has stopped, or the error of the instance.
+    pub async fn next_id_with_hash(&self, data: &[u8]) -> Result<u64, SINTEFlakeError> {
+        let data = data.to_vec();
+        self.request(|reply| Request::NextIdWithHash(data, reply))
+            .await
+    }
+
+    /// Moves the instance to the current time window.
+    ///
+    /// # Errors
+    /// Returns `ActorStopped` if the task has stopped, or the error of the instance.
+    pub async fn update_time(&self) -> Result<(), SINTEFlakeError> {
+        self.request(Request::UpdateTime).await
+    }
+
+    async fn request<T>(
+        &self,
+        request: impl FnOnce(Reply<T>) -> Request,
+    ) -> Result<T, SINTEFlakeError> {
+        let (reply, response) = oneshot::channel();
+        self.sender
+            .send(request(reply))
+            .await
+            .map_err(|_| SINTEFlakeError::ActorStopped)?;
+        response.await.map_err(|_| SINTEFlakeError::ActorStopped)?
+    }
+}
+
+/// Answers the requests until all the handles are dropped.
+async fn run(mut instance: SINTEFlake, mut receiver: mpsc::Receiver<Request>) {
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    while receiver.recv_many(&mut batch, BATCH_SIZE).await > 0 {
+        for request in batch.drain(..) {
+            // like the async functions, sleep until the rate limit allows the next ID
+            if !matches!(request, Request::UpdateTime(_)) {
+                let delay = instance.rate_limit_delay();
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+            }
+            // the client may have given up waiting, the reply is then dropped
+            match request {
+                Request::NextId(reply) => {
+                    let _ = reply.send(instance.next_id());
+                }
+                Request::NextIdWithHash(data, reply) => {
+                    let _ = reply.send(instance.next_id_with_hash(&data));
+                }
+                Request::UpdateTime(reply) => {
+                    let _ = reply.send(instance.update_time());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[tokio::test]
+    async fn test_handle() {
+        let handle = spawn(SINTEFlake::new().unwrap());
+        let id_a = handle.next_id().await.unwrap();
+        let id_b = handle.next_id_with_hash(&[1, 2, 3]).await.unwrap();
+        assert_ne!(id_a, id_b);
+        handle.update_time().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_many_clients() {
+        let handle = spawn(SINTEFlake::new().unwrap());
+        let tasks: Vec<_> = (0..16)
+            .map(|_| {
+                let handle = handle.clone();
+                tokio::spawn(async move {
+                    let mut ids = Vec::new();
+                    for _ in 0..100 {
+                        ids.push(handle.next_id().await.unwrap());
+                    }
+                    ids
+                })
+            })
+            .collect();
+
+        let mut ids = HashSet::new();
+        for task in tasks {
+            for id in task.await.unwrap() {
+                assert!(ids.insert(id));
+            }
+        }
+        assert_eq!(ids.len(), 1600);
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_returned() {
+        // 4 buckets of 2 IDs
+        let layout = crate::layout::Layout::new(2, 31, 10, 1).unwrap();
+        let handle = spawn(SINTEFlake::builder().layout(layout).build().unwrap());
+        for _ in 0..8 {
+            handle.next_id().await.unwrap();
+        }
+        assert!(matches!(
+            handle.next_id().await,
+            Err(SINTEFlakeError::CounterOverflow { .. })
+        ));
+        // the task keeps answering after an error
+        handle.update_time().await.unwrap();
+    }
+}
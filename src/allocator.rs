@@ -0,0 +1,476 @@
+//! Automatic instance IDs leased from a coordination service.
+use std::collections::hash_map::RandomState;
+use std::fmt::Debug;
+use std::hash::{BuildHasher, Hasher};
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::error::SINTEFlakeError;
+
+#[cfg(feature = "etcd")]
+mod etcd;
+mod file;
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "redis")]
+mod redis;
+
+#[cfg(feature = "etcd")]
+pub use self::etcd::EtcdAllocator;
+pub use self::file::FileLockAllocator;
+#[cfg(feature = "postgres")]
+pub use self::postgres::PostgresAllocator;
+#[cfg(feature = "redis")]
+pub use self::redis::RedisAllocator;
+
+/// An instance ID leased from an [`InstanceIdAllocator`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    /// The leased instance ID.
+    pub instance_id: u16,
+
+    /// Random token proving the ownership of the lease.
+    pub token: u64,
+
+    /// Duration of the lease after an acquisition or a renewal.
+    pub ttl: Duration,
+}
+
+/// A non-empty range of instance IDs handed out by an [`InstanceIdAllocator`].
+///
+/// Deployments sharing a coordination backend, like the blue and green environments,
+/// can each use their own range, so they never lease the same instance IDs.
+///
+/// ```rust
+/// use sinteflake::allocator::InstanceIdRange;
+///
+/// let blue = InstanceIdRange::new(0..512)?;
+/// let green = InstanceIdRange::new(512..1024)?;
+/// assert_eq!((blue.len(), green.first(), green.last()), (512, 512, 1023));
+/// assert!(!blue.contains(512));
+/// # Ok::<(), sinteflake::error::SINTEFlakeError>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InstanceIdRange {
+    first: u16,
+    last: u16,
+}
+
+impl InstanceIdRange {
+    /// The instance IDs of the default layout, from 0 to 1023.
+    pub const DEFAULT: InstanceIdRange = InstanceIdRange {
+        first: 0,
+        last: 1023,
+    };
+
+    /// Creates a range of instance IDs, like `0..512` or `512..=1023`.
+    ///
+    /// # Errors
+    /// Returns `InvalidConfig` if the range is empty.
+    pub fn new(range: impl RangeBounds<u16>) -> Result<Self, SINTEFlakeError> {
+        let invalid = || SINTEFlakeError::InvalidConfig {
+            setting: "instance_id_range",
+        };
+        let first = match range.start_bound() {
+            Bound::Included(&first) => first,
+            Bound::Excluded(&first) => first.checked_add(1).ok_or_else(invalid)?,
+            Bound::Unbounded => 0,
+        };
+        let last = match range.end_bound() {
+            Bound::Included(&last) => last,
+            Bound::Excluded(&end) => end.checked_sub(1).ok_or_else(invalid)?,
+            Bound::Unbounded => u16::MAX,
+        };
+        if first > last {
+            return Err(invalid());
+        }
+        Ok(InstanceIdRange { first, last })
+    }
+
+    /// The first instance ID of the range.
+    pub fn first(&self) -> u16 {
+        self.first
+    }
+
+    /// The last instance ID of the range, included.
+    pub fn last(&self) -> u16 {
+        self.last
+    }
+
+    /// Returns the range with `last` as its last instance ID, keeping the first one.
+    ///
+    /// # Errors
+    /// Returns `InvalidConfig` if `last` is before the first instance ID.
+    pub fn with_last(&self, last: u16) -> Result<Self, SINTEFlakeError> {
+        InstanceIdRange::new(self.first..=last)
+    }
+
+    /// Number of instance IDs in the range.
+    pub fn len(&self) -> u32 {
+        (self.last - self.first) as u32 + 1
+    }
+
+    /// Always false, a range has at least one instance ID.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Whether the instance ID is in the range.
+    pub fn contains(&self, instance_id: u16) -> bool {
+        (self.first..=self.last).contains(&instance_id)
+    }
+
+    /// Returns all the instance IDs of the range, starting at the one at `seed` modulo
+    /// the length and wrapping around, so concurrent instances starting at random seeds
+    /// don't all race for the first ones.
+    pub fn starting_at(&self, seed: u64) -> impl Iterator<Item = u16> {
+        let (first, len) = (self.first as u64, self.len() as u64);
+        (0..len).map(move |offset| (first + (seed % len + offset) % len) as u16)
+    }
+}
+
+impl Default for InstanceIdRange {
+    fn default() -> Self {
+        InstanceIdRange::DEFAULT
+    }
+}
+
+/// Hands out unique instance IDs to the instances of a fleet, with expiring leases.
+///
+/// An instance that stops renewing its lease loses its instance ID after the TTL,
+/// so crashed instances don't hold instance IDs forever.
+pub trait InstanceIdAllocator: Debug + Send + Sync {
+    /// Leases a free instance ID for `ttl`.
+    ///
+    /// # Errors
+    /// Returns `InstanceIdUnavailable` if all the instance IDs are leased.
+    fn acquire(&self, ttl: Duration) -> Result<Lease, SINTEFlakeError>;
+
+    /// Extends the lease by its TTL.
+    ///
+    /// # Errors
+    /// Returns `LeaseExpired` if the lease has been lost.
+    fn renew(&self, lease: &Lease) -> Result<(), SINTEFlakeError>;
+
+    /// Gives the instance ID back, if the lease is still owned.
+    fn release(&self, lease: &Lease) -> Result<(), SINTEFlakeError>;
+
+    /// The range of the instance IDs handed out, all the ones of the default layout
+    /// unless the allocator is restricted to a range.
+    fn instance_id_range(&self) -> InstanceIdRange {
+        InstanceIdRange::DEFAULT
+    }
+}
+
+/// Returns a random token for a new lease, for implementations of [`InstanceIdAllocator`].
+pub fn new_token() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    if let Ok(elapsed) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(elapsed.as_nanos());
+    }
+    hasher.finish()
+}
+
+/// Validity of a lease, shared between the heartbeat thread and the generator.
+#[derive(Debug)]
+pub(crate) struct LeaseStatus {
+    anchor: Instant,
+    // milliseconds since the anchor
+    valid_until: AtomicU64,
+}
+
+impl LeaseStatus {
+    fn new(anchor: Instant, ttl: Duration) -> Self {
+        LeaseStatus {
+            anchor,
+            valid_until: AtomicU64::new(ttl.as_millis() as u64),
+        }
+    }
+
+    /// Extends the validity to `ttl` after `renewed_at`, the time the renewal was requested.
+    fn extend(&self, renewed_at: Instant, ttl: Duration) {
+        let valid_until = renewed_at.duration_since(self.anchor) + ttl;
+        self.valid_until
+            .fetch_max(valid_until.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    fn expire(&self) {
+        self.valid_until.store(0, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_valid(&self) -> bool {
+        (self.anchor.elapsed().as_millis() as u64) < self.valid_until.load(Ordering::SeqCst)
+    }
+}
+
+/// A lease kept alive by a background thread, renewing it every third of its TTL.
+///
+/// Give it to [`crate::builder::SINTEFlakeBuilder::instance_id_lease`], and the generator
+/// refuses to generate IDs with `LeaseExpired` once the lease lapses. The lease is released
+/// when dropped.
+#[derive(Debug)]
+pub struct InstanceIdLease {
+    allocator: Arc<dyn InstanceIdAllocator>,
+    lease: Lease,
+    status: Arc<LeaseStatus>,
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    heartbeat: Option<JoinHandle<()>>,
+}
+
+impl InstanceIdLease {
+    /// Acquires an instance ID and starts the heartbeats.
+    ///
+    /// # Errors
+    /// Returns an error if the allocator can't lease an instance ID, or `AllocatorError`
+    /// if it leased one outside of its range, which is released.
+    pub fn acquire<A: InstanceIdAllocator + 'static>(
+        allocator: A,
+        ttl: Duration,
+    ) -> Result<Self, SINTEFlakeError> {
+        let allocator: Arc<dyn InstanceIdAllocator> = Arc::new(allocator);
+        let requested_at = Instant::now();
+        let lease = allocator.acquire(ttl)?;
+        if !allocator.instance_id_range().contains(lease.instance_id) {
+            allocator.release(&lease)?;
+            return Err(SINTEFlakeError::AllocatorError);
+        }
+        let status = Arc::new(LeaseStatus::new(requested_at, ttl));
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+
+        let heartbeat = {
+            let (allocator, lease, status, stop) = (
+                allocator.clone(),
+                lease.clone(),
+                status.clone(),
+                stop.clone(),
+            );
+            std::thread::spawn(move || heartbeat(&*allocator, &lease, &status, &stop))
+        };
+
+        Ok(InstanceIdLease {
+            allocator,
+            lease,
+            status,
+            stop,
+            heartbeat: Some(heartbeat),
+        })
+    }
+
+    /// The leased instance ID.
+    pub fn instance_id(&self) -> u16 {
+        self.lease.instance_id
+    }
+
+    /// Whether the lease is still valid.
+    pub fn is_valid(&self) -> bool {
+        self.status.is_valid()
+    }
+
+    pub(crate) fn status(&self) -> Arc<LeaseStatus> {
+        self.status.clone()
+    }
+}
+
+fn heartbeat(
+    allocator: &dyn InstanceIdAllocator,
+    lease: &Lease,
+    status: &LeaseStatus,
+    stop: &(Mutex<bool>, Condvar),
+) {
+    let (stopped, condvar) = stop;
+    let mut stopped = crate::singleton::lock(stopped).expect("stop flag");
+    loop {
+        stopped = condvar
+            .wait_timeout_while(stopped, lease.ttl / 3, |stopped| !*stopped)
+            .expect("stop flag")
+            .0;
+        if *stopped {
+            return;
+        }
+
+        let renewed_at = Instant::now();
+        match allocator.renew(lease) {
+            Ok(()) => status.extend(renewed_at, lease.ttl),
+            Err(SINTEFlakeError::LeaseExpired) => {
+                status.expire();
+                return;
+            }
+            // the lease may still be valid, the next heartbeat will try again
+            Err(_) => {}
+        }
+    }
+}
+
+impl Drop for InstanceIdLease {
+    fn drop(&mut self) {
+        let (stopped, condvar) = &*self.stop;
+        if let Ok(mut stopped) = crate::singleton::lock(stopped) {
+            *stopped = true;
+        }
+        condvar.notify_all();
+        if let Some(heartbeat) = self.heartbeat.take() {
+            let _ = heartbeat.join();
+        }
+        self.status.expire();
+        // best effort, the lease expires anyway
+        let _ = self.allocator.release(&self.lease);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Allocator keeping the leases in memory.
+    #[derive(Debug, Clone, Default)]
+    pub(crate) struct MemoryAllocator {
+        pub(crate) leases: Arc<Mutex<HashMap<u16, (u64, Instant)>>>,
+        pub(crate) instance_ids: InstanceIdRange,
+    }
+
+    impl MemoryAllocator {
+        pub(crate) fn new(max_instance_id: u16) -> Self {
+            MemoryAllocator {
+                leases: Arc::default(),
+                instance_ids: InstanceIdRange::new(..=max_instance_id).unwrap(),
+            }
+        }
+    }
+
+    impl InstanceIdAllocator for MemoryAllocator {
+        fn acquire(&self, ttl: Duration) -> Result<Lease, SINTEFlakeError> {
+            let mut leases = self.leases.lock().unwrap();
+            let now = Instant::now();
+            let instance_id = (self.instance_ids.first()..=self.instance_ids.last())
+                .find(|id| leases.get(id).is_none_or(|(_, expiry)| *expiry <= now))
+                .ok_or(SINTEFlakeError::InstanceIdUnavailable)?;
+            let token = new_token();
+            leases.insert(instance_id, (token, now + ttl));
+            Ok(Lease {
+                instance_id,
+                token,
+                ttl,
+            })
+        }
+
+        fn renew(&self, lease: &Lease) -> Result<(), SINTEFlakeError> {
+            let mut leases = self.leases.lock().unwrap();
+            match leases.get_mut(&lease.instance_id) {
+                Some((token, expiry)) if *token == lease.token && *expiry > Instant::now() => {
+                    *expiry = Instant::now() + lease.ttl;
+                    Ok(())
+                }
+                _ => Err(SINTEFlakeError::LeaseExpired),
+            }
+        }
+
+        fn release(&self, lease: &Lease) -> Result<(), SINTEFlakeError> {
+            let mut leases = self.leases.lock().unwrap();
+            if leases.get(&lease.instance_id).map(|(token, _)| *token) == Some(lease.token) {
+                leases.remove(&lease.instance_id);
+            }
+            Ok(())
+        }
+
+        fn instance_id_range(&self) -> InstanceIdRange {
+            self.instance_ids
+        }
+    }
+
+    #[test]
+    fn test_memory_allocator() {
+        let allocator = MemoryAllocator::new(1);
+        let ttl = Duration::from_secs(60);
+        let a = allocator.acquire(ttl).unwrap();
+        let b = allocator.acquire(ttl).unwrap();
+        assert_ne!(a.instance_id, b.instance_id);
+        assert!(matches!(
+            allocator.acquire(ttl),
+            Err(SINTEFlakeError::InstanceIdUnavailable)
+        ));
+
+        allocator.release(&a).unwrap();
+        let c = allocator.acquire(ttl).unwrap();
+        assert_eq!(c.instance_id, a.instance_id);
+        assert!(matches!(
+            allocator.renew(&a),
+            Err(SINTEFlakeError::LeaseExpired)
+        ));
+        assert!(allocator.renew(&c).is_ok());
+    }
+
+    #[test]
+    fn test_instance_id_range() {
+        let blue = InstanceIdRange::new(0..512).unwrap();
+        let green = InstanceIdRange::new(512..=1023).unwrap();
+        assert_eq!((blue.first(), blue.last(), blue.len()), (0, 511, 512));
+        assert_eq!((green.first(), green.last(), green.len()), (512, 1023, 512));
+        assert!(blue.contains(511) && !blue.contains(512) && green.contains(512));
+        assert_eq!(InstanceIdRange::new(..).unwrap().len(), 65536);
+        assert_eq!(green.with_last(4095).unwrap().first(), 512);
+        assert!(green.with_last(511).is_err());
+        assert!(matches!(
+            InstanceIdRange::new(5..5),
+            Err(SINTEFlakeError::InvalidConfig {
+                setting: "instance_id_range"
+            })
+        ));
+
+        let ids: Vec<u16> = InstanceIdRange::new(10..14)
+            .unwrap()
+            .starting_at(6)
+            .collect();
+        assert_eq!(ids, [12, 13, 10, 11]);
+
+        // two deployments sharing the backend
+        let leases = Arc::new(Mutex::new(HashMap::new()));
+        let ttl = Duration::from_secs(60);
+        for (range, expected) in [(blue, 0), (green, 512)] {
+            let allocator = MemoryAllocator {
+                leases: leases.clone(),
+                instance_ids: range,
+            };
+            let lease = InstanceIdLease::acquire(allocator, ttl).unwrap();
+            assert_eq!(lease.instance_id(), expected);
+        }
+    }
+
+    #[test]
+    fn test_lease_heartbeats() {
+        let allocator = MemoryAllocator::new(3);
+        let lease = InstanceIdLease::acquire(allocator.clone(), Duration::from_millis(60)).unwrap();
+        assert_eq!(lease.instance_id(), 0);
+
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(lease.is_valid());
+
+        drop(lease);
+        assert!(allocator.leases.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_lost_lease() {
+        let allocator = MemoryAllocator::new(3);
+        let lease = InstanceIdLease::acquire(allocator.clone(), Duration::from_millis(60)).unwrap();
+        allocator.leases.lock().unwrap().clear();
+
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!lease.is_valid());
+    }
+
+    #[test]
+    fn test_lease_status() {
+        let anchor = Instant::now();
+        let status = LeaseStatus::new(anchor, Duration::from_secs(60));
+        assert!(status.is_valid());
+        status.expire();
+        assert!(!status.is_valid());
+        status.extend(anchor, Duration::from_secs(60));
+        assert!(status.is_valid());
+    }
+}
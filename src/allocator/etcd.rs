@@ -0,0 +1,275 @@
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::{json, Value};
+
+use super::{InstanceIdAllocator, InstanceIdRange, Lease};
+use crate::error::SINTEFlakeError;
+
+/// Leases instance IDs with etcd keys `<prefix>/<instance_id>` attached to etcd leases.
+///
+/// It talks to the JSON gateway of the etcd v3 API, so no gRPC stack is needed.
+/// The token of a [`Lease`] is the ID of the etcd lease, kept alive by the renewals.
+/// A key is only created if it doesn't exist, in a transaction, and disappears
+/// with its etcd lease.
+#[derive(Debug, Clone)]
+pub struct EtcdAllocator {
+    endpoints: Vec<String>,
+    prefix: String,
+    instance_ids: InstanceIdRange,
+    agent: ureq::Agent,
+}
+
+impl EtcdAllocator {
+    /// Creates an allocator for the instance IDs of the default layout, from 0 to 1023.
+    ///
+    /// # Arguments
+    /// * `endpoints` - URLs of the etcd members, like `http://127.0.0.1:2379`, tried in order.
+    /// * `prefix` - Prefix of the keys.
+    pub fn new<E: AsRef<str>>(endpoints: &[E], prefix: &str) -> Self {
+        EtcdAllocator {
+            endpoints: endpoints
+                .iter()
+                .map(|endpoint| endpoint.as_ref().trim_end_matches('/').to_string())
+                .collect(),
+            prefix: prefix.to_string(),
+            instance_ids: InstanceIdRange::DEFAULT,
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(5))
+                .build(),
+        }
+    }
+
+    /// Sets the largest instance ID to hand out, for custom layouts, keeping the first
+    /// instance ID of the range.
+    ///
+    /// # Errors
+    /// Returns `InvalidConfig` if it is before the first instance ID of the range.
+    pub fn max_instance_id(mut self, max_instance_id: u16) -> Result<Self, SINTEFlakeError> {
+        self.instance_ids = self.instance_ids.with_last(max_instance_id)?;
+        Ok(self)
+    }
+
+    /// Sets the range of the instance IDs to hand out, see [`InstanceIdRange`].
+    pub fn with_instance_id_range(mut self, instance_ids: InstanceIdRange) -> Self {
+        self.instance_ids = instance_ids;
+        self
+    }
+
+    fn key(&self, instance_id: u16) -> String {
+        format!("{}/{}", self.prefix, instance_id)
+    }
+
+    /// Creates the key of the first free instance ID, attached to the etcd lease.
+    ///
+    /// Returns `None` if all the instance IDs are taken.
+    fn claim_instance_id(&self, lease_id: i64) -> Result<Option<u16>, SINTEFlakeError> {
+        // start at an instance ID depending on the lease, so concurrent instances don't race
+        for instance_id in self.instance_ids.starting_at(lease_id as u64) {
+            let key = STANDARD.encode(self.key(instance_id));
+            let txn = self.call(
+                "kv/txn",
+                json!({
+                    "compare": [{
+                        "key": key,
+                        "target": "CREATE",
+                        "result": "EQUAL",
+                        "create_revision": "0",
+                    }],
+                    "success": [{
+                        "request_put": {
+                            "key": key,
+                            "value": STANDARD.encode(lease_id.to_string()),
+                            "lease": lease_id.to_string(),
+                        },
+                    }],
+                }),
+            )?;
+            if txn["succeeded"] == Value::Bool(true) {
+                return Ok(Some(instance_id));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Calls a method of the gateway on the first endpoint that answers.
+    fn call(&self, method: &str, request: Value) -> Result<Value, SINTEFlakeError> {
+        for endpoint in &self.endpoints {
+            let response = self
+                .agent
+                .post(&format!("{}/v3/{}", endpoint, method))
+                .send_json(request.clone());
+            if let Ok(response) = response {
+                return response
+                    .into_json()
+                    .map_err(|_| SINTEFlakeError::AllocatorError);
+            }
+        }
+        Err(SINTEFlakeError::AllocatorError)
+    }
+}
+
+/// The gateway encodes 64-bit integers as strings.
+fn int_field(value: &Value, field: &str) -> Option<i64> {
+    match &value[field] {
+        Value::String(text) => text.parse().ok(),
+        Value::Number(number) => number.as_i64(),
+        _ => None,
+    }
+}
+
+impl InstanceIdAllocator for EtcdAllocator {
+    fn acquire(&self, ttl: Duration) -> Result<Lease, SINTEFlakeError> {
+        let grant = self.call("lease/grant", json!({ "TTL": ttl.as_secs().max(1) }))?;
+        let lease_id = int_field(&grant, "ID").ok_or(SINTEFlakeError::AllocatorError)?;
+
+        let claimed = self.claim_instance_id(lease_id);
+        if !matches!(claimed, Ok(Some(_))) {
+            // best effort, the etcd lease would otherwise only expire after its TTL
+            let _ = self.call("lease/revoke", json!({ "ID": lease_id.to_string() }));
+        }
+        match claimed? {
+            Some(instance_id) => Ok(Lease {
+                instance_id,
+                token: lease_id as u64,
+                ttl,
+            }),
+            None => Err(SINTEFlakeError::InstanceIdUnavailable),
+        }
+    }
+
+    fn renew(&self, lease: &Lease) -> Result<(), SINTEFlakeError> {
+        let response = self.call(
+            "lease/keepalive",
+            json!({ "ID": (lease.token as i64).to_string() }),
+        )?;
+        // an expired lease comes back without TTL
+        match int_field(&response["result"], "TTL") {
+            Some(ttl) if ttl > 0 => Ok(()),
+            _ => Err(SINTEFlakeError::LeaseExpired),
+        }
+    }
+
+    fn release(&self, lease: &Lease) -> Result<(), SINTEFlakeError> {
+        self.call(
+            "lease/revoke",
+            json!({ "ID": (lease.token as i64).to_string() }),
+        )?;
+        Ok(())
+    }
+
+    fn instance_id_range(&self) -> InstanceIdRange {
+        self.instance_ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_etcd_allocator_keys() {
+        let allocator = EtcdAllocator::new(&["http://127.0.0.1:2379/"], "/sinteflake")
+            .max_instance_id(15)
+            .unwrap();
+        assert_eq!(allocator.key(7), "/sinteflake/7");
+        assert_eq!(allocator.endpoints, ["http://127.0.0.1:2379"]);
+        assert_eq!(
+            allocator.instance_ids,
+            InstanceIdRange::new(0..=15).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_max_instance_id_keeps_the_range() {
+        let green = InstanceIdRange::new(512..1024).unwrap();
+        let new = || EtcdAllocator::new(&["http://127.0.0.1:2379"], "/sinteflake");
+        let allocator = new()
+            .with_instance_id_range(green)
+            .max_instance_id(1023)
+            .unwrap();
+        assert_eq!(allocator.instance_ids, green);
+        let allocator = new()
+            .max_instance_id(1023)
+            .unwrap()
+            .with_instance_id_range(green);
+        assert_eq!(allocator.instance_ids, green);
+        assert!(matches!(
+            new().with_instance_id_range(green).max_instance_id(511),
+            Err(SINTEFlakeError::InvalidConfig { .. })
+        ));
+    }
+
+    #[test]
+    fn test_int_field() {
+        let value = json!({ "ID": "7587862143553498632", "TTL": 30, "other": true });
+        assert_eq!(int_field(&value, "ID"), Some(7587862143553498632));
+        assert_eq!(int_field(&value, "TTL"), Some(30));
+        assert_eq!(int_field(&value, "other"), None);
+        assert_eq!(int_field(&value, "missing"), None);
+    }
+
+    #[test]
+    fn test_lease_revoked_on_error() {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+
+        // a gateway granting leases and failing the transactions
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let gateway = std::thread::spawn(move || {
+            let mut methods = Vec::new();
+            for stream in listener.incoming().take(3) {
+                let mut reader = BufReader::new(stream.unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let method = line.split(' ').nth(1).unwrap().to_string();
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = header.to_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                reader.read_exact(&mut vec![0; length]).unwrap();
+                let (status, body) = match method.as_str() {
+                    "/v3/kv/txn" => ("500 Internal Server Error", ""),
+                    _ => ("200 OK", r#"{"ID":"42"}"#),
+                };
+                let response = format!(
+                    "HTTP/1.1 {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                methods.push(method);
+            }
+            methods
+        });
+
+        let allocator = EtcdAllocator::new(&[endpoint], "/sinteflake");
+        assert!(matches!(
+            allocator.acquire(Duration::from_secs(10)),
+            Err(SINTEFlakeError::AllocatorError)
+        ));
+        assert_eq!(
+            gateway.join().unwrap(),
+            ["/v3/lease/grant", "/v3/kv/txn", "/v3/lease/revoke"]
+        );
+    }
+
+    #[test]
+    fn test_unreachable_etcd() {
+        let allocator = EtcdAllocator::new(&["http://127.0.0.1:1"], "/sinteflake");
+        assert!(matches!(
+            allocator.acquire(Duration::from_secs(10)),
+            Err(SINTEFlakeError::AllocatorError)
+        ));
+    }
+}
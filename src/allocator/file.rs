@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::{new_token, InstanceIdAllocator, InstanceIdRange, Lease};
+use crate::error::SINTEFlakeError;
+use crate::singleton::lock;
+
+/// Hands out distinct instance IDs to the processes of one host, with lock files.
+///
+/// Each instance ID of the range has a lock file `<directory>/<instance_id>.lock`,
+/// exclusively locked by the process using it. The operating system releases the lock
+/// when the process exits, even if it crashes, so no lease ever lingers.
+/// The TTL is not used: a lease stays valid as long as the process holds the lock.
+///
+/// Give each host its own range of instance IDs, so processes on different hosts
+/// don't share instance IDs either.
+#[derive(Debug)]
+pub struct FileLockAllocator {
+    directory: PathBuf,
+    // none for an empty range
+    instance_ids: Option<InstanceIdRange>,
+    files: Mutex<HashMap<u64, File>>,
+}
+
+impl FileLockAllocator {
+    /// Creates an allocator for the instance IDs of the default layout, from 0 to 1023.
+    ///
+    /// The directory must be shared by the processes, and is created if needed.
+    pub fn new<P: AsRef<Path>>(directory: P) -> Self {
+        FileLockAllocator {
+            directory: directory.as_ref().to_path_buf(),
+            instance_ids: Some(InstanceIdRange::DEFAULT),
+            files: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the range of instance IDs of the host.
+    ///
+    /// With an empty range, [`InstanceIdAllocator::acquire`] returns `InstanceIdUnavailable`.
+    pub fn instance_ids(mut self, instance_ids: RangeInclusive<u16>) -> Self {
+        self.instance_ids = InstanceIdRange::new(instance_ids).ok();
+        self
+    }
+
+    /// Sets the range of instance IDs of the host, like [`FileLockAllocator::instance_ids`].
+    pub fn with_instance_id_range(mut self, instance_ids: InstanceIdRange) -> Self {
+        self.instance_ids = Some(instance_ids);
+        self
+    }
+
+    fn path(&self, instance_id: u16) -> PathBuf {
+        self.directory.join(format!("{}.lock", instance_id))
+    }
+}
+
+impl InstanceIdAllocator for FileLockAllocator {
+    fn acquire(&self, ttl: Duration) -> Result<Lease, SINTEFlakeError> {
+        fs::create_dir_all(&self.directory).map_err(|_| SINTEFlakeError::AllocatorError)?;
+
+        let Some(instance_ids) = self.instance_ids else {
+            return Err(SINTEFlakeError::InstanceIdUnavailable);
+        };
+        for instance_id in instance_ids.first()..=instance_ids.last() {
+            let file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(self.path(instance_id))
+                .map_err(|_| SINTEFlakeError::AllocatorError)?;
+            if file.try_lock().is_err() {
+                continue;
+            }
+
+            let token = new_token();
+            lock(&self.files)?.insert(token, file);
+            return Ok(Lease {
+                instance_id,
+                token,
+                ttl,
+            });
+        }
+        Err(SINTEFlakeError::InstanceIdUnavailable)
+    }
+
+    fn renew(&self, lease: &Lease) -> Result<(), SINTEFlakeError> {
+        // the lock is held until it's released
+        match lock(&self.files)?.contains_key(&lease.token) {
+            true => Ok(()),
+            false => Err(SINTEFlakeError::LeaseExpired),
+        }
+    }
+
+    fn release(&self, lease: &Lease) -> Result<(), SINTEFlakeError> {
+        if let Some(file) = lock(&self.files)?.remove(&lease.token) {
+            file.unlock().map_err(|_| SINTEFlakeError::AllocatorError)?;
+        }
+        Ok(())
+    }
+
+    fn instance_id_range(&self) -> InstanceIdRange {
+        // an empty range hands out no lease to check against the range
+        self.instance_ids.unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::tests::temporary_path;
+
+    #[test]
+    fn test_file_lock_allocator() {
+        let directory = temporary_path("locks");
+        let ttl = Duration::from_secs(60);
+        let allocator = FileLockAllocator::new(&directory).instance_ids(10..=11);
+        // another process on the same host
+        let other = FileLockAllocator::new(&directory).instance_ids(10..=11);
+
+        let a = allocator.acquire(ttl).unwrap();
+        let b = other.acquire(ttl).unwrap();
+        assert_eq!((a.instance_id, b.instance_id), (10, 11));
+        assert!(matches!(
+            allocator.acquire(ttl),
+            Err(SINTEFlakeError::InstanceIdUnavailable)
+        ));
+        assert!(allocator.renew(&a).is_ok());
+        assert!(matches!(
+            allocator.renew(&b),
+            Err(SINTEFlakeError::LeaseExpired)
+        ));
+
+        other.release(&b).unwrap();
+        assert_eq!(allocator.acquire(ttl).unwrap().instance_id, 11);
+
+        assert_eq!(
+            allocator.instance_id_range(),
+            InstanceIdRange::new(10..=11).unwrap()
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_empty_range() {
+        let directory = temporary_path("empty");
+        let (first, last) = (11, 10);
+        let allocator = FileLockAllocator::new(&directory).instance_ids(first..=last);
+        assert!(matches!(
+            allocator.acquire(Duration::from_secs(1)),
+            Err(SINTEFlakeError::InstanceIdUnavailable)
+        ));
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
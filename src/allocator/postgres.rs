@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use postgres::{Client, NoTls};
+
+use super::{new_token, InstanceIdAllocator, InstanceIdRange, Lease};
+use crate::error::SINTEFlakeError;
+use crate::singleton::lock;
+
+/// Claims instance IDs with PostgreSQL session-level advisory locks.
+///
+/// Each lease keeps its own connection holding the advisory lock of its instance ID,
+/// so the instance ID is freed by PostgreSQL as soon as the connection is lost.
+/// The owners are also recorded in a table, created if needed, for visibility.
+/// The TTL is not used: a lease stays valid as long as its connection is alive.
+pub struct PostgresAllocator {
+    url: String,
+    table: String,
+    instance_ids: InstanceIdRange,
+    connections: Mutex<HashMap<u64, Client>>,
+}
+
+impl fmt::Debug for PostgresAllocator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PostgresAllocator")
+            .field("table", &self.table)
+            .field("instance_ids", &self.instance_ids)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PostgresAllocator {
+    /// Creates an allocator for the instance IDs of the default layout, from 0 to 1023.
+    ///
+    /// # Arguments
+    /// * `url` - Connection string, like `postgresql://user@localhost/db`.
+    /// * `table` - Table recording the owners, also used as the namespace of the advisory locks.
+    ///
+    /// # Errors
+    /// Returns an error if the table name is not a plain SQL identifier.
+    pub fn new(url: &str, table: &str) -> Result<Self, SINTEFlakeError> {
+        let valid = !table.is_empty()
+            && table
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'.');
+        if !valid {
+            return Err(SINTEFlakeError::AllocatorError);
+        }
+        Ok(PostgresAllocator {
+            url: url.to_string(),
+            table: table.to_string(),
+            instance_ids: InstanceIdRange::DEFAULT,
+            connections: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Sets the largest instance ID to hand out, for custom layouts, keeping the first
+    /// instance ID of the range.
+    ///
+    /// # Errors
+    /// Returns `InvalidConfig` if it is before the first instance ID of the range.
+    pub fn max_instance_id(mut self, max_instance_id: u16) -> Result<Self, SINTEFlakeError> {
+        self.instance_ids = self.instance_ids.with_last(max_instance_id)?;
+        Ok(self)
+    }
+
+    /// Sets the range of the instance IDs to hand out, see [`InstanceIdRange`].
+    pub fn with_instance_id_range(mut self, instance_ids: InstanceIdRange) -> Self {
+        self.instance_ids = instance_ids;
+        self
+    }
+
+    fn try_claim(&self, client: &mut Client, instance_id: u16) -> Result<bool, postgres::Error> {
+        let locked: bool = client
+            .query_one(
+                "SELECT pg_try_advisory_lock(hashtext($1), $2)",
+                &[&self.table, &(instance_id as i32)],
+            )?
+            .get(0);
+        Ok(locked)
+    }
+}
+
+impl InstanceIdAllocator for PostgresAllocator {
+    fn acquire(&self, ttl: Duration) -> Result<Lease, SINTEFlakeError> {
+        let mut client =
+            Client::connect(&self.url, NoTls).map_err(|_| SINTEFlakeError::AllocatorError)?;
+        client
+            .batch_execute(&format!(
+                "CREATE TABLE IF NOT EXISTS {} (
+                    instance_id integer PRIMARY KEY,
+                    token bigint NOT NULL,
+                    renewed_at timestamptz NOT NULL
+                )",
+                self.table
+            ))
+            .map_err(|_| SINTEFlakeError::AllocatorError)?;
+
+        let token = new_token();
+        // start at a random instance ID, so concurrent instances don't all race for the first ones
+        for instance_id in self.instance_ids.starting_at(token) {
+            if !self
+                .try_claim(&mut client, instance_id)
+                .map_err(|_| SINTEFlakeError::AllocatorError)?
+            {
+                continue;
+            }
+
+            client
+                .execute(
+                    &format!(
+                        "INSERT INTO {} (instance_id, token, renewed_at) VALUES ($1, $2, now())
+                        ON CONFLICT (instance_id)
+                        DO UPDATE SET token = $2, renewed_at = now()",
+                        self.table
+                    ),
+                    &[&(instance_id as i32), &(token as i64)],
+                )
+                .map_err(|_| SINTEFlakeError::AllocatorError)?;
+            lock(&self.connections)?.insert(token, client);
+            return Ok(Lease {
+                instance_id,
+                token,
+                ttl,
+            });
+        }
+        Err(SINTEFlakeError::InstanceIdUnavailable)
+    }
+
+    fn renew(&self, lease: &Lease) -> Result<(), SINTEFlakeError> {
+        let mut connections = lock(&self.connections)?;
+        let client = connections
+            .get_mut(&lease.token)
+            .ok_or(SINTEFlakeError::LeaseExpired)?;
+
+        let updated = client.execute(
+            &format!(
+                "UPDATE {} SET renewed_at = now() WHERE instance_id = $1 AND token = $2",
+                self.table
+            ),
+            &[&(lease.instance_id as i32), &(lease.token as i64)],
+        );
+        match updated {
+            Ok(_) => Ok(()),
+            // the advisory lock is gone with the connection
+            Err(_) if client.is_closed() => {
+                connections.remove(&lease.token);
+                Err(SINTEFlakeError::LeaseExpired)
+            }
+            Err(_) => Err(SINTEFlakeError::AllocatorError),
+        }
+    }
+
+    fn release(&self, lease: &Lease) -> Result<(), SINTEFlakeError> {
+        let Some(mut client) = lock(&self.connections)?.remove(&lease.token) else {
+            return Ok(());
+        };
+        client
+            .execute(
+                &format!(
+                    "DELETE FROM {} WHERE instance_id = $1 AND token = $2",
+                    self.table
+                ),
+                &[&(lease.instance_id as i32), &(lease.token as i64)],
+            )
+            .map_err(|_| SINTEFlakeError::AllocatorError)?;
+        // closing the connection releases the advisory lock
+        client.close().map_err(|_| SINTEFlakeError::AllocatorError)
+    }
+
+    fn instance_id_range(&self) -> InstanceIdRange {
+        self.instance_ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_postgres_allocator_table() {
+        let allocator = PostgresAllocator::new("postgresql://localhost/db", "public.instances")
+            .unwrap()
+            .max_instance_id(15)
+            .unwrap();
+        assert_eq!(
+            allocator.instance_ids,
+            InstanceIdRange::new(0..=15).unwrap()
+        );
+        assert!(PostgresAllocator::new("postgresql://localhost/db", "x; DROP TABLE y").is_err());
+        assert!(PostgresAllocator::new("postgresql://localhost/db", "").is_err());
+    }
+
+    #[test]
+    fn test_max_instance_id_keeps_the_range() {
+        let green = InstanceIdRange::new(512..1024).unwrap();
+        let new = || PostgresAllocator::new("postgresql://localhost/db", "instances").unwrap();
+        let allocator = new()
+            .with_instance_id_range(green)
+            .max_instance_id(1023)
+            .unwrap();
+        assert_eq!(allocator.instance_ids, green);
+        let allocator = new()
+            .max_instance_id(1023)
+            .unwrap()
+            .with_instance_id_range(green);
+        assert_eq!(allocator.instance_ids, green);
+        assert!(matches!(
+            new().with_instance_id_range(green).max_instance_id(511),
+            Err(SINTEFlakeError::InvalidConfig { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unknown_lease() {
+        let allocator = PostgresAllocator::new("postgresql://localhost/db", "instances").unwrap();
+        let lease = Lease {
+            instance_id: 1,
+            token: 2,
+            ttl: Duration::from_secs(10),
+        };
+        assert!(matches!(
+            allocator.renew(&lease),
+            Err(SINTEFlakeError::LeaseExpired)
+        ));
+        assert!(allocator.release(&lease).is_ok());
+    }
+}
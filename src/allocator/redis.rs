@@ -0,0 +1,170 @@
+use std::time::Duration;
+
+use redis::{Client, Connection};
+
+use super::{new_token, InstanceIdAllocator, InstanceIdRange, Lease};
+use crate::error::SINTEFlakeError;
+
+// deletes or extends the key only if it still holds the token of the lease
+const RENEW_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("PEXPIRE", KEYS[1], ARGV[2])
+end
+return 0
+"#;
+
+const RELEASE_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+end
+return 0
+"#;
+
+/// Leases instance IDs with Redis keys `<prefix>:<instance_id>` expiring after the TTL.
+///
+/// The keys are created with `SET NX PX`, and renewed or deleted by scripts
+/// checking the token of the lease, so a lapsed lease can't be renewed
+/// once another instance took the instance ID.
+#[derive(Debug, Clone)]
+pub struct RedisAllocator {
+    client: Client,
+    prefix: String,
+    instance_ids: InstanceIdRange,
+}
+
+impl RedisAllocator {
+    /// Creates an allocator for the instance IDs of the default layout, from 0 to 1023.
+    ///
+    /// # Errors
+    /// Returns an error if the URL is invalid.
+    pub fn new(url: &str, prefix: &str) -> Result<Self, SINTEFlakeError> {
+        let client = Client::open(url).map_err(|_| SINTEFlakeError::AllocatorError)?;
+        Ok(RedisAllocator {
+            client,
+            prefix: prefix.to_string(),
+            instance_ids: InstanceIdRange::DEFAULT,
+        })
+    }
+
+    /// Sets the largest instance ID to hand out, for custom layouts, keeping the first
+    /// instance ID of the range.
+    ///
+    /// # Errors
+    /// Returns `InvalidConfig` if it is before the first instance ID of the range.
+    pub fn max_instance_id(mut self, max_instance_id: u16) -> Result<Self, SINTEFlakeError> {
+        self.instance_ids = self.instance_ids.with_last(max_instance_id)?;
+        Ok(self)
+    }
+
+    /// Sets the range of the instance IDs to hand out, see [`InstanceIdRange`].
+    pub fn with_instance_id_range(mut self, instance_ids: InstanceIdRange) -> Self {
+        self.instance_ids = instance_ids;
+        self
+    }
+
+    fn connection(&self) -> Result<Connection, SINTEFlakeError> {
+        self.client
+            .get_connection()
+            .map_err(|_| SINTEFlakeError::AllocatorError)
+    }
+
+    fn key(&self, instance_id: u16) -> String {
+        format!("{}:{}", self.prefix, instance_id)
+    }
+}
+
+impl InstanceIdAllocator for RedisAllocator {
+    fn acquire(&self, ttl: Duration) -> Result<Lease, SINTEFlakeError> {
+        let mut connection = self.connection()?;
+        let token = new_token();
+
+        // start at a random instance ID, so concurrent instances don't all race for the first ones
+        for instance_id in self.instance_ids.starting_at(token) {
+            let created: Option<String> = redis::cmd("SET")
+                .arg(self.key(instance_id))
+                .arg(token)
+                .arg("NX")
+                .arg("PX")
+                .arg(ttl.as_millis() as u64)
+                .query(&mut connection)
+                .map_err(|_| SINTEFlakeError::AllocatorError)?;
+            if created.is_some() {
+                return Ok(Lease {
+                    instance_id,
+                    token,
+                    ttl,
+                });
+            }
+        }
+        Err(SINTEFlakeError::InstanceIdUnavailable)
+    }
+
+    fn renew(&self, lease: &Lease) -> Result<(), SINTEFlakeError> {
+        let renewed: i64 = redis::cmd("EVAL")
+            .arg(RENEW_SCRIPT)
+            .arg(1)
+            .arg(self.key(lease.instance_id))
+            .arg(lease.token)
+            .arg(lease.ttl.as_millis() as u64)
+            .query(&mut self.connection()?)
+            .map_err(|_| SINTEFlakeError::AllocatorError)?;
+        match renewed {
+            0 => Err(SINTEFlakeError::LeaseExpired),
+            _ => Ok(()),
+        }
+    }
+
+    fn release(&self, lease: &Lease) -> Result<(), SINTEFlakeError> {
+        let _: i64 = redis::cmd("EVAL")
+            .arg(RELEASE_SCRIPT)
+            .arg(1)
+            .arg(self.key(lease.instance_id))
+            .arg(lease.token)
+            .query(&mut self.connection()?)
+            .map_err(|_| SINTEFlakeError::AllocatorError)?;
+        Ok(())
+    }
+
+    fn instance_id_range(&self) -> InstanceIdRange {
+        self.instance_ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redis_allocator_keys() {
+        let allocator = RedisAllocator::new("redis://127.0.0.1/", "sinteflake")
+            .unwrap()
+            .max_instance_id(15)
+            .unwrap();
+        assert_eq!(allocator.key(7), "sinteflake:7");
+        assert_eq!(
+            allocator.instance_ids,
+            InstanceIdRange::new(0..=15).unwrap()
+        );
+        assert!(RedisAllocator::new("not a url", "sinteflake").is_err());
+    }
+
+    #[test]
+    fn test_max_instance_id_keeps_the_range() {
+        let green = InstanceIdRange::new(512..1024).unwrap();
+        let new = || RedisAllocator::new("redis://127.0.0.1/", "sinteflake").unwrap();
+        let allocator = new()
+            .with_instance_id_range(green)
+            .max_instance_id(1023)
+            .unwrap();
+        assert_eq!(allocator.instance_ids, green);
+        let allocator = new()
+            .max_instance_id(1023)
+            .unwrap()
+            .with_instance_id_range(green);
+        assert_eq!(allocator.instance_ids, green);
+        assert!(matches!(
+            new().with_instance_id_range(green).max_instance_id(511),
+            Err(SINTEFlakeError::InvalidConfig { .. })
+        ));
+    }
+}
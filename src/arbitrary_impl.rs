@@ -0,0 +1,67 @@
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::decode::DecodedId;
+use crate::id::SINTEFlakeId;
+use crate::layout::Layout;
+
+/// IDs of the default layout, below 2^63.
+impl<'a> Arbitrary<'a> for SINTEFlakeId {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        u.int_in_range(0..=Layout::DEFAULT.max_id())
+            .map(SINTEFlakeId::new)
+    }
+
+    fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+        (8, Some(8))
+    }
+}
+
+/// Fields fitting in the default layout.
+impl<'a> Arbitrary<'a> for DecodedId {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let layout = Layout::DEFAULT;
+        Ok(DecodedId {
+            hash: u.int_in_range(0..=layout.buckets() as u16 - 1)?,
+            timestamp: u.int_in_range(0..=layout.max_timestamp())?,
+            instance_id: u.int_in_range(0..=layout.max_instance_id())?,
+            sequence: u.int_in_range(0..=(layout.bucket_capacity() - 1) as u8)?,
+        })
+    }
+
+    fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+        (15, Some(15))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arbitrary() {
+        let layout = Layout::DEFAULT;
+        let bytes: alloc::vec::Vec<u8> = (0..=255).cycle().take(4096).collect();
+        let mut u = Unstructured::new(&bytes);
+        for _ in 0..100 {
+            let id = SINTEFlakeId::arbitrary(&mut u).unwrap();
+            assert!(id.get() <= layout.max_id());
+
+            let decoded = DecodedId::arbitrary(&mut u).unwrap();
+            let id = layout.construct_identifier(
+                decoded.hash,
+                decoded.timestamp,
+                decoded.instance_id,
+                decoded.sequence,
+            );
+            assert_eq!(
+                layout.deconstruct_identifier(id),
+                (
+                    decoded.hash,
+                    decoded.timestamp,
+                    decoded.instance_id,
+                    decoded.sequence
+                )
+            );
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! Uniqueness audit of large sets of IDs, for example from a database export.
+//!
+//! [`check_unique`] streams the IDs through a Bloom filter, using about 10 bits per ID,
+//! instead of keeping all of them in memory. The IDs found in the filter are duplicate
+//! suspects: most of them are duplicates, but about 1% of the unique IDs, over the
+//! expected number of IDs, are false positives. The suspects are counted per instance
+//! and per time window, to find the misconfigured instances and the incidents.
+//!
+//! [`confirm_duplicates`] streams the IDs a second time, if they can be read again, to
+//! tell the duplicates apart from the false positives, keeping only the suspects in memory.
+//!
+//! ```rust
+//! use sinteflake::audit::{check_unique, confirm_duplicates};
+//! use sinteflake::sinteflake::SINTEFlake;
+//!
+//! let mut instance = SINTEFlake::builder().build()?;
+//! let ids: Vec<u64> = (0..1000).map(|_| instance.next_id()).collect::<Result<_, _>>()?;
+//!
+//! let report = check_unique(ids.iter().chain(&ids[..10]).copied(), &instance.encoding_config());
+//! assert_eq!((report.ids, report.suspects.len()), (1010, 10));
+//! let duplicates = confirm_duplicates(ids.iter().chain(&ids[..10]).copied(), &report);
+//! assert_eq!(duplicates, &ids[..10]);
+//! # Ok::<(), sinteflake::error::SINTEFlakeError>(())
+//! ```
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::decode::{decode, EncodingConfig};
+
+/// Number of IDs the Bloom filter of [`check_unique`] is sized for, 16 millions, using 20 MB.
+pub const DEFAULT_EXPECTED_IDS: u64 = 1 << 24;
+
+// about 1% of false positives with 10 bits per ID and 7 hash functions
+const BITS_PER_ID: u64 = 10;
+const HASH_FUNCTIONS: u64 = 7;
+
+/// Result of a uniqueness audit.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditReport {
+    /// Number of audited IDs.
+    pub ids: u64,
+
+    /// IDs that don't fit in the layout of the settings, not counted per instance or window.
+    pub invalid: u64,
+
+    /// IDs found in the Bloom filter, duplicates or false positives, in order of discovery.
+    /// A duplicate seen `n` times is reported `n - 1` times.
+    pub suspects: Vec<u64>,
+
+    /// Number of suspects per instance ID.
+    pub suspects_by_instance: BTreeMap<u16, u64>,
+
+    /// Number of suspects per time window.
+    pub suspects_by_window: BTreeMap<u64, u64>,
+}
+
+impl AuditReport {
+    /// Returns true if no ID is a duplicate suspect, which proves that the IDs are unique.
+    ///
+    /// With suspects, [`confirm_duplicates`] tells the duplicates apart from the false
+    /// positives of the Bloom filter.
+    pub fn is_unique(&self) -> bool {
+        self.suspects.is_empty()
+    }
+}
+
+/// Checks that IDs are unique, with a Bloom filter sized for [`DEFAULT_EXPECTED_IDS`].
+pub fn check_unique(ids: impl IntoIterator<Item = u64>, config: &EncodingConfig) -> AuditReport {
+    check_unique_with_capacity(ids, config, DEFAULT_EXPECTED_IDS)
+}
+
+/// Checks that IDs are unique, with a Bloom filter sized for `expected_ids` IDs.
+///
+/// More IDs than expected increase the rate of false suspects.
+pub fn check_unique_with_capacity(
+    ids: impl IntoIterator<Item = u64>,
+    config: &EncodingConfig,
+    expected_ids: u64,
+) -> AuditReport {
+    let mut filter = BloomFilter::new(expected_ids.max(1).saturating_mul(BITS_PER_ID));
+    let mut report = AuditReport::default();
+    for id in ids {
+        report.ids += 1;
+        let decoded = decode(id, config);
+        if decoded.is_err() {
+            report.invalid += 1;
+        }
+        if filter.insert(id) {
+            continue;
+        }
+        report.suspects.push(id);
+        if let Ok(decoded) = decoded {
+            *report
+                .suspects_by_instance
+                .entry(decoded.instance_id)
+                .or_default() += 1;
+            *report
+                .suspects_by_window
+                .entry(decoded.timestamp)
+                .or_default() += 1;
+        }
+    }
+    report
+}
+
+/// Returns the suspects of the report seen more than once in `ids`, the same IDs as the
+/// audited ones read again, without the false positives of the Bloom filter.
+///
+/// Only the suspects are kept in memory. A duplicate seen `n` times is returned `n - 1`
+/// times, in order of discovery.
+pub fn confirm_duplicates(ids: impl IntoIterator<Item = u64>, report: &AuditReport) -> Vec<u64> {
+    let mut duplicates = Vec::new();
+    if report.suspects.is_empty() {
+        return duplicates;
+    }
+    // the first occurrences of the duplicates went through the filter, so they are counted too
+    let suspects: BTreeSet<u64> = report.suspects.iter().copied().collect();
+    let mut seen = BTreeSet::new();
+    for id in ids {
+        if suspects.contains(&id) && !seen.insert(id) {
+            duplicates.push(id);
+        }
+    }
+    duplicates
+}
+
+struct BloomFilter {
+    bits: Vec<u64>,
+    len: u64,
+}
+
+impl BloomFilter {
+    fn new(len: u64) -> Self {
+        BloomFilter {
+            bits: vec![0; len.div_ceil(64) as usize],
+            len,
+        }
+    }
+
+    /// Adds an ID, and returns false if it may have been added before.
+    fn insert(&mut self, id: u64) -> bool {
+        // double hashing of two 64-bit mixes of the ID
+        let first = mix(id);
+        let second = mix(first) | 1;
+        let mut added = false;
+        for i in 0..HASH_FUNCTIONS {
+            let bit = first.wrapping_add(i.wrapping_mul(second)) % self.len;
+            let (word, mask) = ((bit / 64) as usize, 1 << (bit % 64));
+            added |= self.bits[word] & mask == 0;
+            self.bits[word] |= mask;
+        }
+        added
+    }
+}
+
+/// The finalizer of SplitMix64.
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinteflake::SINTEFlake;
+
+    #[test]
+    fn test_check_unique() {
+        let mut instance = SINTEFlake::builder().instance_id(5).build().unwrap();
+        let config = instance.encoding_config();
+        let ids: Vec<u64> = (0..100_000).map(|_| instance.next_id().unwrap()).collect();
+
+        let report = check_unique_with_capacity(ids.iter().copied(), &config, 100_000);
+        assert_eq!(report.ids, 100_000);
+        assert_eq!(report.invalid, 0);
+        // about 1% of false positives, none confirmed
+        assert!(report.suspects.len() < 2000, "{}", report.suspects.len());
+        assert!(confirm_duplicates(ids.iter().copied(), &report).is_empty());
+
+        // an overloaded filter has many false positives
+        let report = check_unique_with_capacity(ids.iter().copied(), &config, 1000);
+        assert!(report.suspects.len() > 10_000, "{}", report.suspects.len());
+        assert!(confirm_duplicates(ids.iter().copied(), &report).is_empty());
+
+        let duplicates = [ids[10], ids[500], ids[10]];
+        let audited = || {
+            ids.iter()
+                .chain(&duplicates)
+                .chain([1 << 63].iter())
+                .copied()
+        };
+        let report = check_unique(audited(), &config);
+        assert!(!report.is_unique());
+        assert_eq!((report.ids, report.invalid), (100_004, 1));
+        assert_eq!(report.suspects, duplicates);
+        assert_eq!(report.suspects_by_instance[&5], 3);
+        assert_eq!(report.suspects_by_window.values().sum::<u64>(), 3);
+        assert_eq!(confirm_duplicates(audited(), &report), duplicates);
+
+        let report = check_unique(ids.iter().copied(), &config);
+        assert!(report.is_unique());
+    }
+}
@@ -1,3 +1,37 @@
+//! Bit packing of the default layout, [`Layout::DEFAULT`].
+//!
+//! The functions and constants are `const`, for compile-time ID math, or to generate SQL
+//! extracting the fields of the IDs, like `(id >> 8) & 1023` for the instance ID.
+//! A field is `(id >> SHIFT) & MASK`, the masks are not shifted.
+use crate::layout::Layout;
+
+/// Width of the hash field.
+pub const HASH_BITS: u8 = Layout::DEFAULT.hash_bits();
+/// Width of the timestamp field.
+pub const TIMESTAMP_BITS: u8 = Layout::DEFAULT.timestamp_bits();
+/// Width of the instance identifier field.
+pub const INSTANCE_BITS: u8 = Layout::DEFAULT.instance_bits();
+/// Width of the sequence number field.
+pub const SEQUENCE_BITS: u8 = Layout::DEFAULT.sequence_bits();
+
+/// Offset of the hash field.
+pub const HASH_SHIFT: u8 = Layout::DEFAULT.hash_shift();
+/// Offset of the timestamp field.
+pub const TIMESTAMP_SHIFT: u8 = Layout::DEFAULT.timestamp_shift();
+/// Offset of the instance identifier field.
+pub const INSTANCE_SHIFT: u8 = Layout::DEFAULT.instance_shift();
+/// Offset of the sequence number field.
+pub const SEQUENCE_SHIFT: u8 = 0;
+
+/// Mask of the hash field, once shifted.
+pub const HASH_MASK: u64 = (1 << HASH_BITS) - 1;
+/// Mask of the timestamp field, once shifted.
+pub const TIMESTAMP_MASK: u64 = (1 << TIMESTAMP_BITS) - 1;
+/// Mask of the instance identifier field, once shifted.
+pub const INSTANCE_MASK: u64 = (1 << INSTANCE_BITS) - 1;
+/// Mask of the sequence number field.
+pub const SEQUENCE_MASK: u64 = (1 << SEQUENCE_BITS) - 1;
+
 /// Constructs a 64-bit identifier from the given components.
 ///
 /// # Arguments
@@ -10,15 +44,34 @@
 /// # Returns
 ///
 /// A u64 containing the combined identifier.
-pub fn construct_identifier(hash: u16, timestamp: u32, instance_id: u16, sequence: u8) -> u64 {
+pub const fn construct_identifier(
+    hash: u16,
+    timestamp: u32,
+    instance_id: u16,
+    sequence: u8,
+) -> u64 {
     // Ensure we only use the specified number of bits for each component
-    let hash = (hash & 0x3FFF) as u64; // 14 bits
-    let timestamp = (timestamp & 0x7FFFFFFF) as u64; // 31 bits
-    let instance_id = (instance_id & 0x3FF) as u64; // 10 bits
-    let sequence = sequence as u64; // 8 bits
+    let hash = hash as u64 & HASH_MASK;
+    let timestamp = timestamp as u64 & TIMESTAMP_MASK;
+    let instance_id = instance_id as u64 & INSTANCE_MASK;
+    let sequence = sequence as u64 & SEQUENCE_MASK;
 
     // Combine the components using bitwise operations
-    (hash << 49) | (timestamp << 18) | (instance_id << 8) | sequence
+    (hash << HASH_SHIFT)
+        | (timestamp << TIMESTAMP_SHIFT)
+        | (instance_id << INSTANCE_SHIFT)
+        | (sequence << SEQUENCE_SHIFT)
+}
+
+/// Splits an identifier into its hash, timestamp, instance identifier and sequence number,
+/// the reverse of [`construct_identifier`]. The bit 63 is ignored.
+pub const fn deconstruct_identifier(id: u64) -> (u16, u32, u16, u8) {
+    (
+        ((id >> HASH_SHIFT) & HASH_MASK) as u16,
+        ((id >> TIMESTAMP_SHIFT) & TIMESTAMP_MASK) as u32,
+        ((id >> INSTANCE_SHIFT) & INSTANCE_MASK) as u16,
+        ((id >> SEQUENCE_SHIFT) & SEQUENCE_MASK) as u8,
+    )
 }
 
 #[cfg(test)]
@@ -76,6 +129,26 @@ mod tests {
         assert_eq!(result, 0x000000000000007F, "Sequence component incorrect");
     }
 
+    #[test]
+    fn test_constants() {
+        assert_eq!((HASH_SHIFT, TIMESTAMP_SHIFT, INSTANCE_SHIFT), (49, 18, 8));
+        assert_eq!(HASH_MASK, 0x3FFF);
+        assert_eq!(TIMESTAMP_MASK, 0x7FFFFFFF);
+        assert_eq!(INSTANCE_MASK, 0x3FF);
+        assert_eq!(SEQUENCE_MASK, 0xFF);
+    }
+
+    #[test]
+    fn test_deconstruct_identifier() {
+        const ID: u64 = construct_identifier(0x0ABC, 0x12345678, 0x0123, 0x45);
+        const PARTS: (u16, u32, u16, u8) = deconstruct_identifier(ID);
+        assert_eq!(PARTS, (0x0ABC, 0x12345678, 0x0123, 0x45));
+        assert_eq!(
+            deconstruct_identifier(u64::MAX),
+            (0x3FFF, 0x7FFFFFFF, 0x3FF, 0xFF)
+        );
+    }
+
     #[test]
     fn test_random_values() {
         let result = construct_identifier(0x0ABC, 0x12345678, 0x0123, 0x45);
@@ -0,0 +1,51 @@
+use alloc::vec::{self, Vec};
+
+/// A block of IDs reserved at once, see [`crate::sinteflake::SINTEFlake::reserve_block`].
+///
+/// The IDs are generated when the block is reserved, so iterating over them
+/// doesn't need the generator, nor its lock when it's shared.
+#[derive(Debug, Clone)]
+pub struct IdBlock {
+    ids: vec::IntoIter<u64>,
+}
+
+impl IdBlock {
+    pub(crate) fn new(ids: Vec<u64>) -> Self {
+        IdBlock {
+            ids: ids.into_iter(),
+        }
+    }
+
+    /// Number of IDs left in the block.
+    pub fn remaining(&self) -> usize {
+        self.ids.len()
+    }
+}
+
+impl Iterator for IdBlock {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        self.ids.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl ExactSizeIterator for IdBlock {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_id_block() {
+        let mut block = IdBlock::new(vec![3, 1, 2]);
+        assert_eq!(block.remaining(), 3);
+        assert_eq!(block.next(), Some(3));
+        assert_eq!(block.len(), 2);
+        assert_eq!(block.collect::<Vec<_>>(), [1, 2]);
+    }
+}
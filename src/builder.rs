@@ -0,0 +1,602 @@
+use alloc::sync::Arc;
+use core::time::Duration;
+
+#[cfg(feature = "std")]
+use crate::allocator::{InstanceIdLease, LeaseStatus};
+use crate::clock::{Clock, ClockRollbackPolicy};
+use crate::epoch::Epoch;
+use crate::error::SINTEFlakeError;
+use crate::hash::{IdHasher, SipHash24};
+use crate::hook::EventHook;
+use crate::layout::Layout;
+use crate::permute::PermutationTables;
+use crate::probing::ProbingStrategy;
+#[cfg(feature = "rand")]
+use crate::random::PrefixRng;
+use crate::rate_limit::RateLimit;
+use crate::rotation::KeySchedule;
+use crate::sinteflake::{OverflowPolicy, SINTEFlake};
+use crate::state::{StateRecoveryPolicy, StateStore};
+use crate::time::{validate_time_window, IntoUnixTime, UnixTime, DEFAULT_TIME_WINDOW};
+
+/// Builder for [`SINTEFlake`] instances with custom settings.
+///
+/// Settings that are not provided keep the values used by [`SINTEFlake::new`].
+///
+/// ```rust
+/// use sinteflake::builder::SINTEFlakeBuilder;
+/// use sinteflake::layout::Layout;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///   let mut instance = SINTEFlakeBuilder::new()
+///     .instance_id(42)
+///     .layout(Layout::JAVASCRIPT_SAFE)
+///     .build()?;
+///
+///   let id = instance.next_id()?;
+///   assert!(id <= Layout::JAVASCRIPT_SAFE.max_id());
+///
+///   Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct SINTEFlakeBuilder {
+    pub(crate) instance_id: u16,
+    pub(crate) hash_key: [u8; 16],
+    pub(crate) key_schedule: Option<KeySchedule>,
+    pub(crate) hash_algorithm: Arc<dyn IdHasher>,
+    pub(crate) counter_key: u8,
+    pub(crate) epoch: UnixTime,
+    pub(crate) epoch_id: Option<&'static str>,
+    pub(crate) layout: Layout,
+    pub(crate) probing_strategy: ProbingStrategy,
+    pub(crate) probe_attempts: u32,
+    pub(crate) bucket_capacity: Option<u16>,
+    pub(crate) sequence_mode: bool,
+    pub(crate) monotonic_sequence: bool,
+    pub(crate) feistel_permutation: bool,
+    pub(crate) tenant_bits: u8,
+    pub(crate) stable_id_capacity: usize,
+    pub(crate) duplicate_guard_windows: usize,
+    pub(crate) rate_limit: Option<RateLimit>,
+    pub(crate) overflow_policy: OverflowPolicy,
+    pub(crate) permutation_tables: PermutationTables,
+    pub(crate) time_window: Duration,
+    pub(crate) clock: Option<Arc<dyn Clock>>,
+    pub(crate) clock_rollback_policy: ClockRollbackPolicy,
+    pub(crate) state_store: Option<Arc<dyn StateStore>>,
+    pub(crate) state_recovery_policy: StateRecoveryPolicy,
+    pub(crate) event_hook: Option<Arc<dyn EventHook>>,
+    #[cfg(feature = "std")]
+    pub(crate) lease_status: Option<Arc<LeaseStatus>>,
+    #[cfg(feature = "rand")]
+    pub(crate) rng: Option<PrefixRng>,
+}
+
+impl SINTEFlakeBuilder {
+    /// Creates a builder with the default settings.
+    pub fn new() -> Self {
+        SINTEFlakeBuilder {
+            instance_id: 0,
+
+            // pi digits after the comma in base 16
+            // https://www.wolframalpha.com/input?i=pi+in+base+16
+            // 3.243f6a8885a308d313198a2e03707344
+            hash_key: [
+                0x24, 0x3f, 0x6a, 0x88, 0x85, 0xa3, 0x08, 0xd3, 0x13, 0x19, 0x8a, 0x2e, 0x03, 0x70,
+                0x73, 0x44,
+            ],
+
+            key_schedule: None,
+
+            hash_algorithm: Arc::new(SipHash24),
+
+            // the counter is XORed with this value
+            counter_key: 42,
+
+            // 2024-07-01T00:00:00Z
+            epoch: Epoch::SINTEFLAKE_2024.start(),
+            epoch_id: Some(Epoch::SINTEFLAKE_2024.id()),
+
+            layout: Layout::DEFAULT,
+
+            probing_strategy: ProbingStrategy::Linear,
+
+            // buckets tried after a full one, before giving up
+            probe_attempts: 10,
+            bucket_capacity: None,
+
+            sequence_mode: false,
+            monotonic_sequence: false,
+
+            feistel_permutation: false,
+
+            tenant_bits: 0,
+
+            // payloads remembered by stable_id_with_hash in a time window
+            stable_id_capacity: 1024,
+
+            // no duplicate guard
+            duplicate_guard_windows: 0,
+
+            rate_limit: None,
+            overflow_policy: OverflowPolicy::Fail,
+
+            permutation_tables: PermutationTables::DEFAULT,
+
+            time_window: DEFAULT_TIME_WINDOW,
+
+            clock: None,
+
+            clock_rollback_policy: ClockRollbackPolicy::Ignore,
+
+            state_store: None,
+
+            state_recovery_policy: StateRecoveryPolicy::SkipForward,
+
+            event_hook: None,
+
+            #[cfg(feature = "std")]
+            lease_status: None,
+
+            #[cfg(feature = "rand")]
+            rng: None,
+        }
+    }
+
+    /// Sets the instance identifier, it must fit in the instance bits of the layout.
+    pub fn instance_id(mut self, instance_id: u16) -> Self {
+        self.instance_id = instance_id;
+        self
+    }
+
+    /// Sets the 16 bytes key used for hashing.
+    pub fn hash_key(mut self, hash_key: [u8; 16]) -> Self {
+        self.hash_key = hash_key;
+        self
+    }
+
+    /// Sets hash keys taking effect at given times, none by default.
+    ///
+    /// The key set with [`SINTEFlakeBuilder::hash_key`] is used before the first one.
+    pub fn key_schedule(mut self, key_schedule: KeySchedule) -> Self {
+        self.key_schedule = Some(key_schedule);
+        self
+    }
+
+    /// Sets the algorithm used for hashing, SipHash-2-4 by default.
+    pub fn hash_algorithm<A: IdHasher + 'static>(mut self, hash_algorithm: A) -> Self {
+        self.hash_algorithm = Arc::new(hash_algorithm);
+        self
+    }
+
+    /// Sets the value the sequence counter is XORed with.
+    pub fn counter_key(mut self, counter_key: u8) -> Self {
+        self.counter_key = counter_key;
+        self
+    }
+
+    /// Sets the epoch from which timestamps are measured, it must be in the past.
+    ///
+    /// The epoch can be any [`IntoUnixTime`], like a [`UnixTime`], a `time::OffsetDateTime`
+    /// with the `time` feature, or a `chrono::DateTime<Utc>` with the `chrono` feature.
+    /// It is named if it is the start of one of the [`Epoch::PRESETS`].
+    pub fn epoch(mut self, epoch: impl IntoUnixTime) -> Self {
+        let epoch = epoch.into_unix_time();
+        self.epoch = epoch;
+        self.epoch_id = Epoch::preset_at(epoch).map(|preset| preset.id());
+        self
+    }
+
+    /// Sets a named epoch, a preset like [`Epoch::UNIX`] or a custom one, so its identifier
+    /// is in the [`crate::decode::EncodingConfig`] of the instance.
+    pub fn named_epoch(mut self, epoch: Epoch) -> Self {
+        self.epoch = epoch.start();
+        self.epoch_id = Some(epoch.id());
+        self
+    }
+
+    /// Sets the bit layout of the identifiers.
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Sets how the next bucket is chosen when a hash bucket is full, linear by default.
+    pub fn probing_strategy(mut self, probing_strategy: ProbingStrategy) -> Self {
+        self.probing_strategy = probing_strategy;
+        self
+    }
+
+    /// Sets how many other buckets are tried when a hash bucket is full,
+    /// before returning `CounterOverflow`. 10 by default.
+    pub fn probe_attempts(mut self, probe_attempts: u32) -> Self {
+        self.probe_attempts = probe_attempts;
+        self
+    }
+
+    /// Sets how many IDs a bucket holds in a time window, before the next IDs of its hashes
+    /// probe other buckets. With heavily skewed hashes, a lower capacity spreads the IDs of
+    /// the hot buckets earlier, keeping room in them for the other hashes.
+    ///
+    /// At most, and by default, the `2^sequence_bits` sequence numbers of the layout.
+    /// In sequence mode, it limits the sequence numbers used in each bucket too.
+    pub fn bucket_capacity(mut self, bucket_capacity: u16) -> Self {
+        self.bucket_capacity = Some(bucket_capacity);
+        self
+    }
+
+    /// Enables the sequence mode, disabled by default.
+    ///
+    /// In sequence mode, `next_id` spreads a plain per-window counter over the buckets
+    /// without the collisions map, which saves memory and time. The IDs can't be generated
+    /// from a hash anymore: `next_id_with_hash` and the similar methods return an error.
+    pub fn sequence_mode(mut self, sequence_mode: bool) -> Self {
+        self.sequence_mode = sequence_mode;
+        self
+    }
+
+    /// Uses the counters of the buckets as sequence numbers, without shuffling them,
+    /// disabled by default.
+    ///
+    /// The IDs of a bucket then increase within a time window. In sequence mode, the buckets
+    /// are also filled one after the other, so all the IDs of the instance increase within
+    /// a time window. It can't be used with the Feistel permutation, which hides the order.
+    pub fn monotonic_sequence(mut self, monotonic_sequence: bool) -> Self {
+        self.monotonic_sequence = monotonic_sequence;
+        self
+    }
+
+    /// Permutes the whole IDs with a Feistel network keyed by the hash key, disabled by default.
+    ///
+    /// The IDs look fully opaque: the timestamp, instance and sequence fields are not visible anymore.
+    /// Holders of the hash key can get the original IDs back with [`crate::feistel::FeistelPermutation`].
+    pub fn feistel_permutation(mut self, feistel_permutation: bool) -> Self {
+        self.feistel_permutation = feistel_permutation;
+        self
+    }
+
+    /// Limits the rate of the IDs to the capacity of the time windows, see [`crate::rate_limit`].
+    /// Disabled by default.
+    pub fn rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Sets what to do when a time window has no room left for an ID,
+    /// [`OverflowPolicy::Fail`] by default.
+    pub fn overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Sets how many payloads [`SINTEFlake::stable_id_with_hash`] remembers in a time window,
+    /// 1024 by default. The least recently used payloads are forgotten first, and 0 disables it.
+    pub fn stable_id_capacity(mut self, stable_id_capacity: usize) -> Self {
+        self.stable_id_capacity = stable_id_capacity;
+        self
+    }
+
+    /// Remembers the IDs issued during the last time windows in a [`DuplicateGuard`], and
+    /// returns a `DuplicateId` error instead of issuing one of them again, 0 by default to
+    /// disable it.
+    ///
+    /// It's a last-resort check against bugs like a clock rollback or the restore of an old
+    /// state, as the collisions map already prevents duplicates. It keeps every ID of the
+    /// windows in memory, about 40 bytes per ID, so it suits instances generating few IDs.
+    pub fn duplicate_guard_windows(mut self, windows: usize) -> Self {
+        self.duplicate_guard_windows = windows;
+        self
+    }
+
+    /// Reserves the first bits of the hash for a tenant code, none by default.
+    ///
+    /// The IDs of [`SINTEFlake::next_id_for_tenant`] start with the tenant code, so they can be
+    /// routed by tenant without a lookup table, and [`crate::decode::DecodedId::tenant`] reads it back.
+    /// The number of bits must not be above the hash bits of the layout.
+    pub fn tenant_bits(mut self, tenant_bits: u8) -> Self {
+        self.tenant_bits = tenant_bits;
+        self
+    }
+
+    /// Sets the tables permuting the timestamp and sequence bits,
+    /// derived from the digits of Pi and e by default.
+    pub fn permutation_tables(mut self, permutation_tables: PermutationTables) -> Self {
+        self.permutation_tables = permutation_tables;
+        self
+    }
+
+    /// Sets the duration of the time windows, 8 seconds by default.
+    ///
+    /// Shorter windows give a finer timestamp resolution but overflow sooner:
+    /// with the 31 bits of the default layout, 1-second windows overflow after
+    /// about 68 years, and 32-second windows after about 2177 years.
+    /// The window must be a non-zero whole number of milliseconds.
+    pub fn time_window(mut self, time_window: Duration) -> Self {
+        self.time_window = time_window;
+        self
+    }
+
+    /// Sets the source of the current time, the system clock by default.
+    ///
+    /// Without the `std` feature, there is no system clock and a clock must be set,
+    /// a [`crate::clock::TickClock`] for example.
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// Sets what to do when the clock goes back to an earlier time window,
+    /// [`ClockRollbackPolicy::Ignore`] by default.
+    pub fn clock_rollback_policy(mut self, clock_rollback_policy: ClockRollbackPolicy) -> Self {
+        self.clock_rollback_policy = clock_rollback_policy;
+        self
+    }
+
+    /// Sets a store persisting the last used time window across restarts, none by default.
+    pub fn state_store<S: StateStore + 'static>(mut self, state_store: S) -> Self {
+        self.state_store = Some(Arc::new(state_store));
+        self
+    }
+
+    /// Sets what to do when the clock is not after the time window saved before a restart,
+    /// [`StateRecoveryPolicy::SkipForward`] by default.
+    pub fn state_recovery_policy(mut self, state_recovery_policy: StateRecoveryPolicy) -> Self {
+        self.state_recovery_policy = state_recovery_policy;
+        self
+    }
+
+    /// Sets callbacks for generation events, none by default.
+    pub fn event_hook<H: EventHook + 'static>(mut self, event_hook: H) -> Self {
+        self.event_hook = Some(Arc::new(event_hook));
+        self
+    }
+
+    /// Uses the instance ID of a lease, and refuses to generate IDs once the lease lapses.
+    #[cfg(feature = "std")]
+    pub fn instance_id_lease(mut self, lease: &InstanceIdLease) -> Self {
+        self.instance_id = lease.instance_id();
+        self.lease_status = Some(lease.status());
+        self
+    }
+
+    /// Uses millisecond timestamps: the [`Layout::MILLISECONDS`] layout with 1 millisecond windows.
+    ///
+    /// The IDs are roughly ordered by creation time, but the hash has only 4 bits.
+    pub fn milliseconds(self) -> Self {
+        self.layout(Layout::MILLISECONDS)
+            .time_window(Duration::from_millis(1))
+    }
+
+    /// Sets the random number generator used by `next_id_random`.
+    ///
+    /// By default, a standard RNG seeded from the OS entropy is used.
+    #[cfg(feature = "rand")]
+    pub fn rng<R: rand::RngCore + Send + 'static>(mut self, rng: R) -> Self {
+        self.rng = Some(PrefixRng::new(rng));
+        self
+    }
+
+    /// Builds the SINTEFlake instance.
+    ///
+    /// # Returns
+    /// - `Result<SINTEFlake, SINTEFlakeError>`: A new SINTEFlake instance or an error if creation fails.
+    ///
+    /// # Errors
+    /// Returns an error if the instance ID doesn't fit in the layout, if the time window is invalid,
+    /// if the epoch is in the future, if the current time doesn't fit in the timestamp bits of the layout,
+    /// or if no clock is set without the `std` feature.
+    pub fn build(self) -> Result<SINTEFlake, SINTEFlakeError> {
+        if self.instance_id > self.layout.max_instance_id() {
+            return Err(SINTEFlakeError::InstanceIDTooHigh {
+                given: self.instance_id,
+                max: self.layout.max_instance_id(),
+            });
+        }
+        validate_time_window(self.time_window)?;
+        if let Some(bucket_capacity) = self.bucket_capacity {
+            if bucket_capacity == 0 || bucket_capacity > self.layout.bucket_capacity() {
+                return Err(SINTEFlakeError::InvalidConfig {
+                    setting: "bucket_capacity",
+                });
+            }
+        }
+        if self.tenant_bits > self.layout.hash_bits() {
+            return Err(SINTEFlakeError::InvalidLayout);
+        }
+        // the permutation would hide the version bits from the decoders, and the order of the IDs
+        let ordered = self.monotonic_sequence || self.layout.is_time_sorted();
+        if self.feistel_permutation && (self.layout.version().is_some() || ordered) {
+            return Err(SINTEFlakeError::FeistelPermutation);
+        }
+        SINTEFlake::from_builder(self)
+    }
+}
+
+impl Default for SINTEFlakeBuilder {
+    fn default() -> Self {
+        SINTEFlakeBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_capacity() {
+        let layout = Layout::new(14, 31, 10, 4).unwrap();
+        let instance = SINTEFlake::builder()
+            .layout(layout)
+            .bucket_capacity(16)
+            .build()
+            .unwrap();
+        assert_eq!(instance.bucket_capacity(), 16);
+        assert_eq!(
+            SINTEFlake::builder()
+                .layout(layout)
+                .build()
+                .unwrap()
+                .bucket_capacity(),
+            16
+        );
+        for bucket_capacity in [0, 17] {
+            assert!(matches!(
+                SINTEFlake::builder()
+                    .layout(layout)
+                    .bucket_capacity(bucket_capacity)
+                    .build(),
+                Err(SINTEFlakeError::InvalidConfig {
+                    setting: "bucket_capacity"
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_versioned_layout_without_feistel() {
+        let layout = Layout::new(14, 29, 10, 8).unwrap().versioned(1).unwrap();
+        assert!(SINTEFlake::builder().layout(layout).build().is_ok());
+        assert!(matches!(
+            SINTEFlake::builder()
+                .layout(layout)
+                .feistel_permutation(true)
+                .build(),
+            Err(SINTEFlakeError::FeistelPermutation)
+        ));
+    }
+
+    #[test]
+    fn test_default_build() {
+        let mut instance = SINTEFlakeBuilder::new().build().unwrap();
+        let id_a = instance.next_id().unwrap();
+        let id_b = instance.next_id().unwrap();
+        assert_ne!(id_a, id_b);
+        assert_eq!(instance.layout(), Layout::DEFAULT);
+    }
+
+    #[test]
+    fn test_javascript_safe_build() {
+        let mut instance = SINTEFlakeBuilder::new()
+            .instance_id(1023)
+            .layout(Layout::JAVASCRIPT_SAFE)
+            .build()
+            .unwrap();
+        for _ in 0..1000 {
+            let id = instance.next_id().unwrap();
+            assert!(id < 1 << 53);
+        }
+    }
+
+    #[test]
+    fn test_hash_algorithm() {
+        use crate::hash::KeyedHasher;
+
+        #[derive(Debug)]
+        struct Constant;
+
+        impl IdHasher for Constant {
+            fn hash(&self, _key: &[u8; 16], _data: &[u8]) -> u64 {
+                0x0123
+            }
+
+            fn hasher(&self, key: &[u8; 16]) -> KeyedHasher {
+                SipHash24.hasher(key)
+            }
+        }
+
+        let mut instance = SINTEFlakeBuilder::new()
+            .hash_algorithm(Constant)
+            .build()
+            .unwrap();
+        assert_eq!(instance.next_id_with_hash(b"a").unwrap() >> 49, 0x0123);
+        assert_eq!(instance.next_id_with_hash(b"b").unwrap() >> 49, 0x0123);
+    }
+
+    #[test]
+    fn test_instance_id_must_fit_layout() {
+        let layout = Layout::new(14, 31, 4, 8).unwrap();
+        assert!(SINTEFlakeBuilder::new()
+            .instance_id(15)
+            .layout(layout)
+            .build()
+            .is_ok());
+        assert!(SINTEFlakeBuilder::new()
+            .instance_id(16)
+            .layout(layout)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_time_window() {
+        let instance = SINTEFlakeBuilder::new()
+            .time_window(Duration::from_secs(1))
+            .build()
+            .unwrap();
+        assert_eq!(instance.time_window(), Duration::from_secs(1));
+        // 2024-07-01 + 2^31 seconds
+        assert!(instance.valid_until().to_string().starts_with("2092-"));
+
+        let instance = SINTEFlakeBuilder::new()
+            .time_window(Duration::from_secs(32))
+            .build()
+            .unwrap();
+        assert!(instance.valid_until().to_string().starts_with("4202-"));
+
+        assert!(SINTEFlakeBuilder::new()
+            .time_window(Duration::ZERO)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_monotonic_clock() {
+        let mut instance = SINTEFlakeBuilder::new()
+            .clock(crate::clock::MonotonicClock::new())
+            .build()
+            .unwrap();
+        let id_a = instance.next_id().unwrap();
+        instance.update_time().unwrap();
+        let id_b = instance.next_id().unwrap();
+        assert_ne!(id_a, id_b);
+    }
+
+    #[test]
+    fn test_milliseconds() {
+        let mut instance = SINTEFlakeBuilder::new().milliseconds().build().unwrap();
+        assert_eq!(instance.layout(), Layout::MILLISECONDS);
+        assert_eq!(instance.time_window(), Duration::from_millis(1));
+        assert!(instance.valid_until().to_string().starts_with("2094-"));
+
+        let id_a = instance.next_id().unwrap();
+        let id_b = instance.next_id().unwrap();
+        assert_ne!(id_a, id_b);
+
+        std::thread::sleep(Duration::from_millis(2));
+        instance.update_time().unwrap();
+        let id_c = instance.next_id().unwrap();
+        assert!(id_c > id_a && id_c > id_b);
+    }
+
+    #[test]
+    fn test_epoch_must_fit_layout() {
+        // 2^10 windows of 8 seconds are about 2 hours and 16 minutes
+        let layout = Layout::new(14, 10, 10, 8).unwrap();
+        let recent_epoch = UnixTime::now_utc() - Duration::from_secs(3600);
+        let old_epoch = UnixTime::now_utc() - Duration::from_secs(3 * 3600);
+
+        assert!(SINTEFlakeBuilder::new()
+            .layout(layout)
+            .epoch(recent_epoch)
+            .build()
+            .is_ok());
+        assert!(matches!(
+            SINTEFlakeBuilder::new()
+                .layout(layout)
+                .epoch(old_epoch)
+                .build(),
+            Err(SINTEFlakeError::TimestampOverflow)
+        ));
+    }
+}
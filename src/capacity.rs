@@ -0,0 +1,131 @@
+//! Capacity planning: how many IDs made from hashes fit in a time window.
+//!
+//! The IDs of [`SINTEFlake::next_id_with_hash`] and the similar methods go to the bucket of
+//! their hash. When more IDs than the capacity of a bucket land in it, the next ones move to
+//! other buckets, and once the probed buckets are full too, generation fails with
+//! `CounterOverflow`. The hashes are modelled as uniformly distributed, so the number of IDs
+//! per bucket follows a Poisson distribution.
+//!
+//! The IDs of [`SINTEFlake::next_id`] are spread evenly over the buckets instead,
+//! and only run out after [`Layout::window_capacity`] IDs.
+//!
+//! ```rust
+//! use sinteflake::capacity::{estimate_collision_risk, max_safe_rate};
+//! use sinteflake::layout::Layout;
+//!
+//! let layout = Layout::DEFAULT;
+//! assert!(estimate_collision_risk(100_000, &layout) < 1e-9);
+//! assert!(max_safe_rate(&layout) > 100_000);
+//! ```
+//!
+//! [`SINTEFlake::next_id_with_hash`]: crate::sinteflake::SINTEFlake::next_id_with_hash
+//! [`SINTEFlake::next_id`]: crate::sinteflake::SINTEFlake::next_id
+use crate::hash::TRUNCATED_VALUES;
+use crate::layout::Layout;
+
+/// Risk per time window below which [`max_safe_rate`] considers a rate safe, one in a million.
+pub const SAFE_RISK: f64 = 1e-6;
+
+/// Returns the probability that at least one bucket overflows in a time window with
+/// `ids_per_window` IDs made from uniformly distributed hashes.
+///
+/// An overflow moves IDs to other buckets, which is the first step towards a
+/// `CounterOverflow`, so the estimate is conservative.
+pub fn estimate_collision_risk(ids_per_window: u64, layout: &Layout) -> f64 {
+    let buckets = hashed_buckets(layout) as f64;
+    let capacity = layout.bucket_capacity() as u64;
+    if ids_per_window == 0 {
+        return 0.0;
+    }
+    let bucket_risk = poisson_tail(ids_per_window as f64 / buckets, capacity + 1);
+    // 1 - (1 - p)^buckets, without losing the small probabilities
+    (-(buckets * (-bucket_risk).ln_1p()).exp_m1()).clamp(0.0, 1.0)
+}
+
+/// Returns the largest number of IDs made from hashes per time window whose collision risk
+/// is at most [`SAFE_RISK`].
+pub fn max_safe_rate(layout: &Layout) -> u64 {
+    let (mut low, mut high) = (0, layout.window_capacity());
+    while low < high {
+        let middle = low + (high - low).div_ceil(2);
+        if estimate_collision_risk(middle, layout) <= SAFE_RISK {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    low
+}
+
+/// Buckets reachable from a hash, as only 12 bits of the hashes are used.
+fn hashed_buckets(layout: &Layout) -> usize {
+    layout.buckets().min(TRUNCATED_VALUES)
+}
+
+/// Returns P(X >= k) for a Poisson distribution of mean `mean`, summing the terms of the tail.
+fn poisson_tail(mean: f64, k: u64) -> f64 {
+    let ln_mean = mean.ln();
+    // ln(P(X = k)) = -mean + k ln(mean) - ln(k!)
+    let ln_factorial: f64 = (2..=k).map(|i| (i as f64).ln()).sum();
+    let mut ln_term = -mean + k as f64 * ln_mean - ln_factorial;
+    let last = k.max(mean as u64) + 50 + (20.0 * mean.sqrt()) as u64;
+    let mut sum = 0.0;
+    for i in k..=last {
+        if i > k {
+            ln_term += ln_mean - (i as f64).ln();
+        }
+        sum += ln_term.exp();
+    }
+    sum.min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poisson_tail() {
+        assert!((poisson_tail(1.0, 0) - 1.0).abs() < 1e-12);
+        // P(X >= 1) = 1 - e^-2
+        assert!((poisson_tail(2.0, 1) - (1.0 - (-2.0_f64).exp())).abs() < 1e-12);
+        // P(X >= 3) for a mean of 1: 1 - e^-1 (1 + 1 + 1/2)
+        let expected = 1.0 - (-1.0_f64).exp() * 2.5;
+        assert!((poisson_tail(1.0, 3) - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_estimate_collision_risk() {
+        let layout = Layout::DEFAULT;
+        assert_eq!(estimate_collision_risk(0, &layout), 0.0);
+        assert!(estimate_collision_risk(10_000, &layout) < 1e-100);
+        // a mean of 256 IDs per bucket overflows some buckets
+        assert!(estimate_collision_risk(256 * 4096, &layout) > 0.999);
+
+        let mut previous = 0.0;
+        for ids in (0..2_000_000).step_by(100_000) {
+            let risk = estimate_collision_risk(ids, &layout);
+            assert!(risk >= previous);
+            previous = risk;
+        }
+
+        // fewer buckets of the same capacity overflow sooner
+        let small = Layout::JAVASCRIPT_SAFE;
+        assert!(estimate_collision_risk(10_000, &small) > estimate_collision_risk(10_000, &layout));
+    }
+
+    #[test]
+    fn test_max_safe_rate() {
+        for layout in [
+            Layout::DEFAULT,
+            Layout::JAVASCRIPT_SAFE,
+            Layout::MILLISECONDS,
+        ] {
+            let rate = max_safe_rate(&layout);
+            assert!(rate > 0);
+            assert!(estimate_collision_risk(rate, &layout) <= SAFE_RISK);
+            assert!(estimate_collision_risk(rate + 1, &layout) > SAFE_RISK);
+        }
+        // 169 IDs per bucket on average
+        assert_eq!(max_safe_rate(&Layout::DEFAULT), 694064);
+    }
+}
@@ -0,0 +1,341 @@
+use alloc::sync::Arc;
+use core::fmt::{self, Debug};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "std")]
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::error::SINTEFlakeError;
+
+/// Source of the current time.
+pub trait Clock: Debug + Send + Sync {
+    /// Returns the current time, as a duration since the UNIX epoch.
+    fn now(&self) -> Duration;
+}
+
+/// The system wall clock, the default.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+    }
+}
+
+/// Clock reading the wall clock once, and then advancing with [`Instant`].
+///
+/// NTP step corrections and manual changes of the system clock after the creation
+/// are ignored, so the time never goes backwards within the process.
+/// The time may drift from the wall clock in long running processes,
+/// and the time during which the machine is suspended may not be counted.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    wall_anchor: Duration,
+    instant_anchor: Instant,
+}
+
+#[cfg(feature = "std")]
+impl MonotonicClock {
+    /// Creates a clock anchored to the current system time.
+    pub fn new() -> Self {
+        MonotonicClock {
+            wall_anchor: SystemClock.now(),
+            instant_anchor: Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        MonotonicClock::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for MonotonicClock {
+    fn now(&self) -> Duration {
+        self.wall_anchor + self.instant_anchor.elapsed()
+    }
+}
+
+/// Clock caching the time of another clock, refreshed by a background thread.
+///
+/// Reading the time is an atomic load instead of a system call, so refreshing the time
+/// window before every ID stays cheap. The time lags behind the inner clock by up to the
+/// resolution, so the time windows also start up to the resolution late.
+/// The thread stops when the clock and its clones are dropped.
+///
+/// ```rust
+/// use core::time::Duration;
+/// use sinteflake::clock::CoarseClock;
+/// use sinteflake::sinteflake::SINTEFlake;
+///
+/// let clock = CoarseClock::new(Duration::from_millis(10));
+/// let mut instance = SINTEFlake::builder().clock(clock).build()?;
+/// let id = instance.next_id()?;
+/// # Ok::<(), sinteflake::error::SINTEFlakeError>(())
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct CoarseClock {
+    nanos: Arc<AtomicU64>,
+    resolution: Duration,
+}
+
+#[cfg(feature = "std")]
+impl CoarseClock {
+    /// Creates a clock caching the system clock, refreshed every `resolution`.
+    ///
+    /// # Panics
+    /// Panics if `resolution` is zero.
+    pub fn new(resolution: Duration) -> Self {
+        CoarseClock::with_clock(SystemClock, resolution)
+    }
+
+    /// Creates a clock caching another clock, refreshed every `resolution`.
+    ///
+    /// # Panics
+    /// Panics if `resolution` is zero.
+    pub fn with_clock<C: Clock + 'static>(inner: C, resolution: Duration) -> Self {
+        assert!(!resolution.is_zero(), "resolution must not be zero");
+        let nanos = Arc::new(AtomicU64::new(inner.now().as_nanos() as u64));
+        let cached = Arc::downgrade(&nanos);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(resolution);
+            match cached.upgrade() {
+                Some(nanos) => nanos.store(inner.now().as_nanos() as u64, Ordering::Relaxed),
+                None => break,
+            }
+        });
+        CoarseClock { nanos, resolution }
+    }
+
+    /// Returns how often the time is refreshed.
+    pub fn resolution(&self) -> Duration {
+        self.resolution
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for CoarseClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+}
+
+/// Clock counting the ticks of a hardware timer, for targets without a system clock.
+///
+/// The time is `start` plus the ticks returned by `ticks`, converted with `ticks_per_second`.
+/// `start` is the UNIX time when the tick count was zero, for example read from
+/// a real-time clock at boot. The tick count must not wrap around.
+///
+/// ```rust
+/// use core::sync::atomic::{AtomicU64, Ordering};
+/// use core::time::Duration;
+/// use sinteflake::clock::{Clock, TickClock};
+///
+/// static TICKS: AtomicU64 = AtomicU64::new(0); // incremented by a timer interrupt
+///
+/// let clock = TickClock::new(|| TICKS.load(Ordering::Relaxed), 1000, Duration::from_secs(1719792000));
+/// TICKS.store(1500, Ordering::Relaxed);
+/// assert_eq!(clock.now(), Duration::from_millis(1719792001500));
+/// ```
+pub struct TickClock<F> {
+    ticks: F,
+    ticks_per_second: u64,
+    start: Duration,
+}
+
+impl<F: Fn() -> u64 + Send + Sync> TickClock<F> {
+    /// Creates a clock from a tick counter.
+    ///
+    /// # Panics
+    /// Panics if `ticks_per_second` is zero.
+    pub fn new(ticks: F, ticks_per_second: u64, start: Duration) -> Self {
+        assert!(ticks_per_second > 0, "ticks_per_second must not be zero");
+        TickClock {
+            ticks,
+            ticks_per_second,
+            start,
+        }
+    }
+}
+
+impl<F> Debug for TickClock<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TickClock")
+            .field("ticks_per_second", &self.ticks_per_second)
+            .field("start", &self.start)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F: Fn() -> u64 + Send + Sync> Clock for TickClock<F> {
+    fn now(&self) -> Duration {
+        let ticks = (self.ticks)();
+        let seconds = ticks / self.ticks_per_second;
+        let nanos =
+            (ticks % self.ticks_per_second) as u128 * 1_000_000_000 / self.ticks_per_second as u128;
+        self.start + Duration::new(seconds, nanos as u32)
+    }
+}
+
+/// Returns the clock used when none is set in the builder, the system clock.
+///
+/// Without the `std` feature, there is no system clock and a clock must be set.
+pub(crate) fn default_clock() -> Result<Arc<dyn Clock>, SINTEFlakeError> {
+    #[cfg(feature = "std")]
+    return Ok(Arc::new(SystemClock));
+    #[cfg(not(feature = "std"))]
+    return Err(SINTEFlakeError::MissingClock);
+}
+
+/// Waits for `duration`, sleeping with `std`, or spinning until the clock has advanced without it.
+pub(crate) fn wait(clock: &dyn Clock, duration: Duration) {
+    #[cfg(feature = "std")]
+    {
+        let _ = clock;
+        std::thread::sleep(duration);
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        let deadline = clock.now() + duration;
+        while clock.now() < deadline {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// What to do when the clock goes back to a time window earlier than the last used one.
+///
+/// Switching back to an earlier window could generate IDs that have already been generated,
+/// because the collisions map of that window has been discarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClockRollbackPolicy {
+    /// Switches to the earlier window, like the versions without clock rollback detection.
+    ///
+    /// IDs already generated in that window may be generated again.
+    #[default]
+    Ignore,
+
+    /// Returns a `ClockRollback` error until the clock is back to the last used window.
+    Error,
+
+    /// Blocks the current thread until the clock is back to the last used window.
+    ///
+    /// The functions of the global instance wait without locking it.
+    Wait,
+
+    /// Keeps using the last window if the clock is at most `max_drift` behind its start,
+    /// returns a `ClockRollback` error otherwise.
+    Tolerate { max_drift: Duration },
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Clock returning a time controlled by the tests, in milliseconds since the UNIX epoch.
+    #[derive(Debug, Clone, Default)]
+    pub(crate) struct ManualClock {
+        millis: Arc<AtomicU64>,
+        step: u64,
+    }
+
+    impl ManualClock {
+        pub(crate) fn new(millis: u64) -> Self {
+            ManualClock {
+                millis: Arc::new(AtomicU64::new(millis)),
+                step: 0,
+            }
+        }
+
+        /// The time advances by `step` milliseconds every time it's read.
+        pub(crate) fn with_step(millis: u64, step: u64) -> Self {
+            ManualClock {
+                millis: Arc::new(AtomicU64::new(millis)),
+                step,
+            }
+        }
+
+        pub(crate) fn set(&self, millis: u64) {
+            self.millis.store(millis, Ordering::SeqCst);
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Duration {
+            Duration::from_millis(self.millis.fetch_add(self.step, Ordering::SeqCst))
+        }
+    }
+
+    #[test]
+    fn test_system_clock() {
+        let now = SystemClock.now();
+        // 2024-07-01T00:00:00Z
+        assert!(now > Duration::from_secs(1719792000));
+    }
+
+    #[test]
+    fn test_monotonic_clock() {
+        let clock = MonotonicClock::new();
+        let mut previous = clock.now();
+        for _ in 0..1000 {
+            let now = clock.now();
+            assert!(now >= previous);
+            previous = now;
+        }
+
+        let system = SystemClock.now();
+        assert!(system.abs_diff(clock.now()) < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_coarse_clock() {
+        let inner = ManualClock::new(1000);
+        let clock = CoarseClock::with_clock(inner.clone(), Duration::from_millis(1));
+        assert_eq!(clock.now(), Duration::from_millis(1000));
+
+        inner.set(2000);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while clock.now() != Duration::from_millis(2000) {
+            assert!(Instant::now() < deadline, "the clock was not refreshed");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(clock.resolution(), Duration::from_millis(1));
+
+        let system = CoarseClock::new(Duration::from_millis(10));
+        assert!(SystemClock.now().abs_diff(system.now()) < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_tick_clock() {
+        let ticks = Arc::new(AtomicU64::new(0));
+        let counter = ticks.clone();
+        let start = Duration::from_secs(1719792000);
+        let clock = TickClock::new(move || counter.load(Ordering::SeqCst), 32768, start);
+        assert_eq!(clock.now(), start);
+
+        ticks.store(32768 * 3 + 16384, Ordering::SeqCst);
+        assert_eq!(clock.now(), start + Duration::from_millis(3500));
+        ticks.store(u64::MAX, Ordering::SeqCst);
+        assert!(clock.now() > start);
+    }
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::with_step(1000, 10);
+        assert_eq!(clock.now(), Duration::from_millis(1000));
+        assert_eq!(clock.now(), Duration::from_millis(1010));
+        clock.set(5);
+        assert_eq!(clock.now(), Duration::from_millis(5));
+    }
+}
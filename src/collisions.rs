@@ -0,0 +1,109 @@
+use alloc::boxed::Box;
+use alloc::vec;
+
+/// Number of IDs generated in each hash bucket during the current time window.
+///
+/// A bucket holds up to 256 IDs, one more than a `u8` can count. The counts are stored
+/// in `u8`s, saturating at 255, and a bitmap marks the buckets holding 256 IDs, so the map
+/// of the default layout takes 16 KB and 2 KB. Switching to a new time window clears both.
+#[derive(Debug, Clone)]
+pub(crate) struct CollisionsMap {
+    counts: Box<[u8]>,
+    full: Box<[u64]>,
+}
+
+impl CollisionsMap {
+    pub(crate) fn new(buckets: usize) -> Self {
+        CollisionsMap {
+            counts: vec![0; buckets].into_boxed_slice(),
+            full: vec![0; buckets.div_ceil(64)].into_boxed_slice(),
+        }
+    }
+
+    fn is_full(&self, bucket: usize) -> bool {
+        self.full[bucket / 64] & (1 << (bucket % 64)) != 0
+    }
+
+    /// Number of IDs generated in the bucket.
+    pub(crate) fn get(&self, bucket: usize) -> u16 {
+        if self.is_full(bucket) {
+            256
+        } else {
+            self.counts[bucket] as u16
+        }
+    }
+
+    /// Adds an ID to the bucket, which must hold less than 256 IDs.
+    pub(crate) fn increment(&mut self, bucket: usize) {
+        if self.counts[bucket] == u8::MAX {
+            self.full[bucket / 64] |= 1 << (bucket % 64);
+        } else {
+            self.counts[bucket] += 1;
+        }
+    }
+
+    /// Sets the number of IDs generated in the bucket, at most 256.
+    pub(crate) fn set(&mut self, bucket: usize, count: u16) {
+        self.counts[bucket] = count.min(u8::MAX as u16) as u8;
+        if count > u8::MAX as u16 {
+            self.full[bucket / 64] |= 1 << (bucket % 64);
+        } else {
+            self.full[bucket / 64] &= !(1 << (bucket % 64));
+        }
+    }
+
+    /// Empties all the buckets.
+    pub(crate) fn reset(&mut self) {
+        self.counts.fill(0);
+        self.full.fill(0);
+    }
+
+    /// Iterates over the number of IDs in each bucket.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..self.counts.len()).map(|bucket| self.get(bucket))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_increment_and_reset() {
+        let mut map = CollisionsMap::new(4);
+        map.increment(1);
+        map.increment(1);
+        map.increment(3);
+        assert_eq!(map.iter().collect::<Vec<_>>(), vec![0, 2, 0, 1]);
+
+        map.reset();
+        assert_eq!(map.iter().collect::<Vec<_>>(), vec![0, 0, 0, 0]);
+        map.increment(1);
+        assert_eq!(map.get(1), 1);
+
+        map.set(2, 256);
+        assert_eq!(map.get(2), 256);
+        map.set(2, 3);
+        assert_eq!(map.get(2), 3);
+    }
+
+    #[test]
+    fn test_full_bucket() {
+        let mut map = CollisionsMap::new(100);
+        for _ in 0..256 {
+            map.increment(70);
+        }
+        assert_eq!(map.get(70), 256);
+        assert_eq!(map.get(69), 0);
+        assert_eq!(map.get(71), 0);
+
+        map.reset();
+        assert_eq!(map.get(70), 0);
+    }
+
+    #[test]
+    fn test_footprint() {
+        let map = CollisionsMap::new(16384);
+        assert_eq!(map.counts.len() + map.full.len() * 8, 16384 + 2048);
+    }
+}
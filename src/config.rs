@@ -0,0 +1,353 @@
+//! Settings of a generator from the configuration system of a service, rather than code.
+//!
+//! With the `serde` feature, [`SINTEFlakeConfig`] can be read from TOML, JSON or any
+//! other serde format, and [`SINTEFlakeConfig::from_env`] reads it from environment variables:
+//!
+//! - `SINTEFLAKE_INSTANCE_ID`, the instance ID.
+//! - `SINTEFLAKE_HASH_KEY`, the hash key in hexadecimal, 32 digits.
+//! - `SINTEFLAKE_COUNTER_KEY`, the value the sequence counter is XORed with.
+//! - `SINTEFLAKE_EPOCH`, the identifier of an [`Epoch`] preset, like `unix`,
+//!   or milliseconds since the Unix epoch.
+//! - `SINTEFLAKE_TIME_WINDOW_MS`, the duration of the time windows in milliseconds.
+//! - `SINTEFLAKE_PROBE_ATTEMPTS`, how many other buckets are tried when a bucket is full.
+//! - `SINTEFLAKE_BUCKET_CAPACITY`, how many IDs a bucket holds in a time window.
+//!
+//! [`SINTEFlake::apply_config`] swaps the instance ID, the keys and the probe attempts
+//! of a live generator, to rotate keys or reassign instance IDs without a restart.
+//!
+//! ```rust
+//! use sinteflake::config::SINTEFlakeConfig;
+//! use sinteflake::sinteflake::SINTEFlake;
+//!
+//! let config = SINTEFlakeConfig {
+//!     instance_id: Some(42),
+//!     epoch: Some("unix".to_string()),
+//!     ..Default::default()
+//! };
+//! let mut instance = SINTEFlake::from_config(&config)?;
+//! assert_eq!(instance.encoding_config().epoch_id, Some("unix"));
+//! # Ok::<(), sinteflake::error::SINTEFlakeError>(())
+//! ```
+use core::time::Duration;
+use std::env;
+
+use crate::builder::SINTEFlakeBuilder;
+use crate::epoch::Epoch;
+use crate::error::SINTEFlakeError;
+use crate::sinteflake::{PendingConfig, SINTEFlake};
+use crate::time::UnixTime;
+
+/// Settings of a generator, the ones not set keep the values of [`SINTEFlake::new`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct SINTEFlakeConfig {
+    pub instance_id: Option<u16>,
+
+    /// The hash key in hexadecimal, 32 digits.
+    pub hash_key: Option<String>,
+
+    pub counter_key: Option<u8>,
+
+    /// The identifier of an [`Epoch`] preset, or milliseconds since the Unix epoch.
+    pub epoch: Option<String>,
+
+    /// The duration of the time windows in milliseconds.
+    pub time_window_ms: Option<u64>,
+
+    /// How many other buckets are tried when a hash bucket is full, before returning
+    /// `CounterOverflow`.
+    pub probe_attempts: Option<u32>,
+
+    /// How many IDs a hash bucket holds in a time window, at most the sequence numbers
+    /// of the layout.
+    pub bucket_capacity: Option<u16>,
+}
+
+impl SINTEFlakeConfig {
+    /// Reads the settings from the `SINTEFLAKE_*` environment variables, see [`crate::config`].
+    ///
+    /// # Errors
+    /// Returns `InvalidConfig` if a variable is set to a value that isn't a number where
+    /// one is expected.
+    pub fn from_env() -> Result<Self, SINTEFlakeError> {
+        Ok(SINTEFlakeConfig {
+            instance_id: parse_env_var("SINTEFLAKE_INSTANCE_ID")?,
+            hash_key: env::var("SINTEFLAKE_HASH_KEY").ok(),
+            counter_key: parse_env_var("SINTEFLAKE_COUNTER_KEY")?,
+            epoch: env::var("SINTEFLAKE_EPOCH").ok(),
+            time_window_ms: parse_env_var("SINTEFLAKE_TIME_WINDOW_MS")?,
+            probe_attempts: parse_env_var("SINTEFLAKE_PROBE_ATTEMPTS")?,
+            bucket_capacity: parse_env_var("SINTEFLAKE_BUCKET_CAPACITY")?,
+        })
+    }
+
+    /// Returns a builder with the settings, to change other settings in code.
+    ///
+    /// # Errors
+    /// Returns `InvalidHashKey` if the hash key isn't 32 hexadecimal digits, or
+    /// `InvalidConfig` if the epoch is neither a preset nor a number.
+    pub fn builder(&self) -> Result<SINTEFlakeBuilder, SINTEFlakeError> {
+        let mut builder = SINTEFlake::builder();
+        if let Some(instance_id) = self.instance_id {
+            builder = builder.instance_id(instance_id);
+        }
+        if let Some(hash_key) = &self.hash_key {
+            builder = builder.hash_key(parse_hash_key(hash_key)?);
+        }
+        if let Some(counter_key) = self.counter_key {
+            builder = builder.counter_key(counter_key);
+        }
+        if let Some(epoch) = &self.epoch {
+            builder = builder.epoch(parse_epoch(epoch)?);
+        }
+        if let Some(time_window_ms) = self.time_window_ms {
+            builder = builder.time_window(Duration::from_millis(time_window_ms));
+        }
+        if let Some(probe_attempts) = self.probe_attempts {
+            builder = builder.probe_attempts(probe_attempts);
+        }
+        if let Some(bucket_capacity) = self.bucket_capacity {
+            builder = builder.bucket_capacity(bucket_capacity);
+        }
+        Ok(builder)
+    }
+}
+
+impl SINTEFlake {
+    /// Creates an instance with the settings of a configuration.
+    ///
+    /// # Errors
+    /// Returns an error if a setting is invalid, see [`SINTEFlakeConfig::builder`]
+    /// and [`SINTEFlakeBuilder::build`].
+    pub fn from_config(config: &SINTEFlakeConfig) -> Result<Self, SINTEFlakeError> {
+        config.builder()?.build()
+    }
+
+    /// Swaps the instance ID, the keys and the probe attempts of a live instance for the ones
+    /// of a configuration, for example after reloading the configuration file.
+    ///
+    /// The new settings take effect at the next time window, so the IDs of the current window
+    /// keep unique sequence numbers. The settings not set in the configuration are kept, and
+    /// applying another configuration before the next window replaces this one.
+    ///
+    /// # Errors
+    /// Returns `InvalidConfig` if the epoch or the time window differ from the ones of the
+    /// instance, as they can't change without a restart, `InvalidHashKey` if the hash key is
+    /// invalid, or `InstanceIDTooHigh` if the instance ID doesn't fit in the layout.
+    pub fn apply_config(&mut self, config: &SINTEFlakeConfig) -> Result<(), SINTEFlakeError> {
+        let encoding = self.encoding_config();
+        if let Some(epoch) = &config.epoch {
+            if parse_epoch(epoch)? != encoding.epoch {
+                return Err(SINTEFlakeError::InvalidConfig { setting: "epoch" });
+            }
+        }
+        if let Some(time_window_ms) = config.time_window_ms {
+            if Duration::from_millis(time_window_ms) != encoding.time_window {
+                return Err(SINTEFlakeError::InvalidConfig {
+                    setting: "time_window_ms",
+                });
+            }
+        }
+        self.schedule_config(PendingConfig {
+            instance_id: config.instance_id,
+            hash_key: config.hash_key.as_deref().map(parse_hash_key).transpose()?,
+            counter_key: config.counter_key,
+            probe_attempts: config.probe_attempts,
+        })
+    }
+}
+
+fn parse_env_var<T: core::str::FromStr>(name: &'static str) -> Result<Option<T>, SINTEFlakeError> {
+    match env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| SINTEFlakeError::InvalidConfig { setting: name }),
+        Err(_) => Ok(None),
+    }
+}
+
+fn parse_hash_key(text: &str) -> Result<[u8; 16], SINTEFlakeError> {
+    let text = text.trim();
+    if text.len() != 32 || !text.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(SINTEFlakeError::InvalidHashKey);
+    }
+    let mut key = [0; 16];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[2 * i..2 * i + 2], 16)
+            .map_err(|_| SINTEFlakeError::InvalidHashKey)?;
+    }
+    Ok(key)
+}
+
+// the epoch of a preset is named after it by the builder
+fn parse_epoch(text: &str) -> Result<UnixTime, SINTEFlakeError> {
+    let text = text.trim();
+    if let Some(preset) = Epoch::from_id(text) {
+        return Ok(preset.start());
+    }
+    text.parse::<i64>()
+        .ok()
+        .and_then(|unix_millis| {
+            UnixTime::from_unix_timestamp_nanos(unix_millis as i128 * 1_000_000).ok()
+        })
+        .ok_or(SINTEFlakeError::InvalidConfig { setting: "epoch" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_config() {
+        let config = SINTEFlakeConfig {
+            instance_id: Some(7),
+            hash_key: Some("000102030405060708090a0b0c0d0e0f".into()),
+            counter_key: Some(3),
+            epoch: Some("1735689600000".into()),
+            time_window_ms: Some(1000),
+            probe_attempts: Some(0),
+            bucket_capacity: Some(128),
+        };
+        let instance = SINTEFlake::from_config(&config).unwrap();
+        let encoding = instance.encoding_config();
+        assert_eq!(encoding.hash_key, core::array::from_fn(|i| i as u8));
+        assert_eq!(encoding.counter_key, 3);
+        assert_eq!(
+            encoding.epoch,
+            UnixTime::from_unix_timestamp(1735689600).unwrap()
+        );
+        assert_eq!(encoding.epoch_id, None);
+        assert_eq!(encoding.time_window, Duration::from_secs(1));
+        assert_eq!(instance.bucket_capacity(), 128);
+
+        // the default settings
+        let instance = SINTEFlake::from_config(&SINTEFlakeConfig::default()).unwrap();
+        assert_eq!(
+            instance.encoding_config(),
+            SINTEFlake::new().unwrap().encoding_config()
+        );
+    }
+
+    #[test]
+    fn test_apply_config() {
+        use crate::clock::tests::ManualClock;
+        use crate::decode::decode;
+
+        let clock = ManualClock::new(1719792016000);
+        let mut instance = SINTEFlake::builder()
+            .instance_id(1)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let old_config = instance.encoding_config();
+        let config = SINTEFlakeConfig {
+            instance_id: Some(2),
+            hash_key: Some("000102030405060708090a0b0c0d0e0f".into()),
+            counter_key: Some(9),
+            epoch: Some("sinteflake-2024".into()),
+            ..Default::default()
+        };
+        instance.apply_config(&config).unwrap();
+
+        // the current time window keeps the old settings
+        let id = instance.next_id().unwrap();
+        assert_eq!(decode(id, &old_config).unwrap().instance_id, 1);
+        instance.update_time().unwrap();
+        assert_eq!(instance.encoding_config(), old_config);
+
+        clock.set(1719792024000);
+        instance.update_time().unwrap();
+        let new_config = instance.encoding_config();
+        assert_eq!(new_config.hash_key, core::array::from_fn(|i| i as u8));
+        assert_eq!(new_config.counter_key, 9);
+        let id = instance.next_id().unwrap();
+        assert_eq!(decode(id, &new_config).unwrap().instance_id, 2);
+
+        for config in [
+            SINTEFlakeConfig {
+                epoch: Some("unix".into()),
+                ..Default::default()
+            },
+            SINTEFlakeConfig {
+                time_window_ms: Some(1000),
+                ..Default::default()
+            },
+        ] {
+            assert!(matches!(
+                instance.apply_config(&config),
+                Err(SINTEFlakeError::InvalidConfig { .. })
+            ));
+        }
+        assert!(matches!(
+            instance.apply_config(&SINTEFlakeConfig {
+                instance_id: Some(1024),
+                ..Default::default()
+            }),
+            Err(SINTEFlakeError::InstanceIDTooHigh { .. })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        for hash_key in ["0001", "zz0102030405060708090a0b0c0d0e0f"] {
+            let config = SINTEFlakeConfig {
+                hash_key: Some(hash_key.into()),
+                ..Default::default()
+            };
+            assert!(matches!(
+                SINTEFlake::from_config(&config),
+                Err(SINTEFlakeError::InvalidHashKey)
+            ));
+        }
+        let config = SINTEFlakeConfig {
+            epoch: Some("yesterday".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            SINTEFlake::from_config(&config),
+            Err(SINTEFlakeError::InvalidConfig { setting: "epoch" })
+        ));
+    }
+
+    #[test]
+    fn test_from_env() {
+        env::set_var("SINTEFLAKE_INSTANCE_ID", "12");
+        env::set_var("SINTEFLAKE_EPOCH", "unix");
+        let config = SINTEFlakeConfig::from_env().unwrap();
+        assert_eq!(config.instance_id, Some(12));
+        assert_eq!(config.epoch.as_deref(), Some("unix"));
+        assert_eq!(config.hash_key, None);
+
+        env::set_var("SINTEFLAKE_INSTANCE_ID", "twelve");
+        assert!(matches!(
+            SINTEFlakeConfig::from_env(),
+            Err(SINTEFlakeError::InvalidConfig {
+                setting: "SINTEFLAKE_INSTANCE_ID"
+            })
+        ));
+        env::remove_var("SINTEFLAKE_INSTANCE_ID");
+        env::remove_var("SINTEFLAKE_EPOCH");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let config: SINTEFlakeConfig =
+            serde_json::from_str(r#"{"instance_id": 5, "epoch": "discord"}"#).unwrap();
+        assert_eq!(config.instance_id, Some(5));
+        assert_eq!(
+            SINTEFlake::from_config(&config)
+                .unwrap()
+                .encoding_config()
+                .epoch_id,
+            Some("discord")
+        );
+        assert!(serde_json::from_str::<SINTEFlakeConfig>(r#"{"instance": 5}"#).is_err());
+    }
+}
@@ -0,0 +1,865 @@
+//! Decoding of the IDs back to their fields, and re-encoding with other keys.
+use alloc::string::String;
+use core::fmt;
+use core::time::Duration;
+
+use crate::error::SINTEFlakeError;
+use crate::feistel::FeistelPermutation;
+use crate::layout::Layout;
+use crate::permute::PermutationTables;
+use crate::rotation::KeySchedule;
+use crate::time::{add_millis_saturating, Rfc3339, UnixTime};
+
+/// The settings deciding how the fields of an ID are encoded.
+///
+/// Use [`crate::sinteflake::SINTEFlake::encoding_config`] to get the settings of an instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodingConfig {
+    pub layout: Layout,
+    pub hash_key: [u8; 16],
+    pub counter_key: u8,
+    pub permutation_tables: PermutationTables,
+    pub feistel_permutation: bool,
+    /// The number of bits of the hash holding the tenant code.
+    pub tenant_bits: u8,
+    /// True if the sequence numbers are the counters, without shuffling, see
+    /// [`crate::builder::SINTEFlakeBuilder::monotonic_sequence`].
+    pub monotonic_sequence: bool,
+    pub epoch: UnixTime,
+    /// The identifier of the epoch if it is named, see [`crate::epoch::Epoch`].
+    pub epoch_id: Option<&'static str>,
+    pub time_window: Duration,
+    /// The number of IDs per bucket and time window, which may be below the capacity of
+    /// the layout, see [`crate::builder::SINTEFlakeBuilder::bucket_capacity`].
+    pub bucket_capacity: u16,
+}
+
+/// The fields of an ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    rkyv(derive(Debug, PartialEq, Eq))
+)]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[cfg_attr(feature = "bincode", derive(bincode::Encode, bincode::Decode))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct DecodedId {
+    /// The hash or random number, truncated to the hash bits of the layout.
+    pub hash: u16,
+
+    /// The time window, counted from the epoch.
+    pub timestamp: u64,
+
+    pub instance_id: u16,
+
+    /// The sequence number, as stored in the ID.
+    pub sequence: u8,
+}
+
+/// The fields of an ID with the epoch it was minted against, see [`decode_extended`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtendedDecodedId {
+    pub decoded: DecodedId,
+
+    /// The identifier of the epoch, if it is named.
+    pub epoch_id: Option<&'static str>,
+
+    /// The start of the time window of the ID.
+    pub window_start: UnixTime,
+}
+
+/// How an ID was placed in its time window, see [`explain`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Explanation {
+    pub decoded: DecodedId,
+
+    /// The start of the time window of the ID.
+    pub window_start: UnixTime,
+
+    /// The bucket the ID landed in, the one of its hash or, if it was full, a probed one.
+    pub bucket: u16,
+
+    /// The counter of the bucket, un-shuffled from the sequence number: the ID was the
+    /// `counter + 1`-th one of its bucket in the time window.
+    pub counter: u8,
+
+    /// The number of IDs a bucket holds, with the settings of the generator.
+    pub bucket_capacity: u16,
+}
+
+impl Explanation {
+    /// Returns true if the ID took the last sequence number of its bucket, so the next IDs
+    /// whose hash landed in this bucket during the window fell back to probing other buckets.
+    pub fn filled_bucket(&self) -> bool {
+        self.counter as u16 + 1 == self.bucket_capacity
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "window {} ({})",
+            self.decoded.timestamp,
+            Rfc3339::new(self.window_start)
+        )?;
+        writeln!(f, "instance {}", self.decoded.instance_id)?;
+        writeln!(
+            f,
+            "bucket {}, ID {} of {} (sequence number {})",
+            self.bucket,
+            self.counter as u16 + 1,
+            self.bucket_capacity,
+            self.decoded.sequence
+        )?;
+        if self.filled_bucket() {
+            write!(
+                f,
+                "the ID filled its bucket, the next IDs landing in it probed other buckets"
+            )
+        } else if self.counter > 0 {
+            write!(
+                f,
+                "{} IDs were in the bucket before, from colliding hashes or probing",
+                self.counter
+            )
+        } else {
+            write!(f, "first ID of its bucket in the window")
+        }
+    }
+}
+
+impl EncodingConfig {
+    /// Returns the start of a time window.
+    pub fn window_start(&self, timestamp: u64) -> UnixTime {
+        let offset = (self.time_window.as_millis() as i64).saturating_mul(timestamp as i64);
+        add_millis_saturating(self.epoch, offset)
+    }
+
+    fn feistel(&self) -> Option<FeistelPermutation> {
+        self.feistel_permutation
+            .then(|| FeistelPermutation::new(self.hash_key, self.layout.total_bits()))
+    }
+}
+
+impl DecodedId {
+    /// Returns when the ID was created, the start of its time window.
+    pub fn created_at(&self, config: &EncodingConfig) -> UnixTime {
+        config.window_start(self.timestamp)
+    }
+
+    /// Returns when the ID was created as a `SystemTime`, with the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn created_at_system_time(&self, config: &EncodingConfig) -> std::time::SystemTime {
+        crate::time::to_system_time(self.created_at(config))
+    }
+
+    /// Returns when the ID was created as a chrono time, with the `chrono` feature.
+    #[cfg(feature = "chrono")]
+    pub fn created_at_chrono(&self, config: &EncodingConfig) -> chrono::DateTime<chrono::Utc> {
+        crate::time::to_chrono(self.created_at(config))
+    }
+
+    /// Returns the tenant code of an ID generated with [`crate::sinteflake::SINTEFlake::next_id_for_tenant`],
+    /// the first `tenant_bits` bits of the hash. Returns 0 without tenant bits.
+    pub fn tenant(&self, config: &EncodingConfig) -> u16 {
+        let shift = config.layout.hash_bits() - config.tenant_bits;
+        ((self.hash as u32) >> shift) as u16
+    }
+
+    /// Returns the issuance order of the ID within its bucket and time window, un-shuffled
+    /// from the sequence number, see [`unshuffle_counter`].
+    pub fn counter(&self, config: &EncodingConfig) -> u8 {
+        unshuffle_counter(self.sequence, config)
+    }
+
+    /// Returns a value displaying the fields with the start of the time window as a date,
+    /// like `hash=0x1a2b window=2024-09-01T12:00:08Z instance=42 seq=17`.
+    pub fn display<'a>(&'a self, config: &'a EncodingConfig) -> DisplayDecodedId<'a> {
+        DisplayDecodedId {
+            decoded: self,
+            config,
+        }
+    }
+
+    /// Returns the fields as a JSON object, with the start of the time window as a date:
+    /// `{"hash":6699,"timestamp":675001,"window_start":"2024-09-01T12:00:08Z","instance_id":42,"sequence":17}`.
+    pub fn to_json(&self, config: &EncodingConfig) -> String {
+        alloc::format!(
+            "{{\"hash\":{},\"timestamp\":{},\"window_start\":\"{}\",\"instance_id\":{},\"sequence\":{}}}",
+            self.hash,
+            self.timestamp,
+            Rfc3339::new(config.window_start(self.timestamp)),
+            self.instance_id,
+            self.sequence
+        )
+    }
+}
+
+/// Displays the fields with the time window as a number, as the settings are needed for its date:
+/// `hash=0x1a2b window=9040544 instance=42 seq=17`.
+impl fmt::Display for DecodedId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "hash={:#x} window={} instance={} seq={}",
+            self.hash, self.timestamp, self.instance_id, self.sequence
+        )
+    }
+}
+
+/// Displays a [`DecodedId`] with the start of its time window, see [`DecodedId::display`].
+#[derive(Debug, Clone, Copy)]
+pub struct DisplayDecodedId<'a> {
+    decoded: &'a DecodedId,
+    config: &'a EncodingConfig,
+}
+
+impl fmt::Display for DisplayDecodedId<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "hash={:#x} window={} instance={} seq={}",
+            self.decoded.hash,
+            Rfc3339::new(self.config.window_start(self.decoded.timestamp)),
+            self.decoded.instance_id,
+            self.decoded.sequence
+        )
+    }
+}
+
+/// Decodes an ID generated with the given settings.
+///
+/// # Errors
+/// Returns an error if the ID doesn't fit in the layout, or doesn't have the version
+/// of a versioned layout.
+pub fn decode(id: u64, config: &EncodingConfig) -> Result<DecodedId, SINTEFlakeError> {
+    let layout = config.layout;
+    if id > layout.max_id() {
+        return Err(SINTEFlakeError::InvalidId);
+    }
+    if let Some(version) = layout.version() {
+        if layout.read_version(id) != version {
+            return Err(SINTEFlakeError::InvalidId);
+        }
+    }
+    let id = match config.feistel() {
+        Some(permutation) => permutation.invert(id),
+        None => id,
+    };
+    let (hash, timestamp, instance_id, sequence) = layout.deconstruct_identifier(id);
+    Ok(DecodedId {
+        hash,
+        timestamp: config
+            .permutation_tables
+            .unpermute_layout_timestamp(timestamp, &layout),
+        instance_id,
+        sequence,
+    })
+}
+
+/// Decodes an ID like [`decode`], with the identifier of the epoch of the settings and the
+/// start of its time window, for tools handling the IDs of several configurations.
+///
+/// # Errors
+/// Returns an error if the ID doesn't fit in the layout.
+pub fn decode_extended(
+    id: u64,
+    config: &EncodingConfig,
+) -> Result<ExtendedDecodedId, SINTEFlakeError> {
+    let decoded = decode(id, config)?;
+    Ok(ExtendedDecodedId {
+        decoded,
+        epoch_id: config.epoch_id,
+        window_start: config.window_start(decoded.timestamp),
+    })
+}
+
+/// Start of the time window of an ID, in milliseconds since the Unix epoch,
+/// for the 128-bit identifiers with Unix millisecond timestamps.
+#[cfg(any(feature = "ulid", feature = "uuid"))]
+pub(crate) fn window_start_unix_ms(
+    id: u64,
+    config: &EncodingConfig,
+) -> Result<u64, SINTEFlakeError> {
+    let created_at = decode(id, config)?.created_at(config);
+    u64::try_from(created_at.unix_timestamp_nanos().div_euclid(1_000_000))
+        .map_err(|_| SINTEFlakeError::TimestampOverflow)
+}
+
+/// Explains how an ID was placed in its time window: its bucket, the un-shuffled counter of
+/// the bucket, and whether it filled the bucket, to investigate capacity incidents from
+/// stored IDs alone.
+///
+/// An ID doesn't tell whether it was moved by probing, as its hash is not stored, but IDs
+/// with high counters show the buckets that were close to full.
+///
+/// # Errors
+/// Returns an error if the ID doesn't fit in the layout.
+pub fn explain(id: u64, config: &EncodingConfig) -> Result<Explanation, SINTEFlakeError> {
+    let decoded = decode(id, config)?;
+    Ok(Explanation {
+        decoded,
+        window_start: config.window_start(decoded.timestamp),
+        bucket: decoded.hash,
+        counter: unshuffle_counter(decoded.sequence, config),
+        bucket_capacity: config.bucket_capacity,
+    })
+}
+
+/// Decodes an ID with the settings of its layout version, among the settings of the
+/// [versioned layouts](Layout::versioned) of a deployment.
+///
+/// # Errors
+/// Returns `InvalidId` if no settings have the version of the ID, or if it doesn't fit
+/// in their layout.
+pub fn decode_versioned<'a>(
+    id: u64,
+    configs: impl IntoIterator<Item = &'a EncodingConfig>,
+) -> Result<(DecodedId, &'a EncodingConfig), SINTEFlakeError> {
+    configs
+        .into_iter()
+        .find(|config| config.layout.version() == Some(config.layout.read_version(id)))
+        .ok_or(SINTEFlakeError::InvalidId)
+        .and_then(|config| Ok((decode(id, config)?, config)))
+}
+
+/// Decodes an ID generated with a key schedule, and returns the hash key used for it.
+///
+/// The key is the one in effect at the start of the time window of the ID, or the hash key
+/// of the settings before the first one. With the Feistel permutation, the keys are tried
+/// until the decoded time window matches the key.
+///
+/// # Errors
+/// Returns an error if the ID doesn't fit in the layout, or if no key matches.
+pub fn decode_with_key_schedule(
+    id: u64,
+    config: &EncodingConfig,
+    key_schedule: &KeySchedule,
+) -> Result<(DecodedId, [u8; 16]), SINTEFlakeError> {
+    for &hash_key in core::iter::once(&config.hash_key).chain(key_schedule.keys()) {
+        let candidate = EncodingConfig {
+            hash_key,
+            ..config.clone()
+        };
+        let decoded = decode(id, &candidate)?;
+        let window_start = candidate.window_start(decoded.timestamp);
+        let key = key_schedule
+            .key_at(window_start)
+            .copied()
+            .unwrap_or(config.hash_key);
+        if key == hash_key {
+            return Ok((decoded, hash_key));
+        }
+    }
+    Err(SINTEFlakeError::InvalidId)
+}
+
+/// Encodes the fields of an ID with the given settings, the inverse of [`decode`].
+///
+/// # Errors
+/// Returns an error if a field doesn't fit in the layout.
+pub fn encode(decoded: &DecodedId, config: &EncodingConfig) -> Result<u64, SINTEFlakeError> {
+    let layout = config.layout;
+    if decoded.hash as usize >= layout.buckets()
+        || decoded.timestamp > layout.max_timestamp()
+        || decoded.instance_id > layout.max_instance_id()
+        || decoded.sequence as u16 >= layout.bucket_capacity()
+    {
+        return Err(SINTEFlakeError::InvalidId);
+    }
+    let id = layout.construct_identifier(
+        decoded.hash,
+        config
+            .permutation_tables
+            .permute_layout_timestamp(decoded.timestamp, &layout),
+        decoded.instance_id,
+        decoded.sequence,
+    );
+    Ok(match config.feistel() {
+        Some(permutation) => permutation.permute(id),
+        None => id,
+    })
+}
+
+/// Returns the counter from which the generator shuffled a sequence number, the inverse of
+/// [`shuffle_counter`].
+///
+/// The sequence numbers are XORed with the counter key and permuted, the counter is the
+/// issuance order of the ID within its bucket and time window: 0 for the first ID, 1 for the
+/// second one, and so on.
+pub fn unshuffle_counter(sequence: u8, config: &EncodingConfig) -> u8 {
+    if config.monotonic_sequence {
+        return sequence;
+    }
+    let bits = config.layout.sequence_bits();
+    let mask = ((1u16 << bits) - 1) as u8;
+    (config.permutation_tables.unpermute_sequence(sequence, bits) ^ config.counter_key) & mask
+}
+
+/// Shuffles a counter into a sequence number, like the generator does.
+pub fn shuffle_counter(counter: u8, config: &EncodingConfig) -> u8 {
+    if config.monotonic_sequence {
+        return counter;
+    }
+    config
+        .permutation_tables
+        .sequence_table(config.layout.sequence_bits())[(counter ^ config.counter_key) as usize]
+}
+
+/// Re-encodes an ID generated with the `old` settings as if it was generated with the `new` ones.
+///
+/// The time window, instance ID, hash bits and counter are kept, so the re-keyed IDs
+/// are still unique. It's useful to migrate stored IDs after a key rotation.
+///
+/// # Errors
+/// Returns an error if the ID doesn't fit in the old layout, or its fields in the new layout.
+pub fn rekey_id(
+    id: u64,
+    old: &EncodingConfig,
+    new: &EncodingConfig,
+) -> Result<u64, SINTEFlakeError> {
+    let mut decoded = decode(id, old)?;
+    let counter = unshuffle_counter(decoded.sequence, old);
+    if counter as u16 >= new.layout.bucket_capacity() {
+        return Err(SINTEFlakeError::InvalidId);
+    }
+    decoded.sequence = shuffle_counter(counter, new);
+    encode(&decoded, new)
+}
+
+/// Rearranges the fields of an ID into a key starting with the time window,
+/// to sort IDs by creation time or store a sortable secondary key.
+///
+/// The time window is the decoded one, without the permutation of the timestamp bits.
+/// It's followed by the hash, the instance ID and the sequence number, so the keys
+/// of the IDs generated in the same time window are not ordered.
+/// The key has the same width as the IDs, and [`from_time_ordered`] turns it back into the ID.
+///
+/// # Errors
+/// Returns an error if the ID doesn't fit in the layout.
+pub fn to_time_ordered(id: u64, config: &EncodingConfig) -> Result<u64, SINTEFlakeError> {
+    let layout = config.layout;
+    let decoded = decode(id, config)?;
+    let sequence_shift = 0;
+    let instance_shift = sequence_shift + layout.sequence_bits();
+    let hash_shift = instance_shift + layout.instance_bits();
+    let timestamp_shift = hash_shift + layout.hash_bits();
+    Ok((decoded.timestamp << timestamp_shift)
+        | ((decoded.hash as u64) << hash_shift)
+        | ((decoded.instance_id as u64) << instance_shift)
+        | decoded.sequence as u64)
+}
+
+/// Returns the ID of a key made by [`to_time_ordered`].
+///
+/// # Errors
+/// Returns an error if the key doesn't fit in the layout, or isn't the key of an ID.
+pub fn from_time_ordered(key: u64, config: &EncodingConfig) -> Result<u64, SINTEFlakeError> {
+    let layout = config.layout;
+    if key > layout.max_id() {
+        return Err(SINTEFlakeError::InvalidId);
+    }
+    let mask = |bits: u8| (1_u64 << bits) - 1;
+    let instance_shift = layout.sequence_bits();
+    let hash_shift = instance_shift + layout.instance_bits();
+    let timestamp_shift = hash_shift + layout.hash_bits();
+    let decoded = DecodedId {
+        hash: ((key >> hash_shift) & mask(layout.hash_bits())) as u16,
+        timestamp: key >> timestamp_shift,
+        instance_id: ((key >> instance_shift) & mask(layout.instance_bits())) as u16,
+        sequence: (key & mask(layout.sequence_bits())) as u8,
+    };
+    encode(&decoded, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::tests::ManualClock;
+    use crate::sinteflake::SINTEFlake;
+
+    #[test]
+    fn test_decode() {
+        let mut instance = SINTEFlake::builder().instance_id(42).build().unwrap();
+        let config = instance.encoding_config();
+        let id = instance.next_id().unwrap();
+        let decoded = decode(id, &config).unwrap();
+        assert_eq!(decoded.instance_id, 42);
+        assert_eq!(unshuffle_counter(decoded.sequence, &config), 0);
+        assert_eq!(encode(&decoded, &config).unwrap(), id);
+
+        assert!(decode(u64::MAX, &config).is_err());
+    }
+
+    #[test]
+    fn test_decode_extended() {
+        use crate::epoch::Epoch;
+
+        let mut instance = SINTEFlake::new().unwrap();
+        let id = instance.next_id().unwrap();
+        let extended = decode_extended(id, &instance.encoding_config()).unwrap();
+        assert_eq!(extended.epoch_id, Some("sinteflake-2024"));
+        assert_eq!(
+            extended.decoded,
+            decode(id, &instance.encoding_config()).unwrap()
+        );
+
+        let mut instance = SINTEFlake::builder()
+            .named_epoch(Epoch::custom("billing-2025", 1735689600000).unwrap())
+            .build()
+            .unwrap();
+        let config = instance.encoding_config();
+        let extended = decode_extended(instance.next_id().unwrap(), &config).unwrap();
+        assert_eq!(extended.epoch_id, Some("billing-2025"));
+        assert!(extended.window_start >= Epoch::custom("", 1735689600000).unwrap().start());
+
+        // a preset set as a time is named, not other times
+        let unix = SINTEFlake::builder()
+            .epoch(UnixTime::UNIX_EPOCH)
+            .build()
+            .unwrap();
+        assert_eq!(unix.encoding_config().epoch_id, Some("unix"));
+        let other = SINTEFlake::builder()
+            .epoch(UnixTime::from_unix_timestamp(1719792001).unwrap())
+            .build()
+            .unwrap();
+        assert_eq!(other.encoding_config().epoch_id, None);
+    }
+
+    #[test]
+    fn test_counter() {
+        let mut instance = SINTEFlake::builder().counter_key(0x5a).build().unwrap();
+        let config = instance.encoding_config();
+        for expected in 0..10 {
+            let id = instance.next_id_with_hash(b"same bucket").unwrap();
+            let decoded = decode(id, &config).unwrap();
+            assert_eq!(decoded.counter(&config), expected);
+            assert_eq!(shuffle_counter(expected, &config), decoded.sequence);
+        }
+        for counter in 0..=255 {
+            assert_eq!(
+                unshuffle_counter(shuffle_counter(counter, &config), &config),
+                counter
+            );
+        }
+    }
+
+    #[test]
+    fn test_explain() {
+        // 4 buckets of 2 IDs
+        let layout = Layout::new(2, 31, 10, 1).unwrap();
+        let mut instance = SINTEFlake::builder().layout(layout).build().unwrap();
+        let config = instance.encoding_config();
+        let explanations: Vec<Explanation> = (0..8)
+            .map(|_| explain(instance.next_id().unwrap(), &config).unwrap())
+            .collect();
+        for bucket in 0..4 {
+            let mut counters: Vec<u8> = explanations
+                .iter()
+                .filter(|explanation| explanation.bucket == bucket)
+                .map(|explanation| explanation.counter)
+                .collect();
+            counters.sort();
+            assert_eq!(counters, [0, 1]);
+        }
+        let filled = explanations.iter().find(|e| e.filled_bucket()).unwrap();
+        assert_eq!(filled.counter, 1);
+        assert_eq!(filled.bucket_capacity, 2);
+        assert!(filled.to_string().contains("filled its bucket"));
+        let first = explanations.iter().find(|e| e.counter == 0).unwrap();
+        assert!(!first.filled_bucket());
+        assert!(first.to_string().contains("first ID of its bucket"));
+    }
+
+    #[test]
+    fn test_explain_bucket_capacity() {
+        // 4 buckets of 4 IDs, holding only 2
+        let layout = Layout::new(2, 31, 10, 2).unwrap();
+        let mut instance = SINTEFlake::builder()
+            .layout(layout)
+            .bucket_capacity(2)
+            .build()
+            .unwrap();
+        let config = instance.encoding_config();
+        assert_eq!(config.bucket_capacity, 2);
+        let explanations: Vec<Explanation> = (0..8)
+            .map(|_| explain(instance.next_id().unwrap(), &config).unwrap())
+            .collect();
+        assert!(explanations.iter().all(|e| e.bucket_capacity == 2));
+        assert_eq!(explanations.iter().filter(|e| e.filled_bucket()).count(), 4);
+    }
+
+    #[test]
+    fn test_created_at() {
+        let mut instance = SINTEFlake::new().unwrap();
+        let config = instance.encoding_config();
+        let decoded = decode(instance.next_id().unwrap(), &config).unwrap();
+        assert_eq!(
+            decoded.created_at(&config),
+            config.window_start(decoded.timestamp)
+        );
+    }
+
+    #[test]
+    fn test_system_time_epoch() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let epoch = UNIX_EPOCH + Duration::from_secs(1719792000);
+        let mut instance = SINTEFlake::builder().epoch(epoch).build().unwrap();
+        let config = instance.encoding_config();
+        assert_eq!(config.epoch_id, Some("sinteflake-2024"));
+
+        let decoded = decode(instance.next_id().unwrap(), &config).unwrap();
+        let created_at = decoded.created_at_system_time(&config);
+        assert!(created_at > epoch);
+        assert_eq!(
+            created_at,
+            UNIX_EPOCH
+                + Duration::from_nanos(decoded.created_at(&config).unix_timestamp_nanos() as u64)
+        );
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono_epoch() {
+        use chrono::TimeZone;
+
+        let epoch = chrono::Utc.timestamp_opt(1719792000, 0).unwrap();
+        let mut instance = SINTEFlake::builder().epoch(epoch).build().unwrap();
+        let config = instance.encoding_config();
+        assert_eq!(config.epoch_id, Some("sinteflake-2024"));
+
+        let decoded = decode(instance.next_id().unwrap(), &config).unwrap();
+        let created_at = decoded.created_at_chrono(&config);
+        assert!(created_at > epoch);
+        assert_eq!(
+            created_at.timestamp(),
+            decoded.created_at(&config).unix_timestamp()
+        );
+    }
+
+    #[test]
+    fn test_decode_versioned() {
+        let layouts = [
+            Layout::new(14, 29, 10, 8).unwrap().versioned(0).unwrap(),
+            Layout::new(4, 39, 10, 8).unwrap().versioned(1).unwrap(),
+        ];
+        let mut configs = Vec::new();
+        let mut ids = Vec::new();
+        for layout in layouts {
+            let mut instance = SINTEFlake::builder()
+                .layout(layout)
+                .instance_id(7)
+                .build()
+                .unwrap();
+            configs.push(instance.encoding_config());
+            ids.push(instance.next_id().unwrap());
+        }
+
+        for (id, layout) in ids.iter().zip(layouts) {
+            let (decoded, config) = decode_versioned(*id, &configs).unwrap();
+            assert_eq!(config.layout, layout);
+            assert_eq!(decoded.instance_id, 7);
+            assert_eq!(encode(&decoded, config).unwrap(), *id);
+        }
+        // the settings of the other version don't decode the ID
+        assert!(decode(ids[0], &configs[1]).is_err());
+        // no settings for version 2
+        let v2 = ids[0] | 2 << 61;
+        assert!(matches!(
+            decode_versioned(v2, &configs),
+            Err(SINTEFlakeError::InvalidId)
+        ));
+    }
+
+    #[test]
+    fn test_time_ordered() {
+        let clock = ManualClock::new(1719792000000);
+        let mut instance = SINTEFlake::builder()
+            .epoch(UnixTime::from_unix_timestamp(1719792000).unwrap())
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let config = instance.encoding_config();
+
+        let mut ids = Vec::new();
+        for window in 0..50 {
+            clock.set(1719792000000 + window * 8000);
+            instance.update_time().unwrap();
+            ids.push(instance.next_id().unwrap());
+            ids.push(instance.next_id_with_hash(b"data").unwrap());
+        }
+
+        let keys: Vec<u64> = ids
+            .iter()
+            .map(|&id| to_time_ordered(id, &config).unwrap())
+            .collect();
+        for (window, pair) in keys.chunks(2).enumerate() {
+            assert!(pair.iter().all(|&key| key >> 32 == window as u64));
+        }
+        for (key, id) in keys.iter().zip(&ids) {
+            assert_eq!(from_time_ordered(*key, &config).unwrap(), *id);
+        }
+        // the IDs are not ordered by time, their keys are
+        assert!(ids.windows(2).any(|pair| pair[0] > pair[1]));
+        assert!(keys
+            .chunks(2)
+            .is_sorted_by(|a, b| a[0].max(a[1]) < b[0].min(b[1])));
+
+        assert!(to_time_ordered(u64::MAX, &config).is_err());
+        assert!(from_time_ordered(u64::MAX, &config).is_err());
+    }
+
+    #[test]
+    fn test_time_ordered_feistel() {
+        let mut instance = SINTEFlake::builder()
+            .feistel_permutation(true)
+            .layout(Layout::JAVASCRIPT_SAFE)
+            .build()
+            .unwrap();
+        let config = instance.encoding_config();
+        for _ in 0..100 {
+            let id = instance.next_id().unwrap();
+            let key = to_time_ordered(id, &config).unwrap();
+            assert!(key <= Layout::JAVASCRIPT_SAFE.max_id());
+            assert_eq!(from_time_ordered(key, &config).unwrap(), id);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let instance = SINTEFlake::builder()
+            .epoch(UnixTime::from_unix_timestamp(1719792000).unwrap())
+            .build()
+            .unwrap();
+        let config = instance.encoding_config();
+        let decoded = DecodedId {
+            hash: 0x1a2b,
+            timestamp: 675001,
+            instance_id: 42,
+            sequence: 17,
+        };
+
+        assert_eq!(
+            decoded.to_string(),
+            "hash=0x1a2b window=675001 instance=42 seq=17"
+        );
+        assert_eq!(
+            decoded.display(&config).to_string(),
+            "hash=0x1a2b window=2024-09-01T12:00:08Z instance=42 seq=17"
+        );
+        assert_eq!(
+            decoded.to_json(&config),
+            r#"{"hash":6699,"timestamp":675001,"window_start":"2024-09-01T12:00:08Z","instance_id":42,"sequence":17}"#
+        );
+
+        let config = EncodingConfig {
+            time_window: Duration::from_millis(1),
+            ..config
+        };
+        assert_eq!(
+            decoded.display(&config).to_string(),
+            "hash=0x1a2b window=2024-07-01T00:11:15.001Z instance=42 seq=17"
+        );
+    }
+
+    #[cfg(all(feature = "rkyv", feature = "borsh", feature = "bincode"))]
+    #[test]
+    fn test_binary_serialization() {
+        let decoded = DecodedId {
+            hash: 5,
+            timestamp: 123456,
+            instance_id: 42,
+            sequence: 7,
+        };
+
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&decoded).unwrap();
+        let archived = rkyv::access::<ArchivedDecodedId, rkyv::rancor::Error>(&bytes).unwrap();
+        assert_eq!(archived.timestamp, 123456);
+        assert_eq!(
+            rkyv::from_bytes::<DecodedId, rkyv::rancor::Error>(&bytes).unwrap(),
+            decoded
+        );
+
+        let bytes = borsh::to_vec(&decoded).unwrap();
+        assert_eq!(bytes.len(), 13);
+        assert_eq!(borsh::from_slice::<DecodedId>(&bytes).unwrap(), decoded);
+
+        let config = bincode::config::standard();
+        let bytes = bincode::encode_to_vec(decoded, config).unwrap();
+        let (roundtrip, _) = bincode::decode_from_slice::<DecodedId, _>(&bytes, config).unwrap();
+        assert_eq!(roundtrip, decoded);
+    }
+
+    #[test]
+    fn test_decode_with_key_schedule() {
+        let epoch = UnixTime::from_unix_timestamp(1719792000).unwrap();
+        let rotation = epoch + Duration::from_secs(80);
+        let schedule = KeySchedule::new(vec![(rotation, [2; 16])]).unwrap();
+        let clock = crate::clock::tests::ManualClock::new(1719792016000);
+        let mut instance = SINTEFlake::builder()
+            .epoch(epoch)
+            .hash_key([1; 16])
+            .key_schedule(schedule.clone())
+            .feistel_permutation(true)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let config = instance.encoding_config();
+        assert_eq!(config.hash_key, [1; 16]);
+
+        let id_before = instance.next_id().unwrap();
+        clock.set(1719792085000);
+        instance.update_time().unwrap();
+        assert_eq!(instance.encoding_config().hash_key, [2; 16]);
+        let id_after = instance.next_id().unwrap();
+
+        let (decoded, key) = decode_with_key_schedule(id_before, &config, &schedule).unwrap();
+        assert_eq!((decoded.timestamp, key), (2, [1; 16]));
+        let (decoded, key) = decode_with_key_schedule(id_after, &config, &schedule).unwrap();
+        assert_eq!((decoded.timestamp, key), (10, [2; 16]));
+    }
+
+    #[test]
+    fn test_rekey_id() {
+        let clock = crate::clock::tests::ManualClock::new(1719792016000);
+        let old = SINTEFlake::builder()
+            .instance_id(7)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let old_config = old.encoding_config();
+        let new_config = EncodingConfig {
+            hash_key: [9; 16],
+            counter_key: 3,
+            feistel_permutation: true,
+            ..old_config.clone()
+        };
+        let mut old = old;
+        let mut new = SINTEFlake::builder()
+            .instance_id(7)
+            .hash_key([9; 16])
+            .counter_key(3)
+            .feistel_permutation(true)
+            .clock(clock)
+            .build()
+            .unwrap();
+
+        // the same data in the same window gives the same fields, except the hash
+        let id = old.next_id_with_hash(b"data").unwrap();
+        let rekeyed = rekey_id(id, &old_config, &new_config).unwrap();
+        let new_id = new.next_id_with_hash(b"data").unwrap();
+        let decoded = decode(rekeyed, &new_config).unwrap();
+        let expected = decode(new_id, &new_config).unwrap();
+        assert_eq!(decoded.timestamp, expected.timestamp);
+        assert_eq!(decoded.instance_id, 7);
+        assert_eq!(decoded.sequence, expected.sequence);
+
+        assert_eq!(rekey_id(rekeyed, &new_config, &old_config).unwrap(), id);
+    }
+}
@@ -0,0 +1,125 @@
+//! Named epochs, so tooling can tell which epoch an ID was minted against when
+//! several configurations coexist in an organization.
+//!
+//! ```rust
+//! use sinteflake::epoch::Epoch;
+//! use sinteflake::sinteflake::SINTEFlake;
+//!
+//! let instance = SINTEFlake::builder()
+//!     .named_epoch(Epoch::custom("billing-2025", 1735689600000)?)
+//!     .build()?;
+//! assert_eq!(instance.encoding_config().epoch_id, Some("billing-2025"));
+//! # Ok::<(), sinteflake::error::SINTEFlakeError>(())
+//! ```
+use crate::error::SINTEFlakeError;
+use crate::time::{self, UnixTime};
+
+/// An epoch with an identifier, see [`crate::builder::SINTEFlakeBuilder::named_epoch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Epoch {
+    id: &'static str,
+    unix_millis: i64,
+}
+
+impl Epoch {
+    /// 2024-07-01T00:00:00Z, the default epoch.
+    pub const SINTEFLAKE_2024: Epoch = Epoch::preset("sinteflake-2024", 1719792000000);
+
+    /// 1970-01-01T00:00:00Z.
+    pub const UNIX: Epoch = Epoch::preset("unix", 0);
+
+    /// 2010-11-04T01:42:54.657Z, the epoch of the Twitter Snowflake IDs.
+    pub const TWITTER: Epoch = Epoch::preset("twitter", 1288834974657);
+
+    /// 2015-01-01T00:00:00Z, the epoch of the Discord Snowflake IDs.
+    pub const DISCORD: Epoch = Epoch::preset("discord", 1420070400000);
+
+    /// The named presets.
+    pub const PRESETS: [Epoch; 4] = [
+        Epoch::SINTEFLAKE_2024,
+        Epoch::UNIX,
+        Epoch::TWITTER,
+        Epoch::DISCORD,
+    ];
+
+    /// Creates an epoch starting `unix_millis` milliseconds after the Unix epoch,
+    /// with an identifier unique in the organization.
+    ///
+    /// # Errors
+    /// Returns `TimestampOverflow` if the epoch isn't within the years 1 to 9999.
+    pub const fn custom(id: &'static str, unix_millis: i64) -> Result<Self, SINTEFlakeError> {
+        let unix_nanos = unix_millis as i128 * 1_000_000;
+        if unix_nanos < time::MIN_UNIX_NANOS || unix_nanos > time::MAX_UNIX_NANOS {
+            return Err(SINTEFlakeError::TimestampOverflow);
+        }
+        Ok(Epoch { id, unix_millis })
+    }
+
+    // the presets are within the years 1 to 9999
+    const fn preset(id: &'static str, unix_millis: i64) -> Self {
+        Epoch { id, unix_millis }
+    }
+
+    /// Returns the identifier of the epoch.
+    pub const fn id(&self) -> &'static str {
+        self.id
+    }
+
+    /// Returns the start of the epoch.
+    pub fn start(&self) -> UnixTime {
+        // checked by Epoch::custom, so nothing saturates
+        time::from_unix_nanos_saturating(self.unix_millis as i128 * 1_000_000)
+    }
+
+    /// Returns the preset with this identifier.
+    pub fn from_id(id: &str) -> Option<Epoch> {
+        Epoch::PRESETS.into_iter().find(|epoch| epoch.id == id)
+    }
+
+    /// Returns the preset starting at this time.
+    pub fn preset_at(start: UnixTime) -> Option<Epoch> {
+        Epoch::PRESETS
+            .into_iter()
+            .find(|epoch| epoch.start() == start)
+    }
+}
+
+impl Default for Epoch {
+    fn default() -> Self {
+        Epoch::SINTEFLAKE_2024
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        assert_eq!(
+            Epoch::SINTEFLAKE_2024.start(),
+            UnixTime::from_unix_timestamp(1719792000).unwrap()
+        );
+        assert_eq!(Epoch::UNIX.start(), UnixTime::UNIX_EPOCH);
+        assert_eq!(Epoch::TWITTER.start().millisecond(), 657);
+
+        assert_eq!(Epoch::from_id("discord"), Some(Epoch::DISCORD));
+        assert_eq!(Epoch::from_id("other"), None);
+        assert_eq!(Epoch::preset_at(UnixTime::UNIX_EPOCH), Some(Epoch::UNIX));
+        assert_eq!(
+            Epoch::preset_at(Epoch::custom("a", 1).unwrap().start()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_custom_out_of_range() {
+        assert!(matches!(
+            Epoch::custom("far", i64::MAX),
+            Err(SINTEFlakeError::TimestampOverflow)
+        ));
+        assert!(Epoch::custom("before", i64::MIN).is_err());
+        let last = Epoch::custom("last", 253_402_300_799_999).unwrap();
+        assert_eq!(last.start().unix_timestamp(), 253_402_300_799);
+    }
+}
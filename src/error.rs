@@ -1,6 +1,8 @@
 use thiserror::Error;
 
 #[derive(Error, Debug)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error), uniffi(flat_error))]
+#[non_exhaustive]
 pub enum SINTEFlakeError {
     #[error("Epoch should be in the past")]
     EpochInFuture,
@@ -8,12 +10,153 @@ pub enum SINTEFlakeError {
     #[error("Timestamp overflow")]
     TimestampOverflow,
 
-    #[error("Counter overflow, do you remember to call update_time()?")]
-    CounterOverflow,
+    /// The bucket fits in a `u16`, as the hash field has at most 16 bits, but the time window
+    /// needs a `u64` for the 41 timestamp bits of the millisecond layouts.
+    #[error("Counter overflow in bucket {bucket} of time window {window}, do you remember to call update_time()?")]
+    CounterOverflow { bucket: u16, window: u64 },
 
     #[error("Mutex error")]
     MutexError,
 
-    #[error("Instance ID too high, max 10 bits")]
-    InstanceIDTooHigh,
+    #[error("Instance ID {given} too high, max {max}")]
+    InstanceIDTooHigh { given: u16, max: u16 },
+
+    #[error("No instance ID could be derived from the environment")]
+    InstanceIdUnavailable,
+
+    #[error("The lease of the instance ID has expired")]
+    LeaseExpired,
+
+    #[error("Instance ID allocator error")]
+    AllocatorError,
+
+    #[error("ID service error")]
+    ServiceError,
+
+    #[error("Invalid layout, fields are too wide or use more than 63 bits")]
+    InvalidLayout,
+
+    #[error("Invalid permutation table, every position must appear exactly once")]
+    InvalidPermutation,
+
+    #[error("Invalid ID, it doesn't fit in the layout")]
+    InvalidId,
+
+    #[error("Invalid hash key, it must be 16 bytes long")]
+    InvalidHashKey,
+
+    #[error("Invalid key schedule, two keys take effect at the same time")]
+    InvalidKeySchedule,
+
+    #[error("Invalid time window, it must be a non-zero number of milliseconds")]
+    InvalidTimeWindow,
+
+    #[error("No clock set, one is required without the std feature")]
+    MissingClock,
+
+    #[error("Clock moved backwards to an earlier time window")]
+    ClockRollback,
+
+    #[error("Clock is not after the time window saved before the restart")]
+    ClockBehindSavedState,
+
+    #[error("State store error")]
+    StateStoreError,
+
+    #[error("Invalid shard, it must be below the number of shards and have hash buckets")]
+    InvalidShard,
+
+    #[error("Value {value} too wide for the {field} field, max {max}")]
+    FieldTooWide {
+        field: &'static str,
+        value: u64,
+        max: u64,
+    },
+
+    #[error("Hash or sequence number too wide for the layout")]
+    RawPartsTooWide,
+
+    #[error("Sequence number {sequence} of bucket {bucket} already used in this time window")]
+    SequenceUsed { bucket: u16, sequence: u8 },
+
+    #[error("The global instance is in use by another thread")]
+    WouldBlock,
+
+    #[error("IDs requested faster than the rate limit")]
+    RateLimited,
+
+    #[error("Invalid tenant, it must fit in the tenant bits")]
+    InvalidTenant,
+
+    #[error("Not possible with the Feistel permutation of the IDs")]
+    FeistelPermutation,
+
+    #[error("IDs can't be generated from a hash in sequence mode")]
+    SequenceMode,
+
+    #[error("The global instance is already initialized")]
+    AlreadyInitialized,
+
+    #[error("An instance is already registered with this name")]
+    AlreadyRegistered,
+
+    #[error("Invalid value for the {setting} setting")]
+    InvalidConfig { setting: &'static str },
+
+    #[error("The generator task has stopped")]
+    ActorStopped,
+
+    #[error("Failed to get random bytes from the OS")]
+    EntropyError,
+
+    #[error("ID {id} was already issued, the clock or the state went back")]
+    DuplicateId { id: u64 },
+}
+
+impl SINTEFlakeError {
+    /// Returns true if the same call may succeed later without changing the settings,
+    /// for example after the next time window or once the clock has caught up.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            SINTEFlakeError::CounterOverflow { .. }
+                | SINTEFlakeError::RateLimited
+                | SINTEFlakeError::ClockRollback
+                | SINTEFlakeError::ClockBehindSavedState
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(SINTEFlakeError::CounterOverflow {
+            bucket: 1,
+            window: 2
+        }
+        .is_retryable());
+        assert!(SINTEFlakeError::ClockRollback.is_retryable());
+        assert!(SINTEFlakeError::RateLimited.is_retryable());
+        assert!(!SINTEFlakeError::InstanceIDTooHigh {
+            given: 1024,
+            max: 1023
+        }
+        .is_retryable());
+        assert!(!SINTEFlakeError::EpochInFuture.is_retryable());
+    }
+
+    #[test]
+    fn test_messages() {
+        assert_eq!(
+            SINTEFlakeError::InstanceIDTooHigh {
+                given: 1024,
+                max: 1023
+            }
+            .to_string(),
+            "Instance ID 1024 too high, max 1023"
+        );
+    }
 }
@@ -0,0 +1,130 @@
+use core::hash::Hasher;
+use siphasher::sip::SipHasher24;
+
+const ROUNDS: u8 = 4;
+
+/// Keyed permutation of the identifiers of a layout, using a Feistel network.
+///
+/// The IDs look fully opaque, the fields can't be seen without the key,
+/// but holders of the key can invert the permutation to get the original ID back.
+///
+/// The network works on an even number of bits, at least as wide as the layout.
+/// Results that don't fit in the layout are permuted again (cycle walking),
+/// so permuted IDs stay below [`crate::layout::Layout::max_id`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeistelPermutation {
+    key: [u8; 16],
+    bits: u8,
+    half_bits: u8,
+}
+
+impl FeistelPermutation {
+    /// Creates the permutation of the IDs of `bits` bits, between 2 and 64.
+    pub fn new(key: [u8; 16], bits: u8) -> Self {
+        let bits = bits.clamp(2, 64);
+        FeistelPermutation {
+            key,
+            bits,
+            half_bits: bits.div_ceil(2),
+        }
+    }
+
+    /// Permutes an ID, which must fit in the number of bits of the permutation.
+    pub fn permute(&self, id: u64) -> u64 {
+        let mut value = id;
+        loop {
+            value = self.encrypt(value);
+            if self.fits(value) {
+                return value;
+            }
+        }
+    }
+
+    /// Returns the ID that was permuted into `id`.
+    pub fn invert(&self, id: u64) -> u64 {
+        let mut value = id;
+        loop {
+            value = self.decrypt(value);
+            if self.fits(value) {
+                return value;
+            }
+        }
+    }
+
+    fn fits(&self, value: u64) -> bool {
+        self.bits == 64 || value >> self.bits == 0
+    }
+
+    fn half_mask(&self) -> u64 {
+        (1 << self.half_bits) - 1
+    }
+
+    fn round(&self, round: u8, value: u64) -> u64 {
+        let mut hasher = SipHasher24::new_with_key(&self.key);
+        hasher.write_u8(round);
+        hasher.write_u64(value);
+        hasher.finish() & self.half_mask()
+    }
+
+    fn encrypt(&self, value: u64) -> u64 {
+        let mut left = value >> self.half_bits;
+        let mut right = value & self.half_mask();
+        for round in 0..ROUNDS {
+            let next = left ^ self.round(round, right);
+            left = right;
+            right = next;
+        }
+        (left << self.half_bits) | right
+    }
+
+    fn decrypt(&self, value: u64) -> u64 {
+        let mut left = value >> self.half_bits;
+        let mut right = value & self.half_mask();
+        for round in (0..ROUNDS).rev() {
+            let previous = right ^ self.round(round, left);
+            right = left;
+            left = previous;
+        }
+        (left << self.half_bits) | right
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    const KEY: [u8; 16] = [1, 1, 2, 3, 5, 8, 13, 21, 34, 55, 89, 144, 233, 121, 98, 219];
+
+    #[test]
+    fn test_round_trip() {
+        for bits in [53, 63, 64] {
+            let permutation = FeistelPermutation::new(KEY, bits);
+            for id in [0, 1, 42, 123456789, (1 << 52) - 1] {
+                let permuted = permutation.permute(id);
+                assert!(permutation.fits(permuted));
+                assert_eq!(permutation.invert(permuted), id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_a_permutation() {
+        // odd width, to exercise the cycle walking
+        let permutation = FeistelPermutation::new(KEY, 11);
+        let mut seen = HashSet::new();
+        for id in 0..(1 << 11) {
+            let permuted = permutation.permute(id);
+            assert!(permuted < 1 << 11);
+            assert!(seen.insert(permuted));
+        }
+    }
+
+    #[test]
+    fn test_depends_on_key() {
+        let a = FeistelPermutation::new(KEY, 63);
+        let b = FeistelPermutation::new([0; 16], 63);
+        assert_ne!(a.permute(42), b.permute(42));
+        assert_ne!(a.permute(42), 42);
+    }
+}
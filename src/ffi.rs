@@ -0,0 +1,221 @@
+//! C interface, for C and C++ services embedding the generator.
+//!
+//! The declarations are in `include/sinteflake.h`, generated with cbindgen
+//! from this module (`cbindgen --config cbindgen.toml --output include/sinteflake.h`).
+//! The library is built with the `ffi` feature, as a shared or a static library:
+//!
+//! ```sh
+//! cargo rustc --release --features ffi --crate-type cdylib
+//! cargo rustc --release --features ffi --crate-type staticlib
+//! ```
+//!
+//! ```c
+//! #include "sinteflake.h"
+//!
+//! SINTEFlake *instance = sinteflake_new(42);
+//! uint64_t id;
+//! if (sinteflake_next_id(instance, &id) == SINTEFLAKE_OK) {
+//!     printf("%llu\n", id);
+//! }
+//! sinteflake_free(instance);
+//! ```
+//!
+//! Functions return a status code, and write the ID to an out pointer.
+//! An instance must not be used by several threads at once.
+use core::ptr;
+use core::slice;
+
+use crate::error::SINTEFlakeError;
+use crate::sinteflake::SINTEFlake;
+
+/// The call succeeded.
+pub const SINTEFLAKE_OK: i32 = 0;
+
+/// The call failed, but may succeed later, after updating the time.
+pub const SINTEFLAKE_RETRY: i32 = 1;
+
+/// A pointer argument is null.
+pub const SINTEFLAKE_NULL_POINTER: i32 = -1;
+
+/// The call failed.
+pub const SINTEFLAKE_ERROR: i32 = -2;
+
+fn status(error: SINTEFlakeError) -> i32 {
+    if error.is_retryable() {
+        SINTEFLAKE_RETRY
+    } else {
+        SINTEFLAKE_ERROR
+    }
+}
+
+/// Creates an instance with the default settings and an instance ID.
+///
+/// Returns null if the instance ID is too high, above 1023, or if the instance
+/// can't be created. The instance must be freed with [`sinteflake_free`].
+#[no_mangle]
+pub extern "C" fn sinteflake_new(instance_id: u16) -> *mut SINTEFlake {
+    match SINTEFlake::builder().instance_id(instance_id).build() {
+        Ok(instance) => Box::into_raw(Box::new(instance)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Updates the time of an instance, see [`SINTEFlake::update_time`].
+///
+/// # Safety
+/// `instance` must be null or come from [`sinteflake_new`] and not be freed.
+#[no_mangle]
+pub unsafe extern "C" fn sinteflake_update_time(instance: *mut SINTEFlake) -> i32 {
+    let Some(instance) = instance.as_mut() else {
+        return SINTEFLAKE_NULL_POINTER;
+    };
+    match instance.update_time() {
+        Ok(()) => SINTEFLAKE_OK,
+        Err(error) => status(error),
+    }
+}
+
+/// Generates the next ID and writes it to `id`.
+///
+/// # Safety
+/// `instance` must be null or come from [`sinteflake_new`] and not be freed,
+/// and `id` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn sinteflake_next_id(instance: *mut SINTEFlake, id: *mut u64) -> i32 {
+    let Some(instance) = instance.as_mut() else {
+        return SINTEFLAKE_NULL_POINTER;
+    };
+    if id.is_null() {
+        return SINTEFLAKE_NULL_POINTER;
+    }
+    match instance.next_id() {
+        Ok(next_id) => {
+            id.write(next_id);
+            SINTEFLAKE_OK
+        }
+        Err(error) => status(error),
+    }
+}
+
+/// Generates the next ID with the hash of `length` bytes at `data`, and writes it to `id`.
+///
+/// # Safety
+/// `instance` must be null or come from [`sinteflake_new`] and not be freed,
+/// `data` must be valid for `length` bytes, or may be null if `length` is 0,
+/// and `id` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn sinteflake_next_id_with_hash(
+    instance: *mut SINTEFlake,
+    data: *const u8,
+    length: usize,
+    id: *mut u64,
+) -> i32 {
+    let Some(instance) = instance.as_mut() else {
+        return SINTEFLAKE_NULL_POINTER;
+    };
+    if id.is_null() || (data.is_null() && length > 0) {
+        return SINTEFLAKE_NULL_POINTER;
+    }
+    let data = if length == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, length)
+    };
+    match instance.next_id_with_hash(data) {
+        Ok(next_id) => {
+            id.write(next_id);
+            SINTEFLAKE_OK
+        }
+        Err(error) => status(error),
+    }
+}
+
+/// Frees an instance. Does nothing if `instance` is null.
+///
+/// # Safety
+/// `instance` must be null or come from [`sinteflake_new`], and not be freed already.
+#[no_mangle]
+pub unsafe extern "C" fn sinteflake_free(instance: *mut SINTEFlake) {
+    if !instance.is_null() {
+        drop(Box::from_raw(instance));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffi() {
+        let instance = sinteflake_new(42);
+        assert!(!instance.is_null());
+
+        unsafe {
+            assert_eq!(sinteflake_update_time(instance), SINTEFLAKE_OK);
+
+            let (mut id_a, mut id_b) = (0, 0);
+            assert_eq!(sinteflake_next_id(instance, &mut id_a), SINTEFLAKE_OK);
+            assert_eq!(sinteflake_next_id(instance, &mut id_b), SINTEFLAKE_OK);
+            assert_ne!(id_a, id_b);
+
+            let data = b"data";
+            let (mut id_c, mut id_d) = (0, 0);
+            assert_eq!(
+                sinteflake_next_id_with_hash(instance, data.as_ptr(), data.len(), &mut id_c),
+                SINTEFLAKE_OK
+            );
+            assert_eq!(
+                sinteflake_next_id_with_hash(instance, data.as_ptr(), data.len(), &mut id_d),
+                SINTEFLAKE_OK
+            );
+            assert_eq!(id_c >> 49, id_d >> 49);
+            assert_eq!(
+                sinteflake_next_id_with_hash(instance, ptr::null(), 0, &mut id_d),
+                SINTEFLAKE_OK
+            );
+
+            sinteflake_free(instance);
+        }
+    }
+
+    #[test]
+    fn test_ffi_errors() {
+        assert!(sinteflake_new(1024).is_null());
+
+        let mut id = 0;
+        unsafe {
+            assert_eq!(
+                sinteflake_next_id(ptr::null_mut(), &mut id),
+                SINTEFLAKE_NULL_POINTER
+            );
+            assert_eq!(
+                sinteflake_update_time(ptr::null_mut()),
+                SINTEFLAKE_NULL_POINTER
+            );
+
+            let instance = sinteflake_new(1);
+            assert_eq!(
+                sinteflake_next_id(instance, ptr::null_mut()),
+                SINTEFLAKE_NULL_POINTER
+            );
+            assert_eq!(
+                sinteflake_next_id_with_hash(instance, ptr::null(), 4, &mut id),
+                SINTEFLAKE_NULL_POINTER
+            );
+            sinteflake_free(instance);
+            sinteflake_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_ffi_retry() {
+        assert_eq!(
+            status(SINTEFlakeError::CounterOverflow {
+                bucket: 0,
+                window: 0
+            }),
+            SINTEFLAKE_RETRY
+        );
+        assert_eq!(status(SINTEFlakeError::InvalidId), SINTEFLAKE_ERROR);
+    }
+}
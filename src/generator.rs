@@ -0,0 +1,225 @@
+//! A trait for ID generators, so application code can swap the generator.
+//!
+//! The code generating IDs takes an [`IdGenerator`], a [`SINTEFlake`] instance or the
+//! [`GlobalGenerator`] in production, and a deterministic fake in tests.
+//!
+//! ```rust
+//! use sinteflake::error::SINTEFlakeError;
+//! use sinteflake::generator::IdGenerator;
+//! use sinteflake::sinteflake::SINTEFlake;
+//!
+//! fn create_user(ids: &mut impl IdGenerator, name: &str) -> Result<u64, SINTEFlakeError> {
+//!     ids.next_id_with_hash(name.as_bytes())
+//! }
+//!
+//! fn main() -> Result<(), SINTEFlakeError> {
+//!     let mut instance = SINTEFlake::new()?;
+//!     create_user(&mut instance, "alice")?;
+//!     Ok(())
+//! }
+//! ```
+use alloc::boxed::Box;
+#[cfg(feature = "test-util")]
+use alloc::vec::Vec;
+
+use crate::error::SINTEFlakeError;
+use crate::sinteflake::SINTEFlake;
+
+/// Generates unique IDs, like [`SINTEFlake`].
+pub trait IdGenerator {
+    /// Generates the next unique ID.
+    fn next_id(&mut self) -> Result<u64, SINTEFlakeError>;
+
+    /// Generates the next unique ID using the provided data for hashing.
+    fn next_id_with_hash(&mut self, data: &[u8]) -> Result<u64, SINTEFlakeError>;
+
+    /// Moves to the current time window.
+    fn update_time(&mut self) -> Result<(), SINTEFlakeError>;
+}
+
+impl IdGenerator for SINTEFlake {
+    fn next_id(&mut self) -> Result<u64, SINTEFlakeError> {
+        SINTEFlake::next_id(self)
+    }
+
+    fn next_id_with_hash(&mut self, data: &[u8]) -> Result<u64, SINTEFlakeError> {
+        SINTEFlake::next_id_with_hash(self, data)
+    }
+
+    fn update_time(&mut self) -> Result<(), SINTEFlakeError> {
+        SINTEFlake::update_time(self)
+    }
+}
+
+/// The global SINTEFlake instance, see [`crate::next_id`].
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GlobalGenerator;
+
+#[cfg(feature = "std")]
+impl IdGenerator for GlobalGenerator {
+    fn next_id(&mut self) -> Result<u64, SINTEFlakeError> {
+        crate::singleton::next_id()
+    }
+
+    fn next_id_with_hash(&mut self, data: &[u8]) -> Result<u64, SINTEFlakeError> {
+        crate::singleton::next_id_with_hash(data)
+    }
+
+    fn update_time(&mut self) -> Result<(), SINTEFlakeError> {
+        crate::singleton::update_time()
+    }
+}
+
+/// A deterministic generator for tests, with the `test-util` feature.
+///
+/// The IDs are the seed, the seed plus one, and so on, whatever the data and the time,
+/// so tests don't depend on the clock or on the global instance. The generated IDs and the
+/// number of time updates are recorded for assertions.
+///
+/// ```rust
+/// use sinteflake::generator::{IdGenerator, MockIdGenerator};
+///
+/// let mut ids = MockIdGenerator::new(100);
+/// assert_eq!(ids.next_id()?, 100);
+/// assert_eq!(ids.next_id_with_hash(b"data")?, 101);
+/// assert_eq!(ids.issued(), [100, 101]);
+/// # Ok::<(), sinteflake::error::SINTEFlakeError>(())
+/// ```
+#[cfg(feature = "test-util")]
+#[derive(Debug, Clone, Default)]
+pub struct MockIdGenerator {
+    next: u64,
+    issued: Vec<u64>,
+    time_updates: usize,
+}
+
+#[cfg(feature = "test-util")]
+impl MockIdGenerator {
+    /// Creates a generator starting at `seed`.
+    pub fn new(seed: u64) -> Self {
+        MockIdGenerator {
+            next: seed,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the IDs generated so far, in order.
+    pub fn issued(&self) -> &[u64] {
+        &self.issued
+    }
+
+    /// Returns how many times [`IdGenerator::update_time`] was called.
+    pub fn time_updates(&self) -> usize {
+        self.time_updates
+    }
+}
+
+#[cfg(feature = "test-util")]
+impl IdGenerator for MockIdGenerator {
+    fn next_id(&mut self) -> Result<u64, SINTEFlakeError> {
+        let id = self.next;
+        self.next = self.next.wrapping_add(1);
+        self.issued.push(id);
+        Ok(id)
+    }
+
+    fn next_id_with_hash(&mut self, _data: &[u8]) -> Result<u64, SINTEFlakeError> {
+        self.next_id()
+    }
+
+    fn update_time(&mut self) -> Result<(), SINTEFlakeError> {
+        self.time_updates += 1;
+        Ok(())
+    }
+}
+
+impl<G: IdGenerator + ?Sized> IdGenerator for &mut G {
+    fn next_id(&mut self) -> Result<u64, SINTEFlakeError> {
+        (**self).next_id()
+    }
+
+    fn next_id_with_hash(&mut self, data: &[u8]) -> Result<u64, SINTEFlakeError> {
+        (**self).next_id_with_hash(data)
+    }
+
+    fn update_time(&mut self) -> Result<(), SINTEFlakeError> {
+        (**self).update_time()
+    }
+}
+
+impl<G: IdGenerator + ?Sized> IdGenerator for Box<G> {
+    fn next_id(&mut self) -> Result<u64, SINTEFlakeError> {
+        (**self).next_id()
+    }
+
+    fn next_id_with_hash(&mut self, data: &[u8]) -> Result<u64, SINTEFlakeError> {
+        (**self).next_id_with_hash(data)
+    }
+
+    fn update_time(&mut self) -> Result<(), SINTEFlakeError> {
+        (**self).update_time()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a deterministic fake, counting from 1
+    struct Counter(u64);
+
+    impl IdGenerator for Counter {
+        fn next_id(&mut self) -> Result<u64, SINTEFlakeError> {
+            self.0 += 1;
+            Ok(self.0)
+        }
+
+        fn next_id_with_hash(&mut self, _data: &[u8]) -> Result<u64, SINTEFlakeError> {
+            self.next_id()
+        }
+
+        fn update_time(&mut self) -> Result<(), SINTEFlakeError> {
+            Ok(())
+        }
+    }
+
+    fn two_ids(ids: &mut impl IdGenerator) -> (u64, u64) {
+        ids.update_time().unwrap();
+        (
+            ids.next_id().unwrap(),
+            ids.next_id_with_hash(b"data").unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_swap_generators() {
+        assert_eq!(two_ids(&mut Counter(0)), (1, 2));
+
+        let (id_a, id_b) = two_ids(&mut SINTEFlake::new().unwrap());
+        assert_ne!(id_a, id_b);
+
+        let mut boxed: Box<dyn IdGenerator> = Box::new(Counter(10));
+        assert_eq!(two_ids(&mut boxed), (11, 12));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_mock_id_generator() {
+        let mut mock = MockIdGenerator::new(42);
+        assert_eq!(two_ids(&mut mock), (42, 43));
+        assert_eq!(two_ids(&mut mock), (44, 45));
+        assert_eq!(mock.issued(), [42, 43, 44, 45]);
+        assert_eq!(mock.time_updates(), 2);
+
+        // the same seed gives the same IDs
+        assert_eq!(two_ids(&mut MockIdGenerator::new(42)), (42, 43));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_global_generator() {
+        let (id_a, id_b) = two_ids(&mut GlobalGenerator);
+        assert_ne!(id_a, id_b);
+    }
+}
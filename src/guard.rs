@@ -0,0 +1,118 @@
+//! A last-resort check that an instance never issues the same ID twice,
+//! see [`SINTEFlakeBuilder::duplicate_guard_windows`](crate::builder::SINTEFlakeBuilder::duplicate_guard_windows).
+use alloc::collections::{BTreeMap, BTreeSet};
+
+use crate::error::SINTEFlakeError;
+
+/// The IDs issued during the last time windows, to reject an ID issued again.
+///
+/// The collisions map already prevents duplicates within a time window, so the guard only
+/// catches bugs, like a clock rollback or a restored state going back to a window already
+/// used. It keeps every ID of the windows in memory, about 40 bytes per ID.
+///
+/// ```rust
+/// use sinteflake::guard::DuplicateGuard;
+///
+/// let mut guard = DuplicateGuard::new(2);
+/// guard.check(1, 42)?;
+/// guard.check(2, 43)?;
+/// assert!(guard.check(2, 42).is_err());
+/// # Ok::<(), sinteflake::error::SINTEFlakeError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct DuplicateGuard {
+    windows: usize,
+    // the IDs of each window, by window
+    issued: BTreeMap<u64, BTreeSet<u64>>,
+}
+
+impl DuplicateGuard {
+    /// Creates a guard remembering the IDs of the last `windows` time windows.
+    pub fn new(windows: usize) -> Self {
+        DuplicateGuard {
+            windows,
+            issued: BTreeMap::new(),
+        }
+    }
+
+    /// Number of time windows remembered.
+    pub fn windows(&self) -> usize {
+        self.windows
+    }
+
+    /// Returns true if the ID was issued during the remembered time windows.
+    pub fn contains(&self, id: u64) -> bool {
+        self.issued.values().any(|ids| ids.contains(&id))
+    }
+
+    /// Remembers an ID issued during a time window.
+    ///
+    /// A window already remembered, like after a clock rollback, keeps its IDs. Only the
+    /// most recent windows are remembered, so an ID of an older window is not kept.
+    ///
+    /// # Errors
+    /// Returns `DuplicateId` if the ID was already issued during the remembered time windows.
+    pub fn check(&mut self, window: u64, id: u64) -> Result<(), SINTEFlakeError> {
+        if self.contains(id) {
+            return Err(SINTEFlakeError::DuplicateId { id });
+        }
+        if self.windows == 0 {
+            return Ok(());
+        }
+        self.issued.entry(window).or_default().insert(id);
+        while self.issued.len() > self.windows {
+            self.issued.pop_first();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicate_guard() {
+        let mut guard = DuplicateGuard::new(2);
+        guard.check(1, 10).unwrap();
+        guard.check(1, 11).unwrap();
+        guard.check(2, 20).unwrap();
+        assert!(guard.contains(10) && guard.contains(20));
+        assert!(matches!(
+            guard.check(2, 11),
+            Err(SINTEFlakeError::DuplicateId { id: 11 })
+        ));
+
+        // the first window is forgotten
+        guard.check(3, 30).unwrap();
+        assert!(!guard.contains(10));
+        guard.check(3, 10).unwrap();
+
+        // a window older than the remembered ones is not kept
+        guard.check(1, 40).unwrap();
+        assert!(!guard.contains(40));
+
+        let mut disabled = DuplicateGuard::new(0);
+        disabled.check(1, 10).unwrap();
+        disabled.check(1, 10).unwrap();
+    }
+
+    #[test]
+    fn test_clock_rollback() {
+        let mut guard = DuplicateGuard::new(2);
+        guard.check(1, 10).unwrap();
+        guard.check(2, 20).unwrap();
+
+        // back to the first window, which keeps its IDs
+        guard.check(1, 11).unwrap();
+        assert!(matches!(
+            guard.check(1, 10),
+            Err(SINTEFlakeError::DuplicateId { id: 10 })
+        ));
+        assert!(matches!(
+            guard.check(2, 20),
+            Err(SINTEFlakeError::DuplicateId { id: 20 })
+        ));
+        assert!(guard.contains(11));
+    }
+}
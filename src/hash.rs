@@ -1,11 +1,136 @@
+use alloc::boxed::Box;
+use core::fmt::Debug;
+use core::hash::{Hash, Hasher};
 use siphasher::sip::SipHasher24;
 
-pub(crate) fn hash(array: &[u8], key: &[u8; 16]) -> u16 {
-    let hasher = SipHasher24::new_with_key(key);
-    let hash_64 = hasher.hash(array);
+/// Keyed hash algorithm used to compute the hash part of the IDs.
+///
+/// SipHash-2-4 is the default. Faster but unkeyed algorithms are available behind features,
+/// they make it easier to predict or provoke collisions from the hashed data.
+pub trait IdHasher: Debug + Send + Sync {
+    /// Hashes a byte slice with the given key.
+    fn hash(&self, key: &[u8; 16], data: &[u8]) -> u64;
 
+    /// Creates a hasher initialised with the given key, for incremental hashing.
+    ///
+    /// Writing `data` to the returned hasher must give the same result as [`IdHasher::hash`].
+    fn hasher(&self, key: &[u8; 16]) -> KeyedHasher;
+}
+
+/// Incremental hasher returned by [`IdHasher::hasher`].
+///
+/// The hashers of the provided algorithms are stored inline, so hashing doesn't allocate.
+/// Other algorithms can use [`KeyedHasher::Custom`].
+#[allow(clippy::large_enum_variant)]
+pub enum KeyedHasher {
+    SipHash(SipHasher24),
+    #[cfg(feature = "xxhash")]
+    Xxh3(xxhash_rust::xxh3::Xxh3),
+    #[cfg(feature = "fnv")]
+    Fnv1a(fnv::FnvHasher),
+    Custom(Box<dyn Hasher + Send>),
+}
+
+impl Hasher for KeyedHasher {
+    fn finish(&self) -> u64 {
+        match self {
+            KeyedHasher::SipHash(hasher) => hasher.finish(),
+            #[cfg(feature = "xxhash")]
+            KeyedHasher::Xxh3(hasher) => hasher.finish(),
+            #[cfg(feature = "fnv")]
+            KeyedHasher::Fnv1a(hasher) => hasher.finish(),
+            KeyedHasher::Custom(hasher) => hasher.finish(),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        match self {
+            KeyedHasher::SipHash(hasher) => hasher.write(bytes),
+            #[cfg(feature = "xxhash")]
+            KeyedHasher::Xxh3(hasher) => hasher.write(bytes),
+            #[cfg(feature = "fnv")]
+            KeyedHasher::Fnv1a(hasher) => hasher.write(bytes),
+            KeyedHasher::Custom(hasher) => hasher.write(bytes),
+        }
+    }
+}
+
+/// SipHash-2-4, the default algorithm.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SipHash24;
+
+impl IdHasher for SipHash24 {
+    fn hash(&self, key: &[u8; 16], data: &[u8]) -> u64 {
+        SipHasher24::new_with_key(key).hash(data)
+    }
+
+    fn hasher(&self, key: &[u8; 16]) -> KeyedHasher {
+        KeyedHasher::SipHash(SipHasher24::new_with_key(key))
+    }
+}
+
+/// XXH3 64 bits, seeded with the key folded to 64 bits.
+#[cfg(feature = "xxhash")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Xxh3;
+
+#[cfg(feature = "xxhash")]
+impl IdHasher for Xxh3 {
+    fn hash(&self, key: &[u8; 16], data: &[u8]) -> u64 {
+        xxhash_rust::xxh3::xxh3_64_with_seed(data, fold_key(key))
+    }
+
+    fn hasher(&self, key: &[u8; 16]) -> KeyedHasher {
+        KeyedHasher::Xxh3(xxhash_rust::xxh3::Xxh3::with_seed(fold_key(key)))
+    }
+}
+
+/// FNV-1a 64 bits, with the key folded to 64 bits as initial state.
+#[cfg(feature = "fnv")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Fnv1a;
+
+#[cfg(feature = "fnv")]
+impl IdHasher for Fnv1a {
+    fn hash(&self, key: &[u8; 16], data: &[u8]) -> u64 {
+        let mut hasher = fnv::FnvHasher::with_key(fold_key(key));
+        hasher.write(data);
+        hasher.finish()
+    }
+
+    fn hasher(&self, key: &[u8; 16]) -> KeyedHasher {
+        KeyedHasher::Fnv1a(fnv::FnvHasher::with_key(fold_key(key)))
+    }
+}
+
+#[cfg(any(feature = "xxhash", feature = "fnv"))]
+fn fold_key(key: &[u8; 16]) -> u64 {
+    let (low, high) = key.split_at(8);
+    u64::from_le_bytes(low.try_into().expect("8 bytes"))
+        ^ u64::from_le_bytes(high.try_into().expect("8 bytes"))
+}
+
+pub(crate) fn hash(algorithm: &dyn IdHasher, array: &[u8], key: &[u8; 16]) -> u16 {
+    truncate(algorithm.hash(key, array))
+}
+
+pub(crate) fn hash_value<T: Hash + ?Sized>(
+    algorithm: &dyn IdHasher,
+    value: &T,
+    key: &[u8; 16],
+) -> u16 {
+    let mut hasher = algorithm.hasher(key);
+    value.hash(&mut hasher);
+    truncate(hasher.finish())
+}
+
+/// Number of distinct values of a truncated hash, so the IDs made from a hash
+/// use at most 4096 buckets.
+pub(crate) const TRUNCATED_VALUES: usize = 1 << 12;
+
+pub(crate) fn truncate(hash_64: u64) -> u16 {
     // keep only the last 12 bits
-    const MASK: u64 = 0x0000_0000_0000_0FFF;
+    const MASK: u64 = TRUNCATED_VALUES as u64 - 1;
 
     (hash_64 & MASK) as u16
 }
@@ -14,6 +139,14 @@ pub(crate) fn hash(array: &[u8], key: &[u8; 16]) -> u16 {
 mod tests {
     use super::*;
 
+    fn hash(array: &[u8], key: &[u8; 16]) -> u16 {
+        super::hash(&SipHash24, array, key)
+    }
+
+    fn hash_value<T: Hash + ?Sized>(value: &T, key: &[u8; 16]) -> u16 {
+        super::hash_value(&SipHash24, value, key)
+    }
+
     // Fibonnaci sequence
     // {1, 1, 2, 3, 5, 8, 13, 21, 34, 55, 89, 144, 233, 377, 610, 987}
     // modulo 256
@@ -61,6 +194,52 @@ mod tests {
         assert_ne!(hash(input, &key1), hash(input, &key2));
     }
 
+    #[test]
+    fn test_hash_value() {
+        assert_eq!(
+            hash_value(&42_u64, &TEST_KEY),
+            hash_value(&42_u64, &TEST_KEY)
+        );
+        assert_ne!(
+            hash_value(&42_u64, &TEST_KEY),
+            hash_value(&43_u64, &TEST_KEY)
+        );
+        assert!(hash_value(&(1, "tenant"), &TEST_KEY) <= 0x0FFF);
+    }
+
+    #[test]
+    fn test_siphash_one_shot_matches_hasher() {
+        let mut hasher = SipHash24.hasher(&TEST_KEY);
+        hasher.write(b"Hello, world!");
+        assert_eq!(hasher.finish(), SipHash24.hash(&TEST_KEY, b"Hello, world!"));
+    }
+
+    #[cfg(feature = "xxhash")]
+    #[test]
+    fn test_xxh3() {
+        let mut hasher = Xxh3.hasher(&TEST_KEY);
+        hasher.write(b"Hello, world!");
+        assert_eq!(hasher.finish(), Xxh3.hash(&TEST_KEY, b"Hello, world!"));
+        assert_ne!(
+            Xxh3.hash(&TEST_KEY, b"Hello, world!"),
+            SipHash24.hash(&TEST_KEY, b"Hello, world!")
+        );
+        assert_ne!(
+            Xxh3.hash(&TEST_KEY, b"Hello, world!"),
+            Xxh3.hash(&[0; 16], b"Hello, world!")
+        );
+    }
+
+    #[cfg(feature = "fnv")]
+    #[test]
+    fn test_fnv1a() {
+        // FNV-1a 64 of "a" with the standard offset basis
+        let mut key = [0; 16];
+        key[..8].copy_from_slice(&0xcbf29ce484222325_u64.to_le_bytes());
+        assert_eq!(Fnv1a.hash(&key, b"a"), 0xaf63dc4c8601ec8c);
+        assert_ne!(Fnv1a.hash(&TEST_KEY, b"a"), Fnv1a.hash(&key, b"a"));
+    }
+
     #[test]
     fn test_collisions_should_be_likely_by_design() {
         let good_input = b"Hello, world!";
@@ -0,0 +1,99 @@
+//! Health report of an instance, see [`SINTEFlake::health_check`].
+//!
+//! [`SINTEFlake::health_check`]: crate::sinteflake::SINTEFlake::health_check
+use alloc::string::String;
+
+/// State of the clock of an instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockStatus {
+    /// The clock is after the epoch and in the last time window used, or after it.
+    Ok,
+
+    /// The clock is before the epoch, no ID can be generated.
+    BeforeEpoch,
+
+    /// The clock is in an earlier time window than the last one used.
+    BehindLastWindow,
+
+    /// The clock is after the last time window fitting in the timestamp bits.
+    Overflow,
+}
+
+/// State of the instance ID lease.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaseHealth {
+    /// The instance ID doesn't come from a lease.
+    NotLeased,
+
+    /// The lease is valid.
+    Valid,
+
+    /// The lease has expired, no ID can be generated.
+    Expired,
+}
+
+/// Health of an instance, for a `/healthz` endpoint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HealthReport {
+    pub clock: ClockStatus,
+
+    /// Years left before the timestamps overflow, 0 once they have.
+    pub years_remaining: f64,
+
+    /// IDs generated in the current time window, over the capacity of a window, from 0 to 1.
+    pub window_utilization: f64,
+
+    pub lease: LeaseHealth,
+}
+
+impl HealthReport {
+    /// Returns whether the instance can generate IDs: the clock is usable,
+    /// and the lease, if any, is valid.
+    pub fn is_healthy(&self) -> bool {
+        self.clock == ClockStatus::Ok && self.lease != LeaseHealth::Expired
+    }
+
+    /// Returns the report as a JSON object:
+    /// `{"healthy":true,"clock":"ok","years_remaining":543.8,"window_utilization":0.0,"lease":"not_leased"}`.
+    pub fn to_json(&self) -> String {
+        let clock = match self.clock {
+            ClockStatus::Ok => "ok",
+            ClockStatus::BeforeEpoch => "before_epoch",
+            ClockStatus::BehindLastWindow => "behind_last_window",
+            ClockStatus::Overflow => "overflow",
+        };
+        let lease = match self.lease {
+            LeaseHealth::NotLeased => "not_leased",
+            LeaseHealth::Valid => "valid",
+            LeaseHealth::Expired => "expired",
+        };
+        alloc::format!(
+            "{{\"healthy\":{},\"clock\":\"{}\",\"years_remaining\":{:.1},\"window_utilization\":{},\"lease\":\"{}\"}}",
+            self.is_healthy(),
+            clock,
+            self.years_remaining,
+            self.window_utilization,
+            lease
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json() {
+        let report = HealthReport {
+            clock: ClockStatus::BehindLastWindow,
+            years_remaining: 543.84,
+            window_utilization: 0.25,
+            lease: LeaseHealth::Valid,
+        };
+        assert!(!report.is_healthy());
+        assert_eq!(
+            report.to_json(),
+            "{\"healthy\":false,\"clock\":\"behind_last_window\",\"years_remaining\":543.8,\"window_utilization\":0.25,\"lease\":\"valid\"}"
+        );
+    }
+}
@@ -0,0 +1,17 @@
+use core::fmt::Debug;
+
+/// Callbacks for generation events, to plug in logging or alerting.
+///
+/// All methods do nothing by default. They are called while generating IDs,
+/// so they should return quickly.
+pub trait EventHook: Debug + Send + Sync {
+    /// Called when the instance switches to a new time window.
+    fn on_window_rollover(&self, _instance_id: u16, _window: u64) {}
+
+    /// Called when a bucket is full and the next one is tried.
+    fn on_bucket_fallback(&self, _instance_id: u16, _window: u64, _bucket: u16, _next_bucket: u16) {
+    }
+
+    /// Called when no bucket with free space was found, before returning `CounterOverflow`.
+    fn on_overflow(&self, _instance_id: u16, _window: u64, _bucket: u16) {}
+}
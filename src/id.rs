@@ -0,0 +1,374 @@
+use alloc::string::String;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::error::SINTEFlakeError;
+use crate::layout::Layout;
+
+#[cfg(feature = "diesel")]
+mod diesel_impl;
+#[cfg(feature = "sea-orm")]
+mod sea_orm_impl;
+mod typed;
+
+pub use typed::{Entity, TypedId};
+
+// digits of the encodings, in ASCII order
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+const BASE62_DIGITS: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+// Crockford's base32, without I, L, O and U
+const BASE32_DIGITS: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Length of the longest text encoding of an ID, 16 hexadecimal digits.
+pub const MAX_ENCODED_LEN: usize = 16;
+
+/// Text encodings of the IDs, see [`SINTEFlakeId::format_into`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Lowercase hexadecimal.
+    Hex,
+    /// Base62, with the digits `0-9`, `A-Z` and `a-z`.
+    Base62,
+    /// Crockford's base32, with the digits `0-9` and the uppercase letters but `I`, `L`, `O` and `U`.
+    Base32,
+}
+
+impl Encoding {
+    const fn digits(self) -> &'static [u8] {
+        match self {
+            Encoding::Hex => HEX_DIGITS,
+            Encoding::Base62 => BASE62_DIGITS,
+            Encoding::Base32 => BASE32_DIGITS,
+        }
+    }
+}
+
+/// A SINTEFlake identifier, to tell IDs apart from other integers in APIs and storage.
+///
+/// Besides decimal, IDs can be written in hexadecimal, in base62, shorter for URLs,
+/// and in base32, case-insensitive.
+///
+/// With the `diesel` feature, it can be stored in `BIGINT` columns with Diesel,
+/// for the PostgreSQL and MySQL backends. With the `sea-orm` feature, it can be
+/// the type of `BIGINT` columns and primary keys of SeaORM entities. Converting
+/// an ID above `i64::MAX` to a SeaORM `Value` panics, the generators never make them.
+///
+/// The `rkyv`, `borsh` and `bincode` features implement their serialization traits,
+/// for binary protocols.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(
+    feature = "diesel",
+    derive(diesel::expression::AsExpression, diesel::deserialize::FromSqlRow)
+)]
+#[cfg_attr(feature = "diesel", diesel(sql_type = diesel::sql_types::BigInt))]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    rkyv(derive(Debug, PartialEq, Eq))
+)]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[cfg_attr(feature = "bincode", derive(bincode::Encode, bincode::Decode))]
+pub struct SINTEFlakeId(u64);
+
+impl SINTEFlakeId {
+    /// Wraps an ID.
+    pub const fn new(id: u64) -> Self {
+        SINTEFlakeId(id)
+    }
+
+    /// Returns the ID as an integer.
+    pub const fn get(self) -> u64 {
+        self.0
+    }
+
+    /// Returns the ID as a signed integer, for signed `BIGINT` columns.
+    ///
+    /// # Errors
+    /// Returns `InvalidId` if the ID is above `i64::MAX`, which can't happen
+    /// with the IDs of a generator since the layouts use at most 63 bits.
+    pub const fn to_i64(self) -> Result<i64, SINTEFlakeError> {
+        if self.0 > i64::MAX as u64 {
+            return Err(SINTEFlakeError::InvalidId);
+        }
+        Ok(self.0 as i64)
+    }
+
+    /// Reads an ID stored as a signed integer.
+    ///
+    /// # Errors
+    /// Returns `InvalidId` if the value is negative or doesn't fit in the layout.
+    pub const fn from_i64(value: i64, layout: &Layout) -> Result<Self, SINTEFlakeError> {
+        if value < 0 || value as u64 > layout.max_id() {
+            return Err(SINTEFlakeError::InvalidId);
+        }
+        Ok(SINTEFlakeId(value as u64))
+    }
+
+    /// Writes the ID in an encoding at the start of a buffer, without leading zeros and
+    /// without allocating, and returns the written text.
+    ///
+    /// ```rust
+    /// use sinteflake::id::{Encoding, SINTEFlakeId, MAX_ENCODED_LEN};
+    ///
+    /// let mut buf = [0; MAX_ENCODED_LEN];
+    /// let id = SINTEFlakeId::new(1234567890123);
+    /// assert_eq!(id.format_into(Encoding::Base62, &mut buf), "LjaL3EZ");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if the buffer is too short for the encoded ID, it never is with
+    /// [`MAX_ENCODED_LEN`] bytes.
+    pub fn format_into(self, encoding: Encoding, buf: &mut [u8]) -> &str {
+        let digits = encoding.digits();
+        let radix = digits.len() as u64;
+        let mut encoded = [0; MAX_ENCODED_LEN];
+        let mut start = encoded.len();
+        let mut value = self.0;
+        loop {
+            start -= 1;
+            encoded[start] = digits[(value % radix) as usize];
+            value /= radix;
+            if value == 0 {
+                break;
+            }
+        }
+        let text = &mut buf[..encoded.len() - start];
+        text.copy_from_slice(&encoded[start..]);
+        core::str::from_utf8(text).expect("the digits are ASCII")
+    }
+
+    /// Returns the ID in lowercase hexadecimal, without leading zeros.
+    pub fn to_hex(self) -> String {
+        self.format_into(Encoding::Hex, &mut [0; MAX_ENCODED_LEN])
+            .into()
+    }
+
+    /// Reads an ID written in hexadecimal, in lowercase or uppercase.
+    ///
+    /// # Errors
+    /// Returns `InvalidId` if the text is empty, has other characters, or is above 64 bits.
+    pub fn from_hex(text: &str) -> Result<Self, SINTEFlakeError> {
+        if text.is_empty() || !text.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(SINTEFlakeError::InvalidId);
+        }
+        u64::from_str_radix(text, 16)
+            .map(SINTEFlakeId)
+            .map_err(|_| SINTEFlakeError::InvalidId)
+    }
+
+    /// Returns the ID in base62, with the digits `0-9`, `A-Z` and `a-z`, without leading zeros.
+    pub fn to_base62(self) -> String {
+        self.format_into(Encoding::Base62, &mut [0; MAX_ENCODED_LEN])
+            .into()
+    }
+
+    /// Reads an ID written in base62.
+    ///
+    /// # Errors
+    /// Returns `InvalidId` if the text is empty, has other characters, or is above 64 bits.
+    pub fn from_base62(text: &str) -> Result<Self, SINTEFlakeError> {
+        if text.is_empty() {
+            return Err(SINTEFlakeError::InvalidId);
+        }
+        text.bytes()
+            .try_fold(0_u64, |value, byte| {
+                let digit = match byte {
+                    b'0'..=b'9' => byte - b'0',
+                    b'A'..=b'Z' => byte - b'A' + 10,
+                    b'a'..=b'z' => byte - b'a' + 36,
+                    _ => return None,
+                };
+                value.checked_mul(62)?.checked_add(digit as u64)
+            })
+            .map(SINTEFlakeId)
+            .ok_or(SINTEFlakeError::InvalidId)
+    }
+
+    /// Returns the ID in Crockford's base32, in uppercase, without leading zeros.
+    pub fn to_base32(self) -> String {
+        self.format_into(Encoding::Base32, &mut [0; MAX_ENCODED_LEN])
+            .into()
+    }
+
+    /// Reads an ID written in Crockford's base32, in lowercase or uppercase,
+    /// reading `I` and `L` as `1` and `O` as `0`.
+    ///
+    /// # Errors
+    /// Returns `InvalidId` if the text is empty, has other characters, or is above 64 bits.
+    pub fn from_base32(text: &str) -> Result<Self, SINTEFlakeError> {
+        if text.is_empty() {
+            return Err(SINTEFlakeError::InvalidId);
+        }
+        text.bytes()
+            .try_fold(0_u64, |value, byte| {
+                let digit = match byte.to_ascii_uppercase() {
+                    b'I' | b'L' => 1,
+                    b'O' => 0,
+                    byte => BASE32_DIGITS.iter().position(|&digit| digit == byte)? as u64,
+                };
+                value.checked_mul(32)?.checked_add(digit)
+            })
+            .map(SINTEFlakeId)
+            .ok_or(SINTEFlakeError::InvalidId)
+    }
+}
+
+impl From<u64> for SINTEFlakeId {
+    fn from(id: u64) -> Self {
+        SINTEFlakeId(id)
+    }
+}
+
+impl From<SINTEFlakeId> for u64 {
+    fn from(id: SINTEFlakeId) -> Self {
+        id.0
+    }
+}
+
+impl fmt::Display for SINTEFlakeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for SINTEFlakeId {
+    type Err = core::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(SINTEFlakeId)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        let id = SINTEFlakeId::new(1234567890123);
+        assert_eq!(id.get(), 1234567890123);
+        assert_eq!(u64::from(id), 1234567890123);
+        assert_eq!(SINTEFlakeId::from(1234567890123), id);
+        assert_eq!(id.to_string(), "1234567890123");
+        assert_eq!("1234567890123".parse::<SINTEFlakeId>().unwrap(), id);
+        assert!("-1".parse::<SINTEFlakeId>().is_err());
+    }
+
+    #[test]
+    fn test_text_encodings() {
+        let id = SINTEFlakeId::new(1234567890123);
+        assert_eq!(id.to_hex(), "11f71fb04cb");
+        assert_eq!(SINTEFlakeId::from_hex("11F71FB04CB").unwrap(), id);
+        assert_eq!(id.to_base62(), "LjaL3EZ");
+        assert_eq!(SINTEFlakeId::from_base62("LjaL3EZ").unwrap(), id);
+
+        for id in [0, 61, 62, u64::MAX] {
+            let id = SINTEFlakeId::new(id);
+            assert_eq!(SINTEFlakeId::from_hex(&id.to_hex()).unwrap(), id);
+            assert_eq!(SINTEFlakeId::from_base62(&id.to_base62()).unwrap(), id);
+        }
+        assert_eq!(SINTEFlakeId::new(0).to_base62(), "0");
+        assert_eq!(SINTEFlakeId::new(u64::MAX).to_base62(), "LygHa16AHYF");
+
+        for text in ["", "+1", "12g", "-1", "10000000000000000"] {
+            assert!(SINTEFlakeId::from_hex(text).is_err(), "{}", text);
+        }
+        for text in ["", "+1", "a-b", "LygHa16AHYG"] {
+            assert!(SINTEFlakeId::from_base62(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_base32() {
+        let id = SINTEFlakeId::new(1234567890123);
+        assert_eq!(id.to_base32(), "13XRZP16B");
+        assert_eq!(SINTEFlakeId::from_base32("13xrzp16b").unwrap(), id);
+        assert_eq!(SINTEFlakeId::from_base32("i3XRZP16B").unwrap(), id);
+        assert_eq!(SINTEFlakeId::new(u64::MAX).to_base32(), "FZZZZZZZZZZZZ");
+        assert_eq!(SINTEFlakeId::from_base32("O").unwrap().get(), 0);
+
+        for text in ["", "U", "1-2", "G000000000000"] {
+            assert!(SINTEFlakeId::from_base32(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_format_into() {
+        let mut buf = [0; MAX_ENCODED_LEN];
+        for id in [0, 61, 1234567890123, u64::MAX] {
+            let id = SINTEFlakeId::new(id);
+            assert_eq!(id.format_into(Encoding::Hex, &mut buf), id.to_hex());
+            assert_eq!(id.format_into(Encoding::Base62, &mut buf), id.to_base62());
+            assert_eq!(id.format_into(Encoding::Base32, &mut buf), id.to_base32());
+        }
+        assert_eq!(
+            SINTEFlakeId::new(u64::MAX).format_into(Encoding::Hex, &mut buf),
+            "ffffffffffffffff"
+        );
+
+        // a buffer just long enough
+        let mut short = [0; 2];
+        assert_eq!(
+            SINTEFlakeId::new(255).format_into(Encoding::Hex, &mut short),
+            "ff"
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_format_into_short_buffer() {
+        SINTEFlakeId::new(256).format_into(Encoding::Hex, &mut [0; 2]);
+    }
+
+    #[cfg(feature = "rkyv")]
+    #[test]
+    fn test_rkyv() {
+        let id = SINTEFlakeId::new(1234567890123);
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&id).unwrap();
+        let archived = rkyv::access::<ArchivedSINTEFlakeId, rkyv::rancor::Error>(&bytes).unwrap();
+        assert_eq!(archived.0, 1234567890123);
+        let deserialized = rkyv::from_bytes::<SINTEFlakeId, rkyv::rancor::Error>(&bytes).unwrap();
+        assert_eq!(deserialized, id);
+    }
+
+    #[cfg(feature = "borsh")]
+    #[test]
+    fn test_borsh() {
+        let id = SINTEFlakeId::new(1234567890123);
+        let bytes = borsh::to_vec(&id).unwrap();
+        assert_eq!(bytes, 1234567890123_u64.to_le_bytes());
+        assert_eq!(borsh::from_slice::<SINTEFlakeId>(&bytes).unwrap(), id);
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_bincode() {
+        let id = SINTEFlakeId::new(1234567890123);
+        let config = bincode::config::standard();
+        let bytes = bincode::encode_to_vec(id, config).unwrap();
+        let (decoded, _) = bincode::decode_from_slice::<SINTEFlakeId, _>(&bytes, config).unwrap();
+        assert_eq!(decoded, id);
+    }
+
+    #[test]
+    fn test_i64_conversions() {
+        let id = SINTEFlakeId::new(i64::MAX as u64);
+        assert_eq!(id.to_i64().unwrap(), i64::MAX);
+        assert_eq!(
+            SINTEFlakeId::from_i64(i64::MAX, &Layout::DEFAULT).unwrap(),
+            id
+        );
+        assert!(SINTEFlakeId::new(i64::MAX as u64 + 1).to_i64().is_err());
+
+        assert!(matches!(
+            SINTEFlakeId::from_i64(-1, &Layout::DEFAULT),
+            Err(SINTEFlakeError::InvalidId)
+        ));
+        let max = Layout::JAVASCRIPT_SAFE.max_id() as i64;
+        assert!(SINTEFlakeId::from_i64(max, &Layout::JAVASCRIPT_SAFE).is_ok());
+        assert!(SINTEFlakeId::from_i64(max + 1, &Layout::JAVASCRIPT_SAFE).is_err());
+    }
+}
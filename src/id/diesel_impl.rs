@@ -0,0 +1,44 @@
+use diesel::backend::Backend;
+use diesel::deserialize::{self, FromSql};
+use diesel::mysql::Mysql;
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::BigInt;
+
+use super::SINTEFlakeId;
+
+impl<DB> FromSql<BigInt, DB> for SINTEFlakeId
+where
+    DB: Backend,
+    i64: FromSql<BigInt, DB>,
+{
+    fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Self> {
+        let id = i64::from_sql(bytes)?;
+        Ok(SINTEFlakeId(u64::try_from(id)?))
+    }
+}
+
+impl ToSql<BigInt, Pg> for SINTEFlakeId {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <i64 as ToSql<BigInt, Pg>>::to_sql(&self.to_i64()?, &mut out.reborrow())
+    }
+}
+
+impl ToSql<BigInt, Mysql> for SINTEFlakeId {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Mysql>) -> serialize::Result {
+        <i64 as ToSql<BigInt, Mysql>>::to_sql(&self.to_i64()?, &mut out.reborrow())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::IntoSql;
+
+    #[test]
+    fn test_diesel_expression() {
+        let query = diesel::select(SINTEFlakeId::new(42).into_sql::<BigInt>());
+        let sql = diesel::debug_query::<Pg, _>(&query).to_string();
+        assert_eq!(sql, "SELECT $1 -- binds: [SINTEFlakeId(42)]");
+    }
+}
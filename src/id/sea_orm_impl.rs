@@ -0,0 +1,85 @@
+use sea_orm::sea_query::{ArrayType, ColumnType, Nullable, Value, ValueType, ValueTypeErr};
+use sea_orm::{ColIdx, DbErr, QueryResult, TryFromU64, TryGetError, TryGetable};
+
+use super::SINTEFlakeId;
+
+// SeaORM needs an infallible conversion, the fallible ones check the IDs instead
+impl From<SINTEFlakeId> for Value {
+    fn from(id: SINTEFlakeId) -> Self {
+        match id.to_i64() {
+            Ok(id) => Value::BigInt(Some(id)),
+            Err(_) => panic!("ID {} doesn't fit in a BIGINT column", id.0),
+        }
+    }
+}
+
+impl Nullable for SINTEFlakeId {
+    fn null() -> Value {
+        Value::BigInt(None)
+    }
+}
+
+impl ValueType for SINTEFlakeId {
+    fn try_from(value: Value) -> Result<Self, ValueTypeErr> {
+        match value {
+            Value::BigInt(Some(id)) => <u64 as TryFrom<i64>>::try_from(id)
+                .map(SINTEFlakeId)
+                .map_err(|_| ValueTypeErr),
+            _ => Err(ValueTypeErr),
+        }
+    }
+
+    fn type_name() -> String {
+        "SINTEFlakeId".to_owned()
+    }
+
+    fn array_type() -> ArrayType {
+        ArrayType::BigInt
+    }
+
+    fn column_type() -> ColumnType {
+        ColumnType::BigInteger
+    }
+}
+
+impl TryGetable for SINTEFlakeId {
+    fn try_get_by<I: ColIdx>(result: &QueryResult, index: I) -> Result<Self, TryGetError> {
+        let id = i64::try_get_by(result, index)?;
+        <u64 as TryFrom<i64>>::try_from(id)
+            .map(SINTEFlakeId)
+            .map_err(|error| TryGetError::DbErr(DbErr::Type(error.to_string())))
+    }
+}
+
+impl TryFromU64 for SINTEFlakeId {
+    fn try_from_u64(id: u64) -> Result<Self, DbErr> {
+        let id = SINTEFlakeId(id);
+        id.to_i64()
+            .map(|_| id)
+            .map_err(|error| DbErr::Type(error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sea_orm_value() {
+        let id = SINTEFlakeId::new(42);
+        let value: Value = id.into();
+        assert_eq!(value, Value::BigInt(Some(42)));
+        assert_eq!(<SINTEFlakeId as ValueType>::try_from(value).unwrap(), id);
+        assert!(<SINTEFlakeId as ValueType>::try_from(Value::BigInt(Some(-1))).is_err());
+        assert!(<SINTEFlakeId as ValueType>::try_from(SINTEFlakeId::null()).is_err());
+        assert_eq!(SINTEFlakeId::column_type(), ColumnType::BigInteger);
+        assert_eq!(SINTEFlakeId::try_from_u64(42).unwrap(), id);
+        assert!(SINTEFlakeId::try_from_u64(u64::MAX).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't fit in a BIGINT column")]
+    fn test_sea_orm_value_out_of_range() {
+        let _: Value = SINTEFlakeId::new(u64::MAX).into();
+    }
+}
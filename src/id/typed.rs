@@ -0,0 +1,225 @@
+use alloc::string::String;
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::str::FromStr;
+
+use super::{Encoding, SINTEFlakeId, MAX_ENCODED_LEN};
+use crate::error::SINTEFlakeError;
+
+/// Marker trait of the entities identified by a [`TypedId`].
+///
+/// ```rust
+/// use sinteflake::id::{Entity, TypedId};
+///
+/// enum User {}
+/// impl Entity for User {
+///     const PREFIX: &'static str = "user";
+/// }
+///
+/// type UserId = TypedId<User>;
+/// let id = UserId::new(1234567890123);
+/// assert_eq!(id.to_string(), "user_LjaL3EZ");
+/// assert_eq!("user_LjaL3EZ".parse::<UserId>()?, id);
+/// # Ok::<(), sinteflake::error::SINTEFlakeError>(())
+/// ```
+pub trait Entity {
+    /// Prefix of the IDs in text, before an underscore, like `user` for `user_LjaL3EZ`.
+    const PREFIX: &'static str;
+}
+
+/// An ID of an entity type, so the IDs of different entities can't be mixed up.
+///
+/// The IDs are written as the prefix of the entity, an underscore and the ID in base62.
+/// With the `serde` feature, they are serialized as this text in human-readable formats,
+/// and as an integer in the other formats.
+pub struct TypedId<E: Entity> {
+    id: u64,
+    entity: PhantomData<fn() -> E>,
+}
+
+impl<E: Entity> TypedId<E> {
+    /// Wraps an ID.
+    pub const fn new(id: u64) -> Self {
+        TypedId {
+            id,
+            entity: PhantomData,
+        }
+    }
+
+    /// Returns the ID as an integer.
+    pub const fn get(self) -> u64 {
+        self.id
+    }
+
+    /// Returns the untyped ID.
+    pub const fn untyped(self) -> SINTEFlakeId {
+        SINTEFlakeId::new(self.id)
+    }
+}
+
+// implemented by hand, as the derives would require E to implement the traits too
+impl<E: Entity> Clone for TypedId<E> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<E: Entity> Copy for TypedId<E> {}
+
+impl<E: Entity> PartialEq for TypedId<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<E: Entity> Eq for TypedId<E> {}
+
+impl<E: Entity> PartialOrd for TypedId<E> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<E: Entity> Ord for TypedId<E> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.id.cmp(&other.id)
+    }
+}
+
+impl<E: Entity> Hash for TypedId<E> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<E: Entity> fmt::Debug for TypedId<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TypedId({})", self)
+    }
+}
+
+impl<E: Entity> fmt::Display for TypedId<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buf = [0; MAX_ENCODED_LEN];
+        let id = self.untyped().format_into(Encoding::Base62, &mut buf);
+        write!(f, "{}_{}", E::PREFIX, id)
+    }
+}
+
+impl<E: Entity> FromStr for TypedId<E> {
+    type Err = SINTEFlakeError;
+
+    /// Reads an ID written with the prefix of the entity.
+    ///
+    /// # Errors
+    /// Returns `InvalidId` if the prefix is missing or of another entity, or if the ID
+    /// is not valid base62.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let id = s
+            .strip_prefix(E::PREFIX)
+            .and_then(|rest| rest.strip_prefix('_'))
+            .ok_or(SINTEFlakeError::InvalidId)?;
+        Ok(TypedId::new(SINTEFlakeId::from_base62(id)?.get()))
+    }
+}
+
+impl<E: Entity> From<TypedId<E>> for u64 {
+    fn from(id: TypedId<E>) -> Self {
+        id.id
+    }
+}
+
+impl<E: Entity> From<TypedId<E>> for String {
+    fn from(id: TypedId<E>) -> Self {
+        alloc::string::ToString::to_string(&id)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<E: Entity> serde::Serialize for TypedId<E> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_u64(self.id)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, E: Entity> serde::Deserialize<'de> for TypedId<E> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor<E>(PhantomData<fn() -> E>);
+
+        impl<E: Entity> serde::de::Visitor<'_> for Visitor<E> {
+            type Value = TypedId<E>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "an ID like {}_LjaL3EZ", E::PREFIX)
+            }
+
+            fn visit_str<Err: serde::de::Error>(self, value: &str) -> Result<Self::Value, Err> {
+                value.parse().map_err(Err::custom)
+            }
+
+            fn visit_u64<Err: serde::de::Error>(self, value: u64) -> Result<Self::Value, Err> {
+                Ok(TypedId::new(value))
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(Visitor(PhantomData))
+        } else {
+            deserializer.deserialize_u64(Visitor(PhantomData))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    enum User {}
+    impl Entity for User {
+        const PREFIX: &'static str = "user";
+    }
+
+    enum Order {}
+    impl Entity for Order {
+        const PREFIX: &'static str = "order";
+    }
+
+    #[test]
+    fn test_typed_id_text() {
+        let id = TypedId::<User>::new(1234567890123);
+        assert_eq!(id.to_string(), "user_LjaL3EZ");
+        assert_eq!(format!("{:?}", id), "TypedId(user_LjaL3EZ)");
+        assert_eq!("user_LjaL3EZ".parse::<TypedId<User>>().unwrap(), id);
+        assert_eq!(u64::from(id), 1234567890123);
+
+        for text in [
+            "order_LjaL3EZ",
+            "LjaL3EZ",
+            "userLjaL3EZ",
+            "user_",
+            "user_-1",
+        ] {
+            assert!(text.parse::<TypedId<User>>().is_err(), "{}", text);
+        }
+        assert!("order_LjaL3EZ".parse::<TypedId<Order>>().is_ok());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_typed_id_serde() {
+        let id = TypedId::<Order>::new(1234567890123);
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(json, "\"order_LjaL3EZ\"");
+        assert_eq!(serde_json::from_str::<TypedId<Order>>(&json).unwrap(), id);
+        assert!(serde_json::from_str::<TypedId<User>>(&json).is_err());
+        assert!(serde_json::from_str::<TypedId<Order>>("1234567890123").is_err());
+    }
+}
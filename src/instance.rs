@@ -0,0 +1,194 @@
+//! Helpers to assign instance IDs without manual configuration.
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+
+use crate::error::SINTEFlakeError;
+use crate::layout::Layout;
+
+/// Where to derive an instance ID from, using the low bits of a network identity like Sonyflake.
+///
+/// Hosts of the same private network usually have distinct low IP bits, but it's not
+/// guaranteed: check the addresses of the fleet before relying on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstanceIdSource {
+    /// The private IPv4 address used for outgoing traffic.
+    PrivateIp,
+
+    /// The MAC address of the first network interface that has one, on Linux.
+    MacAddress,
+}
+
+impl InstanceIdSource {
+    /// Derives an instance ID of `bits` bits.
+    ///
+    /// # Errors
+    /// Returns an error if the network identity can't be found.
+    pub fn instance_id(&self, bits: u8) -> Result<u16, SINTEFlakeError> {
+        match self {
+            InstanceIdSource::PrivateIp => {
+                let ip = private_ipv4().ok_or(SINTEFlakeError::InstanceIdUnavailable)?;
+                Ok(instance_id_from_ip(IpAddr::V4(ip), bits))
+            }
+            InstanceIdSource::MacAddress => {
+                let mac = mac_address().ok_or(SINTEFlakeError::InstanceIdUnavailable)?;
+                Ok(instance_id_from_mac(mac, bits))
+            }
+        }
+    }
+}
+
+/// Returns the pod ordinal of a Kubernetes StatefulSet, from the `HOSTNAME` environment variable.
+///
+/// StatefulSet pods are named `<statefulset>-<ordinal>`, for example `myapp-7`.
+///
+/// # Errors
+/// Returns an error if the variable is missing or doesn't end with an ordinal,
+/// or if the ordinal doesn't fit in the 10 bits of the default layout.
+pub fn instance_id_from_env() -> Result<u16, SINTEFlakeError> {
+    instance_id_from_env_var("HOSTNAME")
+}
+
+/// Returns the instance ID from an environment variable, either a number or a name
+/// ending with `-<number>` like a StatefulSet pod name.
+///
+/// # Errors
+/// Returns an error if the variable is missing or doesn't end with a number,
+/// or if the number doesn't fit in the 10 bits of the default layout.
+pub fn instance_id_from_env_var(name: &str) -> Result<u16, SINTEFlakeError> {
+    let value = std::env::var(name).map_err(|_| SINTEFlakeError::InstanceIdUnavailable)?;
+    let ordinal = parse_ordinal(&value).ok_or(SINTEFlakeError::InstanceIdUnavailable)?;
+
+    let max = Layout::DEFAULT.max_instance_id();
+    if ordinal > max as u64 {
+        return Err(SINTEFlakeError::InstanceIDTooHigh {
+            given: ordinal.min(u16::MAX as u64) as u16,
+            max,
+        });
+    }
+    Ok(ordinal as u16)
+}
+
+fn parse_ordinal(value: &str) -> Option<u64> {
+    let digits = value.trim().rsplit('-').next()?;
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Keeps the `bits` lowest bits of an IP address.
+pub fn instance_id_from_ip(ip: IpAddr, bits: u8) -> u16 {
+    let low = match ip {
+        IpAddr::V4(ip) => u16::from_be_bytes([ip.octets()[2], ip.octets()[3]]),
+        IpAddr::V6(ip) => u16::from_be_bytes([ip.octets()[14], ip.octets()[15]]),
+    };
+    low & mask(bits)
+}
+
+/// Keeps the `bits` lowest bits of a MAC address.
+pub fn instance_id_from_mac(mac: [u8; 6], bits: u8) -> u16 {
+    u16::from_be_bytes([mac[4], mac[5]]) & mask(bits)
+}
+
+fn mask(bits: u8) -> u16 {
+    ((1u32 << bits.min(16)) - 1) as u16
+}
+
+/// Returns the IPv4 address used to reach a private network, if it's private.
+fn private_ipv4() -> Option<Ipv4Addr> {
+    // connecting a UDP socket sends nothing, it only selects the local address
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((Ipv4Addr::new(10, 255, 255, 255), 1)).ok()?;
+    match socket.local_addr().ok()?.ip() {
+        IpAddr::V4(ip) if ip.is_private() => Some(ip),
+        _ => None,
+    }
+}
+
+/// Returns the first non-zero MAC address found in `/sys/class/net`.
+fn mac_address() -> Option<[u8; 6]> {
+    let mut interfaces: Vec<_> = std::fs::read_dir("/sys/class/net")
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect();
+    interfaces.sort();
+    interfaces.iter().find_map(|interface| {
+        let address = std::fs::read_to_string(interface.join("address")).ok()?;
+        parse_mac(address.trim()).filter(|mac| mac.iter().any(|&byte| byte != 0))
+    })
+}
+
+fn parse_mac(text: &str) -> Option<[u8; 6]> {
+    let mut mac = [0; 6];
+    let mut parts = text.split(':');
+    for byte in mac.iter_mut() {
+        *byte = u8::from_str_radix(parts.next()?, 16).ok()?;
+    }
+    parts.next().is_none().then_some(mac)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instance_id_from_ip() {
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 3, 42));
+        assert_eq!(instance_id_from_ip(ip, 10), 0x32A);
+        assert_eq!(instance_id_from_ip(ip, 16), 0x032A);
+        let ip: IpAddr = "fd00::1:2a".parse().unwrap();
+        assert_eq!(instance_id_from_ip(ip, 10), 0x2A);
+    }
+
+    #[test]
+    fn test_instance_id_from_mac() {
+        let mac = parse_mac("02:42:ac:11:00:02").unwrap();
+        assert_eq!(mac, [0x02, 0x42, 0xac, 0x11, 0x00, 0x02]);
+        assert_eq!(instance_id_from_mac(mac, 10), 2);
+        assert_eq!(parse_mac("02:42:ac:11:00"), None);
+        assert_eq!(parse_mac("02:42:ac:11:00:02:03"), None);
+    }
+
+    #[test]
+    fn test_parse_ordinal() {
+        assert_eq!(parse_ordinal("myapp-7"), Some(7));
+        assert_eq!(parse_ordinal("my-app-1023"), Some(1023));
+        assert_eq!(parse_ordinal("42"), Some(42));
+        assert_eq!(parse_ordinal("myapp"), None);
+        assert_eq!(parse_ordinal("myapp-"), None);
+        assert_eq!(parse_ordinal("myapp-7a"), None);
+        assert_eq!(parse_ordinal("myapp-+7"), None);
+    }
+
+    #[test]
+    fn test_instance_id_from_env_var() {
+        std::env::set_var("SINTEFLAKE_TEST_ORDINAL", "myapp-12");
+        assert_eq!(
+            instance_id_from_env_var("SINTEFLAKE_TEST_ORDINAL").unwrap(),
+            12
+        );
+
+        std::env::set_var("SINTEFLAKE_TEST_ORDINAL", "myapp-1024");
+        assert!(matches!(
+            instance_id_from_env_var("SINTEFLAKE_TEST_ORDINAL"),
+            Err(SINTEFlakeError::InstanceIDTooHigh {
+                given: 1024,
+                max: 1023
+            })
+        ));
+
+        std::env::set_var("SINTEFLAKE_TEST_ORDINAL", "myapp-99999999");
+        assert!(matches!(
+            instance_id_from_env_var("SINTEFLAKE_TEST_ORDINAL"),
+            Err(SINTEFlakeError::InstanceIDTooHigh {
+                given: u16::MAX,
+                ..
+            })
+        ));
+
+        assert!(matches!(
+            instance_id_from_env_var("SINTEFLAKE_TEST_MISSING"),
+            Err(SINTEFlakeError::InstanceIdUnavailable)
+        ));
+    }
+}
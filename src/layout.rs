@@ -0,0 +1,589 @@
+use crate::error::SINTEFlakeError;
+
+/// Bit layout of a SINTEFlake identifier.
+///
+/// From the most significant to the least significant bits, an identifier is made of
+/// a hash, a timestamp, an instance identifier and a sequence number. The widths of
+/// the fields decide the capacity of a generator:
+///
+/// - `2^hash_bits` buckets of `2^sequence_bits` identifiers can be generated per time window.
+/// - `2^timestamp_bits` time windows can be used after the epoch before overflowing.
+/// - `2^instance_bits` instances can generate identifiers concurrently.
+///
+/// A [versioned](Layout::versioned) layout also has [`VERSION_BITS`] bits above the hash,
+/// holding the version of the layout, so decoders can tell the layouts of a deployment apart.
+///
+/// A [time-sorted](Layout::time_sorted) layout puts the timestamp, not permuted, above
+/// the hash, so the identifiers are roughly ordered by creation time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Layout {
+    hash_bits: u8,
+    timestamp_bits: u8,
+    instance_bits: u8,
+    sequence_bits: u8,
+    // VERSION_BITS in a versioned layout, 0 otherwise
+    version_bits: u8,
+    version: u8,
+    time_sorted: bool,
+}
+
+/// Width of the version field of the versioned layouts, for 4 versions.
+pub const VERSION_BITS: u8 = 2;
+
+impl Layout {
+    /// The default layout, using 63 bits so identifiers are positive signed 64-bit integers.
+    ///
+    /// - 14 bits for a hash or a random number
+    /// - 31 bits for a timestamp, about 544 years with 8-second windows
+    /// - 10 bits for an instance identifier
+    /// - 8 bits for a sequence number
+    pub const DEFAULT: Layout = Layout {
+        hash_bits: 14,
+        timestamp_bits: 31,
+        instance_bits: 10,
+        sequence_bits: 8,
+        version_bits: 0,
+        version: 0,
+        time_sorted: false,
+    };
+
+    /// A layout using 53 bits, so identifiers can be represented exactly
+    /// by a JavaScript `Number` and survive JSON round-trips.
+    ///
+    /// - 7 bits for a hash or a random number
+    /// - 28 bits for a timestamp, about 68 years with 8-second windows
+    /// - 10 bits for an instance identifier
+    /// - 8 bits for a sequence number
+    ///
+    /// Compared to [`Layout::DEFAULT`], an instance can only generate 32768 identifiers
+    /// per time window instead of about 4 millions, and the identifiers overflow 68 years
+    /// after the epoch.
+    pub const JAVASCRIPT_SAFE: Layout = Layout {
+        hash_bits: 7,
+        timestamp_bits: 28,
+        instance_bits: 10,
+        sequence_bits: 8,
+        version_bits: 0,
+        version: 0,
+        time_sorted: false,
+    };
+
+    /// A [time-sorted](Layout::time_sorted) layout with millisecond timestamps,
+    /// for a rough time ordering of the IDs, to use with 1 millisecond time windows.
+    ///
+    /// - 41 bits for a timestamp, about 69 years with 1 millisecond windows
+    /// - 4 bits for a hash or a random number
+    /// - 10 bits for an instance identifier
+    /// - 8 bits for a sequence number
+    ///
+    /// An instance can generate 4096 identifiers per millisecond, like Snowflake,
+    /// but the hash has only 16 possible values.
+    pub const MILLISECONDS: Layout = Layout {
+        hash_bits: 4,
+        timestamp_bits: 41,
+        instance_bits: 10,
+        sequence_bits: 8,
+        version_bits: 0,
+        version: 0,
+        time_sorted: true,
+    };
+
+    /// Creates a custom layout.
+    ///
+    /// # Arguments
+    /// * `hash_bits` - Width of the hash field, between 1 and 16 bits.
+    /// * `timestamp_bits` - Width of the timestamp field, between 1 and 48 bits.
+    /// * `instance_bits` - Width of the instance identifier field, at most 16 bits.
+    /// * `sequence_bits` - Width of the sequence number field, between 1 and 8 bits.
+    ///
+    /// # Returns
+    /// - `Result<Self, SINTEFlakeError>`: The layout, or an error if the widths are invalid.
+    ///
+    /// # Errors
+    /// Returns an error if a width is out of its range or if the layout uses more than 63 bits.
+    pub const fn new(
+        hash_bits: u8,
+        timestamp_bits: u8,
+        instance_bits: u8,
+        sequence_bits: u8,
+    ) -> Result<Self, SINTEFlakeError> {
+        if hash_bits == 0 || hash_bits > 16 {
+            return Err(SINTEFlakeError::InvalidLayout);
+        }
+        if timestamp_bits == 0 || timestamp_bits > 48 {
+            return Err(SINTEFlakeError::InvalidLayout);
+        }
+        if instance_bits > 16 {
+            return Err(SINTEFlakeError::InvalidLayout);
+        }
+        if sequence_bits == 0 || sequence_bits > 8 {
+            return Err(SINTEFlakeError::InvalidLayout);
+        }
+        if hash_bits + timestamp_bits + instance_bits + sequence_bits > 63 {
+            return Err(SINTEFlakeError::InvalidLayout);
+        }
+        Ok(Layout {
+            hash_bits,
+            timestamp_bits,
+            instance_bits,
+            sequence_bits,
+            version_bits: 0,
+            version: 0,
+            time_sorted: false,
+        })
+    }
+
+    /// Returns the layout with its version in [`VERSION_BITS`] bits above the hash.
+    ///
+    /// The format of the IDs can then evolve, for example to millisecond windows, with
+    /// the layouts of a deployment using different versions, and [`crate::decode::decode_versioned`]
+    /// picks the layout of an ID from its version bits. The layouts of a deployment should
+    /// use the same total width, so the version bits are at the same position.
+    ///
+    /// ```rust
+    /// use sinteflake::layout::Layout;
+    ///
+    /// let v0 = Layout::new(14, 30, 9, 8)?.versioned(0)?;
+    /// let v1 = Layout::new(4, 40, 9, 8)?.versioned(1)?;
+    /// assert_eq!(v0.total_bits(), 63);
+    /// let id = v1.construct_identifier(1, 2, 3, 4);
+    /// assert_eq!(v0.read_version(id), 1);
+    /// # Ok::<(), sinteflake::error::SINTEFlakeError>(())
+    /// ```
+    ///
+    /// # Errors
+    /// Returns `InvalidLayout` if the version doesn't fit in the version bits, if the layout
+    /// is already versioned, or if it would use more than 63 bits.
+    pub const fn versioned(self, version: u8) -> Result<Self, SINTEFlakeError> {
+        if version >= 1 << VERSION_BITS || self.version_bits != 0 {
+            return Err(SINTEFlakeError::InvalidLayout);
+        }
+        if self.total_bits() + VERSION_BITS > 63 {
+            return Err(SINTEFlakeError::InvalidLayout);
+        }
+        Ok(Layout {
+            version_bits: VERSION_BITS,
+            version,
+            ..self
+        })
+    }
+
+    /// Returns the layout with the timestamp in the most significant bits, above the hash,
+    /// and without the permutation of its bits.
+    ///
+    /// The identifiers are then roughly sorted by creation time, like Snowflake IDs,
+    /// with the time window as resolution, while the hashes still spread the identifiers
+    /// of a window into buckets to detect collisions. The version bits of a versioned
+    /// layout stay above the timestamp.
+    ///
+    /// ```rust
+    /// use sinteflake::layout::Layout;
+    ///
+    /// let layout = Layout::DEFAULT.time_sorted();
+    /// assert!(layout.construct_identifier(9, 1, 0, 0) < layout.construct_identifier(0, 2, 0, 0));
+    /// ```
+    pub const fn time_sorted(self) -> Self {
+        Layout {
+            time_sorted: true,
+            ..self
+        }
+    }
+
+    /// Returns true if the timestamp is the most significant field, see [`Layout::time_sorted`].
+    pub const fn is_time_sorted(&self) -> bool {
+        self.time_sorted
+    }
+
+    /// Returns the version of a versioned layout.
+    pub const fn version(&self) -> Option<u8> {
+        if self.version_bits == 0 {
+            None
+        } else {
+            Some(self.version)
+        }
+    }
+
+    /// Reads the version bits of an identifier, at the position of the version field
+    /// of this layout, or 0 if the layout is not versioned.
+    pub const fn read_version(&self, id: u64) -> u8 {
+        ((id >> self.version_shift()) & ((1 << self.version_bits) - 1)) as u8
+    }
+
+    pub const fn hash_bits(&self) -> u8 {
+        self.hash_bits
+    }
+
+    pub const fn timestamp_bits(&self) -> u8 {
+        self.timestamp_bits
+    }
+
+    pub const fn instance_bits(&self) -> u8 {
+        self.instance_bits
+    }
+
+    pub const fn sequence_bits(&self) -> u8 {
+        self.sequence_bits
+    }
+
+    /// Total number of bits used by the identifiers, with the version bits.
+    pub const fn total_bits(&self) -> u8 {
+        self.version_shift() + self.version_bits
+    }
+
+    /// Largest identifier that can be generated with this layout.
+    pub const fn max_id(&self) -> u64 {
+        (1 << self.total_bits()) - 1
+    }
+
+    /// Number of hash buckets per time window.
+    pub const fn buckets(&self) -> usize {
+        1 << self.hash_bits
+    }
+
+    /// Number of identifiers a hash bucket can hold per time window.
+    pub const fn bucket_capacity(&self) -> u16 {
+        1 << self.sequence_bits
+    }
+
+    /// Number of identifiers an instance can generate per time window.
+    pub const fn window_capacity(&self) -> u64 {
+        (self.buckets() as u64) * (self.bucket_capacity() as u64)
+    }
+
+    /// Largest timestamp, in time windows since the epoch.
+    pub const fn max_timestamp(&self) -> u64 {
+        (1 << self.timestamp_bits) - 1
+    }
+
+    /// Largest instance identifier.
+    pub const fn max_instance_id(&self) -> u16 {
+        ((1u32 << self.instance_bits) - 1) as u16
+    }
+
+    /// Offset of the version field, the width of the other fields.
+    pub const fn version_shift(&self) -> u8 {
+        if self.time_sorted {
+            self.timestamp_shift() + self.timestamp_bits
+        } else {
+            self.hash_shift() + self.hash_bits
+        }
+    }
+
+    /// Offset of the hash field, the width of the fields below it.
+    pub const fn hash_shift(&self) -> u8 {
+        if self.time_sorted {
+            self.instance_shift() + self.instance_bits
+        } else {
+            self.timestamp_shift() + self.timestamp_bits
+        }
+    }
+
+    /// Offset of the timestamp field.
+    pub const fn timestamp_shift(&self) -> u8 {
+        if self.time_sorted {
+            self.hash_shift() + self.hash_bits
+        } else {
+            self.instance_shift() + self.instance_bits
+        }
+    }
+
+    /// Offset of the instance identifier field, the width of the sequence number.
+    pub const fn instance_shift(&self) -> u8 {
+        self.sequence_bits
+    }
+
+    /// Constructs a 64-bit identifier from the given components.
+    ///
+    /// Like [`crate::bits::construct_identifier`], the components are truncated
+    /// to the width of their field. The version of a versioned layout is added.
+    pub const fn construct_identifier(
+        &self,
+        hash: u16,
+        timestamp: u64,
+        instance_id: u16,
+        sequence: u8,
+    ) -> u64 {
+        let hash = (hash as u64) & ((1 << self.hash_bits) - 1);
+        let timestamp = timestamp & ((1 << self.timestamp_bits) - 1);
+        let instance_id = (instance_id as u64) & ((1 << self.instance_bits) - 1);
+        let sequence = (sequence as u64) & ((1 << self.sequence_bits) - 1);
+
+        ((self.version as u64) << self.version_shift())
+            | (hash << self.hash_shift())
+            | (timestamp << self.timestamp_shift())
+            | (instance_id << self.instance_shift())
+            | sequence
+    }
+
+    /// Splits an identifier into its hash, timestamp, instance identifier and sequence number.
+    ///
+    /// The version bits and the bits above the total width of the layout are ignored.
+    pub const fn deconstruct_identifier(&self, id: u64) -> (u16, u64, u16, u8) {
+        let hash = (id >> self.hash_shift()) & ((1 << self.hash_bits) - 1);
+        let timestamp = (id >> self.timestamp_shift()) & ((1 << self.timestamp_bits) - 1);
+        let instance_id = (id >> self.instance_shift()) & ((1 << self.instance_bits) - 1);
+        let sequence = id & ((1 << self.sequence_bits) - 1);
+
+        (hash as u16, timestamp, instance_id as u16, sequence as u8)
+    }
+}
+
+/// Checked construction of an identifier from its fields, for tools rebuilding IDs
+/// from decoded parts.
+///
+/// Unlike [`Layout::construct_identifier`], which truncates the fields, the setters return
+/// `FieldTooWide` when a value doesn't fit in its field. The fields not set are 0.
+///
+/// ```rust
+/// use sinteflake::layout::{IdBuilder, Layout};
+///
+/// let id = IdBuilder::new(Layout::DEFAULT)
+///     .hash(5)?
+///     .timestamp(123456)?
+///     .instance(42)?
+///     .sequence(7)?
+///     .build();
+/// assert_eq!(Layout::DEFAULT.deconstruct_identifier(id), (5, 123456, 42, 7));
+/// assert!(IdBuilder::new(Layout::DEFAULT).instance(1024).is_err());
+/// # Ok::<(), sinteflake::error::SINTEFlakeError>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdBuilder {
+    layout: Layout,
+    hash: u16,
+    timestamp: u64,
+    instance_id: u16,
+    sequence: u8,
+}
+
+impl IdBuilder {
+    /// Creates a builder for an identifier of the layout, with all the fields at 0.
+    pub const fn new(layout: Layout) -> Self {
+        IdBuilder {
+            layout,
+            hash: 0,
+            timestamp: 0,
+            instance_id: 0,
+            sequence: 0,
+        }
+    }
+
+    /// Sets the hash field.
+    pub fn hash(mut self, hash: u16) -> Result<Self, SINTEFlakeError> {
+        check_field("hash", hash as u64, self.layout.hash_bits)?;
+        self.hash = hash;
+        Ok(self)
+    }
+
+    /// Sets the timestamp field, the permuted timestamp as stored in the identifier.
+    pub fn timestamp(mut self, timestamp: u64) -> Result<Self, SINTEFlakeError> {
+        check_field("timestamp", timestamp, self.layout.timestamp_bits)?;
+        self.timestamp = timestamp;
+        Ok(self)
+    }
+
+    /// Sets the instance identifier field.
+    pub fn instance(mut self, instance_id: u16) -> Result<Self, SINTEFlakeError> {
+        check_field("instance", instance_id as u64, self.layout.instance_bits)?;
+        self.instance_id = instance_id;
+        Ok(self)
+    }
+
+    /// Sets the sequence number field, as stored in the identifier.
+    pub fn sequence(mut self, sequence: u8) -> Result<Self, SINTEFlakeError> {
+        check_field("sequence", sequence as u64, self.layout.sequence_bits)?;
+        self.sequence = sequence;
+        Ok(self)
+    }
+
+    /// Returns the identifier.
+    pub const fn build(self) -> u64 {
+        self.layout
+            .construct_identifier(self.hash, self.timestamp, self.instance_id, self.sequence)
+    }
+}
+
+fn check_field(field: &'static str, value: u64, bits: u8) -> Result<(), SINTEFlakeError> {
+    let max = (1 << bits) - 1;
+    if value > max {
+        return Err(SINTEFlakeError::FieldTooWide { field, value, max });
+    }
+    Ok(())
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bits::construct_identifier;
+
+    #[test]
+    fn test_default_layout() {
+        let layout = Layout::DEFAULT;
+        assert_eq!(layout.total_bits(), 63);
+        assert_eq!(layout.buckets(), 16384);
+        assert_eq!(layout.bucket_capacity(), 256);
+        assert_eq!(layout.max_instance_id(), 1023);
+        assert_eq!(layout.max_timestamp(), 0x7FFFFFFF);
+        assert_eq!(layout.max_id(), i64::MAX as u64);
+    }
+
+    #[test]
+    fn test_default_layout_matches_construct_identifier() {
+        let layout = Layout::DEFAULT;
+        for (hash, timestamp, instance_id, sequence) in [
+            (0x01FFF, 0x3FFFFFFF, 0x01FF, 0x07F),
+            (0xFFFF, 0xFFFFFFFF, 0xFFFF, 0xFF),
+            (0x0ABC, 0x12345678, 0x0123, 0x45),
+        ] {
+            assert_eq!(
+                layout.construct_identifier(hash, timestamp as u64, instance_id, sequence),
+                construct_identifier(hash, timestamp, instance_id, sequence)
+            );
+        }
+    }
+
+    #[test]
+    fn test_javascript_safe_layout() {
+        let layout = Layout::JAVASCRIPT_SAFE;
+        assert_eq!(layout.total_bits(), 53);
+        // Number.MAX_SAFE_INTEGER
+        assert_eq!(layout.max_id(), 9007199254740991);
+        assert_eq!(
+            layout.construct_identifier(0xFFFF, 0xFFFFFFFF, 0xFFFF, 0xFF),
+            9007199254740991
+        );
+        assert_eq!(layout.window_capacity(), 32768);
+    }
+
+    #[test]
+    fn test_milliseconds_layout() {
+        let layout = Layout::MILLISECONDS;
+        assert_eq!(layout.total_bits(), 63);
+        assert_eq!(layout.window_capacity(), 4096);
+        assert_eq!(layout.max_timestamp(), 0x1FFFFFFFFFF);
+        assert!(layout.is_time_sorted());
+        assert_eq!(
+            layout.construct_identifier(0, 0x1FFFFFFFFFF, 0, 0),
+            0x1FFFFFFFFFF << 22
+        );
+        assert!(layout.construct_identifier(15, 1, 0, 0) < layout.construct_identifier(0, 2, 0, 0));
+    }
+
+    #[test]
+    fn test_time_sorted_layout() {
+        let layout = Layout::DEFAULT.time_sorted();
+        assert!(layout.is_time_sorted() && !Layout::DEFAULT.is_time_sorted());
+        assert_eq!(layout.total_bits(), 63);
+        assert_eq!(layout.timestamp_shift(), 32);
+        assert_eq!(layout.hash_shift(), 18);
+        assert_eq!(
+            layout.construct_identifier(0, 0x7FFFFFFF, 0, 0),
+            0x7FFFFFFF << 32
+        );
+
+        let versioned = Layout::new(4, 40, 9, 8)
+            .unwrap()
+            .versioned(1)
+            .unwrap()
+            .time_sorted();
+        assert_eq!(
+            versioned.read_version(versioned.construct_identifier(1, 2, 3, 4)),
+            1
+        );
+    }
+
+    #[test]
+    fn test_deconstruct_identifier() {
+        for layout in [
+            Layout::DEFAULT,
+            Layout::JAVASCRIPT_SAFE,
+            Layout::MILLISECONDS,
+            Layout::DEFAULT.time_sorted(),
+            Layout::new(4, 40, 9, 8)
+                .unwrap()
+                .versioned(1)
+                .unwrap()
+                .time_sorted(),
+        ] {
+            let id = layout.construct_identifier(5, 123456, 42, 7);
+            assert_eq!(layout.deconstruct_identifier(id), (5, 123456, 42, 7));
+        }
+    }
+
+    #[test]
+    fn test_invalid_layouts() {
+        assert!(Layout::new(14, 31, 10, 8).is_ok());
+        assert!(Layout::new(0, 31, 10, 8).is_err());
+        assert!(Layout::new(17, 31, 10, 8).is_err());
+        assert!(Layout::new(14, 32, 10, 8).is_err());
+        assert!(Layout::new(4, 49, 2, 8).is_err());
+        assert!(Layout::new(14, 31, 17, 8).is_err());
+        assert!(Layout::new(14, 31, 10, 9).is_err());
+        assert!(Layout::new(15, 31, 10, 8).is_err());
+    }
+
+    #[test]
+    fn test_id_builder() {
+        let layout = Layout::JAVASCRIPT_SAFE;
+        let id = IdBuilder::new(layout)
+            .hash(127)
+            .unwrap()
+            .timestamp(layout.max_timestamp())
+            .unwrap()
+            .instance(1023)
+            .unwrap()
+            .sequence(255)
+            .unwrap()
+            .build();
+        assert_eq!(id, layout.max_id());
+        assert_eq!(IdBuilder::new(layout).build(), 0);
+
+        assert!(matches!(
+            IdBuilder::new(layout).hash(128),
+            Err(SINTEFlakeError::FieldTooWide {
+                field: "hash",
+                value: 128,
+                max: 127
+            })
+        ));
+        assert!(IdBuilder::new(layout).timestamp(1 << 28).is_err());
+        assert!(IdBuilder::new(layout).instance(1024).is_err());
+        let narrow = Layout::new(12, 20, 0, 4).unwrap();
+        assert!(IdBuilder::new(narrow).instance(1).is_err());
+        assert!(IdBuilder::new(narrow).sequence(16).is_err());
+    }
+
+    #[test]
+    fn test_versioned_layout() {
+        let layout = Layout::new(14, 29, 10, 8).unwrap();
+        assert_eq!(layout.version(), None);
+        assert_eq!(layout.read_version(u64::MAX), 0);
+
+        let v2 = layout.versioned(2).unwrap();
+        assert_eq!(v2.version(), Some(2));
+        assert_eq!(v2.total_bits(), 63);
+        assert_eq!(v2.max_id(), i64::MAX as u64);
+        let id = v2.construct_identifier(5, 123456, 42, 7);
+        assert_eq!(id >> 61, 2);
+        assert_eq!(v2.read_version(id), 2);
+        assert_eq!(v2.deconstruct_identifier(id), (5, 123456, 42, 7));
+        assert_eq!(layout.deconstruct_identifier(id), (5, 123456, 42, 7));
+
+        assert!(layout.versioned(4).is_err());
+        assert!(v2.versioned(1).is_err());
+        assert!(Layout::DEFAULT.versioned(0).is_err());
+    }
+
+    #[test]
+    fn test_custom_layout_without_instance_bits() {
+        let layout = Layout::new(12, 20, 0, 4).unwrap();
+        assert_eq!(layout.max_instance_id(), 0);
+        assert_eq!(layout.construct_identifier(1, 1, 1, 1), 0x1000011);
+    }
+}
@@ -71,8 +71,8 @@
 //! #[cfg(not(feature = "async"))] fn main() {}
 //! ```
 //!
-//! ⚠️ Please note that the `set_instance_id_async` is not setting the instance ID of the non async version.
-//! Similarly, `update_time_async` is not updating the time of the non async version.
+//! The async functions share the global instance with the sync functions,
+//! so `set_instance_id_async` also sets the instance ID used by `next_id`.
 //!
 //! ## Custom Settings
 //!
@@ -80,14 +80,14 @@
 //!
 //! ```rust
 //! use sinteflake::sinteflake::SINTEFlake;
-//! use time::OffsetDateTime;
+//! use sinteflake::time::UnixTime;
 //!
 //! fn main() -> Result<(), Box<dyn std::error::Error>> {
 //!   let mut instance = SINTEFlake::custom(
 //!     42,                                                      // instance_id
 //!     [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16], // hash_key
 //!     123,                                                     // counter hash key
-//!     OffsetDateTime::from_unix_timestamp(1719792000)?,        // epoch
+//!     UnixTime::from_unix_timestamp(1719792000)?,              // epoch
 //!   )?;
 //!
 //!   let id_a = instance.next_id()?;
@@ -96,29 +96,145 @@
 //! }
 //! ```
 //!
+//! ## Configuring the Global Instance
+//!
+//! The global instance is created with the default settings on first use.
+//! A configured instance can be installed before, with [`init_global`]:
+//!
+//! ```rust
+//! use sinteflake::sinteflake::SINTEFlake;
+//! use sinteflake::{init_global, next_id};
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!   init_global(SINTEFlake::builder().instance_id(42).build()?)?;
+//!
+//!   let id = next_id()?;
+//!
+//!   Ok(())
+//! }
+//! ```
+//!
+//! ## Layouts
+//!
+//! The widths of the fields can be changed with a [`layout::Layout`], set through the builder.
+//! [`layout::Layout::JAVASCRIPT_SAFE`] uses only 53 bits, so the IDs can be represented exactly by a JavaScript `Number`:
+//!
+//! ```rust
+//! use sinteflake::layout::Layout;
+//! use sinteflake::sinteflake::SINTEFlake;
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!   let mut instance = SINTEFlake::builder()
+//!     .instance_id(42)
+//!     .layout(Layout::JAVASCRIPT_SAFE)
+//!     .build()?;
+//!
+//!   let id = instance.next_id()?;
+//!   assert!(id <= 9007199254740991); // Number.MAX_SAFE_INTEGER
+//!
+//!   Ok(())
+//! }
+//! ```
+//!
+//! It uses 7 bits for the hash and 28 bits for the timestamp, so an instance can only generate
+//! 32768 IDs per 8-second window, and the IDs overflow about 68 years after the epoch.
+//!
+//! [`layout::Layout::MILLISECONDS`] puts a 41 bits timestamp with a millisecond resolution in the most significant bits,
+//! so the IDs are roughly ordered by creation time, and keeps only 4 bits for the hash.
+//! Use it with `SINTEFlake::builder().milliseconds()`, which also sets 1 millisecond time windows.
+//!
 //! ## Note
 //!
 //! SINTEFlake IDs are not cryptographically secure and should not be used for security-sensitive applications.
 //! For most use cases, UUIDs are recommended over SINTEFlake IDs.
 
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "actix")]
+pub mod actix;
+#[cfg(feature = "async")]
+pub mod actor;
+#[cfg(feature = "std")]
+pub mod allocator;
+pub mod audit;
 pub mod bits;
+pub mod block;
+pub mod builder;
+#[cfg(feature = "std")]
+pub mod capacity;
+pub mod clock;
+#[cfg(feature = "std")]
+pub mod config;
+pub mod decode;
+pub mod epoch;
 pub mod error;
+pub mod feistel;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod generator;
+pub mod guard;
 pub mod hash;
+pub mod health;
+pub mod hook;
+pub mod id;
+#[cfg(feature = "std")]
+pub mod instance;
+pub mod layout;
+#[cfg(feature = "uniffi")]
+pub mod mobile;
 pub mod permute;
+pub mod probing;
+#[cfg(feature = "prost")]
+pub mod proto;
+pub mod query;
+pub mod rate_limit;
+#[cfg(feature = "std")]
+pub mod registry;
+#[cfg(feature = "tower")]
+pub mod request_id;
+pub mod retry;
+pub mod rotation;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod shard;
 pub mod sinteflake;
+pub mod snowflake;
+pub mod state;
+#[cfg(feature = "proptest")]
+pub mod strategy;
 pub mod time;
+#[cfg(feature = "ulid")]
+pub mod ulid;
+#[cfg(feature = "std")]
+pub mod uniformity;
+#[cfg(feature = "uuid")]
+pub mod uuid;
+pub mod validate;
 
+#[cfg(feature = "arbitrary")]
+mod arbitrary_impl;
+mod collisions;
+#[cfg(feature = "rand")]
+mod random;
+#[cfg(feature = "std")]
 mod singleton;
+mod stable;
 
 #[cfg(feature = "async")]
 mod tokio_singleton;
 
+#[cfg(feature = "std")]
 pub use singleton::*;
 
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
+
 #[cfg(feature = "async")]
 pub use tokio_singleton::*;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
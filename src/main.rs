@@ -0,0 +1,346 @@
+//! Command-line tool to generate and inspect SINTEFlake IDs, built with the `cli` feature.
+//!
+//! ```sh
+//! sinteflake generate --count 10 --instance 42
+//! sinteflake decode 1234567890123
+//! sinteflake explain 1234567890123
+//! sinteflake encode 1234567890123 --format base62
+//! sinteflake decode LjaL3EZ --format base62
+//! sinteflake analyze ids.txt
+//! ```
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::process::ExitCode;
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use sinteflake::decode::{self, EncodingConfig};
+use sinteflake::error::SINTEFlakeError;
+use sinteflake::id::SINTEFlakeId;
+use sinteflake::retry::RetryPolicy;
+use sinteflake::sinteflake::SINTEFlake;
+
+fn format_arg() -> Arg {
+    Arg::new("format")
+        .long("format")
+        .value_parser(["decimal", "hex", "base62", "base32"])
+        .default_value("decimal")
+        .help("Text format of the IDs")
+}
+
+fn command() -> Command {
+    let settings = [
+        Arg::new("hash-key")
+            .long("hash-key")
+            .global(true)
+            .help("Hash key of the generator, 32 hexadecimal digits"),
+        Arg::new("counter-key")
+            .long("counter-key")
+            .global(true)
+            .value_parser(value_parser!(u8))
+            .help("Counter key of the generator"),
+    ];
+
+    Command::new("sinteflake")
+        .about("Generates and inspects SINTEFlake IDs")
+        .subcommand_required(true)
+        .args(settings)
+        .subcommand(
+            Command::new("generate")
+                .about("Generates IDs, one per line")
+                .arg(
+                    Arg::new("count")
+                        .long("count")
+                        .short('n')
+                        .value_parser(value_parser!(u64))
+                        .default_value("1"),
+                )
+                .arg(
+                    Arg::new("instance")
+                        .long("instance")
+                        .short('i')
+                        .value_parser(value_parser!(u16))
+                        .default_value("0"),
+                )
+                .arg(format_arg()),
+        )
+        .subcommand(
+            Command::new("decode")
+                .about("Prints the fields of an ID")
+                .arg(Arg::new("id").required(true))
+                .arg(format_arg())
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("Prints the fields as JSON"),
+                ),
+        )
+        .subcommand(
+            Command::new("explain")
+                .about("Explains how an ID was placed in its time window and bucket")
+                .arg(Arg::new("id").required(true))
+                .arg(format_arg()),
+        )
+        .subcommand(
+            Command::new("encode")
+                .about("Writes a decimal ID in another format")
+                .arg(
+                    Arg::new("id")
+                        .required(true)
+                        .value_parser(value_parser!(u64)),
+                )
+                .arg(format_arg()),
+        )
+        .subcommand(
+            Command::new("analyze")
+                .about("Checks the uniqueness and the distribution of IDs read from a file, one per line")
+                .arg(Arg::new("file").required(true).help("File of IDs, - for the standard input"))
+                .arg(format_arg()),
+        )
+}
+
+fn builder(matches: &ArgMatches) -> Result<sinteflake::builder::SINTEFlakeBuilder, String> {
+    let mut builder = SINTEFlake::builder();
+    if let Some(hash_key) = matches.get_one::<String>("hash-key") {
+        builder = builder.hash_key(parse_hash_key(hash_key)?);
+    }
+    if let Some(&counter_key) = matches.get_one::<u8>("counter-key") {
+        builder = builder.counter_key(counter_key);
+    }
+    Ok(builder)
+}
+
+fn parse_hash_key(text: &str) -> Result<[u8; 16], String> {
+    let bytes = (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>();
+    bytes
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| SINTEFlakeError::InvalidHashKey.to_string())
+}
+
+fn format_id(id: SINTEFlakeId, format: &str) -> String {
+    match format {
+        "hex" => id.to_hex(),
+        "base62" => id.to_base62(),
+        "base32" => id.to_base32(),
+        _ => id.to_string(),
+    }
+}
+
+fn parse_id(text: &str, format: &str) -> Result<SINTEFlakeId, String> {
+    match format {
+        "hex" => SINTEFlakeId::from_hex(text),
+        "base62" => SINTEFlakeId::from_base62(text),
+        "base32" => SINTEFlakeId::from_base32(text),
+        _ => text.parse().map_err(|_| SINTEFlakeError::InvalidId),
+    }
+    .map_err(|error| error.to_string())
+}
+
+fn run(matches: &ArgMatches) -> Result<(), String> {
+    let builder = builder(matches)?;
+    match matches.subcommand() {
+        Some(("generate", matches)) => {
+            let format = matches.get_one::<String>("format").unwrap();
+            let mut instance = builder
+                .instance_id(*matches.get_one::<u16>("instance").unwrap())
+                .build()
+                .map_err(|error| error.to_string())?;
+            for _ in 0..*matches.get_one::<u64>("count").unwrap() {
+                let id = instance
+                    .next_id_with_retry(RetryPolicy::default())
+                    .map_err(|error| error.to_string())?;
+                println!("{}", format_id(SINTEFlakeId::new(id), format));
+            }
+        }
+        Some(("decode", matches)) => {
+            let format = matches.get_one::<String>("format").unwrap();
+            let id = parse_id(matches.get_one::<String>("id").unwrap(), format)?;
+            let config = builder
+                .build()
+                .map_err(|error| error.to_string())?
+                .encoding_config();
+            let decoded = decode::decode(id.get(), &config).map_err(|error| error.to_string())?;
+            if matches.get_flag("json") {
+                println!("{}", decoded.to_json(&config));
+            } else {
+                println!("{}", decoded.display(&config));
+            }
+        }
+        Some(("explain", matches)) => {
+            let format = matches.get_one::<String>("format").unwrap();
+            let id = parse_id(matches.get_one::<String>("id").unwrap(), format)?;
+            let config = builder
+                .build()
+                .map_err(|error| error.to_string())?
+                .encoding_config();
+            let explanation =
+                decode::explain(id.get(), &config).map_err(|error| error.to_string())?;
+            println!("{}", explanation);
+        }
+        Some(("encode", matches)) => {
+            let format = matches.get_one::<String>("format").unwrap();
+            let id = SINTEFlakeId::new(*matches.get_one::<u64>("id").unwrap());
+            println!("{}", format_id(id, format));
+        }
+        Some(("analyze", matches)) => {
+            let format = matches.get_one::<String>("format").unwrap();
+            let file = matches.get_one::<String>("file").unwrap();
+            let reader: Box<dyn BufRead> = if file == "-" {
+                Box::new(io::stdin().lock())
+            } else {
+                let file = File::open(file).map_err(|error| format!("{}: {}", file, error))?;
+                Box::new(BufReader::new(file))
+            };
+            let config = builder
+                .build()
+                .map_err(|error| error.to_string())?
+                .encoding_config();
+            let analysis = analyze(reader, format, &config)?;
+            analysis.print(&config);
+            if analysis.duplicates > 0 {
+                return Err(format!("{} duplicate IDs", analysis.duplicates));
+            }
+        }
+        _ => unreachable!("a subcommand is required"),
+    }
+    Ok(())
+}
+
+/// Distribution of a set of IDs over the time windows, the instances and the hash buckets.
+#[derive(Default)]
+struct Analysis {
+    ids: u64,
+    duplicates: u64,
+    duplicate_examples: Vec<SINTEFlakeId>,
+    windows: BTreeMap<u64, u64>,
+    instances: BTreeMap<u16, u64>,
+    buckets: HashMap<u16, u64>,
+    window_buckets: HashMap<(u64, u16), u64>,
+}
+
+fn analyze(
+    reader: impl BufRead,
+    format: &str,
+    config: &EncodingConfig,
+) -> Result<Analysis, String> {
+    let mut analysis = Analysis::default();
+    let mut seen = HashSet::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.map_err(|error| error.to_string())?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let id =
+            parse_id(line, format).map_err(|error| format!("line {}: {}", number + 1, error))?;
+        let decoded = decode::decode(id.get(), config)
+            .map_err(|error| format!("line {}: {}", number + 1, error))?;
+
+        analysis.ids += 1;
+        if !seen.insert(id) {
+            analysis.duplicates += 1;
+            if analysis.duplicate_examples.len() < 10 {
+                analysis.duplicate_examples.push(id);
+            }
+        }
+        *analysis.windows.entry(decoded.timestamp).or_default() += 1;
+        *analysis.instances.entry(decoded.instance_id).or_default() += 1;
+        *analysis.buckets.entry(decoded.hash).or_default() += 1;
+        *analysis
+            .window_buckets
+            .entry((decoded.timestamp, decoded.hash))
+            .or_default() += 1;
+    }
+    Ok(analysis)
+}
+
+impl Analysis {
+    fn print(&self, config: &EncodingConfig) {
+        let layout = config.layout;
+        println!("ids: {}", self.ids);
+        println!("duplicates: {}", self.duplicates);
+        for id in &self.duplicate_examples {
+            println!("  duplicate: {}", id);
+        }
+
+        println!("windows: {}", self.windows.len());
+        for (window, count) in &self.windows {
+            println!(
+                "  window {} ({}): {} IDs",
+                window,
+                config.window_start(*window),
+                count
+            );
+        }
+
+        println!("instances: {}", self.instances.len());
+        for (instance_id, count) in &self.instances {
+            println!("  instance {}: {} IDs", instance_id, count);
+        }
+
+        // number of buckets holding k IDs, over all the windows
+        let mut occupancy = BTreeMap::new();
+        for count in self.buckets.values() {
+            *occupancy.entry(*count).or_insert(0_u64) += 1;
+        }
+        let empty = layout.buckets() as u64 - self.buckets.len() as u64;
+        println!(
+            "buckets: {} of {} used",
+            self.buckets.len(),
+            layout.buckets()
+        );
+        if empty > 0 {
+            println!("  {} buckets with 0 IDs", empty);
+        }
+        for (count, buckets) in &occupancy {
+            println!("  {} buckets with {} IDs", buckets, count);
+        }
+
+        // buckets far above the mean, for a Poisson distribution of the hashes
+        let expected = self.ids as f64 / layout.buckets() as f64;
+        let threshold = expected + 6.0 * expected.sqrt() + 6.0;
+        let mut clustered: Vec<_> = self
+            .buckets
+            .iter()
+            .filter(|(_, count)| **count as f64 > threshold)
+            .collect();
+        clustered.sort();
+        for (bucket, count) in clustered {
+            println!(
+                "warning: bucket {} has {} IDs, {:.2} expected",
+                bucket, count, expected
+            );
+        }
+
+        let mut full: Vec<_> = self
+            .window_buckets
+            .iter()
+            .filter(|(_, count)| **count >= config.bucket_capacity as u64)
+            .map(|(key, _)| *key)
+            .collect();
+        full.sort();
+        for (window, bucket) in full {
+            println!(
+                "warning: bucket {} of window {} is full, IDs may have moved to other buckets",
+                bucket, window
+            );
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    match run(&command().get_matches()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Bindings for Kotlin and Swift apps, generated with UniFFI.
+//!
+//! Mobile apps can generate IDs offline, compatible with the IDs of the servers,
+//! and sync them later. Each app must use its own instance ID, for example one
+//! from a range of instance IDs handed out to the mobile clients by the servers.
+//!
+//! The library is built with the `uniffi` feature as a shared library,
+//! and the Kotlin and Swift code is generated from it with `uniffi-bindgen`:
+//!
+//! ```sh
+//! cargo rustc --release --features uniffi --crate-type cdylib
+//! uniffi-bindgen generate --library target/release/libsinteflake.so --language kotlin --out-dir out
+//! ```
+//!
+//! ```kotlin
+//! val generator = MobileGenerator(instanceId = 742u, hashKey = hashKey, counterKey = 123u)
+//! val id = generator.nextId()
+//! ```
+use std::sync::{Arc, Mutex};
+
+use crate::decode::{self, DecodedId};
+use crate::error::SINTEFlakeError;
+use crate::singleton::lock;
+use crate::sinteflake::SINTEFlake;
+
+/// A generator for mobile apps, with the default layout, epoch and time window.
+///
+/// The hash key and the counter key must be the ones of the servers,
+/// for the IDs to be decoded by the servers.
+#[derive(uniffi::Object)]
+pub struct MobileGenerator {
+    instance: Mutex<SINTEFlake>,
+}
+
+impl std::fmt::Debug for MobileGenerator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MobileGenerator").finish_non_exhaustive()
+    }
+}
+
+#[uniffi::export]
+impl MobileGenerator {
+    /// Creates a generator.
+    ///
+    /// # Errors
+    /// Returns an error if the hash key is not 16 bytes long, or if the instance ID is too high.
+    #[uniffi::constructor]
+    pub fn new(
+        instance_id: u16,
+        hash_key: Vec<u8>,
+        counter_key: u8,
+    ) -> Result<Arc<Self>, SINTEFlakeError> {
+        let hash_key = <[u8; 16]>::try_from(hash_key.as_slice())
+            .map_err(|_| SINTEFlakeError::InvalidHashKey)?;
+        let instance = SINTEFlake::builder()
+            .instance_id(instance_id)
+            .hash_key(hash_key)
+            .counter_key(counter_key)
+            .build()?;
+        Ok(Arc::new(MobileGenerator {
+            instance: Mutex::new(instance),
+        }))
+    }
+
+    /// Updates the time, see [`SINTEFlake::update_time`].
+    pub fn update_time(&self) -> Result<(), SINTEFlakeError> {
+        lock(&self.instance)?.update_time()
+    }
+
+    /// Generates the next ID.
+    pub fn next_id(&self) -> Result<u64, SINTEFlakeError> {
+        lock(&self.instance)?.next_id()
+    }
+
+    /// Generates the next ID with the hash of `data`.
+    pub fn next_id_with_hash(&self, data: Vec<u8>) -> Result<u64, SINTEFlakeError> {
+        lock(&self.instance)?.next_id_with_hash(&data)
+    }
+
+    /// Decodes an ID generated with the same settings, by this app or by the servers.
+    pub fn decode(&self, id: u64) -> Result<DecodedId, SINTEFlakeError> {
+        decode::decode(id, &lock(&self.instance)?.encoding_config())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH_KEY: [u8; 16] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+
+    #[test]
+    fn test_mobile_generator() {
+        let generator = MobileGenerator::new(742, HASH_KEY.to_vec(), 123).unwrap();
+        generator.update_time().unwrap();
+        let id_a = generator.next_id().unwrap();
+        let id_b = generator.next_id_with_hash(b"data".to_vec()).unwrap();
+        assert_ne!(id_a, id_b);
+        assert_eq!(generator.decode(id_a).unwrap().instance_id, 742);
+
+        // the servers decode the IDs of the app
+        let server = SINTEFlake::builder()
+            .instance_id(1)
+            .hash_key(HASH_KEY)
+            .counter_key(123)
+            .build()
+            .unwrap();
+        let decoded = decode::decode(id_b, &server.encoding_config()).unwrap();
+        assert_eq!(decoded, generator.decode(id_b).unwrap());
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        assert!(matches!(
+            MobileGenerator::new(742, vec![1, 2, 3], 123),
+            Err(SINTEFlakeError::InvalidHashKey)
+        ));
+        assert!(matches!(
+            MobileGenerator::new(1024, HASH_KEY.to_vec(), 123),
+            Err(SINTEFlakeError::InstanceIDTooHigh { .. })
+        ));
+    }
+}
@@ -1,42 +1,235 @@
-use bitvec::prelude::*;
+use crate::error::SINTEFlakeError;
+use crate::layout::Layout;
 
-pub(crate) fn permute_31_bits(input: &BitArray<[u32; 1], Lsb0>) -> BitArray<[u32; 1], Lsb0> {
-    const PERMUTATION: [usize; 31] = [
-        4, 16, 22, 21, 2, 5, 20, 12, 13, 6, 24, 25, 17, 8, 23, 0, 28, 3, 19, 18, 14, 1, 15, 27, 29,
-        9, 10, 11, 26, 30, 7,
-    ];
+const PERMUTATION_31_BITS: [u8; 31] = [
+    4, 16, 22, 21, 2, 5, 20, 12, 13, 6, 24, 25, 17, 8, 23, 0, 28, 3, 19, 18, 14, 1, 15, 27, 29, 9,
+    10, 11, 26, 30, 7,
+];
 
-    let mut result = BitArray::<[u32; 1], Lsb0>::new([0]);
+const PERMUTATION_8_BITS: [u8; 8] = [5, 7, 6, 0, 2, 1, 3, 4];
 
-    for (new_position, &old_position) in PERMUTATION.iter().enumerate() {
-        //result.set(new_position, input[old_position]);
-        if input[old_position] {
-            result.set(new_position, true);
+/// Moves the bit at position `table[i]` of the input to the position `i` of the result.
+///
+/// Only the positions of the table lower than `bits` are used, in the same order.
+const fn permute_with_table(input: u32, table: &[u8], bits: u8) -> u32 {
+    let mut result = 0;
+    let mut new_position = 0;
+    let mut i = 0;
+    while i < table.len() {
+        let old_position = table[i];
+        if old_position < bits {
+            if (input >> old_position) & 1 == 1 {
+                result |= 1 << new_position;
+            }
+            new_position += 1;
         }
+        i += 1;
     }
+    result
+}
 
+/// Inverse of [`permute_with_table`].
+const fn unpermute_with_table(input: u32, table: &[u8], bits: u8) -> u32 {
+    let mut result = 0;
+    let mut new_position = 0;
+    let mut i = 0;
+    while i < table.len() {
+        let old_position = table[i];
+        if old_position < bits {
+            if (input >> new_position) & 1 == 1 {
+                result |= 1 << old_position;
+            }
+            new_position += 1;
+        }
+        i += 1;
+    }
     result
 }
 
+// The permutation of each byte of a 31 bits input, the results are ORed together.
+const PERMUTED_31_BITS_BY_BYTE: [[u32; 256]; 4] = {
+    let mut tables = [[0; 256]; 4];
+    let mut byte = 0;
+    while byte < 4 {
+        let mut value = 0;
+        while value < 256 {
+            tables[byte][value] =
+                permute_with_table((value as u32) << (8 * byte), &PERMUTATION_31_BITS, 31);
+            value += 1;
+        }
+        byte += 1;
+    }
+    tables
+};
+
+// The permutations of all the u8 values, for each width from 0 to 8 bits.
+const PERMUTED_U8_N_BITS: [[u8; 256]; 9] = {
+    let mut tables = [[0; 256]; 9];
+    let mut bits = 0;
+    while bits <= 8 {
+        let mut value = 0;
+        while value < 256 {
+            tables[bits][value] =
+                permute_with_table(value as u32, &PERMUTATION_8_BITS, bits as u8) as u8;
+            value += 1;
+        }
+        bits += 1;
+    }
+    tables
+};
+
 pub fn permute_u32_31_bits(input: u32) -> u32 {
-    let input = BitArray::<[u32; 1], Lsb0>::new([input]);
-    let result = permute_31_bits(&input);
-    result.as_raw_slice()[0]
+    let [b0, b1, b2, b3] = input.to_le_bytes();
+    PERMUTED_31_BITS_BY_BYTE[0][b0 as usize]
+        | PERMUTED_31_BITS_BY_BYTE[1][b1 as usize]
+        | PERMUTED_31_BITS_BY_BYTE[2][b2 as usize]
+        | PERMUTED_31_BITS_BY_BYTE[3][b3 as usize]
 }
 
 pub fn permute_u8(input: u8) -> u8 {
-    const PERMUTATION: [usize; 8] = [5, 7, 6, 0, 2, 1, 3, 4];
+    PERMUTED_U8_N_BITS[8][input as usize]
+}
+
+/// Permutes the `bits` lowest bits of the input, for timestamps narrower than 31 bits.
+///
+/// The permutation table is the 31 bits table restricted to the positions
+/// lower than `bits`, in the same order, so it is still derived from the digits of Pi.
+/// The bits above `bits` are discarded.
+pub fn permute_u32_n_bits(input: u32, bits: u8) -> u32 {
+    if bits >= 31 {
+        return permute_u32_31_bits(input);
+    }
+    permute_with_table(input, &PERMUTATION_31_BITS, bits)
+}
 
-    let input = BitArray::<[u8; 1], Lsb0>::new([input]);
-    let mut result = BitArray::<[u8; 1], Lsb0>::new([0]);
+/// Permutes the `bits` lowest bits of the input, for timestamps of any width.
+///
+/// Timestamps up to 31 bits are permuted like [`permute_u32_n_bits`]. For wider timestamps,
+/// only the 31 lowest bits are permuted, the slowly changing upper bits are kept in place.
+pub fn permute_u64_n_bits(input: u64, bits: u8) -> u64 {
+    if bits <= 31 {
+        return permute_u32_n_bits(input as u32, bits) as u64;
+    }
+    let mask = (1 << bits) - 1;
+    let upper = input & mask & !0x7FFFFFFF;
+    upper | permute_u32_31_bits(input as u32 & 0x7FFFFFFF) as u64
+}
+
+/// Permutes the `bits` lowest bits of the input, for sequence numbers narrower than 8 bits.
+///
+/// Same as [`permute_u32_n_bits`], but with the 8 bits table derived from the digits of e.
+pub fn permute_u8_n_bits(input: u8, bits: u8) -> u8 {
+    PERMUTED_U8_N_BITS[bits.min(8) as usize][input as usize]
+}
+
+/// Permutation tables for the timestamp and sequence bits.
+///
+/// The default tables are derived from the digits of Pi and e. Organizations can use
+/// their own "nothing up my sleeve" tables, the IDs then differ from the default ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PermutationTables {
+    timestamp: [u8; 31],
+    sequence: [u8; 8],
+}
 
-    for (new_position, &old_position) in PERMUTATION.iter().enumerate() {
-        if input[old_position] {
-            result.set(new_position, true);
+impl PermutationTables {
+    /// The tables derived from the digits of Pi and e.
+    pub const DEFAULT: PermutationTables = PermutationTables {
+        timestamp: PERMUTATION_31_BITS,
+        sequence: PERMUTATION_8_BITS,
+    };
+
+    /// Creates custom permutation tables.
+    ///
+    /// The bit at position `table[i]` of the input is moved to position `i`.
+    ///
+    /// # Errors
+    /// Returns an error if a table doesn't contain every position exactly once.
+    pub fn new(timestamp: [u8; 31], sequence: [u8; 8]) -> Result<Self, SINTEFlakeError> {
+        if !is_permutation(&timestamp) || !is_permutation(&sequence) {
+            return Err(SINTEFlakeError::InvalidPermutation);
+        }
+        Ok(PermutationTables {
+            timestamp,
+            sequence,
+        })
+    }
+
+    /// Permutes the `bits` lowest bits of a timestamp, like [`permute_u64_n_bits`].
+    pub fn permute_timestamp(&self, input: u64, bits: u8) -> u64 {
+        if *self == PermutationTables::DEFAULT {
+            return permute_u64_n_bits(input, bits);
+        }
+        if bits <= 31 {
+            return permute_with_table(input as u32, &self.timestamp, bits) as u64;
+        }
+        let mask = (1 << bits) - 1;
+        let upper = input & mask & !0x7FFFFFFF;
+        upper | permute_with_table(input as u32 & 0x7FFFFFFF, &self.timestamp, 31) as u64
+    }
+
+    /// Inverse of [`PermutationTables::permute_timestamp`].
+    pub fn unpermute_timestamp(&self, input: u64, bits: u8) -> u64 {
+        if bits <= 31 {
+            return unpermute_with_table(input as u32, &self.timestamp, bits) as u64;
+        }
+        let mask = (1 << bits) - 1;
+        let upper = input & mask & !0x7FFFFFFF;
+        upper | unpermute_with_table(input as u32 & 0x7FFFFFFF, &self.timestamp, 31) as u64
+    }
+
+    /// Permutes the timestamp field of a layout, unless the layout is time-sorted.
+    pub(crate) fn permute_layout_timestamp(&self, timestamp: u64, layout: &Layout) -> u64 {
+        if layout.is_time_sorted() {
+            timestamp
+        } else {
+            self.permute_timestamp(timestamp, layout.timestamp_bits())
         }
     }
 
-    result.as_raw_slice()[0]
+    /// Inverse of [`PermutationTables::permute_layout_timestamp`].
+    pub(crate) fn unpermute_layout_timestamp(&self, timestamp: u64, layout: &Layout) -> u64 {
+        if layout.is_time_sorted() {
+            timestamp
+        } else {
+            self.unpermute_timestamp(timestamp, layout.timestamp_bits())
+        }
+    }
+
+    /// Inverse of the permutation of the sequence numbers of `bits` bits.
+    pub fn unpermute_sequence(&self, input: u8, bits: u8) -> u8 {
+        unpermute_with_table(input as u32, &self.sequence, bits.min(8)) as u8
+    }
+
+    /// Returns the permutations of all the u8 values for sequence numbers of `bits` bits.
+    pub(crate) fn sequence_table(&self, bits: u8) -> [u8; 256] {
+        let bits = bits.min(8);
+        if *self == PermutationTables::DEFAULT {
+            return PERMUTED_U8_N_BITS[bits as usize];
+        }
+        let mut table = [0; 256];
+        for (value, permuted) in table.iter_mut().enumerate() {
+            *permuted = permute_with_table(value as u32, &self.sequence, bits) as u8;
+        }
+        table
+    }
+}
+
+impl Default for PermutationTables {
+    fn default() -> Self {
+        PermutationTables::DEFAULT
+    }
+}
+
+fn is_permutation(table: &[u8]) -> bool {
+    let mut seen = [false; 32];
+    for &position in table {
+        if position as usize >= table.len() || seen[position as usize] {
+            return false;
+        }
+        seen[position as usize] = true;
+    }
+    true
 }
 
 #[cfg(test)]
@@ -112,10 +305,8 @@ mod tests {
 
     #[test]
     fn test_permutation_31_bits() {
-        let input = BitArray::<[u32; 1], Lsb0>::new([0b1010101010101010101010101010101]);
-        let permuted = permute_31_bits(&input);
-        let raw = permuted.as_raw_slice()[0];
-        assert_eq!(raw, 0b0110100000110011010011011010111);
+        let permuted = permute_u32_31_bits(0b1010101010101010101010101010101);
+        assert_eq!(permuted, 0b0110100000110011010011011010111);
     }
 
     #[test]
@@ -129,4 +320,102 @@ mod tests {
         assert_eq!(permute_u8(1), 8);
         assert_eq!(permute_u8(123), 237);
     }
+
+    #[test]
+    fn test_permutation_n_bits_matches_full_width() {
+        for input in [0, 1, 123456789, 0x7FFFFFFF] {
+            assert_eq!(permute_u32_n_bits(input, 31), permute_u32_31_bits(input));
+        }
+        for input in 0..=255 {
+            assert_eq!(permute_u8_n_bits(input, 8), permute_u8(input));
+        }
+    }
+
+    #[test]
+    fn test_permutation_u64_n_bits() {
+        assert_eq!(permute_u64_n_bits(123456789, 31), 475315287);
+        assert_eq!(
+            permute_u64_n_bits(123456789, 12),
+            permute_u32_n_bits(123456789, 12) as u64
+        );
+        assert_eq!(
+            permute_u64_n_bits((1 << 40) | 123456789, 41),
+            (1 << 40) | 475315287
+        );
+        assert_eq!(permute_u64_n_bits(1 << 41, 41), 0);
+    }
+
+    #[test]
+    fn test_tables_match_bit_by_bit_permutation() {
+        for input in [0, 1, 0x80000000, 0xFFFFFFFF, 123456789, 0x7FFFFFFF] {
+            assert_eq!(
+                permute_u32_31_bits(input),
+                permute_with_table(input, &PERMUTATION_31_BITS, 31)
+            );
+        }
+        for bits in 0..=8 {
+            for input in 0..=255 {
+                assert_eq!(
+                    permute_u8_n_bits(input, bits) as u32,
+                    permute_with_table(input as u32, &PERMUTATION_8_BITS, bits)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_permutation_tables() {
+        let tables = PermutationTables::DEFAULT;
+        assert_eq!(tables.permute_timestamp(123456789, 31), 475315287);
+        assert_eq!(tables.sequence_table(8)[123], 237);
+
+        let mut timestamp = [0; 31];
+        for (i, position) in timestamp.iter_mut().enumerate() {
+            *position = 30 - i as u8;
+        }
+        let reversed = PermutationTables::new(timestamp, [7, 6, 5, 4, 3, 2, 1, 0]).unwrap();
+        assert_eq!(reversed.permute_timestamp(1, 31), 1 << 30);
+        assert_eq!(reversed.sequence_table(8)[1], 0x80);
+        assert_eq!(reversed.sequence_table(4)[1], 0x08);
+        assert_eq!(
+            reversed.permute_timestamp((1 << 40) | 1, 41),
+            (1 << 40) | (1 << 30)
+        );
+
+        for tables in [PermutationTables::DEFAULT, reversed] {
+            for bits in [12, 31, 41] {
+                let input = 0x1234_5678_9ABC & ((1 << bits) - 1);
+                let permuted = tables.permute_timestamp(input, bits);
+                assert_eq!(tables.unpermute_timestamp(permuted, bits), input);
+            }
+            for bits in [3, 8] {
+                for input in 0..(1u16 << bits) {
+                    let permuted = tables.sequence_table(bits)[input as usize];
+                    assert_eq!(tables.unpermute_sequence(permuted, bits), input as u8);
+                }
+            }
+        }
+
+        assert!(PermutationTables::new(timestamp, [0, 1, 2, 3, 4, 5, 6, 6]).is_err());
+        assert!(PermutationTables::new(timestamp, [0, 1, 2, 3, 4, 5, 6, 8]).is_err());
+        timestamp[0] = 31;
+        assert!(PermutationTables::new(timestamp, PERMUTATION_8_BITS).is_err());
+    }
+
+    #[test]
+    fn test_permutation_n_bits_is_a_permutation() {
+        let mut seen = HashSet::new();
+        for input in 0..(1 << 12) {
+            let output = permute_u32_n_bits(input, 12);
+            assert!(output < (1 << 12));
+            assert!(seen.insert(output));
+        }
+
+        let mut seen = HashSet::new();
+        for input in 0..(1 << 5) {
+            let output = permute_u8_n_bits(input, 5);
+            assert!(output < (1 << 5));
+            assert!(seen.insert(output));
+        }
+    }
 }
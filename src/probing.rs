@@ -0,0 +1,110 @@
+/// How the next bucket is chosen when a hash bucket is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProbingStrategy {
+    /// Tries the following buckets, one after the other.
+    ///
+    /// Under heavy collisions, the IDs cluster in adjacent buckets.
+    #[default]
+    Linear,
+
+    /// Tries buckets further and further away, at triangular number offsets.
+    Quadratic,
+
+    /// Tries buckets at multiples of a step derived from the hash and the counter key.
+    DoubleHashing,
+}
+
+impl ProbingStrategy {
+    /// Returns the bucket to try after `attempt` full buckets, starting from the `start` bucket.
+    ///
+    /// All the strategies eventually visit every bucket, whatever the number of buckets:
+    /// the quadratic and double hashing offsets visit every position of the next power
+    /// of two in as many attempts, and the positions past the last bucket wrap around,
+    /// so any number of buckets is visited in less than twice as many attempts.
+    pub(crate) fn bucket(
+        &self,
+        start: usize,
+        attempt: u32,
+        counter_key: u8,
+        buckets: usize,
+    ) -> usize {
+        let attempt = attempt as usize;
+        let offset = match self {
+            ProbingStrategy::Linear => return start.wrapping_add(attempt) % buckets,
+            ProbingStrategy::Quadratic => attempt * (attempt + 1) / 2,
+            ProbingStrategy::DoubleHashing => {
+                let mixed = (start as u32 ^ ((counter_key as u32) << 16)).wrapping_mul(0x9E3779B1);
+                // odd, so coprime with the number of buckets
+                attempt.wrapping_mul((mixed >> 16) as usize | 1)
+            }
+        };
+        let position = start.wrapping_add(offset) % buckets.next_power_of_two();
+        if position < buckets {
+            position
+        } else {
+            position - buckets
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_linear() {
+        let buckets: Vec<usize> = (0..4)
+            .map(|attempt| ProbingStrategy::Linear.bucket(14, attempt, 42, 16))
+            .collect();
+        assert_eq!(buckets, vec![14, 15, 0, 1]);
+    }
+
+    #[test]
+    fn test_quadratic() {
+        let buckets: Vec<usize> = (0..5)
+            .map(|attempt| ProbingStrategy::Quadratic.bucket(0, attempt, 42, 64))
+            .collect();
+        assert_eq!(buckets, vec![0, 1, 3, 6, 10]);
+    }
+
+    #[test]
+    fn test_all_buckets_are_visited() {
+        for strategy in [
+            ProbingStrategy::Linear,
+            ProbingStrategy::Quadratic,
+            ProbingStrategy::DoubleHashing,
+        ] {
+            let mut visited = vec![false; 256];
+            for attempt in 0..256 {
+                visited[strategy.bucket(123, attempt, 42, 256)] = true;
+            }
+            assert!(visited.iter().all(|&v| v), "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn test_all_buckets_are_visited_in_any_range() {
+        for strategy in [
+            ProbingStrategy::Linear,
+            ProbingStrategy::Quadratic,
+            ProbingStrategy::DoubleHashing,
+        ] {
+            for buckets in [3, 5, 6, 100, 1000] {
+                for start in 0..buckets {
+                    let mut visited = vec![false; buckets];
+                    for attempt in 0..2 * buckets as u32 {
+                        visited[strategy.bucket(start, attempt, 42, buckets)] = true;
+                    }
+                    assert!(visited.iter().all(|&v| v), "{:?} {}", strategy, buckets);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_double_hashing_depends_on_counter_key() {
+        let with_key =
+            |counter_key| ProbingStrategy::DoubleHashing.bucket(123, 1, counter_key, 16384);
+        assert_ne!(with_key(42), with_key(43));
+    }
+}
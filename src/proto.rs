@@ -0,0 +1,140 @@
+//! Protobuf messages and conversions, to pass IDs between services.
+//!
+//! The messages match `proto/sinteflake.proto`, shipped with the crate:
+//! services in other languages can generate their code from it.
+//!
+//! IDs are `uint64` fields. Languages without 64-bit integers, or JSON mappings
+//! that may lose precision, can pass them as `string` fields in decimal,
+//! with [`id_to_string`] and [`id_from_string`].
+use alloc::string::{String, ToString};
+
+use crate::decode::DecodedId;
+use crate::error::SINTEFlakeError;
+use crate::id::SINTEFlakeId;
+
+/// A SINTEFlake ID, the `sinteflake.Id` message.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, prost::Message)]
+pub struct IdMessage {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+}
+
+/// The fields of a SINTEFlake ID, the `sinteflake.DecodedId` message.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, prost::Message)]
+pub struct DecodedIdMessage {
+    #[prost(uint32, tag = "1")]
+    pub hash: u32,
+
+    #[prost(uint64, tag = "2")]
+    pub timestamp: u64,
+
+    #[prost(uint32, tag = "3")]
+    pub instance_id: u32,
+
+    #[prost(uint32, tag = "4")]
+    pub sequence: u32,
+}
+
+impl From<SINTEFlakeId> for IdMessage {
+    fn from(id: SINTEFlakeId) -> Self {
+        IdMessage { id: id.get() }
+    }
+}
+
+impl From<IdMessage> for SINTEFlakeId {
+    fn from(message: IdMessage) -> Self {
+        SINTEFlakeId::new(message.id)
+    }
+}
+
+impl From<DecodedId> for DecodedIdMessage {
+    fn from(decoded: DecodedId) -> Self {
+        DecodedIdMessage {
+            hash: decoded.hash as u32,
+            timestamp: decoded.timestamp,
+            instance_id: decoded.instance_id as u32,
+            sequence: decoded.sequence as u32,
+        }
+    }
+}
+
+impl TryFrom<DecodedIdMessage> for DecodedId {
+    type Error = SINTEFlakeError;
+
+    /// Returns `InvalidId` if a field is too large for the Rust type.
+    fn try_from(message: DecodedIdMessage) -> Result<Self, Self::Error> {
+        Ok(DecodedId {
+            hash: message
+                .hash
+                .try_into()
+                .map_err(|_| SINTEFlakeError::InvalidId)?,
+            timestamp: message.timestamp,
+            instance_id: message
+                .instance_id
+                .try_into()
+                .map_err(|_| SINTEFlakeError::InvalidId)?,
+            sequence: message
+                .sequence
+                .try_into()
+                .map_err(|_| SINTEFlakeError::InvalidId)?,
+        })
+    }
+}
+
+/// Writes an ID for a `string` field, in decimal.
+pub fn id_to_string(id: SINTEFlakeId) -> String {
+    id.to_string()
+}
+
+/// Reads an ID from a `string` field, in decimal.
+///
+/// # Errors
+/// Returns `InvalidId` if the string is not a decimal integer of 64 bits at most.
+pub fn id_from_string(value: &str) -> Result<SINTEFlakeId, SINTEFlakeError> {
+    value.parse().map_err(|_| SINTEFlakeError::InvalidId)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+
+    #[test]
+    fn test_id_message() {
+        let id = SINTEFlakeId::new(1234567890123);
+        let bytes = IdMessage::from(id).encode_to_vec();
+        let message = IdMessage::decode(bytes.as_slice()).unwrap();
+        assert_eq!(SINTEFlakeId::from(message), id);
+    }
+
+    #[test]
+    fn test_decoded_id_message() {
+        let decoded = DecodedId {
+            hash: 5,
+            timestamp: 123456,
+            instance_id: 42,
+            sequence: 7,
+        };
+        let bytes = DecodedIdMessage::from(decoded).encode_to_vec();
+        let message = DecodedIdMessage::decode(bytes.as_slice()).unwrap();
+        assert_eq!(DecodedId::try_from(message).unwrap(), decoded);
+
+        let message = DecodedIdMessage {
+            sequence: 256,
+            ..message
+        };
+        assert!(matches!(
+            DecodedId::try_from(message),
+            Err(SINTEFlakeError::InvalidId)
+        ));
+    }
+
+    #[test]
+    fn test_string_fields() {
+        let id = SINTEFlakeId::new(u64::MAX);
+        assert_eq!(id_to_string(id), "18446744073709551615");
+        assert_eq!(id_from_string("18446744073709551615").unwrap(), id);
+        assert!(id_from_string("18446744073709551616").is_err());
+        assert!(id_from_string("0x12").is_err());
+    }
+}
@@ -0,0 +1,190 @@
+//! Helpers to query stored IDs.
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+use crate::decode::EncodingConfig;
+use crate::error::SINTEFlakeError;
+use crate::time::UnixTime;
+
+/// Returns the ranges of IDs generated between `window_start` and `window_end`,
+/// to query a database for the IDs created in a period.
+///
+/// The IDs of the time windows overlapping the interval `[window_start, window_end)` are
+/// in the ranges, so IDs generated shortly before or after the interval may be too.
+/// As the IDs start with the hash, there is a range per hash value and per run of
+/// consecutive permuted timestamps, or a single range with a
+/// [time-sorted](crate::layout::Layout::time_sorted) layout. The ranges are sorted and disjoint.
+///
+/// The permuted timestamps of all the windows are computed first, so long intervals
+/// of short windows use a lot of memory.
+///
+/// # Errors
+/// Returns `FeistelPermutation` if the IDs are permuted, as their ranges would be single IDs.
+pub fn id_range_for(
+    window_start: UnixTime,
+    window_end: UnixTime,
+    config: &EncodingConfig,
+) -> Result<impl Iterator<Item = RangeInclusive<u64>>, SINTEFlakeError> {
+    if config.feistel_permutation {
+        return Err(SINTEFlakeError::FeistelPermutation);
+    }
+    let layout = config.layout;
+    let window = config.time_window.as_nanos() as i128;
+    let epoch = config.epoch.unix_timestamp_nanos();
+    let start = (window_start.unix_timestamp_nanos() - epoch).max(0);
+    let end = window_end.unix_timestamp_nanos() - epoch;
+
+    // permuted timestamps of the windows, merged into runs of consecutive values
+    let mut runs: Vec<(u64, u64)> = Vec::new();
+    if end > start {
+        let first = (start / window) as u64;
+        let last = ((end - 1) / window).min(layout.max_timestamp() as i128) as u64;
+        let mut timestamps: Vec<u64> = (first..=last)
+            .map(|timestamp| {
+                config
+                    .permutation_tables
+                    .permute_layout_timestamp(timestamp, &layout)
+            })
+            .collect();
+        timestamps.sort_unstable();
+        for timestamp in timestamps {
+            match runs.last_mut() {
+                Some((_, run_end)) if *run_end + 1 == timestamp => *run_end = timestamp,
+                _ => runs.push((timestamp, timestamp)),
+            }
+        }
+    }
+
+    let timestamp_shift = layout.timestamp_shift();
+    let hash_shift = layout.hash_shift();
+    let low_bits = (1_u64 << timestamp_shift) - 1;
+    // with the timestamp above the hash, a run covers all the hash values
+    let buckets = if layout.is_time_sorted() {
+        1
+    } else {
+        layout.buckets()
+    };
+    Ok((0..buckets * runs.len()).map(move |index| {
+        let prefix = ((index / runs.len()) as u64) << hash_shift;
+        let (run_start, run_end) = runs[index % runs.len()];
+        (prefix | run_start << timestamp_shift)..=(prefix | run_end << timestamp_shift | low_bits)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::tests::ManualClock;
+    use crate::layout::Layout;
+    use crate::sinteflake::SINTEFlake;
+    use core::time::Duration;
+
+    #[test]
+    fn test_id_range_for() {
+        let epoch = UnixTime::from_unix_timestamp(1719792000).unwrap();
+        let clock = ManualClock::new(1719792000000);
+        let mut instance = SINTEFlake::builder()
+            .epoch(epoch)
+            .clock(clock.clone())
+            .layout(Layout::JAVASCRIPT_SAFE)
+            .build()
+            .unwrap();
+        let config = instance.encoding_config();
+
+        // 10 IDs in each of the 20 first windows
+        let mut ids = Vec::new();
+        for window in 0..20 {
+            clock.set(1719792000000 + window * 8000);
+            instance.update_time().unwrap();
+            for _ in 0..10 {
+                ids.push((window, instance.next_id().unwrap()));
+            }
+        }
+
+        // from the middle of window 5 to the end of window 12
+        let ranges: Vec<_> = id_range_for(
+            epoch + Duration::from_secs(44),
+            epoch + Duration::from_secs(104),
+            &config,
+        )
+        .unwrap()
+        .collect();
+        assert!(ranges
+            .windows(2)
+            .all(|pair| pair[0].end() < pair[1].start()));
+        for (window, id) in ids {
+            let found = ranges.iter().any(|range| range.contains(&id));
+            assert_eq!(found, (5..=12).contains(&window), "window {}", window);
+        }
+
+        let empty = id_range_for(epoch + Duration::from_secs(8), epoch, &config).unwrap();
+        assert_eq!(empty.count(), 0);
+    }
+
+    #[test]
+    fn test_id_range_for_time_sorted() {
+        let epoch = UnixTime::from_unix_timestamp(1719792000).unwrap();
+        let clock = ManualClock::new(1719792000000);
+        let mut instance = SINTEFlake::builder()
+            .epoch(epoch)
+            .clock(clock.clone())
+            .layout(Layout::DEFAULT.time_sorted())
+            .build()
+            .unwrap();
+        let config = instance.encoding_config();
+
+        let mut ids = Vec::new();
+        for window in 0..20 {
+            clock.set(1719792000000 + window * 8000);
+            instance.update_time().unwrap();
+            for i in 0..10_u8 {
+                ids.push((window, instance.next_id_with_hash(&[i]).unwrap()));
+            }
+        }
+
+        let ranges: Vec<_> = id_range_for(
+            epoch + Duration::from_secs(44),
+            epoch + Duration::from_secs(104),
+            &config,
+        )
+        .unwrap()
+        .collect();
+        assert_eq!(ranges.len(), 1);
+        for (window, id) in ids {
+            assert_eq!(ranges[0].contains(&id), (5..=12).contains(&window));
+        }
+    }
+
+    #[test]
+    fn test_id_range_for_merges_runs() {
+        let epoch = UnixTime::from_unix_timestamp(1719792000).unwrap();
+        let config = SINTEFlake::builder()
+            .epoch(epoch)
+            .clock(ManualClock::new(1719792000000))
+            .layout(Layout::new(14, 10, 10, 8).unwrap())
+            .build()
+            .unwrap()
+            .encoding_config();
+        // all the timestamps of the layout, a single run per hash value
+        let ranges: Vec<_> = id_range_for(epoch, epoch + Duration::from_secs(86400), &config)
+            .unwrap()
+            .collect();
+        assert_eq!(ranges.len(), config.layout.buckets());
+        assert_eq!(*ranges[0].start(), 0);
+        assert_eq!(*ranges.last().unwrap().end(), config.layout.max_id());
+    }
+
+    #[test]
+    fn test_id_range_for_feistel() {
+        let config = SINTEFlake::builder()
+            .feistel_permutation(true)
+            .build()
+            .unwrap()
+            .encoding_config();
+        let now = UnixTime::from_unix_timestamp(1719792000).unwrap();
+        assert!(matches!(
+            id_range_for(now, now + Duration::from_secs(3600), &config),
+            Err(SINTEFlakeError::FeistelPermutation)
+        ));
+    }
+}
@@ -0,0 +1,43 @@
+use alloc::boxed::Box;
+use core::fmt;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+/// Random number generator drawing the prefix of the random IDs.
+pub(crate) struct PrefixRng(Box<dyn RngCore + Send>);
+
+impl PrefixRng {
+    pub(crate) fn new<R: RngCore + Send + 'static>(rng: R) -> Self {
+        PrefixRng(Box::new(rng))
+    }
+
+    /// Uses the standard RNG of the `rand` crate, seeded from the OS entropy.
+    pub(crate) fn from_entropy() -> Self {
+        PrefixRng::new(StdRng::from_entropy())
+    }
+
+    pub(crate) fn next_prefix(&mut self) -> u16 {
+        // the hash part is at most 16 bits
+        (self.0.next_u32() & 0xFFFF) as u16
+    }
+}
+
+impl fmt::Debug for PrefixRng {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PrefixRng")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_prefixes_are_reproducible() {
+        let mut rng_a = PrefixRng::new(StdRng::seed_from_u64(42));
+        let mut rng_b = PrefixRng::new(StdRng::seed_from_u64(42));
+        for _ in 0..100 {
+            assert_eq!(rng_a.next_prefix(), rng_b.next_prefix());
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Rate limiting of the ID generation to the capacity of the time windows.
+//!
+//! Without a limit, a burst of IDs can use the whole capacity of a time window early,
+//! and every ID fails with `CounterOverflow` until the next window. With a limit, the
+//! IDs are spread over the window: a token bucket refills at the sustainable rate,
+//! [`Layout::window_capacity`](crate::layout::Layout::window_capacity) IDs per time window,
+//! and holds at most a burst of IDs.
+use core::time::Duration;
+
+/// What happens to an ID requested above the rate limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitPolicy {
+    /// Waits until the ID can be generated.
+    Block,
+
+    /// Returns a `RateLimited` error, retryable.
+    Error,
+}
+
+/// Settings of the rate limit of an instance, see
+/// [`SINTEFlakeBuilder::rate_limit`](crate::builder::SINTEFlakeBuilder::rate_limit).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub(crate) policy: RateLimitPolicy,
+    pub(crate) burst: Option<u64>,
+}
+
+impl RateLimit {
+    /// Waits until the IDs can be generated.
+    pub const fn blocking() -> Self {
+        RateLimit {
+            policy: RateLimitPolicy::Block,
+            burst: None,
+        }
+    }
+
+    /// Returns a `RateLimited` error when the IDs are requested too fast.
+    pub const fn failing() -> Self {
+        RateLimit {
+            policy: RateLimitPolicy::Error,
+            burst: None,
+        }
+    }
+
+    /// Sets how many IDs can be generated at once after an idle period,
+    /// an eighth of the capacity of a time window by default.
+    pub const fn burst(mut self, burst: u64) -> Self {
+        self.burst = Some(burst);
+        self
+    }
+}
+
+/// Token bucket, as the generic cell rate algorithm: instead of counting tokens,
+/// it keeps the time at which the bucket would be full again.
+///
+/// The times are in nanoseconds multiplied by the capacity of a window, so an ID
+/// costs exactly `window` of them.
+#[derive(Debug, Clone)]
+pub(crate) struct RateLimiter {
+    pub(crate) policy: RateLimitPolicy,
+    capacity: u128,
+    window: u128,
+    tolerance: u128,
+    full_at: u128,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: RateLimit, window_capacity: u64, window: Duration) -> Self {
+        let burst = limit
+            .burst
+            .unwrap_or(window_capacity / 8)
+            .clamp(1, window_capacity.max(1)) as u128;
+        let window = window.as_nanos();
+        RateLimiter {
+            policy: limit.policy,
+            capacity: window_capacity.max(1) as u128,
+            window,
+            tolerance: (burst - 1) * window,
+            full_at: 0,
+        }
+    }
+
+    /// Returns how long to wait before an ID can be generated, at the time `now`.
+    pub(crate) fn delay(&self, now: Duration) -> Duration {
+        let now = now.as_nanos() * self.capacity;
+        let ready_at = self.full_at.saturating_sub(self.tolerance);
+        let wait = ready_at.saturating_sub(now).div_ceil(self.capacity);
+        Duration::from_nanos(wait as u64)
+    }
+
+    /// Takes a token at the time `now`, which must be after the delay.
+    pub(crate) fn take(&mut self, now: Duration) {
+        let now = now.as_nanos() * self.capacity;
+        self.full_at = self.full_at.max(now) + self.window;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        // 4 IDs per second, bursts of 2
+        let limit = RateLimit::failing().burst(2);
+        let mut limiter = RateLimiter::new(limit, 4, Duration::from_secs(1));
+        let now = Duration::from_secs(1000);
+
+        assert_eq!(limiter.delay(now), Duration::ZERO);
+        limiter.take(now);
+        assert_eq!(limiter.delay(now), Duration::ZERO);
+        limiter.take(now);
+        // the bucket is empty, a token every 250 ms
+        assert_eq!(limiter.delay(now), Duration::from_millis(250));
+        let later = now + Duration::from_millis(100);
+        assert_eq!(limiter.delay(later), Duration::from_millis(150));
+        let later = now + Duration::from_millis(250);
+        assert_eq!(limiter.delay(later), Duration::ZERO);
+        limiter.take(later);
+        assert_eq!(limiter.delay(later), Duration::from_millis(250));
+
+        // after a long idle period, only a burst is available
+        let idle = now + Duration::from_secs(60);
+        limiter.take(idle);
+        limiter.take(idle);
+        assert!(limiter.delay(idle) > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_default_burst() {
+        let mut limiter = RateLimiter::new(RateLimit::blocking(), 800, Duration::from_secs(8));
+        let now = Duration::from_secs(1000);
+        for _ in 0..100 {
+            assert_eq!(limiter.delay(now), Duration::ZERO);
+            limiter.take(now);
+        }
+        assert_eq!(limiter.delay(now), Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,143 @@
+//! Named global SINTEFlake instances, for processes generating IDs in several ID spaces.
+//!
+//! ```rust
+//! use sinteflake::registry;
+//! use sinteflake::sinteflake::SINTEFlake;
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!   registry::register("orders", SINTEFlake::builder().instance_id(42).build()?)?;
+//!
+//!   let orders = registry::get("orders").expect("registered above");
+//!   let id = orders.next_id()?;
+//!
+//!   Ok(())
+//! }
+//! ```
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::error::SINTEFlakeError;
+use crate::singleton::lock;
+use crate::sinteflake::SINTEFlake;
+
+static REGISTRY: Lazy<Mutex<HashMap<String, RegisteredInstance>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Handle to a named global SINTEFlake instance.
+///
+/// Clones share the same instance.
+#[derive(Clone)]
+pub struct RegisteredInstance(Arc<Mutex<SINTEFlake>>);
+
+impl std::fmt::Debug for RegisteredInstance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegisteredInstance").finish_non_exhaustive()
+    }
+}
+
+impl RegisteredInstance {
+    /// Locks the instance, to call any of its methods.
+    /// Returns an error if the mutex is poisoned.
+    pub fn lock(&self) -> Result<MutexGuard<'_, SINTEFlake>, SINTEFlakeError> {
+        lock(&self.0)
+    }
+
+    /// Sets the instance ID.
+    /// Returns an error if the mutex is poisoned or if the ID is invalid.
+    pub fn set_instance_id(&self, id: u16) -> Result<(), SINTEFlakeError> {
+        self.lock()?.set_instance_id(id)
+    }
+
+    /// Updates the time.
+    /// Returns an error if the mutex is poisoned or if the time update fails.
+    pub fn update_time(&self) -> Result<(), SINTEFlakeError> {
+        self.lock()?.update_time()
+    }
+
+    /// Generates the next unique ID.
+    /// Returns an error if the mutex is poisoned or if ID generation fails.
+    pub fn next_id(&self) -> Result<u64, SINTEFlakeError> {
+        self.lock()?.next_id()
+    }
+
+    /// Generates the next unique ID with a hash.
+    /// Returns an error if the mutex is poisoned or if ID generation fails.
+    pub fn next_id_with_hash(&self, data: &[u8]) -> Result<u64, SINTEFlakeError> {
+        self.lock()?.next_id_with_hash(data)
+    }
+}
+
+/// Registers an instance under a name.
+/// Returns an error if the mutex is poisoned or if the name is already registered.
+pub fn register(name: &str, instance: SINTEFlake) -> Result<RegisteredInstance, SINTEFlakeError> {
+    let mut registry = lock(&REGISTRY)?;
+    if registry.contains_key(name) {
+        return Err(SINTEFlakeError::AlreadyRegistered);
+    }
+    let registered = RegisteredInstance(Arc::new(Mutex::new(instance)));
+    registry.insert(name.to_string(), registered.clone());
+    Ok(registered)
+}
+
+/// Returns the instance registered under a name, if any.
+pub fn get(name: &str) -> Option<RegisteredInstance> {
+    let registry = lock(&REGISTRY).ok()?;
+    registry.get(name).cloned()
+}
+
+/// Removes the instance registered under a name and returns it.
+///
+/// Existing handles keep working, but the name can be registered again.
+pub fn unregister(name: &str) -> Option<RegisteredInstance> {
+    let mut registry = lock(&REGISTRY).ok()?;
+    registry.remove(name)
+}
+
+/// Returns the registered names, in no particular order.
+pub fn names() -> Vec<String> {
+    match lock(&REGISTRY) {
+        Ok(registry) => registry.keys().cloned().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_and_get() {
+        let orders = SINTEFlake::builder()
+            .instance_id(1)
+            .hash_key([1; 16])
+            .build()
+            .unwrap();
+        register("test_orders", orders).unwrap();
+        assert!(names().contains(&"test_orders".to_string()));
+
+        let instance = get("test_orders").unwrap();
+        let id_a = instance.next_id().unwrap();
+        let id_b = get("test_orders").unwrap().next_id().unwrap();
+        assert_ne!(id_a, id_b);
+        assert_eq!(instance.lock().unwrap().ids_in_current_window(), 2);
+
+        assert!(matches!(
+            register("test_orders", SINTEFlake::new().unwrap()),
+            Err(SINTEFlakeError::AlreadyRegistered)
+        ));
+    }
+
+    #[test]
+    fn test_independent_instances() {
+        let users = register("test_users", SINTEFlake::new().unwrap()).unwrap();
+        let invoices = register("test_invoices", SINTEFlake::new().unwrap()).unwrap();
+        users.set_instance_id(3).unwrap();
+        users.next_id_with_hash(b"a").unwrap();
+        assert_eq!(invoices.lock().unwrap().ids_in_current_window(), 0);
+
+        assert!(unregister("test_invoices").is_some());
+        assert!(get("test_invoices").is_none());
+        assert!(get("test_unknown").is_none());
+    }
+}
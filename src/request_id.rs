@@ -0,0 +1,270 @@
+//! Tower middleware giving a SINTEFlake ID to every request.
+//!
+//! ```rust
+//! use sinteflake::request_id::RequestIdLayer;
+//! use tower_layer::Layer;
+//!
+//! # #[derive(Clone)] struct Service;
+//! # let service = Service;
+//! let service = RequestIdLayer::new().layer(service);
+//! ```
+//!
+//! Handlers can read the ID from the [`SINTEFlakeId`] extension of the request,
+//! and the response gets it in the `x-request-id` header. The middleware behaves
+//! like the Actix-web middleware of the `actix` feature.
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use http::{HeaderName, HeaderValue, Request, Response};
+use pin_project_lite::pin_project;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::id::SINTEFlakeId;
+use crate::singleton;
+
+/// Layer generating a [`SINTEFlakeId`] per request, with the global SINTEFlake instance.
+///
+/// The ID is added to the extensions of the request, and to the `x-request-id` header
+/// of both the request and the response. A request already having the header keeps it,
+/// and the response gets it back; its value is in the extensions if it is a decimal ID.
+///
+/// The ID is generated without waiting for the rate limit or the clock, so the async
+/// runtime isn't blocked. Requests are not refused if no ID can be generated, they just
+/// don't get one.
+#[derive(Debug, Clone)]
+pub struct RequestIdLayer {
+    header: HeaderName,
+}
+
+impl RequestIdLayer {
+    /// Creates a layer using the `x-request-id` header.
+    pub fn new() -> Self {
+        RequestIdLayer {
+            header: HeaderName::from_static("x-request-id"),
+        }
+    }
+
+    /// Sets the name of the header.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+}
+
+impl Default for RequestIdLayer {
+    fn default() -> Self {
+        RequestIdLayer::new()
+    }
+}
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestIdService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestIdService {
+            inner,
+            header: self.header.clone(),
+        }
+    }
+}
+
+/// Service added by [`RequestIdLayer`].
+#[derive(Debug, Clone)]
+pub struct RequestIdService<S> {
+    inner: S,
+    header: HeaderName,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RequestIdService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<ReqBody>) -> Self::Future {
+        let value = match request.headers().get(&self.header).cloned() {
+            Some(value) => {
+                if let Some(id) = parse_id(&value) {
+                    request.extensions_mut().insert(id);
+                }
+                Some(value)
+            }
+            None => singleton::next_id_without_waiting().ok().map(|id| {
+                let value = HeaderValue::from(id);
+                request.extensions_mut().insert(SINTEFlakeId::new(id));
+                request
+                    .headers_mut()
+                    .insert(self.header.clone(), value.clone());
+                value
+            }),
+        };
+
+        ResponseFuture {
+            inner: self.inner.call(request),
+            header: self.header.clone(),
+            value,
+        }
+    }
+}
+
+fn parse_id(value: &HeaderValue) -> Option<SINTEFlakeId> {
+    value.to_str().ok()?.parse().ok()
+}
+
+pin_project! {
+    /// Future of [`RequestIdService`], adding the header to the response.
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+        header: HeaderName,
+        value: Option<HeaderValue>,
+    }
+}
+
+impl<F, ResBody, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<ResBody>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut response = match this.inner.poll(cx) {
+            Poll::Ready(Ok(response)) => response,
+            other => return other,
+        };
+        if let Some(value) = this.value.take() {
+            response
+                .headers_mut()
+                .entry(this.header.clone())
+                .or_insert(value);
+        }
+        Poll::Ready(Ok(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use std::future::{ready, Ready};
+
+    /// Service answering with the request ID of the extensions in the body.
+    #[derive(Clone)]
+    struct Echo;
+
+    impl Service<Request<()>> for Echo {
+        type Response = Response<String>;
+        type Error = Infallible;
+        type Future = Ready<Result<Response<String>, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: Request<()>) -> Self::Future {
+            let id = request.extensions().get::<SINTEFlakeId>();
+            ready(Ok(Response::new(
+                id.map(|id| id.to_string()).unwrap_or_default(),
+            )))
+        }
+    }
+
+    /// Service answering with the request ID of the header in the body.
+    #[derive(Clone)]
+    struct EchoHeader;
+
+    impl Service<Request<()>> for EchoHeader {
+        type Response = Response<String>;
+        type Error = Infallible;
+        type Future = Ready<Result<Response<String>, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: Request<()>) -> Self::Future {
+            let header = request.headers()["x-request-id"].to_str().unwrap();
+            ready(Ok(Response::new(header.to_string())))
+        }
+    }
+
+    fn call<S: Service<Request<()>, Response = Response<String>, Error = Infallible>>(
+        service: &mut S,
+    ) -> Response<String> {
+        call_with(service, Request::new(()))
+    }
+
+    fn call_with<S: Service<Request<()>, Response = Response<String>, Error = Infallible>>(
+        service: &mut S,
+        request: Request<()>,
+    ) -> Response<String> {
+        let mut future = std::pin::pin!(service.call(request));
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(Ok(response)) => response,
+            _ => panic!("the echo service is ready immediately"),
+        }
+    }
+
+    #[test]
+    fn test_request_id_layer() {
+        let mut service = RequestIdLayer::new().layer(Echo);
+        let response_a = call(&mut service);
+        let response_b = call(&mut service);
+
+        assert_eq!(
+            response_a.headers()["x-request-id"].to_str().unwrap(),
+            response_a.body()
+        );
+        assert_ne!(response_a.body(), response_b.body());
+
+        // the request gets the header too
+        let mut service = RequestIdLayer::new().layer(EchoHeader);
+        let response = call(&mut service);
+        assert_eq!(
+            response.headers()["x-request-id"].to_str().unwrap(),
+            response.body()
+        );
+    }
+
+    #[test]
+    fn test_custom_header() {
+        let mut service = RequestIdLayer::new()
+            .header(HeaderName::from_static("x-trace-id"))
+            .layer(Echo);
+        let response = call(&mut service);
+        assert!(response.headers().contains_key("x-trace-id"));
+        assert!(!response.headers().contains_key("x-request-id"));
+    }
+
+    #[test]
+    fn test_incoming_header() {
+        let mut service = RequestIdLayer::new().layer(Echo);
+        let request = Request::builder()
+            .header("x-request-id", "1234")
+            .body(())
+            .unwrap();
+        let response = call_with(&mut service, request);
+        assert_eq!(response.headers()["x-request-id"], "1234");
+        assert_eq!(response.body(), "1234");
+
+        // not an ID, kept in the headers only
+        let mut service = RequestIdLayer::new().layer(Echo);
+        let request = Request::builder()
+            .header("x-request-id", "upstream-id")
+            .body(())
+            .unwrap();
+        let response = call_with(&mut service, request);
+        assert_eq!(response.headers()["x-request-id"], "upstream-id");
+        assert_eq!(response.body(), "");
+    }
+}
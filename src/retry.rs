@@ -0,0 +1,108 @@
+use core::time::Duration;
+
+use crate::error::SINTEFlakeError;
+use crate::sinteflake::SINTEFlake;
+
+/// How to retry ID generation on retryable errors, such as `CounterOverflow`.
+///
+/// Between two attempts, the time is updated, after sleeping until the next
+/// time window if `wait_for_next_window` is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt.
+    pub max_retries: u32,
+
+    /// Sleeps until the next time window before retrying.
+    pub wait_for_next_window: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            wait_for_next_window: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns how long to sleep before the next attempt.
+    pub(crate) fn delay(&self, instance: &SINTEFlake) -> Duration {
+        if self.wait_for_next_window {
+            instance.window_remaining()
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// Updates the time before a new attempt.
+///
+/// Retryable errors are ignored, the next attempt will fail again if they persist.
+pub(crate) fn refresh(instance: &mut SINTEFlake) -> Result<(), SINTEFlakeError> {
+    match instance.update_time() {
+        Err(error) if !error.is_retryable() => Err(error),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::tests::ManualClock;
+    use crate::time::UnixTime;
+
+    fn full_instance(clock: &ManualClock) -> SINTEFlake {
+        let mut instance = SINTEFlake::builder()
+            .epoch(UnixTime::from_unix_timestamp(1719792000).unwrap())
+            .layout(crate::layout::Layout::new(1, 31, 10, 1).unwrap())
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        for _ in 0..4 {
+            instance.next_id().unwrap();
+        }
+        instance
+    }
+
+    #[test]
+    fn test_retry_in_next_window() {
+        // the clock moves 5 seconds at every reading
+        let clock = ManualClock::with_step(1719792016000, 5000);
+        let mut instance = full_instance(&clock);
+        let policy = RetryPolicy {
+            max_retries: 3,
+            wait_for_next_window: false,
+        };
+        assert!(instance.next_id_with_retry(policy).is_ok());
+    }
+
+    #[test]
+    fn test_retry_limit() {
+        let clock = ManualClock::new(1719792016000);
+        let mut instance = full_instance(&clock);
+        let policy = RetryPolicy {
+            max_retries: 2,
+            wait_for_next_window: false,
+        };
+        assert!(matches!(
+            instance.next_id_with_retry(policy),
+            Err(SINTEFlakeError::CounterOverflow { .. })
+        ));
+    }
+
+    #[test]
+    fn test_delay() {
+        let clock = ManualClock::new(1719792022000);
+        let instance = full_instance(&clock);
+        assert_eq!(
+            RetryPolicy::default().delay(&instance),
+            Duration::from_secs(2)
+        );
+        let policy = RetryPolicy {
+            max_retries: 3,
+            wait_for_next_window: false,
+        };
+        assert_eq!(policy.delay(&instance), Duration::ZERO);
+    }
+}
@@ -0,0 +1,67 @@
+use alloc::vec::Vec;
+
+use crate::error::SINTEFlakeError;
+use crate::time::UnixTime;
+
+/// Hash keys taking effect at given times, for long-lived deployments rotating their keys.
+///
+/// The generator uses the key in effect at the start of each time window,
+/// or the key set in the builder before the first entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeySchedule {
+    // sorted by effective date
+    entries: Vec<(UnixTime, [u8; 16])>,
+}
+
+impl KeySchedule {
+    /// Creates a schedule from `(effective_from, hash_key)` pairs, in any order.
+    ///
+    /// # Errors
+    /// Returns an error if two keys take effect at the same time.
+    pub fn new(mut entries: Vec<(UnixTime, [u8; 16])>) -> Result<Self, SINTEFlakeError> {
+        entries.sort_by_key(|(effective_from, _)| *effective_from);
+        if entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err(SINTEFlakeError::InvalidKeySchedule);
+        }
+        Ok(KeySchedule { entries })
+    }
+
+    /// Returns the key in effect at the given time, if any.
+    pub fn key_at(&self, time: UnixTime) -> Option<&[u8; 16]> {
+        let index = self
+            .entries
+            .partition_point(|(effective_from, _)| *effective_from <= time);
+        index.checked_sub(1).map(|index| &self.entries[index].1)
+    }
+
+    /// Iterates over the keys, by effective date.
+    pub fn keys(&self) -> impl Iterator<Item = &[u8; 16]> {
+        self.entries.iter().map(|(_, key)| key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_at() {
+        let year_2025 = UnixTime::from_unix_timestamp(1735689600).unwrap();
+        let year_2026 = UnixTime::from_unix_timestamp(1767225600).unwrap();
+        let schedule = KeySchedule::new(vec![(year_2026, [2; 16]), (year_2025, [1; 16])]).unwrap();
+
+        assert_eq!(
+            schedule.key_at(year_2025 - core::time::Duration::from_secs(1)),
+            None
+        );
+        assert_eq!(schedule.key_at(year_2025), Some(&[1; 16]));
+        assert_eq!(
+            schedule.key_at(year_2026 - core::time::Duration::from_secs(1)),
+            Some(&[1; 16])
+        );
+        assert_eq!(schedule.key_at(year_2026), Some(&[2; 16]));
+        assert_eq!(schedule.keys().count(), 2);
+
+        assert!(KeySchedule::new(vec![(year_2025, [1; 16]), (year_2025, [2; 16])]).is_err());
+    }
+}
@@ -0,0 +1,468 @@
+//! A small HTTP service generating IDs from a central instance, and its client.
+//!
+//! The service speaks plain HTTP/1.1 with text bodies, so any language can call it:
+//!
+//! - `POST /next_id` returns an ID.
+//! - `POST /next_id_with_hash` returns an ID with the hash of the request body.
+//! - `POST /reserve_block?n=<n>` returns `n` IDs, one per line.
+//! - `GET /healthz` returns the [`HealthReport`](crate::health::HealthReport) of the instance
+//!   as JSON, with the status `503` if it is unhealthy.
+//!
+//! IDs are written in decimal. Retryable errors, like a full time window,
+//! are returned with the status `503`, and a body whose first line names the error:
+//! `counter_overflow <bucket> <window>`, `rate_limited`, `clock_rollback` or
+//! `clock_behind_saved_state`.
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::error::SINTEFlakeError;
+use crate::singleton::lock;
+use crate::sinteflake::SINTEFlake;
+
+// requests bigger than this are refused
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+// blocks bigger than this are refused
+const MAX_BLOCK_SIZE: usize = 65536;
+
+// pause after a failed accept, so errors like a full file table don't spin
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(10);
+
+/// HTTP service generating IDs from a shared instance.
+pub struct IdServer {
+    listener: TcpListener,
+    instance: Arc<Mutex<SINTEFlake>>,
+    max_connections: usize,
+}
+
+impl IdServer {
+    /// Binds the service to an address.
+    pub fn bind<A: ToSocketAddrs>(address: A, instance: SINTEFlake) -> io::Result<Self> {
+        Ok(IdServer {
+            listener: TcpListener::bind(address)?,
+            instance: Arc::new(Mutex::new(instance)),
+            max_connections: 64,
+        })
+    }
+
+    /// Sets the maximum number of connections served at once, 64 by default.
+    ///
+    /// Further connections wait in the listen backlog until one is closed.
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections.max(1);
+        self
+    }
+
+    /// The address the service is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serves requests forever, with a thread per connection.
+    ///
+    /// Failing to accept a connection doesn't stop the service.
+    pub fn serve(self) -> io::Result<()> {
+        let slots = Arc::new(ConnectionSlots::new(self.max_connections));
+        loop {
+            let slot = ConnectionSlots::acquire(&slots);
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(_error) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(error = %_error, "failed to accept a connection");
+                    std::thread::sleep(ACCEPT_ERROR_DELAY);
+                    continue;
+                }
+            };
+            let instance = self.instance.clone();
+            std::thread::spawn(move || {
+                let _ = handle_connection(stream, &instance);
+                drop(slot);
+            });
+        }
+    }
+}
+
+impl fmt::Debug for IdServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IdServer")
+            .field("listener", &self.listener)
+            .field("max_connections", &self.max_connections)
+            .finish_non_exhaustive()
+    }
+}
+
+// counts the connections being served, to bound the number of threads
+struct ConnectionSlots {
+    free: Mutex<usize>,
+    released: Condvar,
+}
+
+impl ConnectionSlots {
+    fn new(max_connections: usize) -> Self {
+        ConnectionSlots {
+            free: Mutex::new(max_connections),
+            released: Condvar::new(),
+        }
+    }
+
+    // waits for a free slot, held until the returned guard is dropped
+    fn acquire(slots: &Arc<Self>) -> ConnectionSlot {
+        let mut free = slots.free.lock().unwrap_or_else(|e| e.into_inner());
+        while *free == 0 {
+            free = slots.released.wait(free).unwrap_or_else(|e| e.into_inner());
+        }
+        *free -= 1;
+        ConnectionSlot(slots.clone())
+    }
+}
+
+struct ConnectionSlot(Arc<ConnectionSlots>);
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        *self.0.free.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+        self.0.released.notify_one();
+    }
+}
+
+fn handle_connection(stream: TcpStream, instance: &Mutex<SINTEFlake>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(usize::MAX);
+            }
+        }
+    }
+
+    let (status, body) = if content_length > MAX_BODY_SIZE {
+        (413, "request too large\n".to_string())
+    } else {
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        respond(method, target, &body, instance)
+    };
+
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\ncontent-type: text/plain\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn respond(method: &str, target: &str, body: &[u8], instance: &Mutex<SINTEFlake>) -> (u16, String) {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if path == "/healthz" {
+        if method != "GET" {
+            return (405, "use GET\n".to_string());
+        }
+        return match lock(instance) {
+            Ok(instance) => {
+                let report = instance.health_check();
+                let status = if report.is_healthy() { 200 } else { 503 };
+                (status, format!("{}\n", report.to_json()))
+            }
+            Err(error) => (500, format!("{}\n", error)),
+        };
+    }
+    if !matches!(path, "/next_id" | "/next_id_with_hash" | "/reserve_block") {
+        return (404, "not found\n".to_string());
+    }
+    if method != "POST" {
+        return (405, "use POST\n".to_string());
+    }
+
+    let mut instance = match lock(instance) {
+        Ok(instance) => instance,
+        Err(error) => return (500, format!("{}\n", error)),
+    };
+    let result = match path {
+        "/next_id" => instance.next_id().map(|id| format!("{}\n", id)),
+        "/next_id_with_hash" => instance
+            .next_id_with_hash(body)
+            .map(|id| format!("{}\n", id)),
+        _ => {
+            let n = query
+                .split('&')
+                .find_map(|parameter| parameter.strip_prefix("n="))
+                .and_then(|n| n.parse::<usize>().ok());
+            match n {
+                Some(n) if n <= MAX_BLOCK_SIZE => instance
+                    .reserve_block(n)
+                    .map(|block| block.map(|id| format!("{}\n", id)).collect()),
+                _ => return (400, format!("n must be at most {}\n", MAX_BLOCK_SIZE)),
+            }
+        }
+    };
+
+    match result {
+        Ok(body) => (200, body),
+        Err(error) if error.is_retryable() => (503, format!("{}\n{}\n", error_kind(&error), error)),
+        Err(error) => (500, format!("{}\n", error)),
+    }
+}
+
+// first line of the body of a retryable error, parsed back by `parse_error_kind`
+fn error_kind(error: &SINTEFlakeError) -> String {
+    match error {
+        SINTEFlakeError::CounterOverflow { bucket, window } => {
+            format!("counter_overflow {} {}", bucket, window)
+        }
+        SINTEFlakeError::RateLimited => "rate_limited".to_string(),
+        SINTEFlakeError::ClockRollback => "clock_rollback".to_string(),
+        SINTEFlakeError::ClockBehindSavedState => "clock_behind_saved_state".to_string(),
+        _ => "error".to_string(),
+    }
+}
+
+fn parse_error_kind(line: &str) -> Option<SINTEFlakeError> {
+    let mut words = line.split_whitespace();
+    match words.next()? {
+        "counter_overflow" => Some(SINTEFlakeError::CounterOverflow {
+            bucket: words.next()?.parse().ok()?,
+            window: words.next()?.parse().ok()?,
+        }),
+        "rate_limited" => Some(SINTEFlakeError::RateLimited),
+        "clock_rollback" => Some(SINTEFlakeError::ClockRollback),
+        "clock_behind_saved_state" => Some(SINTEFlakeError::ClockBehindSavedState),
+        _ => None,
+    }
+}
+
+/// Client of an [`IdServer`].
+#[derive(Debug, Clone)]
+pub struct IdClient {
+    address: SocketAddr,
+    timeout: Duration,
+}
+
+impl IdClient {
+    /// Creates a client for the service at `address`.
+    pub fn new(address: SocketAddr) -> Self {
+        IdClient {
+            address,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Sets the timeout of the requests, 10 seconds by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Gets the next ID from the service.
+    ///
+    /// # Errors
+    /// Returns a `ServiceError` if the service can't be reached or fails,
+    /// or the retryable error of the service, like `CounterOverflow` if it is
+    /// temporarily out of IDs.
+    pub fn next_id(&self) -> Result<u64, SINTEFlakeError> {
+        let ids = self.request("/next_id", &[])?;
+        ids.first().copied().ok_or(SINTEFlakeError::ServiceError)
+    }
+
+    /// Gets the next ID with the hash of `data` from the service.
+    ///
+    /// # Errors
+    /// Same as [`IdClient::next_id`].
+    pub fn next_id_with_hash(&self, data: &[u8]) -> Result<u64, SINTEFlakeError> {
+        let ids = self.request("/next_id_with_hash", data)?;
+        ids.first().copied().ok_or(SINTEFlakeError::ServiceError)
+    }
+
+    /// Reserves `n` IDs from the service.
+    ///
+    /// # Errors
+    /// Same as [`IdClient::next_id`].
+    pub fn reserve_block(&self, n: usize) -> Result<Vec<u64>, SINTEFlakeError> {
+        self.request(&format!("/reserve_block?n={}", n), &[])
+    }
+
+    fn request(&self, target: &str, body: &[u8]) -> Result<Vec<u64>, SINTEFlakeError> {
+        let response = self
+            .send(target, body)
+            .map_err(|_| SINTEFlakeError::ServiceError)?;
+        let (head, body) = response
+            .split_once("\r\n\r\n")
+            .ok_or(SINTEFlakeError::ServiceError)?;
+        match head.split_whitespace().nth(1) {
+            Some("200") => body
+                .lines()
+                .map(|line| line.parse().map_err(|_| SINTEFlakeError::ServiceError))
+                .collect(),
+            Some("503") => Err(body
+                .lines()
+                .next()
+                .and_then(parse_error_kind)
+                .unwrap_or(SINTEFlakeError::ServiceError)),
+            _ => Err(SINTEFlakeError::ServiceError),
+        }
+    }
+
+    fn send(&self, target: &str, body: &[u8]) -> io::Result<String> {
+        let mut stream = TcpStream::connect_timeout(&self.address, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nhost: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+            target,
+            self.address,
+            body.len()
+        )?;
+        stream.write_all(body)?;
+        stream.flush()?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::Layout;
+    use crate::rate_limit::RateLimit;
+
+    fn start_server(instance: SINTEFlake) -> IdClient {
+        let server = IdServer::bind("127.0.0.1:0", instance).unwrap();
+        let address = server.local_addr().unwrap();
+        std::thread::spawn(move || server.serve());
+        IdClient::new(address)
+    }
+
+    #[test]
+    fn test_server() {
+        let client = start_server(SINTEFlake::new().unwrap());
+
+        let id_a = client.next_id().unwrap();
+        let id_b = client.next_id().unwrap();
+        assert_ne!(id_a, id_b);
+
+        let id_c = client.next_id_with_hash(b"data").unwrap();
+        let id_d = client.next_id_with_hash(b"data").unwrap();
+        assert_eq!(id_c >> 49, id_d >> 49);
+
+        let block = client.reserve_block(100).unwrap();
+        assert_eq!(block.len(), 100);
+        assert!(client.reserve_block(MAX_BLOCK_SIZE + 1).is_err());
+    }
+
+    #[test]
+    fn test_server_overflow() {
+        let instance = SINTEFlake::builder()
+            .layout(Layout::new(1, 31, 10, 1).unwrap())
+            .build()
+            .unwrap();
+        let client = start_server(instance);
+        // 4 IDs per time window
+        let result = client.reserve_block(5);
+        assert!(matches!(
+            result,
+            Err(SINTEFlakeError::CounterOverflow { window, .. }) if window > 0
+        ));
+    }
+
+    #[test]
+    fn test_server_rate_limited() {
+        let instance = SINTEFlake::builder()
+            .layout(Layout::new(1, 31, 10, 1).unwrap())
+            .rate_limit(RateLimit::failing().burst(1))
+            .build()
+            .unwrap();
+        let client = start_server(instance);
+        client.next_id().unwrap();
+        assert!(matches!(
+            client.next_id(),
+            Err(SINTEFlakeError::RateLimited)
+        ));
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        for error in [
+            SINTEFlakeError::CounterOverflow {
+                bucket: 3,
+                window: 70,
+            },
+            SINTEFlakeError::RateLimited,
+            SINTEFlakeError::ClockRollback,
+            SINTEFlakeError::ClockBehindSavedState,
+        ] {
+            let parsed = parse_error_kind(&error_kind(&error)).unwrap();
+            assert_eq!(parsed.to_string(), error.to_string());
+        }
+        assert!(parse_error_kind("something else").is_none());
+    }
+
+    #[test]
+    fn test_max_connections() {
+        let server = IdServer::bind("127.0.0.1:0", SINTEFlake::new().unwrap())
+            .unwrap()
+            .max_connections(1);
+        let address = server.local_addr().unwrap();
+        std::thread::spawn(move || server.serve());
+        let client = IdClient::new(address);
+
+        // an idle connection holds the only slot until it times out or closes
+        let idle = TcpStream::connect(address).unwrap();
+        let waiting = std::thread::spawn(move || client.next_id());
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!waiting.is_finished());
+        drop(idle);
+        assert!(waiting.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_healthz() {
+        let client = start_server(SINTEFlake::new().unwrap());
+        let mut stream = TcpStream::connect(client.address).unwrap();
+        write!(stream, "GET /healthz HTTP/1.1\r\nconnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains("\"healthy\":true"));
+
+        let response = client.send("/healthz", &[]).unwrap();
+        assert!(response.starts_with("HTTP/1.1 405"));
+    }
+
+    #[test]
+    fn test_unknown_path() {
+        let client = start_server(SINTEFlake::new().unwrap());
+        let response = client.send("/unknown", &[]).unwrap();
+        assert!(response.starts_with("HTTP/1.1 404"));
+    }
+}
@@ -0,0 +1,97 @@
+//! Routing of IDs to shards with the hash prefix.
+//!
+//! The hash buckets are split into `num_shards` ranges of consecutive buckets. As the hash
+//! is the first field of the IDs, each shard is also a range of IDs, which suits range
+//! partitioning. The IDs made from a hash stay in the same shard, apart from the IDs that
+//! overflow to other buckets.
+//!
+//! ```rust
+//! use sinteflake::layout::Layout;
+//! use sinteflake::shard::shard_of;
+//! use sinteflake::sinteflake::SINTEFlake;
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!   let mut instance = SINTEFlake::new()?;
+//!   let id = instance.next_id_in_shard(3, 8)?;
+//!   assert_eq!(shard_of(id, 8, &Layout::DEFAULT), 3);
+//!
+//!   Ok(())
+//! }
+//! ```
+use core::ops::Range;
+
+use crate::error::SINTEFlakeError;
+use crate::layout::Layout;
+
+/// Returns the hash field of an ID, its first bits.
+pub const fn hash_prefix(id: u64, layout: &Layout) -> u16 {
+    layout.deconstruct_identifier(id).0
+}
+
+/// Returns the shard of an ID, from 0 to `num_shards - 1`.
+///
+/// # Panics
+/// Panics if `num_shards` is 0.
+pub fn shard_of(id: u64, num_shards: u16, layout: &Layout) -> u16 {
+    assert!(num_shards > 0, "num_shards must not be zero");
+    (hash_prefix(id, layout) as usize * num_shards as usize / layout.buckets()) as u16
+}
+
+/// Returns the hash buckets of a shard.
+///
+/// # Errors
+/// Returns `InvalidShard` if the shard is not below `num_shards`, or has no bucket
+/// because there are more shards than buckets.
+pub(crate) fn shard_buckets(
+    shard: u16,
+    num_shards: u16,
+    layout: &Layout,
+) -> Result<Range<usize>, SINTEFlakeError> {
+    let (shard, num_shards, buckets) = (shard as usize, num_shards as usize, layout.buckets());
+    if shard >= num_shards {
+        return Err(SINTEFlakeError::InvalidShard);
+    }
+    // the buckets b such that b * num_shards / buckets == shard
+    let first = (shard * buckets).div_ceil(num_shards);
+    let end = ((shard + 1) * buckets).div_ceil(num_shards);
+    if first == end {
+        return Err(SINTEFlakeError::InvalidShard);
+    }
+    Ok(first..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_prefix() {
+        let layout = Layout::DEFAULT;
+        let id = layout.construct_identifier(0x1a2b, 5, 42, 7);
+        assert_eq!(hash_prefix(id, &layout), 0x1a2b);
+        assert_eq!(shard_of(id, 1, &layout), 0);
+        assert_eq!(shard_of(id, 2, &layout), 0);
+        assert_eq!(shard_of(id, 4, &layout), 1);
+        assert_eq!(shard_of(layout.max_id(), 7, &layout), 6);
+    }
+
+    #[test]
+    fn test_shard_buckets() {
+        let layout = Layout::new(4, 31, 10, 8).unwrap();
+        for num_shards in 1..=16 {
+            let mut next = 0;
+            for shard in 0..num_shards {
+                let buckets = shard_buckets(shard, num_shards, &layout).unwrap();
+                assert_eq!(buckets.start, next);
+                for bucket in buckets.clone() {
+                    let id = layout.construct_identifier(bucket as u16, 0, 0, 0);
+                    assert_eq!(shard_of(id, num_shards, &layout), shard);
+                }
+                next = buckets.end;
+            }
+            assert_eq!(next, 16);
+            assert!(shard_buckets(num_shards, num_shards, &layout).is_err());
+        }
+        assert!(shard_buckets(16, 17, &layout).is_err());
+    }
+}
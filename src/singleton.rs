@@ -1,37 +1,213 @@
-use once_cell::sync::Lazy;
-use std::sync::Mutex;
+use once_cell::sync::OnceCell;
+use std::sync::{Mutex, MutexGuard, TryLockError};
 
+use crate::block::IdBlock;
 use crate::error::SINTEFlakeError;
+use crate::retry::{self, RetryPolicy};
 use crate::sinteflake::SINTEFlake;
 
-static SINTEFLAKE: Lazy<Mutex<SINTEFlake>> =
-    Lazy::new(|| Mutex::new(SINTEFlake::new().expect("Failed to create SINTEFlake instance")));
+// with the parking_lot feature, the global instance is behind a faster mutex, without poisoning
+#[cfg(not(feature = "parking_lot"))]
+type GlobalMutex = Mutex<SINTEFlake>;
+#[cfg(feature = "parking_lot")]
+type GlobalMutex = parking_lot::Mutex<SINTEFlake>;
+
+#[cfg(not(feature = "parking_lot"))]
+pub(crate) type GlobalGuard = MutexGuard<'static, SINTEFlake>;
+#[cfg(feature = "parking_lot")]
+pub(crate) type GlobalGuard = parking_lot::MutexGuard<'static, SINTEFlake>;
+
+static SINTEFLAKE: OnceCell<GlobalMutex> = OnceCell::new();
+
+/// Returns the global SINTEFlake instance, created with the default settings on first use.
+fn global() -> Result<&'static GlobalMutex, SINTEFlakeError> {
+    SINTEFLAKE.get_or_try_init(|| SINTEFlake::new().map(GlobalMutex::new))
+}
+
+/// Locks the global SINTEFlake instance, see [`lock`].
+pub(crate) fn lock_global() -> Result<GlobalGuard, SINTEFlakeError> {
+    #[cfg(not(feature = "parking_lot"))]
+    {
+        lock(global()?)
+    }
+    #[cfg(feature = "parking_lot")]
+    {
+        Ok(global()?.lock())
+    }
+}
+
+/// Locks the global SINTEFlake instance if it is not locked already, see [`try_lock`].
+pub(crate) fn try_lock_global() -> Result<GlobalGuard, SINTEFlakeError> {
+    #[cfg(not(feature = "parking_lot"))]
+    {
+        try_lock(global()?)
+    }
+    #[cfg(feature = "parking_lot")]
+    {
+        global()?.try_lock().ok_or(SINTEFlakeError::WouldBlock)
+    }
+}
+
+/// Locks a mutex of the crate.
+///
+/// A panic while the mutex was locked poisons it. By default the poison is ignored,
+/// so the instance keeps working after an unrelated panic. With the `poison-error`
+/// feature, a `MutexError` is returned instead.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> Result<MutexGuard<'_, T>, SINTEFlakeError> {
+    #[cfg(not(feature = "poison-error"))]
+    {
+        Ok(mutex
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner))
+    }
+    #[cfg(feature = "poison-error")]
+    {
+        mutex.lock().map_err(|_| SINTEFlakeError::MutexError)
+    }
+}
+
+/// Locks a mutex of the crate if it is not locked already, like [`lock`] otherwise.
+#[cfg_attr(feature = "parking_lot", allow(dead_code))]
+pub(crate) fn try_lock<T>(mutex: &Mutex<T>) -> Result<MutexGuard<'_, T>, SINTEFlakeError> {
+    match mutex.try_lock() {
+        Ok(guard) => Ok(guard),
+        Err(TryLockError::WouldBlock) => Err(SINTEFlakeError::WouldBlock),
+        #[cfg(not(feature = "poison-error"))]
+        Err(TryLockError::Poisoned(poison)) => Ok(poison.into_inner()),
+        #[cfg(feature = "poison-error")]
+        Err(TryLockError::Poisoned(_)) => Err(SINTEFlakeError::MutexError),
+    }
+}
+
+/// Installs a configured instance as the global SINTEFlake instance.
+///
+/// It must be called before any other function of the global instance,
+/// otherwise an instance with the default settings has already been created.
+/// Returns an error if the global instance already exists.
+pub fn init_global(instance: SINTEFlake) -> Result<(), SINTEFlakeError> {
+    SINTEFLAKE
+        .set(GlobalMutex::new(instance))
+        .map_err(|_| SINTEFlakeError::AlreadyInitialized)
+}
+
+/// Replaces the global SINTEFlake instance and returns the previous one.
+///
+/// Meant for tests that need an isolated global instance.
+/// Returns an error if the mutex is poisoned or if the default instance can't be created.
+#[cfg(any(test, feature = "test-util"))]
+pub fn replace_global(instance: SINTEFlake) -> Result<SINTEFlake, SINTEFlakeError> {
+    let mut global = lock_global()?;
+    Ok(std::mem::replace(&mut *global, instance))
+}
+
+/// Replaces the global SINTEFlake instance by a new one with the default settings.
+///
+/// Meant for tests that need an isolated global instance. The global instance stays
+/// initialized, so [`init_global`] still fails afterwards, use [`replace_global`] instead.
+/// Returns an error if the mutex is poisoned or if the instance can't be created.
+#[cfg(any(test, feature = "test-util"))]
+pub fn reset_global() -> Result<(), SINTEFlakeError> {
+    replace_global(SINTEFlake::new()?).map(|_| ())
+}
 
 /// Sets the instance ID for the global SINTEFlake instance.
 /// Returns an error if the mutex is poisoned or if the ID is invalid.
 pub fn set_instance_id(id: u16) -> Result<(), SINTEFlakeError> {
-    let mut instance = SINTEFLAKE.lock().map_err(|_| SINTEFlakeError::MutexError)?;
+    let mut instance = lock_global()?;
     instance.set_instance_id(id)
 }
 
+/// Sleeps until the clock is back to the last used time window of the global instance,
+/// with the `Wait` clock rollback policy, without locking it.
+fn wait_for_clock() -> Result<(), SINTEFlakeError> {
+    loop {
+        let delay = lock_global()?.clock_rollback_delay();
+        if delay.is_zero() {
+            return Ok(());
+        }
+        std::thread::sleep(delay);
+    }
+}
+
 /// Update the time for the global SINTEFlake instance.
+/// The global instance is not locked while waiting for the clock after a rollback.
 /// Returns an error if the mutex is poisoned or if the time update fails.
 pub fn update_time() -> Result<(), SINTEFlakeError> {
-    let mut instance = SINTEFLAKE.lock().map_err(|_| SINTEFlakeError::MutexError)?;
+    wait_for_clock()?;
+    let mut instance = lock_global()?;
     instance.update_time()
 }
 
 /// Generates the next unique ID using the global SINTEFlake instance.
 /// Returns an error if the mutex is poisoned or if ID generation fails.
 pub fn next_id() -> Result<u64, SINTEFlakeError> {
-    let mut instance = SINTEFLAKE.lock().map_err(|_| SINTEFlakeError::MutexError)?;
+    let mut instance = lock_global()?;
+    instance.next_id()
+}
+
+/// Generates the next unique ID using the global SINTEFlake instance, if it isn't locked.
+///
+/// Returns a `WouldBlock` error instead of waiting when another thread is using the global
+/// instance, so latency-sensitive code can fall back to a local generator.
+/// Returns an error if the mutex is poisoned or if ID generation fails.
+pub fn try_next_id() -> Result<u64, SINTEFlakeError> {
+    let mut instance = try_lock_global()?;
     instance.next_id()
 }
 
+/// Generates the next unique ID using the global SINTEFlake instance, without sleeping.
+///
+/// Returns a `WouldBlock` error instead of waiting for the rate limit or for the clock
+/// after a rollback, for the middlewares running in async code. The global instance is
+/// only locked for the generation itself.
+#[cfg(any(feature = "tower", feature = "actix"))]
+pub(crate) fn next_id_without_waiting() -> Result<u64, SINTEFlakeError> {
+    let mut instance = lock_global()?;
+    if !instance.rate_limit_delay().is_zero() || !instance.clock_rollback_delay().is_zero() {
+        return Err(SINTEFlakeError::WouldBlock);
+    }
+    instance.next_id()
+}
+
+/// Generates the next unique ID using the global SINTEFlake instance,
+/// retrying on retryable errors such as `CounterOverflow`.
+/// The global instance is not locked while sleeping until the next time window.
+/// Returns an error if the mutex is poisoned or if all the attempts failed.
+pub fn next_id_with_retry(policy: RetryPolicy) -> Result<u64, SINTEFlakeError> {
+    let mut retries = 0;
+    loop {
+        if retries > 0 {
+            wait_for_clock()?;
+        }
+        let delay = {
+            let mut instance = lock_global()?;
+            if retries > 0 {
+                retry::refresh(&mut instance)?;
+            }
+            match instance.next_id() {
+                Err(error) if error.is_retryable() && retries < policy.max_retries => {
+                    policy.delay(&instance)
+                }
+                result => return result,
+            }
+        };
+        retries += 1;
+        std::thread::sleep(delay);
+    }
+}
+
+/// Reserves a block of `n` IDs from the global SINTEFlake instance.
+/// The IDs of the block are consumed without locking the global instance.
+/// Returns an error if the mutex is poisoned or if the time window has less than `n` IDs left.
+pub fn reserve_block(n: usize) -> Result<IdBlock, SINTEFlakeError> {
+    let mut instance = lock_global()?;
+    instance.reserve_block(n)
+}
+
 /// Generates the next unique ID with a hash using the global SINTEFlake instance.
 /// Returns an error if the mutex is poisoned or if ID generation fails.
 pub fn next_id_with_hash(data: &[u8]) -> Result<u64, SINTEFlakeError> {
-    let mut instance = SINTEFLAKE.lock().map_err(|_| SINTEFlakeError::MutexError)?;
+    let mut instance = lock_global()?;
     instance.next_id_with_hash(data)
 }
 
@@ -46,6 +222,14 @@ mod tests {
         assert_ne!(id_a, id_b);
     }
 
+    #[test]
+    fn test_reserve_block() {
+        let block = reserve_block(100).unwrap();
+        assert_eq!(block.len(), 100);
+        let ids: std::collections::HashSet<_> = block.collect();
+        assert_eq!(ids.len(), 100);
+    }
+
     #[test]
     fn test_with_hash() {
         let data = [1, 2, 3];
@@ -62,6 +246,68 @@ mod tests {
         assert_ne!(id_a, id_b);
     }
 
+    #[test]
+    fn test_init_global_after_use() {
+        next_id().unwrap();
+        assert!(matches!(
+            init_global(SINTEFlake::new().unwrap()),
+            Err(SINTEFlakeError::AlreadyInitialized)
+        ));
+    }
+
+    #[test]
+    fn test_replace_global() {
+        let instance = SINTEFlake::builder().hash_key([7; 16]).build().unwrap();
+        replace_global(instance).unwrap();
+        next_id().unwrap();
+        reset_global().unwrap();
+        let id_a = next_id().unwrap();
+        let id_b = next_id().unwrap();
+        assert_ne!(id_a, id_b);
+    }
+
+    #[test]
+    fn test_lock_poisoned_mutex() {
+        let mutex = Mutex::new(42);
+        let _ = std::panic::catch_unwind(|| {
+            let _guard = mutex.lock().unwrap();
+            panic!("unrelated panic");
+        });
+        assert!(mutex.is_poisoned());
+
+        #[cfg(not(feature = "poison-error"))]
+        assert_eq!(*lock(&mutex).unwrap(), 42);
+        #[cfg(feature = "poison-error")]
+        assert!(matches!(lock(&mutex), Err(SINTEFlakeError::MutexError)));
+    }
+
+    #[test]
+    fn test_try_lock() {
+        let mutex = Mutex::new(42);
+        {
+            let _guard = lock(&mutex).unwrap();
+            assert!(matches!(try_lock(&mutex), Err(SINTEFlakeError::WouldBlock)));
+        }
+        assert_eq!(*try_lock(&mutex).unwrap(), 42);
+
+        // the other tests may be using the global instance
+        match try_lock_global() {
+            Ok(_) | Err(SINTEFlakeError::WouldBlock) => {}
+            Err(error) => panic!("{}", error),
+        }
+        match try_next_id() {
+            Ok(_) | Err(SINTEFlakeError::WouldBlock) => {}
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    #[test]
+    fn test_next_id_with_retry() {
+        let id_a = next_id_with_retry(RetryPolicy::default()).unwrap();
+        let id_b = next_id_with_retry(RetryPolicy::default()).unwrap();
+        assert_ne!(id_a, id_b);
+    }
+
     #[test]
     fn test_update_time() {
         update_time().unwrap();
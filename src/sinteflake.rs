@@ -1,9 +1,45 @@
-use crate::bits::construct_identifier;
+#[cfg(feature = "std")]
+use crate::allocator::LeaseStatus;
+use crate::block::IdBlock;
+use crate::builder::SINTEFlakeBuilder;
+use crate::clock::{self, Clock, ClockRollbackPolicy};
+use crate::collisions::CollisionsMap;
+use crate::decode::EncodingConfig;
+use crate::epoch::Epoch;
 use crate::error::SINTEFlakeError;
-use crate::hash;
-use crate::permute::{permute_u32_31_bits, permute_u8};
-use crate::time::get_current_timestamp;
-use ::time::OffsetDateTime;
+use crate::feistel::FeistelPermutation;
+use crate::guard::DuplicateGuard;
+use crate::hash::{self, KeyedHasher};
+use crate::health::{ClockStatus, HealthReport, LeaseHealth};
+use crate::hook::EventHook;
+use crate::id::{Entity, TypedId};
+#[cfg(feature = "std")]
+use crate::instance::InstanceIdSource;
+use crate::layout::Layout;
+use crate::permute::PermutationTables;
+use crate::probing::ProbingStrategy;
+#[cfg(feature = "rand")]
+use crate::random::PrefixRng;
+use crate::rate_limit::{RateLimitPolicy, RateLimiter};
+use crate::retry::{self, RetryPolicy};
+use crate::rotation::KeySchedule;
+use crate::shard;
+use crate::snowflake::{decode_snowflake, SnowflakeDialect};
+use crate::stable::StableIds;
+use crate::state::{State, StateRecoveryPolicy, StateStore};
+use crate::time::{
+    add_millis_saturating, elapsed_since_epoch, timestamp_of, IntoUnixTime, UnixTime,
+};
+#[cfg(feature = "std")]
+use crate::uniformity::{self, UniformityReport};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::hash::{Hash, Hasher};
+use core::ops::Range;
+use core::time::Duration;
 
 /// SINTEFlake is a 64-bit ID generator inspired by Twitter's Snowflake and Sony's Sonyflake.
 /// It generates unique identifiers that start with a hash or a pseudo-random number instead of a timestamp.
@@ -11,211 +47,2722 @@ pub struct SINTEFlake {
     instance_id: u16,
 
     hash_key: [u8; 16],
+    // hash key used before the first entry of the key schedule
+    base_hash_key: [u8; 16],
+    key_schedule: Option<KeySchedule>,
+
+    hash_algorithm: Arc<dyn hash::IdHasher>,
 
     counter_key: u8,
 
-    epoch: OffsetDateTime,
+    epoch: UnixTime,
+    epoch_id: Option<&'static str>,
+
+    layout: Layout,
+    probing_strategy: ProbingStrategy,
+    probe_attempts: u32,
+    bucket_capacity: u16,
+    sequence_mode: bool,
+    monotonic_sequence: bool,
+    feistel_permutation: Option<FeistelPermutation>,
+    tenant_bits: u8,
+    permutation_tables: PermutationTables,
+    sequence_permutation: [u8; 256],
+
+    time_window: Duration,
+
+    clock: Arc<dyn Clock>,
+
+    clock_rollback_policy: ClockRollbackPolicy,
+
+    last_timestamp: Option<u64>,
+
+    state_store: Option<Arc<dyn StateStore>>,
+
+    event_hook: Option<Arc<dyn EventHook>>,
+
+    #[cfg(feature = "std")]
+    lease_status: Option<Arc<LeaseStatus>>,
+
+    // timestamp to use until the clock catches up with a time window saved before a restart
+    minimum_timestamp: u64,
+
+    collisions_map: CollisionsMap, // 2^hash_bits, or empty in sequence mode
+
+    stable_ids: StableIds,
+    duplicate_guard: Option<DuplicateGuard>,
+
+    // keys with reserved buckets, see reserve_buckets
+    hot_keys: Vec<HotKey>,
+
+    rate_limiter: Option<RateLimiter>,
+
+    // settings applied at the next time window
+    pending_config: Option<PendingConfig>,
+
+    // with OverflowPolicy::BorrowPrecedingWindow
+    preceding_window: Option<PrecedingWindow>,
+
+    current_timestamp_bits: u64,
+
+    ids_count_at_current_timestamp: u64,
+
+    #[cfg(feature = "rand")]
+    rng: PrefixRng,
+}
+
+impl SINTEFlake {
+    /// Creates a new SINTEFlake instance with default settings.
+    ///
+    /// # Returns
+    /// - `Result<Self, SINTEFlakeError>`: A new SINTEFlake instance or an error if creation fails.
+    ///
+    /// # Errors
+    /// Returns an error if the initial time update fails.
+    pub fn new() -> Result<Self, SINTEFlakeError> {
+        SINTEFlakeBuilder::new().build()
+    }
+
+    /// Returns a builder to create a SINTEFlake instance with custom settings.
+    pub fn builder() -> SINTEFlakeBuilder {
+        SINTEFlakeBuilder::new()
+    }
+
+    /// Creates a custom SINTEFlake instance with specified settings.
+    ///
+    /// # Arguments
+    /// * `instance_id` - A 10-bit unsigned integer representing the instance ID.
+    /// * `hash_key` - A 16-byte array used as the key for hashing.
+    /// * `counter_key` - An 8-bit unsigned integer used to XOR the counter.
+    /// * `epoch` - The epoch time from which to measure timestamps, any [`IntoUnixTime`] like
+    ///   a [`UnixTime`], or a `time::OffsetDateTime` with the `time` feature.
+    ///
+    /// # Returns
+    /// - `Result<Self, SINTEFlakeError>`: A new SINTEFlake instance or an error if creation fails.
+    ///
+    /// # Errors
+    /// Returns an error if the instance_id is too high (>1023) or if the initial time update fails.
+    pub fn custom(
+        instance_id: u16,
+        hash_key: [u8; 16],
+        counter_key: u8,
+        epoch: impl IntoUnixTime,
+    ) -> Result<Self, SINTEFlakeError> {
+        SINTEFlakeBuilder::new()
+            .instance_id(instance_id)
+            .hash_key(hash_key)
+            .counter_key(counter_key)
+            .epoch(epoch)
+            .build()
+    }
+
+    /// Creates a SINTEFlake instance with an instance ID derived from the network identity.
+    ///
+    /// The low bits of the private IP address are used, or of the MAC address
+    /// if the host has no private IP address.
+    ///
+    /// # Returns
+    /// - `Result<Self, SINTEFlakeError>`: A new SINTEFlake instance or an error if creation fails.
+    ///
+    /// # Errors
+    /// Returns an error if no network identity is found or if the initial time update fails.
+    #[cfg(feature = "std")]
+    pub fn with_auto_instance_id() -> Result<Self, SINTEFlakeError> {
+        let bits = Layout::DEFAULT.instance_bits();
+        let instance_id = InstanceIdSource::PrivateIp
+            .instance_id(bits)
+            .or_else(|_| InstanceIdSource::MacAddress.instance_id(bits))?;
+
+        SINTEFlakeBuilder::new().instance_id(instance_id).build()
+    }
+
+    /// Creates a SINTEFlake instance with the hash key and the counter key drawn from the OS entropy.
+    ///
+    /// The hashes of the same data will differ between instances,
+    /// which is fine when the hashes don't need to be stable across instances or restarts.
+    ///
+    /// # Returns
+    /// - `Result<Self, SINTEFlakeError>`: A new SINTEFlake instance or an error if creation fails.
+    ///
+    /// # Errors
+    /// Returns an error if the OS entropy is not available or if the initial time update fails.
+    #[cfg(feature = "getrandom")]
+    pub fn with_random_key() -> Result<Self, SINTEFlakeError> {
+        let mut keys = [0; 17];
+        getrandom::getrandom(&mut keys).map_err(|_| SINTEFlakeError::EntropyError)?;
+        let (hash_key, counter_key) = keys.split_at(16);
+
+        SINTEFlakeBuilder::new()
+            .hash_key(hash_key.try_into().expect("16 bytes"))
+            .counter_key(counter_key[0])
+            .build()
+    }
+
+    /// Creates a SINTEFlake instance with the hash key and the counter key derived from a namespace.
+    ///
+    /// The keys are derived with HKDF-SHA-256, so each namespace gets distinct keys
+    /// that are the same on every instance and across restarts.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the namespace, for example the logical domain of the IDs.
+    ///
+    /// # Returns
+    /// - `Result<Self, SINTEFlakeError>`: A new SINTEFlake instance or an error if creation fails.
+    ///
+    /// # Errors
+    /// Returns an error if the initial time update fails.
+    #[cfg(feature = "hkdf")]
+    pub fn from_namespace(name: &str) -> Result<Self, SINTEFlakeError> {
+        let (hash_key, counter_key) = namespace_keys(name);
+
+        SINTEFlakeBuilder::new()
+            .hash_key(hash_key)
+            .counter_key(counter_key)
+            .build()
+    }
+
+    pub(crate) fn from_builder(builder: SINTEFlakeBuilder) -> Result<Self, SINTEFlakeError> {
+        let mut instance = SINTEFlake {
+            instance_id: builder.instance_id,
+            hash_key: builder.hash_key,
+            base_hash_key: builder.hash_key,
+            key_schedule: builder.key_schedule,
+            hash_algorithm: builder.hash_algorithm,
+            counter_key: builder.counter_key,
+            epoch: builder.epoch,
+            epoch_id: builder.epoch_id,
+            layout: builder.layout,
+            probing_strategy: builder.probing_strategy,
+            probe_attempts: builder.probe_attempts,
+            bucket_capacity: builder
+                .bucket_capacity
+                .unwrap_or(builder.layout.bucket_capacity()),
+            sequence_mode: builder.sequence_mode,
+            monotonic_sequence: builder.monotonic_sequence,
+            feistel_permutation: builder
+                .feistel_permutation
+                .then(|| FeistelPermutation::new(builder.hash_key, builder.layout.total_bits())),
+            tenant_bits: builder.tenant_bits,
+            permutation_tables: builder.permutation_tables,
+            sequence_permutation: builder
+                .permutation_tables
+                .sequence_table(builder.layout.sequence_bits()),
+            time_window: builder.time_window,
+            clock: match builder.clock {
+                Some(clock) => clock,
+                None => clock::default_clock()?,
+            },
+            clock_rollback_policy: builder.clock_rollback_policy,
+            last_timestamp: None,
+            state_store: builder.state_store,
+            event_hook: builder.event_hook,
+            #[cfg(feature = "std")]
+            lease_status: builder.lease_status,
+            minimum_timestamp: 0,
+            collisions_map: CollisionsMap::new(if builder.sequence_mode {
+                0
+            } else {
+                builder.layout.buckets()
+            }),
+            stable_ids: StableIds::new(builder.stable_id_capacity),
+            duplicate_guard: (builder.duplicate_guard_windows > 0)
+                .then(|| DuplicateGuard::new(builder.duplicate_guard_windows)),
+            hot_keys: Vec::new(),
+            rate_limiter: builder.rate_limit.map(|limit| {
+                let window_capacity = builder.layout.buckets() as u64
+                    * builder
+                        .bucket_capacity
+                        .unwrap_or(builder.layout.bucket_capacity()) as u64;
+                RateLimiter::new(limit, window_capacity, builder.time_window)
+            }),
+            pending_config: None,
+            preceding_window: (builder.overflow_policy == OverflowPolicy::BorrowPrecedingWindow)
+                .then(|| PrecedingWindow {
+                    timestamp_bits: None,
+                    collisions_map: CollisionsMap::new(if builder.sequence_mode {
+                        0
+                    } else {
+                        builder.layout.buckets()
+                    }),
+                    ids_count: 0,
+                }),
+            current_timestamp_bits: 0,
+            ids_count_at_current_timestamp: 0,
+            #[cfg(feature = "rand")]
+            rng: builder.rng.unwrap_or_else(PrefixRng::from_entropy),
+        };
+
+        if let Some(state_store) = &instance.state_store {
+            if let Some(state) = state_store.load()? {
+                instance.recover_state(state, builder.state_recovery_policy)?;
+            }
+        }
+
+        instance.update_time()?;
+
+        Ok(instance)
+    }
+
+    /// Makes sure the time windows used before a restart are not used again.
+    fn recover_state(
+        &mut self,
+        state: State,
+        policy: StateRecoveryPolicy,
+    ) -> Result<(), SINTEFlakeError> {
+        let elapsed = elapsed_since_epoch(&*self.clock, self.epoch)?;
+        let current_timestamp =
+            timestamp_of(elapsed, self.time_window, self.layout.max_timestamp())?;
+        if current_timestamp > state.timestamp {
+            return Ok(());
+        }
+
+        match policy {
+            StateRecoveryPolicy::Refuse => Err(SINTEFlakeError::ClockBehindSavedState),
+            StateRecoveryPolicy::SkipForward => {
+                if state.timestamp >= self.layout.max_timestamp() {
+                    return Err(SINTEFlakeError::TimestampOverflow);
+                }
+                self.minimum_timestamp = state.timestamp + 1;
+
+                #[cfg(feature = "tracing")]
+                tracing::info!(
+                    saved_window = state.timestamp,
+                    window = self.minimum_timestamp,
+                    instance_id = self.instance_id,
+                    "skipping forward past the saved time window"
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Saves the current time window and the number of IDs generated in it to the state store.
+    ///
+    /// The state is already saved when switching to a new time window, saving it
+    /// again only updates the number of IDs. Dropping the instance doesn't save it,
+    /// see [`SINTEFlake::close`].
+    ///
+    /// # Errors
+    /// Returns an error if the state store fails.
+    pub fn save_state(&self) -> Result<(), SINTEFlakeError> {
+        match (&self.state_store, self.last_timestamp) {
+            (Some(state_store), Some(timestamp)) => state_store.save(&State {
+                timestamp,
+                ids_count: self.ids_count_at_current_timestamp,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Saves the state with [`SINTEFlake::save_state`] and drops the instance,
+    /// for a graceful shutdown.
+    ///
+    /// # Errors
+    /// Returns an error if the state store fails.
+    pub fn close(self) -> Result<(), SINTEFlakeError> {
+        self.save_state()
+    }
+
+    /// Returns the bit layout of the identifiers generated by this instance.
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Returns the settings deciding how the fields of the IDs are encoded, to decode them.
+    pub fn encoding_config(&self) -> EncodingConfig {
+        EncodingConfig {
+            layout: self.layout,
+            hash_key: self.hash_key,
+            counter_key: self.counter_key,
+            permutation_tables: self.permutation_tables,
+            feistel_permutation: self.feistel_permutation.is_some(),
+            tenant_bits: self.tenant_bits,
+            monotonic_sequence: self.monotonic_sequence,
+            epoch: self.epoch,
+            epoch_id: self.epoch_id,
+            time_window: self.time_window,
+            bucket_capacity: self.bucket_capacity,
+        }
+    }
+
+    /// Returns the duration of the time windows.
+    pub fn time_window(&self) -> Duration {
+        self.time_window
+    }
+
+    /// Returns the end of the last time window that fits in the timestamp bits of the layout.
+    ///
+    /// After this date, the timestamps overflow and no ID can be generated.
+    pub fn valid_until(&self) -> UnixTime {
+        let windows = self.layout.max_timestamp() as i64 + 1;
+        let lifetime = (self.time_window.as_millis() as i64).saturating_mul(windows);
+        add_millis_saturating(self.epoch, lifetime)
+    }
+
+    /// Returns the number of IDs generated in the current time window.
+    pub fn ids_in_current_window(&self) -> u64 {
+        self.ids_count_at_current_timestamp
+    }
+
+    /// Returns the number of IDs that can still be generated in the current time window.
+    ///
+    /// This is an upper bound: `CounterOverflow` can happen earlier when the
+    /// buckets around a hash are full.
+    pub fn remaining_capacity(&self) -> u64 {
+        self.window_capacity()
+            .saturating_sub(self.ids_count_at_current_timestamp)
+    }
+
+    /// Returns how many IDs a bucket holds in a time window, see
+    /// [`SINTEFlakeBuilder::bucket_capacity`].
+    pub fn bucket_capacity(&self) -> u16 {
+        self.bucket_capacity
+    }
+
+    /// Returns how many IDs fit in a time window with the bucket capacity.
+    fn window_capacity(&self) -> u64 {
+        self.layout.buckets() as u64 * self.bucket_capacity as u64
+    }
+
+    /// Returns the time left before the clock reaches the next time window.
+    pub fn window_remaining(&self) -> Duration {
+        let (Some(timestamp), Ok(elapsed)) = (
+            self.last_timestamp,
+            elapsed_since_epoch(&*self.clock, self.epoch),
+        ) else {
+            return Duration::ZERO;
+        };
+        let window_end = self.time_window.as_nanos() * (timestamp as u128 + 1);
+        Duration::from_nanos(window_end.saturating_sub(elapsed.as_nanos()) as u64)
+    }
+
+    /// Returns the number of seconds left before the clock reaches the next time window.
+    pub fn window_remaining_secs(&self) -> f64 {
+        self.window_remaining().as_secs_f64()
+    }
+
+    /// Checks the clock, the remaining lifetime of the timestamps, the use of the current
+    /// time window and the lease of the instance ID, for a `/healthz` endpoint.
+    pub fn health_check(&self) -> HealthReport {
+        let lifetime = self.time_window.as_secs_f64() * (self.layout.max_timestamp() as f64 + 1.0);
+        let (clock, elapsed) = match elapsed_since_epoch(&*self.clock, self.epoch) {
+            Err(_) => (ClockStatus::BeforeEpoch, 0.0),
+            Ok(elapsed) => {
+                let status =
+                    match timestamp_of(elapsed, self.time_window, self.layout.max_timestamp()) {
+                        Err(_) => ClockStatus::Overflow,
+                        Ok(timestamp)
+                            if self.last_timestamp.is_some_and(|last| timestamp < last) =>
+                        {
+                            ClockStatus::BehindLastWindow
+                        }
+                        Ok(_) => ClockStatus::Ok,
+                    };
+                (status, elapsed.as_secs_f64())
+            }
+        };
+
+        #[cfg(feature = "std")]
+        let lease = match &self.lease_status {
+            Some(status) if status.is_valid() => LeaseHealth::Valid,
+            Some(_) => LeaseHealth::Expired,
+            None => LeaseHealth::NotLeased,
+        };
+        #[cfg(not(feature = "std"))]
+        let lease = LeaseHealth::NotLeased;
+
+        HealthReport {
+            clock,
+            years_remaining: (lifetime - elapsed).max(0.0) / (365.25 * 24.0 * 3600.0),
+            window_utilization: self.ids_count_at_current_timestamp as f64
+                / self.window_capacity() as f64,
+            lease,
+        }
+    }
+
+    /// Returns how full the buckets of the current time window are.
+    ///
+    /// The value at index `n` is the number of buckets holding `n` IDs,
+    /// up to the configured bucket capacity.
+    /// In sequence mode, there is no collisions map and all the values are 0.
+    pub fn collision_histogram(&self) -> Vec<u64> {
+        let mut histogram = vec![0; self.bucket_capacity as usize + 1];
+        for count in self.collisions_map.iter() {
+            histogram[count as usize] += 1;
+        }
+        histogram
+    }
+
+    /// Sets the instance ID for this SINTEFlake instance.
+    ///
+    /// # Arguments
+    /// * `instance_id` - An unsigned integer fitting in the instance bits of the layout.
+    ///
+    /// # Returns
+    /// - `Result<(), SINTEFlakeError>`: Ok if successful, or an error if the instance_id is too high.
+    ///
+    /// # Errors
+    /// Returns an error if the instance_id is greater than the maximum of the layout, 1023 by default.
+    pub fn set_instance_id(&mut self, instance_id: u16) -> Result<(), SINTEFlakeError> {
+        if instance_id > self.layout.max_instance_id() {
+            return Err(SINTEFlakeError::InstanceIDTooHigh {
+                given: instance_id,
+                max: self.layout.max_instance_id(),
+            });
+        }
+        self.instance_id = instance_id;
+        // the preceding window may have been used by another instance with this instance ID
+        if let Some(preceding_window) = &mut self.preceding_window {
+            preceding_window.timestamp_bits = None;
+        }
+        Ok(())
+    }
+
+    /// Sets the epoch from which timestamps are measured, it must be in the past.
+    ///
+    /// The time is updated with the new epoch. If the current time window stays the last used
+    /// one, its IDs are kept, otherwise the time window state starts over in the new window.
+    /// Like [`SINTEFlakeBuilder::epoch`], the epoch is named if it is the start of one of the
+    /// [`Epoch::PRESETS`].
+    ///
+    /// The IDs issued before only decode to the right time with the old epoch. A later epoch
+    /// can't bring back a time window already used, where the instance may issue the IDs it
+    /// issued before, so it is refused unless it moves the epoch by less than the time elapsed
+    /// in the current window.
+    ///
+    /// # Errors
+    /// Returns `EpochInFuture` if the epoch is in the future, `TimestampOverflow` if the
+    /// current time window doesn't fit in the timestamp bits of the layout, or
+    /// `InvalidConfig` if the current time window would be before the last used one. The
+    /// epoch is not changed then.
+    pub fn set_epoch(&mut self, epoch: impl IntoUnixTime) -> Result<(), SINTEFlakeError> {
+        let epoch = epoch.into_unix_time();
+        self.replace_epoch(epoch, Epoch::preset_at(epoch).map(|preset| preset.id()))
+    }
+
+    /// Sets a named epoch, a preset like [`Epoch::UNIX`] or a custom one, like
+    /// [`SINTEFlake::set_epoch`].
+    ///
+    /// # Errors
+    /// Returns an error if the epoch is in the future, see [`SINTEFlake::set_epoch`].
+    pub fn set_named_epoch(&mut self, epoch: Epoch) -> Result<(), SINTEFlakeError> {
+        self.replace_epoch(epoch.start(), Some(epoch.id()))
+    }
+
+    fn replace_epoch(
+        &mut self,
+        epoch: UnixTime,
+        epoch_id: Option<&'static str>,
+    ) -> Result<(), SINTEFlakeError> {
+        let elapsed = elapsed_since_epoch(&*self.clock, epoch)?;
+        let timestamp = timestamp_of(elapsed, self.time_window, self.layout.max_timestamp())?;
+        // the last used time window keeps its IDs, the earlier ones are forgotten
+        if matches!(self.last_timestamp, Some(last_timestamp) if timestamp < last_timestamp) {
+            return Err(SINTEFlakeError::InvalidConfig { setting: "epoch" });
+        }
+        self.epoch = epoch;
+        self.epoch_id = epoch_id;
+        self.update_time()
+    }
+
+    /// Updates the internal timestamp of the SINTEFlake instance.
+    ///
+    /// # Returns
+    /// - `Result<(), SINTEFlakeError>`: Ok if successful, or an error if the time update fails.
+    ///
+    /// If the clock went back to an earlier time window than the last used one,
+    /// the clock rollback policy set in the builder decides what happens.
+    ///
+    /// # Errors
+    /// Returns an error if unable to get the current timestamp,
+    /// if it doesn't fit in the timestamp bits of the layout,
+    /// or if the clock went backwards and the clock rollback policy refuses it.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), fields(instance_id = self.instance_id))
+    )]
+    pub fn update_time(&mut self) -> Result<(), SINTEFlakeError> {
+        let previous_timestamp = self.last_timestamp;
+        let elapsed = elapsed_since_epoch(&*self.clock, self.epoch)?;
+        let mut current_timestamp =
+            timestamp_of(elapsed, self.time_window, self.layout.max_timestamp())?
+                .max(self.minimum_timestamp);
+        if let Some(last_timestamp) = self.last_timestamp {
+            if current_timestamp < last_timestamp {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(
+                    window = current_timestamp,
+                    last_window = last_timestamp,
+                    instance_id = self.instance_id,
+                    "clock moved backwards"
+                );
+                current_timestamp =
+                    self.handle_clock_rollback(elapsed, current_timestamp, last_timestamp)?;
+            }
+        }
+        if self.last_timestamp != Some(current_timestamp) {
+            if let Some(state_store) = &self.state_store {
+                state_store.save(&State {
+                    timestamp: current_timestamp,
+                    ids_count: 0,
+                })?;
+            }
+        }
+        self.last_timestamp = Some(current_timestamp);
+
+        let permuted_timestamp = self
+            .permutation_tables
+            .permute_layout_timestamp(current_timestamp, &self.layout);
+        if permuted_timestamp != self.current_timestamp_bits {
+            self.collisions_map.reset();
+            self.stable_ids.clear();
+            let (instance_id, hash_key) = (self.instance_id, self.hash_key);
+            self.apply_pending_config();
+            self.rotate_hash_key(current_timestamp);
+            self.place_hot_keys();
+            self.current_timestamp_bits = permuted_timestamp;
+            self.ids_count_at_current_timestamp = 0;
+
+            if let Some(preceding_window) = &mut self.preceding_window {
+                // the preceding window is free if the instance skipped it, with the same settings
+                let skipped = matches!(previous_timestamp, Some(previous) if previous + 1 < current_timestamp);
+                let unchanged = instance_id == self.instance_id && hash_key == self.hash_key;
+                preceding_window.timestamp_bits = (skipped && unchanged).then(|| {
+                    self.permutation_tables
+                        .permute_layout_timestamp(current_timestamp - 1, &self.layout)
+                });
+                preceding_window.collisions_map.reset();
+                preceding_window.ids_count = 0;
+            }
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                window = current_timestamp,
+                instance_id = self.instance_id,
+                "time window rollover"
+            );
+
+            if let Some(event_hook) = &self.event_hook {
+                event_hook.on_window_rollover(self.instance_id, current_timestamp);
+            }
+        }
+        Ok(())
+    }
+
+    /// Schedules new settings for the next time window, replacing the ones already scheduled.
+    ///
+    /// Changing the instance ID or the keys within a time window could reuse the sequence
+    /// numbers of IDs already generated, so they are only swapped when switching to a new one.
+    ///
+    /// # Errors
+    /// Returns `InstanceIDTooHigh` if the instance ID doesn't fit in the layout.
+    #[cfg(feature = "std")]
+    pub(crate) fn schedule_config(&mut self, config: PendingConfig) -> Result<(), SINTEFlakeError> {
+        if let Some(instance_id) = config.instance_id {
+            if instance_id > self.layout.max_instance_id() {
+                return Err(SINTEFlakeError::InstanceIDTooHigh {
+                    given: instance_id,
+                    max: self.layout.max_instance_id(),
+                });
+            }
+        }
+        self.pending_config = Some(config);
+        Ok(())
+    }
+
+    /// Applies the settings scheduled by [`SINTEFlake::schedule_config`], at a window rollover.
+    fn apply_pending_config(&mut self) {
+        let Some(config) = self.pending_config.take() else {
+            return;
+        };
+        if let Some(instance_id) = config.instance_id {
+            self.instance_id = instance_id;
+        }
+        if let Some(hash_key) = config.hash_key {
+            self.base_hash_key = hash_key;
+            self.hash_key = hash_key;
+            if self.feistel_permutation.is_some() {
+                self.feistel_permutation =
+                    Some(FeistelPermutation::new(hash_key, self.layout.total_bits()));
+            }
+        }
+        if let Some(counter_key) = config.counter_key {
+            self.counter_key = counter_key;
+        }
+        if let Some(probe_attempts) = config.probe_attempts {
+            self.probe_attempts = probe_attempts;
+        }
+    }
+
+    /// Switches to the hash key of the key schedule in effect at the start of the time window.
+    fn rotate_hash_key(&mut self, timestamp: u64) {
+        let Some(key_schedule) = &self.key_schedule else {
+            return;
+        };
+        let window_start = self.encoding_config().window_start(timestamp);
+        let hash_key = key_schedule
+            .key_at(window_start)
+            .copied()
+            .unwrap_or(self.base_hash_key);
+        if hash_key != self.hash_key {
+            self.hash_key = hash_key;
+            if self.feistel_permutation.is_some() {
+                self.feistel_permutation =
+                    Some(FeistelPermutation::new(hash_key, self.layout.total_bits()));
+            }
+        }
+    }
+
+    /// Returns the timestamp to use when the clock is behind the last used time window.
+    fn handle_clock_rollback(
+        &self,
+        mut elapsed: Duration,
+        current_timestamp: u64,
+        last_timestamp: u64,
+    ) -> Result<u64, SINTEFlakeError> {
+        let last_window_start = self.window_start_since_epoch(last_timestamp);
+
+        match self.clock_rollback_policy {
+            ClockRollbackPolicy::Ignore => Ok(current_timestamp),
+            ClockRollbackPolicy::Error => Err(SINTEFlakeError::ClockRollback),
+            ClockRollbackPolicy::Tolerate { max_drift } => {
+                if last_window_start - elapsed <= max_drift {
+                    Ok(last_timestamp)
+                } else {
+                    Err(SINTEFlakeError::ClockRollback)
+                }
+            }
+            ClockRollbackPolicy::Wait => {
+                while elapsed < last_window_start {
+                    clock::wait(&*self.clock, last_window_start - elapsed);
+                    elapsed = elapsed_since_epoch(&*self.clock, self.epoch)?;
+                }
+                timestamp_of(elapsed, self.time_window, self.layout.max_timestamp())
+            }
+        }
+    }
+
+    /// Returns the start of a time window, as a duration since the epoch.
+    fn window_start_since_epoch(&self, timestamp: u64) -> Duration {
+        Duration::from_millis((self.time_window.as_millis() as u64).saturating_mul(timestamp))
+    }
+
+    /// Returns how long [`SINTEFlake::update_time`] would block, with the `Wait` clock rollback
+    /// policy, until the clock is back to the last used time window.
+    ///
+    /// Zero with the other policies, or if the clock isn't behind. Used to wait
+    /// without holding a lock on a shared instance.
+    pub fn clock_rollback_delay(&self) -> Duration {
+        let (ClockRollbackPolicy::Wait, Some(last_timestamp)) =
+            (self.clock_rollback_policy, self.last_timestamp)
+        else {
+            return Duration::ZERO;
+        };
+        match elapsed_since_epoch(&*self.clock, self.epoch) {
+            Ok(elapsed) => self
+                .window_start_since_epoch(last_timestamp)
+                .saturating_sub(elapsed),
+            // update_time reports the error
+            Err(_) => Duration::ZERO,
+        }
+    }
+
+    /// Generates the next unique ID.
+    ///
+    /// # Returns
+    /// - `Result<u64, SINTEFlakeError>`: A new unique 64-bit ID, or an error if generation fails.
+    ///
+    /// # Errors
+    /// Returns an error if there's a counter overflow.
+    pub fn next_id(&mut self) -> Result<u64, SINTEFlakeError> {
+        if self.sequence_mode {
+            return self.next_id_in_sequence();
+        }
+        self.next_id_with_hash(&self.ids_count_at_current_timestamp.to_be_bytes())
+    }
+
+    /// Generates the next unique ID, retrying on retryable errors such as `CounterOverflow`.
+    ///
+    /// Between two attempts, the time is updated, after sleeping until the next
+    /// time window if the policy says so.
+    ///
+    /// # Errors
+    /// Returns the last error if all the attempts failed, or a non-retryable error.
+    pub fn next_id_with_retry(&mut self, policy: RetryPolicy) -> Result<u64, SINTEFlakeError> {
+        let mut retries = 0;
+        loop {
+            match self.next_id() {
+                Err(error) if error.is_retryable() && retries < policy.max_retries => {
+                    retries += 1;
+                    let delay = policy.delay(self);
+                    if !delay.is_zero() {
+                        clock::wait(&*self.clock, delay);
+                    }
+                    retry::refresh(self)?;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Reserves `n` IDs of the current time window at once, for batch writers.
+    ///
+    /// The IDs are the ones `next_id` would have generated, and can be consumed
+    /// from the returned block without calling the generator again.
+    ///
+    /// # Errors
+    /// Returns a `CounterOverflow` error if the time window has less than `n` IDs left,
+    /// or the error of the first ID that couldn't be generated. Nothing is reserved then.
+    pub fn reserve_block(&mut self, n: usize) -> Result<IdBlock, SINTEFlakeError> {
+        self.next_ids_atomically(n, |instance, _| instance.next_id())
+            .map(IdBlock::new)
+    }
+
+    /// Generates `n` IDs with `next`, called with the index of each ID, or none of them:
+    /// if one fails, the state of the time window is rolled back.
+    fn next_ids_atomically(
+        &mut self,
+        n: usize,
+        mut next: impl FnMut(&mut Self, usize) -> Result<u64, SINTEFlakeError>,
+    ) -> Result<Vec<u64>, SINTEFlakeError> {
+        if n as u64 > self.remaining_capacity() {
+            return Err(SINTEFlakeError::CounterOverflow {
+                bucket: 0,
+                window: self.last_timestamp.unwrap_or_default(),
+            });
+        }
+        let checkpoint = self.checkpoint();
+        let ids = (0..n)
+            .map(|index| next(self, index))
+            .collect::<Result<Vec<_>, _>>();
+        if ids.is_err() {
+            self.restore(checkpoint);
+        }
+        ids
+    }
+
+    /// Saves the state changed by generating IDs in the current time window.
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            collisions_map: self.collisions_map.clone(),
+            ids_count: self.ids_count_at_current_timestamp,
+            preceding_window: self.preceding_window.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            duplicate_guard: self.duplicate_guard.clone(),
+        }
+    }
+
+    /// Rolls back the state saved by [`SINTEFlake::checkpoint`].
+    fn restore(&mut self, checkpoint: Checkpoint) {
+        self.collisions_map = checkpoint.collisions_map;
+        self.ids_count_at_current_timestamp = checkpoint.ids_count;
+        self.preceding_window = checkpoint.preceding_window;
+        self.rate_limiter = checkpoint.rate_limiter;
+        self.duplicate_guard = checkpoint.duplicate_guard;
+    }
+
+    /// Generates `n` unique IDs of the current time window using all the cores,
+    /// for bulk data generation jobs.
+    ///
+    /// The buckets are split in one range per thread of the rayon pool, and each range gets a
+    /// share of the IDs, proportional to its room left, and its own counters to hash. The
+    /// threads fill their ranges independently, probing the next bucket of their range when
+    /// one is full, and their bucket counts are merged afterwards. The IDs are unique, but not
+    /// the ones `next_id` would have generated.
+    ///
+    /// In sequence mode, with a rate limit, reserved buckets or an event hook, the IDs are
+    /// generated one by one like [`SINTEFlake::reserve_block`] does.
+    ///
+    /// # Errors
+    /// Returns a `CounterOverflow` error if the time window has less than `n` IDs left,
+    /// or a `DuplicateId` error from the duplicate guard. Nothing is generated then.
+    #[cfg(feature = "rayon")]
+    pub fn par_next_ids(&mut self, n: usize) -> Result<Vec<u64>, SINTEFlakeError> {
+        use rayon::prelude::*;
+
+        if self.sequence_mode
+            || self.rate_limiter.is_some()
+            || !self.hot_keys.is_empty()
+            || self.event_hook.is_some()
+        {
+            return self.reserve_block(n).map(Iterator::collect);
+        }
+        self.check_lease()?;
+
+        let buckets = self.layout.buckets();
+        let parts = rayon::current_num_threads().clamp(1, buckets);
+        let mut partitions: Vec<Partition> = (0..parts)
+            .map(|part| Partition::new(self, part * buckets / parts..(part + 1) * buckets / parts))
+            .collect();
+        let room: usize = partitions.iter().map(|partition| partition.room).sum();
+        if n > room {
+            return Err(SINTEFlakeError::CounterOverflow {
+                bucket: 0,
+                window: self.last_timestamp.unwrap_or_default(),
+            });
+        }
+        if n == 0 {
+            return Ok(Vec::new());
+        }
+        // shares proportional to the room left, the remainder going to the first ones with room
+        let mut left = n;
+        for partition in &mut partitions {
+            partition.share = (n as u128 * partition.room as u128 / room as u128) as usize;
+            left -= partition.share;
+        }
+        let mut first_counter = self.ids_count_at_current_timestamp;
+        for partition in &mut partitions {
+            let extra = left.min(partition.room - partition.share);
+            partition.share += extra;
+            left -= extra;
+            partition.first_counter = first_counter;
+            first_counter += partition.share as u64;
+        }
+
+        let instance = &*self;
+        let ids: Vec<u64> = partitions
+            .par_iter_mut()
+            .map(|partition| partition.fill(instance))
+            .collect::<Vec<_>>()
+            .concat();
+
+        let checkpoint = self.checkpoint();
+        for partition in &partitions {
+            for (offset, &count) in partition.counts.iter().enumerate() {
+                self.collisions_map
+                    .set(partition.buckets.start + offset, count);
+            }
+        }
+        self.ids_count_at_current_timestamp += n as u64;
+        for &id in &ids {
+            if let Err(error) = self.guard(id) {
+                self.restore(checkpoint);
+                return Err(error);
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Generates the next unique ID with a pseudo-random number instead of a hash.
+    ///
+    /// The random number is drawn from the RNG set in the builder,
+    /// and the collisions map still prevents duplicates.
+    ///
+    /// # Returns
+    /// - `Result<u64, SINTEFlakeError>`: A new unique 64-bit ID, or an error if generation fails.
+    ///
+    /// # Errors
+    /// Returns an error if there's a counter overflow.
+    #[cfg(feature = "rand")]
+    pub fn next_id_random(&mut self) -> Result<u64, SINTEFlakeError> {
+        let prefix = self.rng.next_prefix();
+        self.next_id_in_bucket(prefix)
+    }
+
+    fn shuffle_hash_counter(&self, counter: u8) -> u8 {
+        if self.monotonic_sequence {
+            return counter;
+        }
+        self.sequence_permutation[(counter ^ self.counter_key) as usize]
+    }
+
+    /// Returns the probability that a bucket overflows in a time window with `ids_per_window`
+    /// IDs made from hashes, with the layout of the instance, see [`crate::capacity`].
+    #[cfg(feature = "std")]
+    pub fn estimate_collision_risk(&self, ids_per_window: u64) -> f64 {
+        crate::capacity::estimate_collision_risk(ids_per_window, &self.layout)
+    }
+
+    /// Hashes `samples` synthetic keys with the hash key and the hash algorithm of the instance,
+    /// and reports how they spread over the buckets.
+    ///
+    /// Meant to check at startup that a custom hash key or algorithm distributes the IDs well:
+    /// a clustered distribution overflows buckets sooner. About 100 samples per bucket,
+    /// 400 000 with the default layout, give a reliable test.
+    #[cfg(feature = "std")]
+    pub fn uniformity_report(&self, samples: u64) -> UniformityReport {
+        uniformity::report(&*self.hash_algorithm, &self.hash_key, &self.layout, samples)
+    }
+
+    /// Generates the next unique ID of an entity type, see [`TypedId`].
+    ///
+    /// # Errors
+    /// Returns an error if there's a counter overflow.
+    pub fn next_typed<E: Entity>(&mut self) -> Result<TypedId<E>, SINTEFlakeError> {
+        self.next_id().map(TypedId::new)
+    }
+
+    /// Generates the next unique ID using the provided data for hashing.
+    ///
+    /// # Arguments
+    /// * `data` - A byte slice used to generate the hash part of the ID.
+    ///
+    /// # Returns
+    /// - `Result<u64, SINTEFlakeError>`: A new unique 64-bit ID, or an error if generation fails.
+    ///
+    /// # Errors
+    /// Returns an error if there's a counter overflow.
+    pub fn next_id_with_hash(&mut self, data: &[u8]) -> Result<u64, SINTEFlakeError> {
+        self.next_id_for_full_hash(self.hash_algorithm.hash(&self.hash_key, data))
+    }
+
+    /// Generates the next unique ID from the 64 bits hash of some data.
+    fn next_id_for_full_hash(&mut self, full_hash: u64) -> Result<u64, SINTEFlakeError> {
+        if let Some(index) = self
+            .hot_keys
+            .iter()
+            .position(|hot_key| hot_key.full_hash == full_hash)
+        {
+            return self.next_id_for_hot_key(index);
+        }
+        self.next_id_in_bucket(hash::truncate(full_hash))
+    }
+
+    /// Reserves `n` adjacent buckets for the IDs of a hot key, for example the tenant
+    /// generating most of the IDs, from the current time window on.
+    ///
+    /// The IDs of `next_id_with_hash(data)` fill the reserved buckets one after the other,
+    /// instead of probing again from the bucket of the hash once it's full, and the IDs of the
+    /// other hashes landing in the reserved buckets start probing after them. Once the reserved
+    /// buckets are full, the IDs of the key probe the other buckets as usual. The buckets are
+    /// placed from the bucket of the hash at the start of every time window, as the hash key
+    /// may rotate. Reserving buckets for a key again replaces its reservation.
+    ///
+    /// # Errors
+    /// Returns `SequenceMode` in sequence mode, or `InvalidConfig` if `n` is zero or the
+    /// reserved buckets would be more than half of the buckets.
+    pub fn reserve_buckets(&mut self, data: &[u8], n: usize) -> Result<(), SINTEFlakeError> {
+        if self.sequence_mode {
+            return Err(SINTEFlakeError::SequenceMode);
+        }
+        self.release_buckets(data);
+        let reserved: usize = self.hot_keys.iter().map(|hot_key| hot_key.len).sum();
+        if n == 0 || reserved + n > self.layout.buckets() / 2 {
+            return Err(SINTEFlakeError::InvalidConfig {
+                setting: "reserve_buckets",
+            });
+        }
+        self.hot_keys.push(HotKey {
+            data: data.into(),
+            len: n,
+            full_hash: 0,
+            buckets: 0..0,
+            cursor: 0,
+        });
+        self.place_hot_keys();
+        Ok(())
+    }
+
+    /// Releases the buckets reserved for a key with [`SINTEFlake::reserve_buckets`].
+    ///
+    /// Returns false if no buckets were reserved for the key.
+    pub fn release_buckets(&mut self, data: &[u8]) -> bool {
+        let len = self.hot_keys.len();
+        self.hot_keys.retain(|hot_key| *hot_key.data != *data);
+        self.hot_keys.len() != len
+    }
+
+    /// Places the reserved buckets of the hot keys from the buckets of their hashes.
+    fn place_hot_keys(&mut self) {
+        let buckets = self.layout.buckets();
+        for hot_key in &mut self.hot_keys {
+            hot_key.full_hash = self.hash_algorithm.hash(&self.hash_key, &hot_key.data);
+            let start =
+                (hash::truncate(hot_key.full_hash) as usize % buckets).min(buckets - hot_key.len);
+            hot_key.buckets = start..start + hot_key.len;
+            hot_key.cursor = start;
+        }
+    }
+
+    /// Generates an ID in the first reserved bucket of a hot key with room left,
+    /// or probing from the bucket of its hash once they are full.
+    fn next_id_for_hot_key(&mut self, index: usize) -> Result<u64, SINTEFlakeError> {
+        let hot_key = &mut self.hot_keys[index];
+        while hot_key.cursor < hot_key.buckets.end
+            && self.collisions_map.get(hot_key.cursor) >= self.bucket_capacity
+        {
+            hot_key.cursor += 1;
+        }
+        let bucket = hot_key.cursor;
+        if bucket == hot_key.buckets.end {
+            let hash = hash::truncate(hot_key.full_hash);
+            return self.next_id_in_buckets(hash, 0..self.layout.buckets());
+        }
+        self.check_lease()?;
+        self.apply_rate_limit()?;
+        let count = self.collisions_map.get(bucket);
+        self.collisions_map.increment(bucket);
+        self.ids_count_at_current_timestamp += 1;
+        self.guard(self.construct_id(bucket as u16, self.shuffle_hash_counter(count as u8)))
+    }
+
+    /// Generates the next unique ID with a hash computed by the caller, for example from an
+    /// existing shard key, in the current time window and with the instance ID.
+    ///
+    /// The ID goes to the bucket of the hash, without probing other buckets. Without a sequence
+    /// number, the next one of the bucket is used, like [`SINTEFlake::next_id_with_hash`] does.
+    /// A sequence number is the counter of the bucket, shuffled like the other counters: it must
+    /// not be below the number of IDs of the bucket, and the sequence numbers it skips are used.
+    ///
+    /// # Errors
+    /// Returns `RawPartsTooWide` if the hash doesn't fit in the layout or the sequence number
+    /// is not below the bucket capacity, `SequenceUsed` if the sequence number is already used,
+    /// `CounterOverflow` if the bucket is full, or `RateLimited` if the rate limit is reached.
+    pub fn next_id_with_raw_parts(
+        &mut self,
+        hash: u16,
+        sequence: Option<u8>,
+    ) -> Result<u64, SINTEFlakeError> {
+        let bucket = hash as usize;
+        if bucket >= self.layout.buckets() {
+            return Err(SINTEFlakeError::RawPartsTooWide);
+        }
+        let Some(sequence) = sequence else {
+            return self.next_id_in_buckets(0, bucket..bucket + 1);
+        };
+        if sequence as u16 >= self.bucket_capacity {
+            return Err(SINTEFlakeError::RawPartsTooWide);
+        }
+        if self.sequence_mode {
+            return Err(SINTEFlakeError::SequenceMode);
+        }
+        self.check_lease()?;
+        self.apply_rate_limit()?;
+        if (sequence as u16) < self.collisions_map.get(bucket) {
+            return Err(SINTEFlakeError::SequenceUsed {
+                bucket: hash,
+                sequence,
+            });
+        }
+        self.collisions_map.set(bucket, sequence as u16 + 1);
+        self.ids_count_at_current_timestamp += 1;
+        self.guard(self.construct_id(hash, self.shuffle_hash_counter(sequence)))
+    }
+
+    /// Derives an ID from the data and a logical timestamp only, without any counter.
+    ///
+    /// The same data and timestamp always give the same ID on the same instance, so duplicated
+    /// events collapse to one ID. The hash and the sequence number come from the 64 bits hash of
+    /// the data, and the logical timestamp, for example a batch number, replaces the time window.
+    /// Different data may give the same ID, and the IDs may collide with the IDs generated from
+    /// the counter in the same window, so content IDs should use their own instance ID.
+    /// With a key schedule, the ID depends on the current hash key.
+    ///
+    /// # Errors
+    /// Returns `TimestampOverflow` if the timestamp doesn't fit in the timestamp bits of the layout.
+    pub fn content_id(&self, data: &[u8], timestamp: u64) -> Result<u64, SINTEFlakeError> {
+        if timestamp > self.layout.max_timestamp() {
+            return Err(SINTEFlakeError::TimestampOverflow);
+        }
+        let full_hash = self.hash_algorithm.hash(&self.hash_key, data);
+        let id = self.layout.construct_identifier(
+            full_hash as u16,
+            self.permutation_tables
+                .permute_layout_timestamp(timestamp, &self.layout),
+            self.instance_id,
+            (full_hash >> 56) as u8,
+        );
+        Ok(match &self.feistel_permutation {
+            Some(permutation) => permutation.permute(id),
+            None => id,
+        })
+    }
+
+    /// Creates an importer minting the IDs of Snowflake-like IDs imported from another
+    /// system, in the time windows of their creation times, to migrate the IDs while keeping
+    /// their time semantics.
+    ///
+    /// The importer places the IDs like the generator does, starting from the bucket of the
+    /// hash of the source ID and probing the next ones, and remembers the buckets it used in
+    /// each window, so the imported IDs are unique. Like content IDs, imported IDs should use
+    /// their own instance ID, not to collide with the IDs generated from the counter.
+    ///
+    /// ```rust
+    /// use sinteflake::decode::decode;
+    /// use sinteflake::sinteflake::SINTEFlake;
+    /// use sinteflake::snowflake::SnowflakeDialect;
+    ///
+    /// let instance = SINTEFlake::builder().instance_id(1000).build()?;
+    /// let mut importer = instance.snowflake_importer(SnowflakeDialect::Twitter);
+    /// let id = importer.import(1288834974657 << 22 | 42)?;
+    /// let decoded = decode(id, &instance.encoding_config())?;
+    /// assert_eq!(decoded.instance_id, 1000);
+    /// # Ok::<(), sinteflake::error::SINTEFlakeError>(())
+    /// ```
+    pub fn snowflake_importer(&self, dialect: SnowflakeDialect) -> SnowflakeImporter<'_> {
+        SnowflakeImporter {
+            instance: self,
+            dialect,
+            windows: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the ID already issued for identical data during the current time window,
+    /// or generates it like [`SINTEFlake::next_id_with_hash`].
+    ///
+    /// Retries of the same payload get the same ID instead of a new one. The data is
+    /// identified by its full 64 bits hash, and only the last
+    /// [`SINTEFlakeBuilder::stable_id_capacity`] payloads are remembered.
+    ///
+    /// # Errors
+    /// Returns an error if there's a counter overflow.
+    pub fn stable_id_with_hash(&mut self, data: &[u8]) -> Result<u64, SINTEFlakeError> {
+        let full_hash = self.hash_algorithm.hash(&self.hash_key, data);
+        if let Some(id) = self.stable_ids.get(full_hash) {
+            return Ok(id);
+        }
+        let id = self.next_id_in_bucket(hash::truncate(full_hash))?;
+        self.stable_ids.insert(full_hash, id);
+        Ok(id)
+    }
+
+    /// Generates the next unique ID in a shard, see [`crate::shard`].
+    ///
+    /// The hash is chosen among the hash buckets of the shard, like [`SINTEFlake::next_id`]
+    /// does among all the buckets, so `shard_of(id, num_shards, &layout)` returns `shard`.
+    ///
+    /// # Errors
+    /// Returns an error if the shard is invalid, if the IDs are permuted with the Feistel
+    /// permutation, or if the buckets of the shard are full.
+    pub fn next_id_in_shard(
+        &mut self,
+        shard: u16,
+        num_shards: u16,
+    ) -> Result<u64, SINTEFlakeError> {
+        if self.feistel_permutation.is_some() {
+            return Err(SINTEFlakeError::FeistelPermutation);
+        }
+        let range = shard::shard_buckets(shard, num_shards, &self.layout)?;
+        let hash = hash::hash(
+            &*self.hash_algorithm,
+            &self.ids_count_at_current_timestamp.to_be_bytes(),
+            &self.hash_key,
+        );
+        self.next_id_in_buckets(hash, range)
+    }
+
+    /// Generates the next unique ID of a tenant, using the provided data for hashing.
+    ///
+    /// The first [`SINTEFlakeBuilder::tenant_bits`] bits of the hash are the tenant code,
+    /// and the hash of the data picks a bucket among the buckets of the tenant, so the tenant
+    /// can be read back from the ID with [`crate::decode::DecodedId::tenant`].
+    ///
+    /// # Errors
+    /// Returns an error if the tenant code doesn't fit in the tenant bits, if the IDs are
+    /// permuted with the Feistel permutation, or if the buckets of the tenant are full.
+    pub fn next_id_for_tenant(&mut self, tenant: u16, data: &[u8]) -> Result<u64, SINTEFlakeError> {
+        if self.feistel_permutation.is_some() {
+            return Err(SINTEFlakeError::FeistelPermutation);
+        }
+        if (tenant as u32) >> self.tenant_bits != 0 {
+            return Err(SINTEFlakeError::InvalidTenant);
+        }
+        let shift = self.layout.hash_bits() - self.tenant_bits;
+        let first = (tenant as usize) << shift;
+        let hash = hash::hash(&*self.hash_algorithm, data, &self.hash_key);
+        self.next_id_in_buckets(hash, first..first + (1 << shift))
+    }
+
+    /// Generates the next unique ID using any hashable value for the hash part of the ID.
+    ///
+    /// The value is fed to the keyed hasher through its [`Hash`] implementation,
+    /// so keys don't need to be serialized first.
+    ///
+    /// Note that the [`Hash`] implementation of `str` and slices adds a length or a terminator,
+    /// so `next_id_for("abc")` doesn't use the same hash as `next_id_with_hash(b"abc")`.
+    /// Use [`SINTEFlake::next_id_for_str`] or [`SINTEFlake::next_id_for_bytes`] to hash the raw bytes.
+    ///
+    /// # Arguments
+    /// * `value` - A hashable value used to generate the hash part of the ID.
+    ///
+    /// # Returns
+    /// - `Result<u64, SINTEFlakeError>`: A new unique 64-bit ID, or an error if generation fails.
+    ///
+    /// # Errors
+    /// Returns an error if there's a counter overflow.
+    pub fn next_id_for<T: Hash + ?Sized>(&mut self, value: &T) -> Result<u64, SINTEFlakeError> {
+        let hash = hash::hash_value(&*self.hash_algorithm, value, &self.hash_key);
+        self.next_id_in_bucket(hash)
+    }
+
+    /// Generates the next unique ID using the UTF-8 bytes of a string for hashing.
+    ///
+    /// Same as `next_id_with_hash(value.as_bytes())`.
+    pub fn next_id_for_str(&mut self, value: &str) -> Result<u64, SINTEFlakeError> {
+        self.next_id_with_hash(value.as_bytes())
+    }
+
+    /// Generates the next unique ID using anything that can be seen as bytes for hashing.
+    ///
+    /// Same as `next_id_with_hash(value.as_ref())`.
+    pub fn next_id_for_bytes(&mut self, value: impl AsRef<[u8]>) -> Result<u64, SINTEFlakeError> {
+        self.next_id_with_hash(value.as_ref())
+    }
+
+    /// Starts hashing a composite key incrementally, see [`IdHasher`].
+    ///
+    /// ```rust
+    /// use sinteflake::sinteflake::SINTEFlake;
+    ///
+    /// fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///   let mut instance = SINTEFlake::new()?;
+    ///   let (tenant_id, user_id, ts) = (7_u32, 42_u64, 1719792000_i64);
+    ///
+    ///   let id = instance.hasher().write(tenant_id).write(user_id).write(ts).finish_id()?;
+    ///
+    ///   Ok(())
+    /// }
+    /// ```
+    pub fn hasher(&mut self) -> IdHasher<'_> {
+        let hasher = self.hash_algorithm.hasher(&self.hash_key);
+        IdHasher {
+            instance: self,
+            hasher,
+        }
+    }
+
+    /// Generates the next ID of the per-window counter, in sequence mode.
+    fn next_id_in_sequence(&mut self) -> Result<u64, SINTEFlakeError> {
+        self.check_lease()?;
+        self.apply_rate_limit()?;
+        let count = self.ids_count_at_current_timestamp;
+        if count >= self.window_capacity() {
+            if let Some(id) = self.next_id_in_sequence_of_preceding_window() {
+                return self.guard(id);
+            }
+            if let Some(event_hook) = &self.event_hook {
+                event_hook.on_overflow(
+                    self.instance_id,
+                    self.last_timestamp.unwrap_or_default(),
+                    0,
+                );
+            }
+            return Err(SINTEFlakeError::CounterOverflow {
+                bucket: 0,
+                window: self.last_timestamp.unwrap_or_default(),
+            });
+        }
+
+        let (bucket, sequence) = self.sequence_position(count);
+        self.ids_count_at_current_timestamp += 1;
+        self.guard(self.construct_id(bucket, self.shuffle_hash_counter(sequence)))
+    }
+
+    /// Returns the bucket and the sequence number of the `count`-th ID of a window, in sequence mode.
+    fn sequence_position(&self, count: u64) -> (u16, u8) {
+        if self.monotonic_sequence {
+            // the buckets one after the other, so the IDs increase
+            let capacity = self.bucket_capacity as u64;
+            return ((count / capacity) as u16, (count % capacity) as u8);
+        }
+        // consecutive IDs go to different buckets, in an order depending on the hash key:
+        // multiplying by an odd number and adding modulo a power of two is a bijection
+        let buckets = self.layout.buckets() as u64;
+        let offset = u16::from_le_bytes([self.hash_key[0], self.hash_key[1]]) as u64;
+        let bucket = ((count % buckets).wrapping_mul(0x9E37) + offset) % buckets;
+        (bucket as u16, (count / buckets) as u8)
+    }
+
+    /// Generates the next ID of the counter of the preceding time window, in sequence mode,
+    /// if its capacity can be borrowed and isn't used up.
+    fn next_id_in_sequence_of_preceding_window(&mut self) -> Option<u64> {
+        let preceding_window = self.preceding_window.as_ref()?;
+        let timestamp_bits = preceding_window.timestamp_bits?;
+        let count = preceding_window.ids_count;
+        if count >= self.window_capacity() {
+            return None;
+        }
+        let (bucket, sequence) = self.sequence_position(count);
+        self.preceding_window.as_mut()?.ids_count += 1;
+        Some(self.construct_id_in_window(
+            timestamp_bits,
+            bucket,
+            self.shuffle_hash_counter(sequence),
+        ))
+    }
+
+    /// Generates an ID in the preceding time window, probing from the `start` bucket of the
+    /// range like in the current window, if its capacity can be borrowed.
+    fn next_id_in_preceding_window(&mut self, start: usize, range: &Range<usize>) -> Option<u64> {
+        let preceding_window = self.preceding_window.as_mut()?;
+        let timestamp_bits = preceding_window.timestamp_bits?;
+        let (bucket, count) = (0..=self.probe_attempts).find_map(|attempt| {
+            let bucket = range.start
+                + self
+                    .probing_strategy
+                    .bucket(start, attempt, self.counter_key, range.len());
+            let count = preceding_window.collisions_map.get(bucket);
+            (count < self.bucket_capacity).then_some((bucket, count))
+        })?;
+        preceding_window.collisions_map.increment(bucket);
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            bucket,
+            instance_id = self.instance_id,
+            "window full, borrowing the preceding one"
+        );
+        Some(self.construct_id_in_window(
+            timestamp_bits,
+            bucket as u16,
+            self.shuffle_hash_counter(count as u8),
+        ))
+    }
+
+    /// Returns how long to wait before the rate limit allows the next ID, zero without a limit.
+    ///
+    /// Async code can sleep this long before generating an ID, instead of blocking.
+    pub fn rate_limit_delay(&self) -> Duration {
+        match &self.rate_limiter {
+            Some(limiter) => limiter.delay(self.clock.now()),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Takes a token of the rate limiter, waiting for it or failing according to its policy.
+    fn apply_rate_limit(&mut self) -> Result<(), SINTEFlakeError> {
+        let Some(limiter) = &mut self.rate_limiter else {
+            return Ok(());
+        };
+        let mut now = self.clock.now();
+        let delay = limiter.delay(now);
+        if !delay.is_zero() {
+            match limiter.policy {
+                RateLimitPolicy::Error => return Err(SINTEFlakeError::RateLimited),
+                RateLimitPolicy::Block => {
+                    clock::wait(&*self.clock, delay);
+                    now = self.clock.now();
+                }
+            }
+        }
+        limiter.take(now);
+        Ok(())
+    }
+
+    /// Makes sure the instance ID is still leased, if it comes from a lease.
+    fn check_lease(&self) -> Result<(), SINTEFlakeError> {
+        #[cfg(feature = "std")]
+        if let Some(status) = &self.lease_status {
+            if !status.is_valid() {
+                return Err(SINTEFlakeError::LeaseExpired);
+            }
+        }
+        Ok(())
+    }
+
+    /// Constructs an ID in the current time window, permuted if the Feistel permutation is enabled.
+    fn construct_id(&self, hash: u16, sequence: u8) -> u64 {
+        self.construct_id_in_window(self.current_timestamp_bits, hash, sequence)
+    }
+
+    /// Checks with the duplicate guard, if any, that the ID wasn't issued before.
+    fn guard(&mut self, id: u64) -> Result<u64, SINTEFlakeError> {
+        if let Some(duplicate_guard) = &mut self.duplicate_guard {
+            duplicate_guard.check(self.last_timestamp.unwrap_or_default(), id)?;
+        }
+        Ok(id)
+    }
+
+    /// Constructs an ID in the time window of the permuted timestamp.
+    fn construct_id_in_window(&self, timestamp_bits: u64, hash: u16, sequence: u8) -> u64 {
+        let id = self
+            .layout
+            .construct_identifier(hash, timestamp_bits, self.instance_id, sequence);
+        match &self.feistel_permutation {
+            Some(permutation) => permutation.permute(id),
+            None => id,
+        }
+    }
+
+    fn next_id_in_bucket(&mut self, hash: u16) -> Result<u64, SINTEFlakeError> {
+        let buckets = self.layout.buckets();
+        // the hashes landing in the reserved buckets of a hot key start probing after them
+        let hash = match self
+            .hot_keys
+            .iter()
+            .find(|hot_key| hot_key.buckets.contains(&(hash as usize % buckets)))
+        {
+            Some(hot_key) => (hot_key.buckets.end % buckets) as u16,
+            None => hash,
+        };
+        self.next_id_in_buckets(hash, 0..buckets)
+    }
+
+    /// Generates an ID in a range of buckets, probing only the buckets of the range.
+    fn next_id_in_buckets(
+        &mut self,
+        hash: u16,
+        range: Range<usize>,
+    ) -> Result<u64, SINTEFlakeError> {
+        if self.sequence_mode {
+            return Err(SINTEFlakeError::SequenceMode);
+        }
+        self.check_lease()?;
+        self.apply_rate_limit()?;
+        let buckets = range.len();
+        let start = hash as usize % buckets;
+        let mut hash = range.start + start;
+        let mut attempt = 0;
+
+        loop {
+            let hash_counter = self.collisions_map.get(hash);
+            if hash_counter >= self.bucket_capacity {
+                // we give ourselves a few tries to find a new hash
+                // with enough space
+                if attempt == self.probe_attempts {
+                    if let Some(id) = self.next_id_in_preceding_window(start, &range) {
+                        return self.guard(id);
+                    }
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(
+                        bucket = hash,
+                        window = self.last_timestamp,
+                        instance_id = self.instance_id,
+                        "counter overflow"
+                    );
+                    if let Some(event_hook) = &self.event_hook {
+                        event_hook.on_overflow(
+                            self.instance_id,
+                            self.last_timestamp.unwrap_or_default(),
+                            hash as u16,
+                        );
+                    }
+                    return Err(SINTEFlakeError::CounterOverflow {
+                        bucket: hash as u16,
+                        window: self.last_timestamp.unwrap_or_default(),
+                    });
+                }
+                attempt += 1;
+                let next_hash = range.start
+                    + self
+                        .probing_strategy
+                        .bucket(start, attempt, self.counter_key, buckets);
+
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    bucket = hash,
+                    next_bucket = next_hash,
+                    window = self.last_timestamp,
+                    instance_id = self.instance_id,
+                    "bucket full, probing the next one"
+                );
+                if let Some(event_hook) = &self.event_hook {
+                    event_hook.on_bucket_fallback(
+                        self.instance_id,
+                        self.last_timestamp.unwrap_or_default(),
+                        hash as u16,
+                        next_hash as u16,
+                    );
+                }
+                hash = next_hash;
+                continue;
+            }
+            self.collisions_map.increment(hash);
+
+            let shuffled_counter = self.shuffle_hash_counter(hash_counter as u8);
+            self.ids_count_at_current_timestamp += 1;
+            return self.guard(self.construct_id(hash as u16, shuffled_counter));
+        }
+    }
+}
+
+/// What to do when a time window has no room left for an ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Returns a `CounterOverflow` error until the next time window.
+    #[default]
+    Fail,
+
+    /// Generates the IDs in the time window preceding the current one, if the instance
+    /// skipped it without generating any ID, and fails like [`OverflowPolicy::Fail`] otherwise.
+    ///
+    /// The IDs are unique as no other ID of the instance is in that window, but their
+    /// timestamp is one window earlier than their creation. A window can only be borrowed
+    /// while it precedes the current one, and not after a change of the instance ID or
+    /// of the hash key.
+    BorrowPrecedingWindow,
+}
+
+/// A key with reserved buckets, see [`SINTEFlake::reserve_buckets`].
+struct HotKey {
+    data: Box<[u8]>,
+    len: usize,
+    // placed at the start of every time window
+    full_hash: u64,
+    buckets: Range<usize>,
+    // first reserved bucket that may have room left
+    cursor: usize,
+}
+
+/// IDs generated in the time window preceding the current one, borrowing its capacity.
+#[derive(Clone)]
+struct PrecedingWindow {
+    // permuted timestamp of the window, None if it can't be borrowed
+    timestamp_bits: Option<u64>,
+    collisions_map: CollisionsMap, // empty in sequence mode
+    ids_count: u64,
+}
+
+/// A range of buckets filled by one thread, see [`SINTEFlake::par_next_ids`].
+#[cfg(feature = "rayon")]
+struct Partition {
+    buckets: Range<usize>,
+    // number of IDs of each bucket of the range
+    counts: Vec<u16>,
+    room: usize,
+    share: usize,
+    first_counter: u64,
+}
+
+#[cfg(feature = "rayon")]
+impl Partition {
+    fn new(instance: &SINTEFlake, buckets: Range<usize>) -> Self {
+        let counts: Vec<u16> = buckets
+            .clone()
+            .map(|bucket| instance.collisions_map.get(bucket))
+            .collect();
+        let room = counts
+            .iter()
+            .map(|&count| instance.bucket_capacity.saturating_sub(count) as usize)
+            .sum();
+        Partition {
+            buckets,
+            counts,
+            room,
+            share: 0,
+            first_counter: 0,
+        }
+    }
+
+    /// Generates the share of IDs of the partition, which has room for them.
+    fn fill(&mut self, instance: &SINTEFlake) -> Vec<u64> {
+        let len = self.counts.len();
+        (0..self.share as u64)
+            .map(|i| {
+                let counter = (self.first_counter + i).to_be_bytes();
+                let full_hash = instance.hash_algorithm.hash(&instance.hash_key, &counter);
+                let mut offset = (full_hash % len as u64) as usize;
+                while self.counts[offset] >= instance.bucket_capacity {
+                    offset = (offset + 1) % len;
+                }
+                let sequence = instance.shuffle_hash_counter(self.counts[offset] as u8);
+                self.counts[offset] += 1;
+                instance.construct_id((self.buckets.start + offset) as u16, sequence)
+            })
+            .collect()
+    }
+}
+
+/// State of the time window before a block of IDs, see [`SINTEFlake::checkpoint`].
+struct Checkpoint {
+    collisions_map: CollisionsMap,
+    ids_count: u64,
+    preceding_window: Option<PrecedingWindow>,
+    rate_limiter: Option<RateLimiter>,
+    duplicate_guard: Option<DuplicateGuard>,
+}
+
+/// Settings swapped on a live instance at the next time window,
+/// see [`SINTEFlake::apply_config`](crate::config).
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PendingConfig {
+    pub(crate) instance_id: Option<u16>,
+    pub(crate) hash_key: Option<[u8; 16]>,
+    pub(crate) counter_key: Option<u8>,
+    pub(crate) probe_attempts: Option<u32>,
+}
+
+/// Derives the hash key and the counter key of a namespace.
+#[cfg(feature = "hkdf")]
+pub(crate) fn namespace_keys(name: &str) -> ([u8; 16], u8) {
+    let hkdf = hkdf::Hkdf::<sha2::Sha256>::new(Some(b"sinteflake"), name.as_bytes());
+    let mut keys = [0; 17];
+    hkdf.expand(b"hash_key and counter_key", &mut keys)
+        .expect("17 bytes is a valid length for HKDF-SHA-256");
+    let (hash_key, counter_key) = keys.split_at(16);
+
+    (hash_key.try_into().expect("16 bytes"), counter_key[0])
+}
+
+/// Importer of Snowflake-like IDs, created by [`SINTEFlake::snowflake_importer`].
+///
+/// It keeps the collisions map of every time window it imported IDs in, about 18 KB each
+/// with the default layout, until it is dropped.
+pub struct SnowflakeImporter<'a> {
+    instance: &'a SINTEFlake,
+    dialect: SnowflakeDialect,
+    windows: BTreeMap<u64, CollisionsMap>,
+}
+
+impl SnowflakeImporter<'_> {
+    /// Mints the ID of an imported ID, in the time window of its creation time.
+    ///
+    /// Each call mints a new ID, so each source ID should be imported once.
+    ///
+    /// # Errors
+    /// Returns `InvalidId` if the ID doesn't fit in the dialect, `EpochInFuture` if it was
+    /// created before the epoch, `TimestampOverflow` if its time window doesn't fit in the
+    /// timestamp bits of the layout, or `CounterOverflow` if its time window is full.
+    pub fn import(&mut self, id: u64) -> Result<u64, SINTEFlakeError> {
+        let instance = self.instance;
+        let created_at = decode_snowflake(id, self.dialect)?.created_at();
+        let elapsed = created_at.unix_timestamp_nanos() - instance.epoch.unix_timestamp_nanos();
+        if elapsed < 0 {
+            return Err(SINTEFlakeError::EpochInFuture);
+        }
+        let timestamp = timestamp_of(
+            Duration::from_nanos(elapsed as u64),
+            instance.time_window,
+            instance.layout.max_timestamp(),
+        )?;
+
+        let buckets = instance.layout.buckets();
+        let collisions_map = self
+            .windows
+            .entry(timestamp)
+            .or_insert_with(|| CollisionsMap::new(buckets));
+        let full_hash = instance
+            .hash_algorithm
+            .hash(&instance.hash_key, &id.to_be_bytes());
+        let start = hash::truncate(full_hash) as usize % buckets;
+        // twice as many attempts as buckets visit every bucket, see ProbingStrategy::bucket
+        let bucket = (0..2 * buckets as u32)
+            .map(|attempt| {
+                instance
+                    .probing_strategy
+                    .bucket(start, attempt, instance.counter_key, buckets)
+            })
+            .find(|&bucket| collisions_map.get(bucket) < instance.bucket_capacity)
+            .ok_or(SINTEFlakeError::CounterOverflow {
+                bucket: start as u16,
+                window: timestamp,
+            })?;
+        let count = collisions_map.get(bucket);
+        collisions_map.increment(bucket);
+
+        let timestamp_bits = instance
+            .permutation_tables
+            .permute_layout_timestamp(timestamp, &instance.layout);
+        Ok(instance.construct_id_in_window(
+            timestamp_bits,
+            bucket as u16,
+            instance.shuffle_hash_counter(count as u8),
+        ))
+    }
+}
+
+/// Incremental hasher generating an ID from several fields,
+/// without concatenating them in an intermediate buffer first.
+///
+/// Created by [`SINTEFlake::hasher`]. Writing the fields `a`, `b` and `c` one by one
+/// gives the same hash as `next_id_for(&(a, b, c))`.
+pub struct IdHasher<'a> {
+    instance: &'a mut SINTEFlake,
+    hasher: KeyedHasher,
+}
+
+impl IdHasher<'_> {
+    /// Feeds a hashable value to the hasher.
+    pub fn write<T: Hash>(mut self, value: T) -> Self {
+        value.hash(&mut self.hasher);
+        self
+    }
+
+    /// Feeds raw bytes to the hasher, without length prefix.
+    pub fn write_bytes(mut self, bytes: &[u8]) -> Self {
+        self.hasher.write(bytes);
+        self
+    }
+
+    /// Generates the next unique ID using the hash of the written values.
+    ///
+    /// # Returns
+    /// - `Result<u64, SINTEFlakeError>`: A new unique 64-bit ID, or an error if generation fails.
+    ///
+    /// # Errors
+    /// Returns an error if there's a counter overflow.
+    pub fn finish_id(self) -> Result<u64, SINTEFlakeError> {
+        let hash = hash::truncate(self.hasher.finish());
+        self.instance.next_id_in_bucket(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basic() {
+        let mut instance = SINTEFlake::new().unwrap();
+        let id_a = instance.next_id().unwrap();
+        let id_b = instance.next_id().unwrap();
+        assert_ne!(id_a, id_b);
+    }
+
+    #[test]
+    fn test_with_hash() {
+        let mut instance = SINTEFlake::new().unwrap();
+        let data = [1, 2, 3];
+        let id_a = instance.next_id_with_hash(&data).unwrap();
+        let id_b = instance.next_id_with_hash(&data).unwrap();
+        assert_ne!(id_a, id_b);
+    }
+
+    #[test]
+    fn test_next_id_for() {
+        let mut instance = SINTEFlake::new().unwrap();
+        let id_a = instance.next_id_for(&(42_u32, "tenant")).unwrap();
+        let id_b = instance.next_id_for(&(42_u32, "tenant")).unwrap();
+        assert_ne!(id_a, id_b);
+        // same hash bucket
+        assert_eq!(id_a >> 49, id_b >> 49);
+    }
+
+    #[test]
+    fn test_next_id_for_str_and_bytes() {
+        let mut instance = SINTEFlake::new().unwrap();
+        let id_a = instance.next_id_with_hash(b"hello").unwrap();
+        let id_b = instance.next_id_for_str("hello").unwrap();
+        let id_c = instance.next_id_for_bytes(String::from("hello")).unwrap();
+        let id_d = instance
+            .next_id_for_bytes(vec![104, 101, 108, 108, 111])
+            .unwrap();
+        assert_eq!(id_a >> 49, id_b >> 49);
+        assert_eq!(id_a >> 49, id_c >> 49);
+        assert_eq!(id_a >> 49, id_d >> 49);
+        assert_ne!(id_a, id_b);
+        assert_ne!(id_b, id_c);
+        assert_ne!(id_c, id_d);
+    }
+
+    #[test]
+    fn test_streaming_hasher() {
+        let mut instance = SINTEFlake::new().unwrap();
+        let id_a = instance
+            .hasher()
+            .write(7_u32)
+            .write("user")
+            .write(1719792000_i64)
+            .finish_id()
+            .unwrap();
+        let id_b = instance
+            .next_id_for(&(7_u32, "user", 1719792000_i64))
+            .unwrap();
+        assert_ne!(id_a, id_b);
+        assert_eq!(id_a >> 49, id_b >> 49);
+
+        let id_c = instance
+            .hasher()
+            .write_bytes(b"he")
+            .write_bytes(b"llo")
+            .finish_id()
+            .unwrap();
+        let id_d = instance.next_id_with_hash(b"hello").unwrap();
+        assert_eq!(id_c >> 49, id_d >> 49);
+    }
+
+    #[test]
+    fn test_next_id_in_shard() {
+        use crate::shard::shard_of;
+
+        // 4 buckets of 2 IDs, 2 buckets per shard
+        let layout = Layout::new(2, 31, 10, 1).unwrap();
+        let mut instance = SINTEFlake::builder().layout(layout).build().unwrap();
+        let mut ids = std::collections::HashSet::new();
+        for _ in 0..4 {
+            let id = instance.next_id_in_shard(1, 2).unwrap();
+            assert_eq!(shard_of(id, 2, &layout), 1);
+            assert!(ids.insert(id));
+        }
+        // the buckets of the shard are full, not the ones of the other shard
+        assert!(matches!(
+            instance.next_id_in_shard(1, 2),
+            Err(SINTEFlakeError::CounterOverflow { .. })
+        ));
+        assert_eq!(
+            shard_of(instance.next_id_in_shard(0, 2).unwrap(), 2, &layout),
+            0
+        );
+
+        assert!(matches!(
+            instance.next_id_in_shard(2, 2),
+            Err(SINTEFlakeError::InvalidShard)
+        ));
+        let mut permuted = SINTEFlake::builder()
+            .feistel_permutation(true)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            permuted.next_id_in_shard(0, 2),
+            Err(SINTEFlakeError::FeistelPermutation)
+        ));
+    }
+
+    #[test]
+    fn test_next_id_in_three_shards() {
+        use crate::shard::shard_of;
+
+        // 16 buckets of 2 IDs, the middle shard has 5 buckets
+        let layout = Layout::new(4, 31, 10, 1).unwrap();
+        for strategy in [
+            ProbingStrategy::Linear,
+            ProbingStrategy::Quadratic,
+            ProbingStrategy::DoubleHashing,
+        ] {
+            let mut instance = SINTEFlake::builder()
+                .layout(layout)
+                .probing_strategy(strategy)
+                .probe_attempts(16)
+                .build()
+                .unwrap();
+            let mut ids = std::collections::HashSet::new();
+            // every bucket of the shard is found before the overflow
+            for _ in 0..10 {
+                let id = instance.next_id_in_shard(1, 3).unwrap();
+                assert_eq!(shard_of(id, 3, &layout), 1);
+                assert!(ids.insert(id));
+            }
+            assert!(matches!(
+                instance.next_id_in_shard(1, 3),
+                Err(SINTEFlakeError::CounterOverflow { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_next_id_with_raw_parts() {
+        use crate::decode::decode;
+
+        let mut instance = SINTEFlake::builder().instance_id(3).build().unwrap();
+        let config = instance.encoding_config();
+        let id_a = instance.next_id_with_raw_parts(1234, None).unwrap();
+        let id_b = instance.next_id_with_raw_parts(1234, Some(5)).unwrap();
+        let id_c = instance.next_id_with_raw_parts(1234, None).unwrap();
+        for id in [id_a, id_b, id_c] {
+            let decoded = decode(id, &config).unwrap();
+            assert_eq!((decoded.hash, decoded.instance_id), (1234, 3));
+        }
+        assert_eq!(instance.collisions_map.get(1234), 7);
+
+        // the sequence numbers skipped by Some(5) are used
+        assert!(matches!(
+            instance.next_id_with_raw_parts(1234, Some(3)),
+            Err(SINTEFlakeError::SequenceUsed {
+                bucket: 1234,
+                sequence: 3
+            })
+        ));
+        assert!(matches!(
+            instance.next_id_with_raw_parts(1 << 14, None),
+            Err(SINTEFlakeError::RawPartsTooWide)
+        ));
+
+        // no other bucket is probed
+        instance.next_id_with_raw_parts(7, Some(255)).unwrap();
+        assert!(matches!(
+            instance.next_id_with_raw_parts(7, None),
+            Err(SINTEFlakeError::CounterOverflow { bucket: 7, .. })
+        ));
+
+        let layout = Layout::new(14, 31, 10, 4).unwrap();
+        let mut narrow = SINTEFlake::builder().layout(layout).build().unwrap();
+        assert!(matches!(
+            narrow.next_id_with_raw_parts(0, Some(16)),
+            Err(SINTEFlakeError::RawPartsTooWide)
+        ));
+
+        // the configured capacity is below the one of the layout
+        let mut limited = SINTEFlake::builder().bucket_capacity(16).build().unwrap();
+        limited.next_id_with_raw_parts(0, Some(15)).unwrap();
+        assert!(matches!(
+            limited.next_id_with_raw_parts(1, Some(16)),
+            Err(SINTEFlakeError::RawPartsTooWide)
+        ));
+    }
+
+    #[test]
+    fn test_raw_parts_rate_limit() {
+        use crate::rate_limit::RateLimit;
+
+        let clock = crate::clock::tests::ManualClock::new(1719792016000);
+        let mut instance = SINTEFlake::builder()
+            .clock(clock.clone())
+            .rate_limit(RateLimit::failing().burst(2))
+            .build()
+            .unwrap();
+        instance.next_id_with_raw_parts(1, Some(0)).unwrap();
+        instance.next_id_with_raw_parts(1, None).unwrap();
+        assert!(matches!(
+            instance.next_id_with_raw_parts(1, Some(5)),
+            Err(SINTEFlakeError::RateLimited)
+        ));
+    }
+
+    #[test]
+    fn test_estimate_collision_risk() {
+        let instance = SINTEFlake::builder()
+            .layout(Layout::JAVASCRIPT_SAFE)
+            .build()
+            .unwrap();
+        assert_eq!(
+            instance.estimate_collision_risk(20_000),
+            crate::capacity::estimate_collision_risk(20_000, &Layout::JAVASCRIPT_SAFE)
+        );
+    }
+
+    #[test]
+    fn test_uniformity_report() {
+        let instance = SINTEFlake::builder()
+            .hash_key([0xA5; 16])
+            .layout(Layout::JAVASCRIPT_SAFE)
+            .build()
+            .unwrap();
+        let report = instance.uniformity_report(12_800);
+        assert_eq!(report.buckets, 128);
+        assert_eq!(report.expected, 100.0);
+        assert!(report.is_uniform(), "{:?}", report);
+    }
+
+    #[test]
+    fn test_health_check() {
+        let clock = crate::clock::tests::ManualClock::new(1719792016000);
+        let mut instance = SINTEFlake::builder()
+            .clock(clock.clone())
+            .layout(Layout::JAVASCRIPT_SAFE)
+            .build()
+            .unwrap();
+        for _ in 0..8192 {
+            instance.next_id().unwrap();
+        }
+        let report = instance.health_check();
+        assert!(report.is_healthy());
+        assert_eq!(report.clock, ClockStatus::Ok);
+        assert_eq!(report.lease, LeaseHealth::NotLeased);
+        assert_eq!(report.window_utilization, 0.25);
+        // 2^28 windows of 8 seconds, about 68 years
+        assert!((68.0..68.1).contains(&report.years_remaining));
+
+        clock.set(1719792000000);
+        assert_eq!(instance.health_check().clock, ClockStatus::BehindLastWindow);
+        clock.set(1719791000000);
+        assert_eq!(instance.health_check().clock, ClockStatus::BeforeEpoch);
+        clock.set(1719792000000 + 8000 * (1 << 28));
+        let report = instance.health_check();
+        assert_eq!(report.clock, ClockStatus::Overflow);
+        assert_eq!(report.years_remaining, 0.0);
+        assert!(!report.is_healthy());
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        use crate::rate_limit::RateLimit;
+
+        // 32768 IDs per 8 seconds, a token every 244 µs
+        let clock = crate::clock::tests::ManualClock::new(1719792016000);
+        let mut instance = SINTEFlake::builder()
+            .clock(clock.clone())
+            .layout(Layout::JAVASCRIPT_SAFE)
+            .rate_limit(RateLimit::failing().burst(10))
+            .build()
+            .unwrap();
+        for _ in 0..10 {
+            instance.next_id().unwrap();
+        }
+        assert!(matches!(
+            instance.next_id(),
+            Err(SINTEFlakeError::RateLimited)
+        ));
+        assert_eq!(instance.rate_limit_delay(), Duration::from_nanos(244141));
+        clock.set(1719792016001);
+        for _ in 0..4 {
+            instance.next_id_with_hash(b"data").unwrap();
+        }
+        assert!(instance.next_id_with_hash(b"data").is_err());
+
+        let mut unlimited = SINTEFlake::new().unwrap();
+        assert_eq!(unlimited.rate_limit_delay(), Duration::ZERO);
+        unlimited.next_id().unwrap();
+    }
+
+    #[test]
+    fn test_rate_limit_blocking() {
+        use crate::rate_limit::RateLimit;
+
+        // 4 IDs per 100 ms windows, a token every 25 ms, bursts of 1
+        let mut instance = SINTEFlake::builder()
+            .layout(Layout::new(1, 31, 10, 1).unwrap())
+            .time_window(Duration::from_millis(100))
+            .rate_limit(RateLimit::blocking().burst(1))
+            .build()
+            .unwrap();
+        let start = std::time::Instant::now();
+        for _ in 0..3 {
+            instance.next_id_with_retry(RetryPolicy::default()).unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_next_typed() {
+        use crate::id::{Entity, TypedId};
+
+        enum User {}
+        impl Entity for User {
+            const PREFIX: &'static str = "user";
+        }
+
+        let mut instance = SINTEFlake::new().unwrap();
+        let id_a: TypedId<User> = instance.next_typed().unwrap();
+        let id_b = instance.next_typed::<User>().unwrap();
+        assert_ne!(id_a, id_b);
+        assert!(id_a.to_string().starts_with("user_"));
+    }
+
+    #[test]
+    fn test_content_id() {
+        use crate::decode::decode;
+
+        let instance = SINTEFlake::builder().instance_id(7).build().unwrap();
+        let id = instance.content_id(b"event", 1234).unwrap();
+        assert_eq!(instance.content_id(b"event", 1234).unwrap(), id);
+        assert_ne!(instance.content_id(b"other", 1234).unwrap(), id);
+        assert_ne!(instance.content_id(b"event", 1235).unwrap(), id);
+
+        // another instance with the same settings derives the same ID
+        let other = SINTEFlake::builder().instance_id(7).build().unwrap();
+        assert_eq!(other.content_id(b"event", 1234).unwrap(), id);
+
+        let decoded = decode(id, &instance.encoding_config()).unwrap();
+        assert_eq!((decoded.timestamp, decoded.instance_id), (1234, 7));
+
+        assert!(matches!(
+            instance.content_id(b"event", 1 << 31),
+            Err(SINTEFlakeError::TimestampOverflow)
+        ));
+    }
+
+    #[test]
+    fn test_set_epoch() {
+        use crate::decode::decode;
+
+        let clock = crate::clock::tests::ManualClock::new(1719792080000);
+        let mut instance = SINTEFlake::builder().clock(clock.clone()).build().unwrap();
+        let id = instance.next_id().unwrap();
+        assert_eq!(
+            decode(id, &instance.encoding_config()).unwrap().timestamp,
+            10
+        );
+
+        instance
+            .set_epoch(UnixTime::from_unix_timestamp(1719791920).unwrap())
+            .unwrap();
+        let config = instance.encoding_config();
+        assert_eq!(config.epoch_id, None);
+        assert_eq!(instance.ids_in_current_window(), 0);
+        let id = instance.next_id().unwrap();
+        assert_eq!(decode(id, &config).unwrap().timestamp, 20);
+
+        instance.set_named_epoch(Epoch::UNIX).unwrap();
+        assert_eq!(instance.encoding_config().epoch_id, Some("unix"));
+
+        // the time windows already used can't come again
+        assert!(matches!(
+            instance.set_named_epoch(Epoch::SINTEFLAKE_2024),
+            Err(SINTEFlakeError::InvalidConfig { setting: "epoch" })
+        ));
+        let future = UnixTime::from_unix_timestamp(1719792100).unwrap();
+        assert!(matches!(
+            instance.set_epoch(future),
+            Err(SINTEFlakeError::EpochInFuture)
+        ));
+        assert_eq!(instance.encoding_config().epoch_id, Some("unix"));
+    }
+
+    #[test]
+    fn test_set_epoch_in_same_window() {
+        // 1 second into the time window 10
+        let clock = crate::clock::tests::ManualClock::new(1719792081000);
+        let mut instance = SINTEFlake::builder().clock(clock.clone()).build().unwrap();
+        let mut ids = std::collections::HashSet::new();
+        for _ in 0..1000 {
+            assert!(ids.insert(instance.next_id().unwrap()));
+        }
+
+        // still in the time window 10, which keeps its IDs
+        instance
+            .set_epoch(UnixTime::from_unix_timestamp(1719792000).unwrap())
+            .unwrap();
+        instance
+            .set_epoch(UnixTime::from_unix_timestamp_nanos(1719792000500000000).unwrap())
+            .unwrap();
+        assert_eq!(instance.ids_in_current_window(), 1000);
+        for _ in 0..1000 {
+            assert!(ids.insert(instance.next_id().unwrap()));
+        }
+
+        // 2 seconds later, the epoch brings back the time window 9
+        assert!(matches!(
+            instance.set_epoch(UnixTime::from_unix_timestamp(1719792002).unwrap()),
+            Err(SINTEFlakeError::InvalidConfig { setting: "epoch" })
+        ));
+        assert_eq!(instance.ids_in_current_window(), 1000 * 2);
+    }
+
+    #[test]
+    fn test_duplicate_guard() {
+        let mut instance = SINTEFlake::builder()
+            .duplicate_guard_windows(2)
+            .build()
+            .unwrap();
+        let id = instance.next_id_with_hash(b"data").unwrap();
+        instance.next_id_with_hash(b"data").unwrap();
+
+        // a bug losing the collisions map would issue the same IDs again
+        instance.collisions_map.reset();
+        assert!(matches!(
+            instance.next_id_with_hash(b"data"),
+            Err(SINTEFlakeError::DuplicateId { id: duplicate }) if duplicate == id
+        ));
+
+        let mut unguarded = SINTEFlake::builder().build().unwrap();
+        let id = unguarded.next_id_with_hash(b"data").unwrap();
+        unguarded.collisions_map.reset();
+        assert_eq!(unguarded.next_id_with_hash(b"data").unwrap(), id);
+    }
+
+    #[test]
+    fn test_import_snowflake() {
+        use crate::decode::decode;
+
+        let epoch = UnixTime::from_unix_timestamp(1288834974).unwrap();
+        let instance = SINTEFlake::builder()
+            .epoch(epoch)
+            .instance_id(1000)
+            .build()
+            .unwrap();
+        let config = instance.encoding_config();
+        let mut importer = instance.snowflake_importer(SnowflakeDialect::Twitter);
+        // created 80 seconds after the Twitter epoch
+        let source = 80000 << 22 | 3 << 12 | 42;
+        let id = importer.import(source).unwrap();
+        assert_ne!(importer.import(source + 1).unwrap(), id);
+        let decoded = decode(id, &config).unwrap();
+        assert_eq!((decoded.timestamp, decoded.instance_id), (10, 1000));
+
+        let late = SINTEFlake::builder()
+            .epoch(UnixTime::from_unix_timestamp(1719792000).unwrap())
+            .build()
+            .unwrap();
+        assert!(matches!(
+            late.snowflake_importer(SnowflakeDialect::Twitter)
+                .import(source),
+            Err(SINTEFlakeError::EpochInFuture)
+        ));
+        assert!(matches!(
+            importer.import(u64::MAX),
+            Err(SINTEFlakeError::InvalidId)
+        ));
+    }
+
+    #[test]
+    fn test_import_snowflake_window() {
+        // 4 buckets of 2 IDs
+        let instance = SINTEFlake::builder()
+            .layout(Layout::new(2, 31, 10, 1).unwrap())
+            .epoch(UnixTime::from_unix_timestamp(1288834974).unwrap())
+            .build()
+            .unwrap();
+        let mut importer = instance.snowflake_importer(SnowflakeDialect::Twitter);
+        // 8 IDs created in the same millisecond fill the window
+        let mut ids = std::collections::HashSet::new();
+        for sequence in 0..8 {
+            let id = importer.import(80000 << 22 | sequence).unwrap();
+            assert!(ids.insert(id));
+        }
+        assert!(matches!(
+            importer.import(80000 << 22 | 8),
+            Err(SINTEFlakeError::CounterOverflow { window: 10, .. })
+        ));
+        // the next window is empty
+        assert!(ids.insert(importer.import(88000 << 22).unwrap()));
+
+        // many IDs of the default layout
+        let instance = SINTEFlake::builder()
+            .epoch(UnixTime::from_unix_timestamp(1288834974).unwrap())
+            .build()
+            .unwrap();
+        let mut importer = instance.snowflake_importer(SnowflakeDialect::Twitter);
+        let mut ids = std::collections::HashSet::new();
+        for source in 0..100_000 {
+            assert!(ids.insert(importer.import(80000 << 22 | source).unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_stable_id_with_hash() {
+        let clock = crate::clock::tests::ManualClock::new(1719792000000);
+        let mut instance = SINTEFlake::builder()
+            .clock(clock.clone())
+            .stable_id_capacity(2)
+            .build()
+            .unwrap();
+        let id_a = instance.stable_id_with_hash(b"a").unwrap();
+        let id_b = instance.stable_id_with_hash(b"b").unwrap();
+        assert_ne!(id_a, id_b);
+        assert_eq!(instance.stable_id_with_hash(b"a").unwrap(), id_a);
+        assert_ne!(instance.next_id_with_hash(b"a").unwrap(), id_a);
+
+        // b is forgotten, as the least recently used payload
+        instance.stable_id_with_hash(b"c").unwrap();
+        assert_ne!(instance.stable_id_with_hash(b"b").unwrap(), id_b);
+
+        // a new time window forgets all the payloads
+        clock.set(1719792008000);
+        instance.update_time().unwrap();
+        assert_ne!(instance.stable_id_with_hash(b"a").unwrap(), id_a);
+    }
 
-    collisions_map: [u16; 16384], // 2^14
+    #[test]
+    fn test_next_id_for_tenant() {
+        use crate::decode::decode;
 
-    current_timestamp_bits: u32,
+        let mut instance = SINTEFlake::builder().tenant_bits(4).build().unwrap();
+        let config = instance.encoding_config();
+        for tenant in [0, 5, 15] {
+            for data in [&b"a"[..], b"b", b"c"] {
+                let id = instance.next_id_for_tenant(tenant, data).unwrap();
+                assert_eq!(decode(id, &config).unwrap().tenant(&config), tenant);
+            }
+        }
+        assert!(matches!(
+            instance.next_id_for_tenant(16, b"a"),
+            Err(SINTEFlakeError::InvalidTenant)
+        ));
 
-    ids_count_at_current_timestamp: u64,
-}
+        // without tenant bits, only the tenant 0 exists
+        let mut instance = SINTEFlake::new().unwrap();
+        let config = instance.encoding_config();
+        let id = instance.next_id_for_tenant(0, b"a").unwrap();
+        assert_eq!(decode(id, &config).unwrap().tenant(&config), 0);
+        assert!(instance.next_id_for_tenant(1, b"a").is_err());
 
-impl SINTEFlake {
-    /// Creates a new SINTEFlake instance with default settings.
-    ///
-    /// # Returns
-    /// - `Result<Self, SINTEFlakeError>`: A new SINTEFlake instance or an error if creation fails.
-    ///
-    /// # Errors
-    /// Returns an error if the initial time update fails.
-    pub fn new() -> Result<Self, SINTEFlakeError> {
-        let mut instance = SINTEFlake {
-            instance_id: 0,
+        assert!(matches!(
+            SINTEFlake::builder().tenant_bits(15).build(),
+            Err(SINTEFlakeError::InvalidLayout)
+        ));
+    }
 
-            // hash_key: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
-            // pi digits after the comma in base 16
-            // https://www.wolframalpha.com/input?i=pi+in+base+16
-            // 3.243f6a8885a308d313198a2e03707344
-            hash_key: [
-                0x24, 0x3f, 0x6a, 0x88, 0x85, 0xa3, 0x08, 0xd3, 0x13, 0x19, 0x8a, 0x2e, 0x03, 0x70,
-                0x73, 0x44,
-            ],
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_next_id_random() {
+        use rand::SeedableRng;
 
-            // the counter is XORed with this value
-            counter_key: 42,
+        let mut instance = SINTEFlake::builder()
+            .rng(rand::rngs::StdRng::seed_from_u64(42))
+            .build()
+            .unwrap();
+        let mut ids = std::collections::HashSet::new();
+        for _ in 0..10000 {
+            assert!(ids.insert(instance.next_id_random().unwrap()));
+        }
+    }
 
-            // 2024-07-01T00:00:00Z
-            epoch: OffsetDateTime::from_unix_timestamp(1719792000)
-                .expect("Invalid timestamp, shouldn't happen #1719792000"),
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_next_id_random_with_constant_rng() {
+        let mut instance = SINTEFlake::builder()
+            .rng(rand::rngs::mock::StepRng::new(0x0123, 0))
+            .build()
+            .unwrap();
+        for _ in 0..256 {
+            assert_eq!(instance.next_id_random().unwrap() >> 49, 0x0123);
+        }
+        // the bucket is full, the next one is used
+        assert_eq!(instance.next_id_random().unwrap() >> 49, 0x0124);
+    }
 
-            collisions_map: [0; 16384],
+    #[test]
+    fn test_reserve_block() {
+        let clock = crate::clock::tests::ManualClock::new(1719792016000);
+        let mut instance = SINTEFlake::builder()
+            .layout(Layout::new(2, 31, 10, 2).unwrap())
+            .clock(clock)
+            .build()
+            .unwrap();
 
-            current_timestamp_bits: 0,
+        let block = instance.reserve_block(10).unwrap();
+        assert_eq!(block.len(), 10);
+        assert_eq!(instance.ids_in_current_window(), 10);
+        let mut ids: std::collections::HashSet<u64> = block.collect();
 
-            ids_count_at_current_timestamp: 0,
-        };
+        // only 6 IDs left in the window
+        assert!(matches!(
+            instance.reserve_block(7),
+            Err(SINTEFlakeError::CounterOverflow { .. })
+        ));
+        assert_eq!(instance.ids_in_current_window(), 10);
+        ids.extend(instance.reserve_block(6).unwrap());
+        assert_eq!(ids.len(), 16);
+        assert_eq!(instance.reserve_block(0).unwrap().len(), 0);
+    }
 
-        instance.update_time()?;
+    #[test]
+    fn test_reserve_block_is_atomic() {
+        use crate::rate_limit::RateLimit;
 
-        Ok(instance)
+        let clock = crate::clock::tests::ManualClock::new(1719792016000);
+        let mut instance = SINTEFlake::builder()
+            .layout(Layout::new(2, 31, 10, 2).unwrap())
+            .probe_attempts(0)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+
+        // without probing, a full bucket fails before the window is full
+        assert!(matches!(
+            instance.reserve_block(16),
+            Err(SINTEFlakeError::CounterOverflow { .. })
+        ));
+        assert_eq!(instance.ids_in_current_window(), 0);
+        assert_eq!(instance.collision_histogram()[0], 4);
+
+        let mut limited = SINTEFlake::builder()
+            .clock(clock)
+            .rate_limit(RateLimit::failing().burst(4))
+            .build()
+            .unwrap();
+        assert!(matches!(
+            limited.reserve_block(5),
+            Err(SINTEFlakeError::RateLimited)
+        ));
+        assert_eq!(limited.ids_in_current_window(), 0);
+        assert_eq!(limited.reserve_block(4).unwrap().len(), 4);
     }
 
-    /// Creates a custom SINTEFlake instance with specified settings.
-    ///
-    /// # Arguments
-    /// * `instance_id` - A 14-bit unsigned integer representing the instance ID.
-    /// * `hash_key` - A 16-byte array used as the key for hashing.
-    /// * `counter_key` - An 8-bit unsigned integer used to XOR the counter.
-    /// * `epoch` - The epoch time from which to measure timestamps.
-    ///
-    /// # Returns
-    /// - `Result<Self, SINTEFlakeError>`: A new SINTEFlake instance or an error if creation fails.
-    ///
-    /// # Errors
-    /// Returns an error if the instance_id is too high (>16383) or if the initial time update fails.
-    pub fn custom(
-        instance_id: u16,
-        hash_key: [u8; 16],
-        counter_key: u8,
-        epoch: OffsetDateTime,
-    ) -> Result<Self, SINTEFlakeError> {
-        if instance_id > 16383 {
-            return Err(SINTEFlakeError::InstanceIDTooHigh);
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_next_ids() {
+        let clock = crate::clock::tests::ManualClock::new(1719792016000);
+        let mut instance = SINTEFlake::builder().clock(clock.clone()).build().unwrap();
+        let mut one_by_one = SINTEFlake::builder().clock(clock).build().unwrap();
+
+        let config = instance.encoding_config();
+        let par_ids = instance.par_next_ids(100_000).unwrap();
+        let window = crate::decode::decode(one_by_one.next_id().unwrap(), &config)
+            .unwrap()
+            .timestamp;
+        for &id in &par_ids {
+            assert_eq!(
+                crate::decode::decode(id, &config).unwrap().timestamp,
+                window
+            );
         }
-        let mut instance = SINTEFlake {
-            instance_id,
-            hash_key,
-            counter_key,
-            epoch,
-            collisions_map: [0; 16384],
-            current_timestamp_bits: 0,
-            ids_count_at_current_timestamp: 0,
-        };
 
-        instance.update_time()?;
+        let mut ids: std::collections::HashSet<u64> = par_ids.into_iter().collect();
+        assert_eq!(ids.len(), 100_000);
+        assert_eq!(instance.ids_in_current_window(), 100_000);
+        assert_eq!(
+            instance
+                .collision_histogram()
+                .iter()
+                .enumerate()
+                .map(|(n, &buckets)| n as u64 * buckets)
+                .sum::<u64>(),
+            100_000
+        );
+        // the IDs generated one by one afterwards are distinct
+        for _ in 0..1000 {
+            assert!(ids.insert(instance.next_id().unwrap()));
+        }
 
-        Ok(instance)
+        // filling the window completely, 16 IDs
+        let mut instance = SINTEFlake::builder()
+            .layout(Layout::new(2, 31, 10, 2).unwrap())
+            .build()
+            .unwrap();
+        let mut ids: std::collections::HashSet<u64> =
+            (0..3).map(|_| instance.next_id().unwrap()).collect();
+        assert!(matches!(
+            instance.par_next_ids(14),
+            Err(SINTEFlakeError::CounterOverflow { .. })
+        ));
+        ids.extend(instance.par_next_ids(13).unwrap());
+        assert_eq!(ids.len(), 16);
+        assert_eq!(instance.par_next_ids(0).unwrap().len(), 0);
     }
 
-    /// Sets the instance ID for this SINTEFlake instance.
-    ///
-    /// # Arguments
-    /// * `instance_id` - A 14-bit unsigned integer representing the new instance ID.
-    ///
-    /// # Returns
-    /// - `Result<(), SINTEFlakeError>`: Ok if successful, or an error if the instance_id is too high.
-    ///
-    /// # Errors
-    /// Returns an error if the instance_id is greater than 16383.
-    pub fn set_instance_id(&mut self, instance_id: u16) -> Result<(), SINTEFlakeError> {
-        if instance_id > 16383 {
-            return Err(SINTEFlakeError::InstanceIDTooHigh);
-        }
-        self.instance_id = instance_id;
-        Ok(())
+    #[test]
+    fn test_instance_id_lease() {
+        use crate::allocator::tests::MemoryAllocator;
+        use crate::allocator::InstanceIdLease;
+
+        let allocator = MemoryAllocator::new(1023);
+        let _other = InstanceIdLease::acquire(allocator.clone(), Duration::from_secs(60)).unwrap();
+        let lease = InstanceIdLease::acquire(allocator, Duration::from_secs(60)).unwrap();
+        let mut instance = SINTEFlake::builder()
+            .instance_id_lease(&lease)
+            .build()
+            .unwrap();
+        assert_eq!(instance.instance_id, 1);
+        assert!(instance.next_id().is_ok());
+
+        drop(lease);
+        assert!(matches!(
+            instance.next_id(),
+            Err(SINTEFlakeError::LeaseExpired)
+        ));
+        assert!(matches!(
+            instance.next_id_with_hash(b"data"),
+            Err(SINTEFlakeError::LeaseExpired)
+        ));
     }
 
-    /// Updates the internal timestamp of the SINTEFlake instance.
-    ///
-    /// # Returns
-    /// - `Result<(), SINTEFlakeError>`: Ok if successful, or an error if the time update fails.
-    ///
-    /// # Errors
-    /// Returns an error if unable to get the current timestamp.
-    pub fn update_time(&mut self) -> Result<(), SINTEFlakeError> {
-        let current_timestamp = get_current_timestamp(self.epoch)?;
-        let permuted_timestamp = permute_u32_31_bits(current_timestamp);
-        if permuted_timestamp != self.current_timestamp_bits {
-            // not clear because we want to start
-            // from a clean memory allocation
-            self.collisions_map = [0; 16384];
-            self.current_timestamp_bits = permuted_timestamp;
-            self.ids_count_at_current_timestamp = 0;
+    #[test]
+    fn test_with_auto_instance_id() {
+        // the sandbox may have no network interface at all
+        match SINTEFlake::with_auto_instance_id() {
+            Ok(instance) => assert!(instance.instance_id <= 1023),
+            Err(error) => assert!(matches!(error, SINTEFlakeError::InstanceIdUnavailable)),
         }
-        Ok(())
     }
 
-    /// Generates the next unique ID.
-    ///
-    /// # Returns
-    /// - `Result<u64, SINTEFlakeError>`: A new unique 64-bit ID, or an error if generation fails.
-    ///
-    /// # Errors
-    /// Returns an error if there's a counter overflow.
-    pub fn next_id(&mut self) -> Result<u64, SINTEFlakeError> {
-        self.next_id_with_hash(&self.ids_count_at_current_timestamp.to_be_bytes())
+    #[cfg(feature = "getrandom")]
+    #[test]
+    fn test_with_random_key() {
+        let mut instance_a = SINTEFlake::with_random_key().unwrap();
+        let mut instance_b = SINTEFlake::with_random_key().unwrap();
+        assert_ne!(instance_a.hash_key, instance_b.hash_key);
+
+        let id_a = instance_a.next_id().unwrap();
+        let id_b = instance_a.next_id().unwrap();
+        assert_ne!(id_a, id_b);
+        assert!(instance_b.next_id().is_ok());
     }
 
-    fn shuffle_hash_counter(&self, counter: u8) -> u8 {
-        permute_u8(counter ^ self.counter_key)
+    #[cfg(feature = "hkdf")]
+    #[test]
+    fn test_from_namespace() {
+        let orders_a = SINTEFlake::from_namespace("orders").unwrap();
+        let orders_b = SINTEFlake::from_namespace("orders").unwrap();
+        let users = SINTEFlake::from_namespace("users").unwrap();
+        assert_eq!(orders_a.hash_key, orders_b.hash_key);
+        assert_eq!(orders_a.counter_key, orders_b.counter_key);
+        assert_ne!(orders_a.hash_key, users.hash_key);
+
+        // the derivation must stay stable across versions
+        assert_eq!(
+            namespace_keys("orders"),
+            (
+                [
+                    0x90, 0x49, 0x31, 0xbc, 0xe0, 0x95, 0xb7, 0x51, 0x89, 0x4d, 0x87, 0x8c, 0x40,
+                    0x09, 0x5e, 0xce
+                ],
+                0x80
+            )
+        );
     }
 
-    /// Generates the next unique ID using the provided data for hashing.
-    ///
-    /// # Arguments
-    /// * `data` - A byte slice used to generate the hash part of the ID.
-    ///
-    /// # Returns
-    /// - `Result<u64, SINTEFlakeError>`: A new unique 64-bit ID, or an error if generation fails.
-    ///
-    /// # Errors
-    /// Returns an error if there's a counter overflow.
-    pub fn next_id_with_hash(&mut self, data: &[u8]) -> Result<u64, SINTEFlakeError> {
-        let mut hash = hash::hash(data, &self.hash_key);
-        let mut counter = 0;
+    fn instance_with_clock(
+        clock: &crate::clock::tests::ManualClock,
+        policy: ClockRollbackPolicy,
+    ) -> SINTEFlake {
+        SINTEFlake::builder()
+            .epoch(UnixTime::from_unix_timestamp(1719792000).unwrap())
+            .clock(clock.clone())
+            .clock_rollback_policy(policy)
+            .build()
+            .unwrap()
+    }
 
-        loop {
-            let hash_counter = self.collisions_map[hash as usize];
-            // if the hash counter has overflowed
-            if hash_counter == 256 {
-                // we give ourselves 10 tries to find a new hash
-                // with enough space
-                if counter == 10 {
-                    return Err(SINTEFlakeError::CounterOverflow);
-                }
-                counter += 1;
-                // we just increment the hash by one
-                hash = (hash + 1) % 16384;
-                continue;
-            }
-            self.collisions_map[hash as usize] += 1;
+    #[test]
+    fn test_clock_rollback_ignored_by_default() {
+        let clock = crate::clock::tests::ManualClock::new(1719792016000);
+        let mut instance = SINTEFlake::builder()
+            .epoch(UnixTime::from_unix_timestamp(1719792000).unwrap())
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        assert_eq!(instance.last_timestamp, Some(2));
 
-            let timestamp = self.current_timestamp_bits;
-            let instance_id = 0; // no instance id
-            let shuffled_counter = self.shuffle_hash_counter(hash_counter as u8);
-            self.ids_count_at_current_timestamp += 1;
-            return Ok(construct_identifier(
-                hash,
-                timestamp,
-                instance_id,
-                shuffled_counter,
-            ));
-        }
+        clock.set(1719792015000);
+        instance.update_time().unwrap();
+        assert_eq!(instance.last_timestamp, Some(1));
+        assert_eq!(instance.clock_rollback_delay(), Duration::ZERO);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_clock_rollback_error() {
+        let clock = crate::clock::tests::ManualClock::new(1719792016000);
+        let mut instance = instance_with_clock(&clock, ClockRollbackPolicy::Error);
+        let id_a = instance.next_id().unwrap();
+
+        // back to the previous window
+        clock.set(1719792015000);
+        assert!(matches!(
+            instance.update_time(),
+            Err(SINTEFlakeError::ClockRollback)
+        ));
+
+        // back to the last window
+        clock.set(1719792016500);
+        instance.update_time().unwrap();
+        let id_b = instance.next_id().unwrap();
+        assert_ne!(id_a, id_b);
+    }
 
     #[test]
-    fn test_basic() {
-        let mut instance = SINTEFlake::new().unwrap();
+    fn test_clock_rollback_tolerate() {
+        let clock = crate::clock::tests::ManualClock::new(1719792016000);
+        let policy = ClockRollbackPolicy::Tolerate {
+            max_drift: Duration::from_secs(2),
+        };
+        let mut instance = instance_with_clock(&clock, policy);
         let id_a = instance.next_id().unwrap();
+
+        clock.set(1719792015000);
+        instance.update_time().unwrap();
         let id_b = instance.next_id().unwrap();
         assert_ne!(id_a, id_b);
+        assert_eq!(instance.last_timestamp, Some(2));
+
+        clock.set(1719792010000);
+        assert!(instance.update_time().is_err());
     }
 
     #[test]
-    fn test_with_hash() {
-        let mut instance = SINTEFlake::new().unwrap();
-        let data = [1, 2, 3];
-        let id_a = instance.next_id_with_hash(&data).unwrap();
-        let id_b = instance.next_id_with_hash(&data).unwrap();
+    fn test_clock_rollback_wait() {
+        let clock = crate::clock::tests::ManualClock::new(1719792000100);
+        let mut instance = SINTEFlake::builder()
+            .epoch(UnixTime::from_unix_timestamp(1719792000).unwrap())
+            .time_window(Duration::from_millis(1))
+            .clock(clock.clone())
+            .clock_rollback_policy(ClockRollbackPolicy::Wait)
+            .build()
+            .unwrap();
+        assert_eq!(instance.last_timestamp, Some(100));
+        assert_eq!(instance.clock_rollback_delay(), Duration::ZERO);
+
+        clock.set(1719792000095);
+        assert_eq!(instance.clock_rollback_delay(), Duration::from_millis(5));
+
+        // the clock goes back by 5 ms, then advances by 1 ms every time it's read
+        let clock_with_step = crate::clock::tests::ManualClock::with_step(1719792000095, 1);
+        instance.clock = Arc::new(clock_with_step);
+        instance.update_time().unwrap();
+        assert!(instance.last_timestamp.unwrap() >= 100);
+    }
+
+    #[test]
+    fn test_window_metrics() {
+        let clock = crate::clock::tests::ManualClock::new(1719792016000);
+        let mut instance = instance_with_clock(&clock, ClockRollbackPolicy::Error);
+        let capacity = instance.layout().window_capacity();
+        assert_eq!(instance.ids_in_current_window(), 0);
+        assert_eq!(instance.remaining_capacity(), capacity);
+        assert_eq!(instance.window_remaining(), Duration::from_secs(8));
+
+        for _ in 0..3 {
+            instance.next_id_with_hash(b"same").unwrap();
+        }
+        instance.next_id_with_hash(b"other").unwrap();
+        assert_eq!(instance.ids_in_current_window(), 4);
+        assert_eq!(instance.remaining_capacity(), capacity - 4);
+
+        let histogram = instance.collision_histogram();
+        assert_eq!(histogram.len(), 257);
+        assert_eq!(histogram[0], 16382);
+        assert_eq!(histogram[1], 1);
+        assert_eq!(histogram[3], 1);
+
+        clock.set(1719792021500);
+        assert_eq!(instance.window_remaining_secs(), 2.5);
+
+        clock.set(1719792024000);
+        instance.update_time().unwrap();
+        assert_eq!(instance.ids_in_current_window(), 0);
+        assert_eq!(instance.collision_histogram()[0], 16384);
+    }
+
+    #[test]
+    fn test_state_store_skip_forward() {
+        let path =
+            std::env::temp_dir().join(format!("sinteflake-skip-forward-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let clock = crate::clock::tests::ManualClock::new(1719792016000);
+        let build = || {
+            SINTEFlake::builder()
+                .epoch(UnixTime::from_unix_timestamp(1719792000).unwrap())
+                .clock(clock.clone())
+                .state_store(crate::state::FileStateStore::new(&path))
+                .build()
+        };
+
+        let mut instance = build().unwrap();
+        let id_a = instance.next_id().unwrap();
+        instance.close().unwrap();
+        let store = crate::state::FileStateStore::new(&path);
+        assert_eq!(
+            store.load().unwrap(),
+            Some(State {
+                timestamp: 2,
+                ids_count: 1
+            })
+        );
+
+        // restart in the same window, the next window is used
+        let mut instance = build().unwrap();
+        assert_eq!(instance.last_timestamp, Some(3));
+        let id_b = instance.next_id().unwrap();
         assert_ne!(id_a, id_b);
+
+        // the clock is still behind, no rollback error
+        clock.set(1719792017000);
+        instance.update_time().unwrap();
+        assert_eq!(instance.last_timestamp, Some(3));
+
+        // the clock caught up
+        clock.set(1719792040000);
+        instance.update_time().unwrap();
+        assert_eq!(instance.last_timestamp, Some(5));
+        instance.close().unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_state_store_refuse() {
+        let path = std::env::temp_dir().join(format!("sinteflake-refuse-{}", std::process::id()));
+        let store = crate::state::FileStateStore::new(&path);
+        store
+            .save(&State {
+                timestamp: 10,
+                ids_count: 0,
+            })
+            .unwrap();
+
+        let clock = crate::clock::tests::ManualClock::new(1719792016000);
+        let result = SINTEFlake::builder()
+            .epoch(UnixTime::from_unix_timestamp(1719792000).unwrap())
+            .clock(clock.clone())
+            .state_store(store.clone())
+            .state_recovery_policy(StateRecoveryPolicy::Refuse)
+            .build();
+        assert!(matches!(
+            result,
+            Err(SINTEFlakeError::ClockBehindSavedState)
+        ));
+
+        clock.set(1719792100000);
+        let instance = SINTEFlake::builder()
+            .epoch(UnixTime::from_unix_timestamp(1719792000).unwrap())
+            .clock(clock)
+            .state_store(store)
+            .state_recovery_policy(StateRecoveryPolicy::Refuse)
+            .build()
+            .unwrap();
+        instance.close().unwrap();
+
+        std::fs::remove_file(&path).unwrap();
     }
 
     #[test]
@@ -242,6 +2789,356 @@ mod tests {
         assert!(instance.next_id_with_hash(&data).is_err());
     }
 
+    #[test]
+    fn test_event_hook() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        #[derive(Debug, Default)]
+        struct Counters {
+            rollovers: AtomicU64,
+            fallbacks: AtomicU64,
+            overflows: AtomicU64,
+        }
+
+        #[derive(Debug, Clone, Default)]
+        struct CountingHook(Arc<Counters>);
+
+        impl EventHook for CountingHook {
+            fn on_window_rollover(&self, _instance_id: u16, _window: u64) {
+                self.0.rollovers.fetch_add(1, Ordering::Relaxed);
+            }
+
+            fn on_bucket_fallback(
+                &self,
+                instance_id: u16,
+                _window: u64,
+                bucket: u16,
+                next_bucket: u16,
+            ) {
+                assert_eq!(instance_id, 7);
+                assert_eq!(next_bucket, bucket + 1);
+                self.0.fallbacks.fetch_add(1, Ordering::Relaxed);
+            }
+
+            fn on_overflow(&self, _instance_id: u16, _window: u64, _bucket: u16) {
+                self.0.overflows.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let hook = CountingHook::default();
+        let mut instance = SINTEFlake::builder()
+            .instance_id(7)
+            .event_hook(hook.clone())
+            .build()
+            .unwrap();
+        assert_eq!(hook.0.rollovers.load(Ordering::Relaxed), 1);
+
+        let data = [1, 2, 3];
+        for _ in 0..2816 {
+            instance.next_id_with_hash(&data).unwrap();
+        }
+        let error = instance.next_id_with_hash(&data).unwrap_err();
+        assert!(matches!(error, SINTEFlakeError::CounterOverflow { .. }));
+        assert!(error.is_retryable());
+        assert_eq!(hook.0.overflows.load(Ordering::Relaxed), 1);
+        assert!(hook.0.fallbacks.load(Ordering::Relaxed) > 10);
+    }
+
+    #[test]
+    fn test_probing() {
+        let data = [1, 2, 3];
+        for strategy in [
+            ProbingStrategy::Linear,
+            ProbingStrategy::Quadratic,
+            ProbingStrategy::DoubleHashing,
+        ] {
+            let mut instance = SINTEFlake::builder()
+                .probing_strategy(strategy)
+                .probe_attempts(3)
+                .build()
+                .unwrap();
+            let mut ids = std::collections::HashSet::new();
+            for _ in 0..1024 {
+                assert!(ids.insert(instance.next_id_with_hash(&data).unwrap()));
+            }
+            assert!(instance.next_id_with_hash(&data).is_err());
+            assert_eq!(instance.collision_histogram()[256], 4);
+        }
+    }
+
+    #[test]
+    fn test_overflow_borrows_preceding_window() {
+        use crate::decode::decode;
+
+        for sequence_mode in [false, true] {
+            let clock = crate::clock::tests::ManualClock::new(1719792016000);
+            // 4 buckets of 2 IDs
+            let mut instance = SINTEFlake::builder()
+                .layout(Layout::new(2, 31, 10, 1).unwrap())
+                .epoch(UnixTime::from_unix_timestamp(1719792000).unwrap())
+                .clock(clock.clone())
+                .sequence_mode(sequence_mode)
+                .overflow_policy(OverflowPolicy::BorrowPrecedingWindow)
+                .build()
+                .unwrap();
+            let config = instance.encoding_config();
+            let mut ids = std::collections::HashSet::new();
+
+            // the instance may have used the window before its creation
+            for _ in 0..8 {
+                assert!(ids.insert(instance.next_id().unwrap()));
+            }
+            assert!(instance.next_id().is_err());
+
+            // window 4 is skipped and can be borrowed from window 5
+            clock.set(1719792040000);
+            instance.update_time().unwrap();
+            for i in 0..16 {
+                let id = instance.next_id().unwrap();
+                assert!(ids.insert(id));
+                let timestamp = decode(id, &config).unwrap().timestamp;
+                assert_eq!(timestamp, if i < 8 { 5 } else { 4 });
+            }
+            assert!(matches!(
+                instance.next_id(),
+                Err(SINTEFlakeError::CounterOverflow { .. })
+            ));
+
+            // window 5 has been used
+            clock.set(1719792048000);
+            instance.update_time().unwrap();
+            for _ in 0..8 {
+                assert!(ids.insert(instance.next_id().unwrap()));
+            }
+            assert!(instance.next_id().is_err());
+        }
+    }
+
+    #[test]
+    fn test_reserve_buckets() {
+        use crate::decode::decode;
+
+        // 16 buckets of 4 IDs
+        let layout = Layout::new(4, 31, 10, 2).unwrap();
+        let mut instance = SINTEFlake::builder().layout(layout).build().unwrap();
+        let config = instance.encoding_config();
+        instance.reserve_buckets(b"hot", 3).unwrap();
+        let reserved = instance.hot_keys[0].buckets.clone();
+        let bucket_of = |id| decode(id, &config).unwrap().hash as usize;
+
+        // the reserved buckets are filled one after the other
+        for i in 0..12 {
+            let id = instance.next_id_with_hash(b"hot").unwrap();
+            assert_eq!(bucket_of(id), reserved.start + i / 4);
+        }
+        // then the other buckets
+        let id = instance.next_id_with_hash(b"hot").unwrap();
+        assert!(!reserved.contains(&bucket_of(id)));
+
+        // other keys landing in the reserved buckets start after them
+        let mut instance = SINTEFlake::builder().layout(layout).build().unwrap();
+        instance.reserve_buckets(b"hot", 3).unwrap();
+        let other = (0u32..)
+            .map(u32::to_be_bytes)
+            .find(|data| {
+                let hash = hash::hash(&*instance.hash_algorithm, data, &instance.hash_key);
+                hash as usize % 16 == reserved.start
+            })
+            .unwrap();
+        let id = instance.next_id_with_hash(&other).unwrap();
+        assert_eq!(bucket_of(id), reserved.end % 16);
+
+        assert!(instance.release_buckets(b"hot"));
+        assert!(!instance.release_buckets(b"hot"));
+        for n in [0, 9] {
+            assert!(matches!(
+                instance.reserve_buckets(b"hot", n),
+                Err(SINTEFlakeError::InvalidConfig { .. })
+            ));
+        }
+        let mut instance = SINTEFlake::builder().sequence_mode(true).build().unwrap();
+        assert!(matches!(
+            instance.reserve_buckets(b"hot", 1),
+            Err(SINTEFlakeError::SequenceMode)
+        ));
+    }
+
+    #[test]
+    fn test_bucket_capacity() {
+        use crate::decode::{decode, unshuffle_counter};
+
+        // 4 buckets of 4 sequence numbers, of which 2 are used
+        let layout = Layout::new(2, 31, 10, 2).unwrap();
+        for sequence_mode in [false, true] {
+            let mut instance = SINTEFlake::builder()
+                .layout(layout)
+                .bucket_capacity(2)
+                .sequence_mode(sequence_mode)
+                .build()
+                .unwrap();
+            let config = instance.encoding_config();
+            assert_eq!(instance.remaining_capacity(), 8);
+            let mut ids = std::collections::HashSet::new();
+            for _ in 0..8 {
+                let id = instance.next_id().unwrap();
+                let sequence = unshuffle_counter(decode(id, &config).unwrap().sequence, &config);
+                assert!(sequence < 2);
+                assert!(ids.insert(id));
+            }
+            assert!(matches!(
+                instance.next_id(),
+                Err(SINTEFlakeError::CounterOverflow { .. })
+            ));
+            let full = if sequence_mode { 0 } else { 4 };
+            assert_eq!(instance.collision_histogram(), [0, 0, full]);
+        }
+    }
+
+    #[test]
+    fn test_time_sorted_layout() {
+        use crate::decode::decode;
+
+        let clock = crate::clock::tests::ManualClock::new(1719792000000);
+        let mut instance = SINTEFlake::builder()
+            .epoch(UnixTime::from_unix_timestamp(1719792000).unwrap())
+            .layout(Layout::DEFAULT.time_sorted())
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let config = instance.encoding_config();
+        let mut previous = 0;
+        for window in 1..20 {
+            clock.set(1719792000000 + window * 8000);
+            instance.update_time().unwrap();
+            let ids: Vec<u64> = (0..50)
+                .map(|i| instance.next_id_with_hash(&[i as u8]).unwrap())
+                .collect();
+            assert!(ids.iter().all(|&id| id > previous));
+            assert!(ids
+                .iter()
+                .all(|&id| decode(id, &config).unwrap().timestamp == window));
+            previous = *ids.iter().max().unwrap();
+        }
+
+        assert!(matches!(
+            SINTEFlake::builder()
+                .layout(Layout::DEFAULT.time_sorted())
+                .feistel_permutation(true)
+                .build(),
+            Err(SINTEFlakeError::FeistelPermutation)
+        ));
+    }
+
+    #[test]
+    fn test_monotonic_sequence() {
+        use crate::decode::decode;
+
+        let mut instance = SINTEFlake::builder()
+            .sequence_mode(true)
+            .monotonic_sequence(true)
+            .build()
+            .unwrap();
+        let config = instance.encoding_config();
+        assert!(config.monotonic_sequence);
+        let ids: Vec<u64> = (0..1000).map(|_| instance.next_id().unwrap()).collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        let decoded = decode(ids[300], &config).unwrap();
+        assert_eq!((decoded.hash, decoded.sequence), (1, 44));
+        assert_eq!(decoded.counter(&config), 44);
+
+        // without the sequence mode, the IDs of a bucket increase
+        let mut instance = SINTEFlake::builder()
+            .monotonic_sequence(true)
+            .build()
+            .unwrap();
+        let ids: Vec<u64> = (0..100)
+            .map(|_| instance.next_id_with_hash(b"bucket").unwrap())
+            .collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+
+        assert!(matches!(
+            SINTEFlake::builder()
+                .monotonic_sequence(true)
+                .feistel_permutation(true)
+                .build(),
+            Err(SINTEFlakeError::FeistelPermutation)
+        ));
+    }
+
+    #[test]
+    fn test_sequence_mode() {
+        let layout = Layout::new(4, 31, 10, 2).unwrap();
+        let mut instance = SINTEFlake::builder()
+            .layout(layout)
+            .sequence_mode(true)
+            .build()
+            .unwrap();
+        let mut ids = std::collections::HashSet::new();
+        for _ in 0..64 {
+            assert!(ids.insert(instance.next_id().unwrap()));
+        }
+        assert!(matches!(
+            instance.next_id(),
+            Err(SINTEFlakeError::CounterOverflow { .. })
+        ));
+        assert!(matches!(
+            instance.next_id_with_hash(b"data"),
+            Err(SINTEFlakeError::SequenceMode)
+        ));
+
+        // consecutive IDs have different hash prefixes
+        let mut instance = SINTEFlake::builder().sequence_mode(true).build().unwrap();
+        let id_a = instance.next_id().unwrap();
+        let id_b = instance.next_id().unwrap();
+        assert_ne!(id_a >> 49, id_b >> 49);
+    }
+
+    #[test]
+    fn test_feistel_permutation() {
+        let hash_key = [7; 16];
+        let clock = crate::clock::tests::ManualClock::new(1719792016000);
+        let build = |feistel_permutation| {
+            SINTEFlake::builder()
+                .instance_id(42)
+                .hash_key(hash_key)
+                .epoch(UnixTime::from_unix_timestamp(1719792000).unwrap())
+                .clock(clock.clone())
+                .feistel_permutation(feistel_permutation)
+                .build()
+                .unwrap()
+        };
+        let mut plain = build(false);
+        let mut opaque = build(true);
+
+        let permutation = FeistelPermutation::new(hash_key, 63);
+        for _ in 0..100 {
+            let id = opaque.next_id().unwrap();
+            assert!(id <= Layout::DEFAULT.max_id());
+            assert_eq!(permutation.invert(id), plain.next_id().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_custom_permutation_tables() {
+        let mut timestamp = [0; 31];
+        for (i, position) in timestamp.iter_mut().enumerate() {
+            *position = i as u8;
+        }
+        let identity = PermutationTables::new(timestamp, [0, 1, 2, 3, 4, 5, 6, 7]).unwrap();
+        let clock = crate::clock::tests::ManualClock::new(1719792016000);
+        let mut instance = SINTEFlake::builder()
+            .epoch(UnixTime::from_unix_timestamp(1719792000).unwrap())
+            .clock(clock)
+            .counter_key(0)
+            .permutation_tables(identity)
+            .build()
+            .unwrap();
+        let id = instance.next_id().unwrap();
+        // window 2, first ID of its bucket
+        assert_eq!((id >> 18) & 0x7FFFFFFF, 2);
+        assert_eq!(id & 0xFF, 0);
+    }
+
     #[test]
     fn test_custom() {
         let mut normal_instance = SINTEFlake::new().unwrap();
@@ -250,28 +3147,28 @@ mod tests {
             0,
             [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
             123,
-            OffsetDateTime::from_unix_timestamp(1719792000).unwrap(),
+            UnixTime::from_unix_timestamp(1719792000).unwrap(),
         )
         .unwrap();
         let mut custom_instance_b = SINTEFlake::custom(
             0,
             [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 17],
             123,
-            OffsetDateTime::from_unix_timestamp(1719792000).unwrap(),
+            UnixTime::from_unix_timestamp(1719792000).unwrap(),
         )
         .unwrap();
         let mut custom_instance_c = SINTEFlake::custom(
             0,
             [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
             124,
-            OffsetDateTime::from_unix_timestamp(1719792000).unwrap(),
+            UnixTime::from_unix_timestamp(1719792000).unwrap(),
         )
         .unwrap();
         let mut custom_instance_d = SINTEFlake::custom(
             0,
             [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
             123,
-            OffsetDateTime::from_unix_timestamp(1719792008).unwrap(),
+            UnixTime::from_unix_timestamp(1719792008).unwrap(),
         )
         .unwrap();
 
@@ -298,8 +3195,8 @@ mod tests {
     fn test_set_instance_id() {
         let mut instance = SINTEFlake::new().unwrap();
         let id_a = instance.next_id().unwrap();
-        assert!(instance.set_instance_id(16384).is_err());
-        assert!(instance.set_instance_id(16383).is_ok());
+        assert!(instance.set_instance_id(1024).is_err());
+        assert!(instance.set_instance_id(1023).is_ok());
         let id_b = instance.next_id().unwrap();
         assert_ne!(id_a, id_b);
     }
@@ -307,10 +3204,10 @@ mod tests {
     #[test]
     fn test_custom_instance_id() {
         let mut instance = SINTEFlake::custom(
-            16383,
+            1023,
             [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
             123,
-            OffsetDateTime::from_unix_timestamp(1719792000).unwrap(),
+            UnixTime::from_unix_timestamp(1719792000).unwrap(),
         )
         .unwrap();
         let id_a = instance.next_id().unwrap();
@@ -318,11 +3215,19 @@ mod tests {
         assert_ne!(id_a, id_b);
 
         assert!(SINTEFlake::custom(
-            16384,
+            1024,
             [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
             123,
-            OffsetDateTime::from_unix_timestamp(1719792000).unwrap(),
+            UnixTime::from_unix_timestamp(1719792000).unwrap(),
         )
         .is_err());
     }
+
+    #[test]
+    fn test_instance_id_is_embedded() {
+        let mut instance = SINTEFlake::new().unwrap();
+        instance.set_instance_id(0x2A5).unwrap();
+        let id = instance.next_id().unwrap();
+        assert_eq!((id >> 8) & 0x3FF, 0x2A5);
+    }
 }
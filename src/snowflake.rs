@@ -0,0 +1,168 @@
+//! Decoding of the 64-bit IDs of other Snowflake-like generators, to introspect
+//! all the IDs of a data store with one crate, not just SINTEFlake ones.
+//!
+//! ```rust
+//! use sinteflake::snowflake::{decode_snowflake, SnowflakeDialect};
+//!
+//! // a Discord ID, from the Discord documentation
+//! let decoded = decode_snowflake(175928847299117063, SnowflakeDialect::Discord)?;
+//! assert_eq!(decoded.unix_millis(), 1462015105796);
+//! assert_eq!((decoded.node, decoded.sequence), (1 << 5, 7));
+//! # Ok::<(), sinteflake::error::SINTEFlakeError>(())
+//! ```
+use crate::error::SINTEFlakeError;
+use crate::time::{add_millis_saturating, UnixTime};
+
+/// A layout of Snowflake-like IDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SnowflakeDialect {
+    /// Twitter Snowflake: 41 bits of milliseconds since 2010-11-04, 5 bits of datacenter,
+    /// 5 bits of worker and 12 bits of sequence number.
+    Twitter,
+    /// Discord: 42 bits of milliseconds since 2015-01-01, 5 bits of worker, 5 bits of
+    /// process and 12 bits of increment.
+    Discord,
+    /// Instagram: 41 bits of milliseconds since 2011-08-24, 13 bits of logical shard
+    /// and 10 bits of sequence number.
+    Instagram,
+    /// Sonyflake: 39 bits of 10 milliseconds units since 2014-09-01, 8 bits of sequence
+    /// number and 16 bits of machine, below the sequence number.
+    Sonyflake,
+}
+
+impl SnowflakeDialect {
+    /// All the dialects.
+    pub const ALL: [SnowflakeDialect; 4] = [
+        SnowflakeDialect::Twitter,
+        SnowflakeDialect::Discord,
+        SnowflakeDialect::Instagram,
+        SnowflakeDialect::Sonyflake,
+    ];
+
+    /// Epoch of the timestamps, in milliseconds since the Unix epoch.
+    pub const fn epoch_millis(&self) -> u64 {
+        match self {
+            SnowflakeDialect::Twitter => 1288834974657,
+            SnowflakeDialect::Discord => 1420070400000,
+            SnowflakeDialect::Instagram => 1314220021721,
+            SnowflakeDialect::Sonyflake => 1409529600000,
+        }
+    }
+
+    /// Duration of a unit of the timestamps, in milliseconds.
+    pub const fn time_unit_millis(&self) -> u64 {
+        match self {
+            SnowflakeDialect::Sonyflake => 10,
+            _ => 1,
+        }
+    }
+
+    /// Widths of the timestamp, node and sequence number fields.
+    pub const fn field_bits(&self) -> (u8, u8, u8) {
+        match self {
+            SnowflakeDialect::Twitter => (41, 10, 12),
+            SnowflakeDialect::Discord => (42, 10, 12),
+            SnowflakeDialect::Instagram => (41, 13, 10),
+            SnowflakeDialect::Sonyflake => (39, 16, 8),
+        }
+    }
+}
+
+/// The fields of a Snowflake-like ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DecodedSnowflake {
+    pub dialect: SnowflakeDialect,
+    /// Time units since the epoch of the dialect.
+    pub timestamp: u64,
+    /// The generator of the ID: datacenter and worker for Twitter, worker and process
+    /// for Discord, logical shard for Instagram and machine for Sonyflake.
+    pub node: u32,
+    pub sequence: u16,
+}
+
+impl DecodedSnowflake {
+    /// Returns when the ID was created, in milliseconds since the Unix epoch.
+    pub fn unix_millis(&self) -> u64 {
+        self.dialect.epoch_millis() + self.timestamp * self.dialect.time_unit_millis()
+    }
+
+    /// Returns when the ID was created.
+    pub fn created_at(&self) -> UnixTime {
+        add_millis_saturating(UnixTime::UNIX_EPOCH, self.unix_millis() as i64)
+    }
+}
+
+/// Splits a Snowflake-like ID into its timestamp, node and sequence number.
+///
+/// # Errors
+/// Returns `InvalidId` if the ID uses more bits than the fields of the dialect,
+/// like negative signed Twitter IDs.
+pub fn decode_snowflake(
+    id: u64,
+    dialect: SnowflakeDialect,
+) -> Result<DecodedSnowflake, SINTEFlakeError> {
+    let (timestamp_bits, node_bits, sequence_bits) = dialect.field_bits();
+    let total_bits = timestamp_bits + node_bits + sequence_bits;
+    if total_bits < 64 && id >> total_bits != 0 {
+        return Err(SINTEFlakeError::InvalidId);
+    }
+    let mask = |bits: u8| (1_u64 << bits) - 1;
+    let (node, sequence) = match dialect {
+        SnowflakeDialect::Sonyflake => (
+            id & mask(node_bits),
+            (id >> node_bits) & mask(sequence_bits),
+        ),
+        _ => (
+            (id >> sequence_bits) & mask(node_bits),
+            id & mask(sequence_bits),
+        ),
+    };
+    Ok(DecodedSnowflake {
+        dialect,
+        timestamp: id >> (node_bits + sequence_bits),
+        node: node as u32,
+        sequence: sequence as u16,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_snowflake() {
+        let decoded = decode_snowflake(5 << 17 | 9 << 12 | 3, SnowflakeDialect::Twitter).unwrap();
+        assert_eq!(
+            (decoded.timestamp, decoded.node, decoded.sequence),
+            (0, 5 << 5 | 9, 3)
+        );
+
+        let decoded = decode_snowflake(1212161404 << 22 | 42, SnowflakeDialect::Twitter).unwrap();
+        assert_eq!(decoded.unix_millis(), 1288834974657 + 1212161404);
+
+        let decoded = decode_snowflake(175928847299117063, SnowflakeDialect::Discord).unwrap();
+        assert_eq!(
+            decoded.created_at(),
+            UnixTime::from_unix_timestamp_nanos(1462015105796 * 1_000_000).unwrap()
+        );
+
+        let decoded =
+            decode_snowflake(1000 << 23 | 1341 << 10 | 1, SnowflakeDialect::Instagram).unwrap();
+        assert_eq!(
+            (decoded.timestamp, decoded.node, decoded.sequence),
+            (1000, 1341, 1)
+        );
+
+        let decoded =
+            decode_snowflake(100 << 24 | 7 << 16 | 0xBEEF, SnowflakeDialect::Sonyflake).unwrap();
+        assert_eq!((decoded.node, decoded.sequence), (0xBEEF, 7));
+        assert_eq!(decoded.unix_millis(), 1409529600000 + 1000);
+
+        assert!(matches!(
+            decode_snowflake(u64::MAX, SnowflakeDialect::Twitter),
+            Err(SINTEFlakeError::InvalidId)
+        ));
+        assert!(decode_snowflake(u64::MAX, SnowflakeDialect::Discord).is_ok());
+    }
+}
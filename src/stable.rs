@@ -0,0 +1,80 @@
+use alloc::collections::BTreeMap;
+
+/// IDs issued for payloads during the current time window, keyed on the full 64 bits hash
+/// of the payload, evicting the least recently used entry when full.
+///
+/// Entries are stamped with a use counter, `recent` finds the oldest stamp.
+#[derive(Debug)]
+pub(crate) struct StableIds {
+    capacity: usize,
+    entries: BTreeMap<u64, (u64, u64)>, // hash -> (id, stamp)
+    recent: BTreeMap<u64, u64>,         // stamp -> hash
+    stamp: u64,
+}
+
+impl StableIds {
+    pub(crate) fn new(capacity: usize) -> Self {
+        StableIds {
+            capacity,
+            entries: BTreeMap::new(),
+            recent: BTreeMap::new(),
+            stamp: 0,
+        }
+    }
+
+    /// Returns the ID issued for the hash, and marks it as recently used.
+    pub(crate) fn get(&mut self, hash: u64) -> Option<u64> {
+        let (id, stamp) = self.entries.get_mut(&hash)?;
+        self.recent.remove(stamp);
+        self.stamp += 1;
+        *stamp = self.stamp;
+        self.recent.insert(self.stamp, hash);
+        Some(*id)
+    }
+
+    /// Remembers the ID issued for a hash that isn't in the cache.
+    pub(crate) fn insert(&mut self, hash: u64, id: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.recent.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.stamp += 1;
+        self.entries.insert(hash, (id, self.stamp));
+        self.recent.insert(self.stamp, hash);
+    }
+
+    /// Forgets all the IDs, at the start of a time window.
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.recent.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stable_ids() {
+        let mut ids = StableIds::new(2);
+        ids.insert(1, 10);
+        ids.insert(2, 20);
+        assert_eq!(ids.get(1), Some(10));
+        // 2 is the least recently used
+        ids.insert(3, 30);
+        assert_eq!(ids.get(2), None);
+        assert_eq!(ids.get(1), Some(10));
+        assert_eq!(ids.get(3), Some(30));
+
+        ids.clear();
+        assert_eq!(ids.get(1), None);
+
+        let mut disabled = StableIds::new(0);
+        disabled.insert(1, 10);
+        assert_eq!(disabled.get(1), None);
+    }
+}
@@ -0,0 +1,129 @@
+use core::fmt::Debug;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+use crate::error::SINTEFlakeError;
+
+/// State of a generator that is persisted across restarts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct State {
+    /// The last time window in which IDs may have been generated.
+    pub timestamp: u64,
+
+    /// The number of IDs generated in this time window, as of the last save.
+    pub ids_count: u64,
+}
+
+/// Storage for the state of a generator.
+///
+/// The state is saved every time the generator switches to a new time window,
+/// before any ID is generated in it, and loaded when the generator is built.
+pub trait StateStore: Debug + Send + Sync {
+    /// Loads the saved state, or `None` if nothing has been saved yet.
+    fn load(&self) -> Result<Option<State>, SINTEFlakeError>;
+
+    /// Saves the state.
+    fn save(&self, state: &State) -> Result<(), SINTEFlakeError>;
+}
+
+/// What to do when the clock is not after the last time window saved before a restart.
+///
+/// The collisions map is not persisted, so generating IDs again in a window used before
+/// the restart could generate duplicates. This also happens when the generator restarts
+/// within the same time window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StateRecoveryPolicy {
+    /// Uses the time window following the saved one until the clock catches up.
+    #[default]
+    SkipForward,
+
+    /// Refuses to build the generator.
+    Refuse,
+}
+
+/// Stores the state in a small text file.
+///
+/// The file is replaced atomically by writing a temporary file next to it first.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct FileStateStore {
+    path: PathBuf,
+}
+
+#[cfg(feature = "std")]
+impl FileStateStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        FileStateStore {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl StateStore for FileStateStore {
+    fn load(&self) -> Result<Option<State>, SINTEFlakeError> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(_) => return Err(SINTEFlakeError::StateStoreError),
+        };
+
+        let mut values = content.split_whitespace().map(str::parse::<u64>);
+        match (values.next(), values.next(), values.next()) {
+            (Some(Ok(timestamp)), Some(Ok(ids_count)), None) => Ok(Some(State {
+                timestamp,
+                ids_count,
+            })),
+            _ => Err(SINTEFlakeError::StateStoreError),
+        }
+    }
+
+    fn save(&self, state: &State) -> Result<(), SINTEFlakeError> {
+        let mut temporary_path = self.path.clone().into_os_string();
+        temporary_path.push(".tmp");
+
+        fs::write(
+            &temporary_path,
+            format!("{} {}\n", state.timestamp, state.ids_count),
+        )
+        .map_err(|_| SINTEFlakeError::StateStoreError)?;
+        fs::rename(&temporary_path, &self.path).map_err(|_| SINTEFlakeError::StateStoreError)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn temporary_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "sinteflake-{}-{}-{}",
+            name,
+            std::process::id(),
+            crate::clock::Clock::now(&crate::clock::SystemClock).as_nanos()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_file_state_store() {
+        let path = temporary_path("state");
+        let store = FileStateStore::new(&path);
+        assert_eq!(store.load().unwrap(), None);
+
+        let state = State {
+            timestamp: 123456,
+            ids_count: 42,
+        };
+        store.save(&state).unwrap();
+        assert_eq!(store.load().unwrap(), Some(state));
+
+        fs::write(&path, "123456").unwrap();
+        assert!(store.load().is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
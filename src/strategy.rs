@@ -0,0 +1,89 @@
+//! Proptest strategies generating valid IDs, with the `proptest` feature.
+//!
+//! ```rust
+//! use proptest::prelude::*;
+//! use proptest::test_runner::TestRunner;
+//! use sinteflake::layout::Layout;
+//! use sinteflake::strategy::ids;
+//!
+//! let layout = Layout::JAVASCRIPT_SAFE;
+//! TestRunner::default()
+//!     .run(&ids(layout), |id| {
+//!         prop_assert!(id.get() <= layout.max_id());
+//!         Ok(())
+//!     })
+//!     .unwrap();
+//! ```
+use proptest::prelude::*;
+
+use crate::decode::DecodedId;
+use crate::id::SINTEFlakeId;
+use crate::layout::Layout;
+
+/// IDs fitting in a layout.
+pub fn ids(layout: Layout) -> impl Strategy<Value = SINTEFlakeId> {
+    (0..=layout.max_id()).prop_map(SINTEFlakeId::new)
+}
+
+/// Fields of IDs, each fitting in its field of the layout.
+pub fn decoded_ids(layout: Layout) -> impl Strategy<Value = DecodedId> {
+    (
+        0..=(layout.buckets() - 1) as u16,
+        0..=layout.max_timestamp(),
+        0..=layout.max_instance_id(),
+        0..=(layout.bucket_capacity() - 1) as u8,
+    )
+        .prop_map(|(hash, timestamp, instance_id, sequence)| DecodedId {
+            hash,
+            timestamp,
+            instance_id,
+            sequence,
+        })
+}
+
+impl Arbitrary for SINTEFlakeId {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    /// IDs of the default layout.
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        ids(Layout::DEFAULT).boxed()
+    }
+}
+
+impl Arbitrary for DecodedId {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    /// Fields of the default layout.
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        decoded_ids(Layout::DEFAULT).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn test_decoded_ids_fit(decoded in decoded_ids(Layout::MILLISECONDS)) {
+            let layout = Layout::MILLISECONDS;
+            let id = layout.construct_identifier(
+                decoded.hash,
+                decoded.timestamp,
+                decoded.instance_id,
+                decoded.sequence,
+            );
+            prop_assert_eq!(
+                layout.deconstruct_identifier(id),
+                (decoded.hash, decoded.timestamp, decoded.instance_id, decoded.sequence)
+            );
+        }
+
+        #[test]
+        fn test_any_id(id in any::<SINTEFlakeId>()) {
+            prop_assert!(id.get() <= Layout::DEFAULT.max_id());
+        }
+    }
+}
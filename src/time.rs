@@ -1,60 +1,440 @@
-use time::OffsetDateTime;
+use core::fmt;
+use core::time::Duration;
 
+use crate::clock::Clock;
 use crate::error::SINTEFlakeError;
 
-pub(crate) fn get_current_timestamp(epoch: OffsetDateTime) -> Result<u32, SINTEFlakeError> {
-    let current_time = OffsetDateTime::now_utc();
-    if current_time < epoch {
+/// A minimal UTC time, the type of the epochs and the creation times in the API.
+///
+/// It is the same with or without the `time` feature, which only adds the conversions
+/// from and to `time::OffsetDateTime`. It displays in RFC 3339.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UnixTime {
+    unix_nanos: i128,
+}
+
+/// Range of the times, the years 1 to 9999, nanoseconds since the Unix epoch.
+pub(crate) const MIN_UNIX_NANOS: i128 = -62_135_596_800 * 1_000_000_000;
+pub(crate) const MAX_UNIX_NANOS: i128 = 253_402_300_800 * 1_000_000_000 - 1;
+
+impl UnixTime {
+    /// 1970-01-01T00:00:00Z.
+    pub const UNIX_EPOCH: UnixTime = UnixTime { unix_nanos: 0 };
+
+    /// Creates a time from the seconds since the Unix epoch.
+    ///
+    /// # Errors
+    /// Returns `TimestampOverflow` outside of the years 1 to 9999.
+    pub const fn from_unix_timestamp(seconds: i64) -> Result<Self, SINTEFlakeError> {
+        Self::from_unix_timestamp_nanos(seconds as i128 * 1_000_000_000)
+    }
+
+    /// Creates a time from the nanoseconds since the Unix epoch.
+    ///
+    /// # Errors
+    /// Returns `TimestampOverflow` outside of the years 1 to 9999.
+    pub const fn from_unix_timestamp_nanos(nanos: i128) -> Result<Self, SINTEFlakeError> {
+        if nanos < MIN_UNIX_NANOS || nanos > MAX_UNIX_NANOS {
+            return Err(SINTEFlakeError::TimestampOverflow);
+        }
+        Ok(UnixTime { unix_nanos: nanos })
+    }
+
+    /// Returns the current time.
+    #[cfg(feature = "std")]
+    pub fn now_utc() -> Self {
+        let nanos = crate::clock::SystemClock.now().as_nanos() as i128;
+        UnixTime {
+            unix_nanos: nanos.min(MAX_UNIX_NANOS),
+        }
+    }
+
+    /// Returns the whole seconds since the Unix epoch.
+    pub const fn unix_timestamp(self) -> i64 {
+        self.unix_nanos.div_euclid(1_000_000_000) as i64
+    }
+
+    /// Returns the nanoseconds since the Unix epoch.
+    pub const fn unix_timestamp_nanos(self) -> i128 {
+        self.unix_nanos
+    }
+
+    /// Returns the nanoseconds within the second.
+    pub const fn nanosecond(self) -> u32 {
+        self.unix_nanos.rem_euclid(1_000_000_000) as u32
+    }
+
+    /// Returns the milliseconds within the second.
+    pub const fn millisecond(self) -> u16 {
+        (self.nanosecond() / 1_000_000) as u16
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<::time::OffsetDateTime> for UnixTime {
+    /// Converts the time, saturating to the years 1 to 9999.
+    fn from(time: ::time::OffsetDateTime) -> Self {
+        from_unix_nanos_saturating(time.unix_timestamp_nanos())
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<UnixTime> for ::time::OffsetDateTime {
+    fn from(time: UnixTime) -> Self {
+        ::time::OffsetDateTime::from_unix_timestamp_nanos(time.unix_nanos)
+            .expect("the years 1 to 9999 are in the range of time")
+    }
+}
+
+impl core::ops::Add<Duration> for UnixTime {
+    type Output = UnixTime;
+
+    /// # Panics
+    /// Panics after the year 9999, like `time::OffsetDateTime`.
+    fn add(self, duration: Duration) -> UnixTime {
+        UnixTime::from_unix_timestamp_nanos(self.unix_nanos + duration.as_nanos() as i128)
+            .expect("resulting value is out of range")
+    }
+}
+
+impl core::ops::Sub<Duration> for UnixTime {
+    type Output = UnixTime;
+
+    /// # Panics
+    /// Panics before the year 1, like `time::OffsetDateTime`.
+    fn sub(self, duration: Duration) -> UnixTime {
+        UnixTime::from_unix_timestamp_nanos(self.unix_nanos - duration.as_nanos() as i128)
+            .expect("resulting value is out of range")
+    }
+}
+
+impl fmt::Display for UnixTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Rfc3339 {
+            unix_nanos: self.unix_nanos,
+        }
+        .fmt(f)
+    }
+}
+
+/// Creates a time from the nanoseconds since the Unix epoch, saturating to the years
+/// 1 to 9999.
+pub(crate) fn from_unix_nanos_saturating(nanos: i128) -> UnixTime {
+    UnixTime {
+        unix_nanos: nanos.clamp(MIN_UNIX_NANOS, MAX_UNIX_NANOS),
+    }
+}
+
+/// Returns the time a number of milliseconds after another, saturating.
+pub(crate) fn add_millis_saturating(time: UnixTime, millis: i64) -> UnixTime {
+    from_unix_nanos_saturating(time.unix_timestamp_nanos() + millis as i128 * 1_000_000)
+}
+
+/// Displays a time in RFC 3339 in UTC, with milliseconds only if there are some.
+pub(crate) struct Rfc3339 {
+    unix_nanos: i128,
+}
+
+impl Rfc3339 {
+    pub(crate) fn new(time: UnixTime) -> Self {
+        Rfc3339 {
+            unix_nanos: time.unix_timestamp_nanos(),
+        }
+    }
+}
+
+impl fmt::Display for Rfc3339 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.unix_nanos.div_euclid(1_000_000_000) as i64;
+        let millisecond = self.unix_nanos.rem_euclid(1_000_000_000) / 1_000_000;
+        let (days, second_of_day) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+
+        // civil date of the days since the Unix epoch, by Howard Hinnant
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let day_of_era = z.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            second_of_day / 3600,
+            second_of_day / 60 % 60,
+            second_of_day % 60
+        )?;
+        if millisecond != 0 {
+            write!(f, ".{:03}", millisecond)?;
+        }
+        f.write_str("Z")
+    }
+}
+
+/// A point in time usable as an epoch, see [`crate::builder::SINTEFlakeBuilder::epoch`].
+///
+/// Implemented for [`UnixTime`], for `std::time::SystemTime` with the `std` feature,
+/// for `time::OffsetDateTime` with the `time` feature, and for `chrono::DateTime<Utc>`
+/// with the `chrono` feature, so codebases using another datetime crate, or none,
+/// don't need the `time` crate.
+pub trait IntoUnixTime {
+    /// Converts the time, saturating to the years 1 to 9999.
+    fn into_unix_time(self) -> UnixTime;
+}
+
+impl IntoUnixTime for UnixTime {
+    fn into_unix_time(self) -> UnixTime {
+        self
+    }
+}
+
+#[cfg(feature = "time")]
+impl IntoUnixTime for ::time::OffsetDateTime {
+    fn into_unix_time(self) -> UnixTime {
+        self.into()
+    }
+}
+
+#[cfg(feature = "std")]
+impl IntoUnixTime for std::time::SystemTime {
+    fn into_unix_time(self) -> UnixTime {
+        let nanos = match self.duration_since(std::time::UNIX_EPOCH) {
+            Ok(after) => after.as_nanos() as i128,
+            Err(before) => -(before.duration().as_nanos() as i128),
+        };
+        from_unix_nanos_saturating(nanos)
+    }
+}
+
+/// Converts a time to `SystemTime`, with the `std` feature.
+#[cfg(feature = "std")]
+pub(crate) fn to_system_time(time: UnixTime) -> std::time::SystemTime {
+    let nanos = time.unix_timestamp_nanos();
+    let magnitude = Duration::new(
+        (nanos.unsigned_abs() / 1_000_000_000) as u64,
+        (nanos.unsigned_abs() % 1_000_000_000) as u32,
+    );
+    if nanos < 0 {
+        std::time::UNIX_EPOCH - magnitude
+    } else {
+        std::time::UNIX_EPOCH + magnitude
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl IntoUnixTime for chrono::DateTime<chrono::Utc> {
+    fn into_unix_time(self) -> UnixTime {
+        let nanos =
+            self.timestamp() as i128 * 1_000_000_000 + self.timestamp_subsec_nanos() as i128;
+        from_unix_nanos_saturating(nanos)
+    }
+}
+
+/// Converts a time to chrono, for the `chrono` feature.
+#[cfg(feature = "chrono")]
+pub(crate) fn to_chrono(time: UnixTime) -> chrono::DateTime<chrono::Utc> {
+    chrono::DateTime::from_timestamp(time.unix_timestamp(), time.nanosecond())
+        .expect("the range of chrono is wider than the years 1 to 9999")
+}
+
+/// Default duration of a time window, the resolution of the timestamps.
+pub const DEFAULT_TIME_WINDOW: Duration = Duration::from_secs(8);
+
+/// Returns the time elapsed since the epoch.
+pub(crate) fn elapsed_since_epoch(
+    clock: &dyn Clock,
+    epoch: UnixTime,
+) -> Result<Duration, SINTEFlakeError> {
+    let elapsed = clock.now().as_nanos() as i128 - epoch.unix_timestamp_nanos();
+    if elapsed < 0 {
         return Err(SINTEFlakeError::EpochInFuture);
     }
-    let duration = current_time - epoch;
-    let whole_seconds = duration.whole_seconds();
+    Ok(Duration::new(
+        (elapsed / 1_000_000_000) as u64,
+        (elapsed % 1_000_000_000) as u32,
+    ))
+}
 
-    if whole_seconds > 0x3fffffff8 {
+/// Returns the number of time windows in the elapsed time.
+pub(crate) fn timestamp_of(
+    elapsed: Duration,
+    window: Duration,
+    max_timestamp: u64,
+) -> Result<u64, SINTEFlakeError> {
+    let timestamp = elapsed.as_millis() / window.as_millis();
+
+    if timestamp > max_timestamp as u128 {
         return Err(SINTEFlakeError::TimestampOverflow);
     }
 
-    // divide by 8 because we are interested in 8 seconds intervals,
-    // the number should be max 31 bits at this point
-    Ok((whole_seconds >> 3) as u32)
+    Ok(timestamp as u64)
+}
+
+/// Checks that a time window is a non-zero whole number of milliseconds.
+pub(crate) fn validate_time_window(window: Duration) -> Result<(), SINTEFlakeError> {
+    if window.is_zero() || !window.subsec_nanos().is_multiple_of(1_000_000) {
+        return Err(SINTEFlakeError::InvalidTimeWindow);
+    }
+    Ok(())
 }
 #[cfg(test)]
 mod tests {
     use super::*;
-    use time::OffsetDateTime;
+    use crate::clock::SystemClock;
+
+    fn get_current_timestamp(epoch: UnixTime) -> Result<u64, SINTEFlakeError> {
+        timestamp_with_window(epoch, DEFAULT_TIME_WINDOW, 0x7FFFFFFF)
+    }
+
+    fn timestamp_with_window(
+        epoch: UnixTime,
+        window: Duration,
+        max_timestamp: u64,
+    ) -> Result<u64, SINTEFlakeError> {
+        let elapsed = elapsed_since_epoch(&SystemClock, epoch)?;
+        timestamp_of(elapsed, window, max_timestamp)
+    }
 
     const EPOCH_2024: i64 = 1719792000; // January 1, 2024 00:00:00 UTC
     const EPOCH_2000: i64 = 946684800; // January 1, 2000 00:00:00 UTC
     const EPOCH_2100: i64 = 4102444800; // January 1, 2100 00:00:00 UTC
 
+    #[test]
+    fn test_unix_time() {
+        let time = UnixTime::from_unix_timestamp_nanos(1_719_792_000_123_000_000).unwrap();
+        assert_eq!(time.unix_timestamp(), 1719792000);
+        assert_eq!(time.nanosecond(), 123_000_000);
+        assert_eq!(time.to_string(), "2024-07-01T00:00:00.123Z");
+        assert_eq!(
+            (time + Duration::from_secs(86400)).to_string(),
+            "2024-07-02T00:00:00.123Z"
+        );
+        assert_eq!(UnixTime::UNIX_EPOCH.to_string(), "1970-01-01T00:00:00Z");
+
+        let before = UnixTime::UNIX_EPOCH - Duration::from_millis(1500);
+        assert_eq!(before.unix_timestamp(), -2);
+        assert_eq!(before.nanosecond(), 500_000_000);
+        assert_eq!(before.to_string(), "1969-12-31T23:59:58.500Z");
+
+        assert!(UnixTime::from_unix_timestamp(253402300799).is_ok());
+        assert!(UnixTime::from_unix_timestamp(253402300800).is_err());
+        assert!(UnixTime::now_utc() > time);
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_rfc3339_matches_time() {
+        use ::time::OffsetDateTime;
+
+        for seconds in [
+            0,
+            951782400,
+            1719792000,
+            4102444800,
+            -86400 * 365 * 100,
+            253402300799,
+        ] {
+            let time = OffsetDateTime::from_unix_timestamp(seconds).unwrap();
+            let expected = format!(
+                "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+                time.year(),
+                time.month() as u8,
+                time.day(),
+                time.hour(),
+                time.minute(),
+                time.second()
+            );
+            assert_eq!(Rfc3339::new(time.into()).to_string(), expected);
+        }
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_offset_date_time_conversions() {
+        use ::time::OffsetDateTime;
+
+        let time = OffsetDateTime::from_unix_timestamp_nanos(1_719_792_000_123_000_000).unwrap();
+        let unix_time = time.into_unix_time();
+        assert_eq!(unix_time.unix_timestamp(), 1719792000);
+        assert_eq!(unix_time.millisecond(), 123);
+        assert_eq!(OffsetDateTime::from(unix_time), time);
+
+        // before the year 1
+        let early = OffsetDateTime::from_unix_timestamp(-62_135_596_801).unwrap();
+        assert_eq!(UnixTime::from(early).unix_timestamp(), -62_135_596_800);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_system_time_conversions() {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let system_time = UNIX_EPOCH + Duration::from_millis(1719792000123);
+        let time = system_time.into_unix_time();
+        assert_eq!(time.unix_timestamp(), 1719792000);
+        assert_eq!(time.millisecond(), 123);
+        assert_eq!(to_system_time(time), system_time);
+
+        let before = UNIX_EPOCH - Duration::from_secs(1);
+        assert_eq!(before.into_unix_time().unix_timestamp(), -1);
+        assert!(SystemTime::now().into_unix_time() > time);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono_conversions() {
+        use chrono::TimeZone;
+
+        let chrono_time = chrono::Utc.with_ymd_and_hms(2024, 7, 1, 0, 0, 0).unwrap()
+            + chrono::Duration::milliseconds(123);
+        let time = chrono_time.into_unix_time();
+        assert_eq!(time.unix_timestamp(), 1719792000);
+        assert_eq!(time.millisecond(), 123);
+        assert_eq!(to_chrono(time), chrono_time);
+
+        // beyond the range of time
+        let far = chrono::Utc.with_ymd_and_hms(100000, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(far.into_unix_time().unix_timestamp(), 253402300799);
+    }
+
     #[test]
     fn test_get_current_timestamp_with_2024_epoch() {
-        let epoch = OffsetDateTime::from_unix_timestamp(EPOCH_2024).unwrap();
+        let epoch = UnixTime::from_unix_timestamp(EPOCH_2024).unwrap();
         let timestamp = get_current_timestamp(epoch).unwrap();
         assert!(timestamp > 0, "Timestamp should be greater than 0");
     }
 
     #[test]
     fn test_get_current_timestamp_with_2000_epoch() {
-        let epoch = OffsetDateTime::from_unix_timestamp(EPOCH_2000).unwrap();
+        let epoch = UnixTime::from_unix_timestamp(EPOCH_2000).unwrap();
         let timestamp = get_current_timestamp(epoch).unwrap();
         assert!(timestamp > 0, "Timestamp should be greater than 0");
     }
 
     #[test]
     fn test_get_current_timestamp_with_2100_epoch() {
-        let epoch = OffsetDateTime::from_unix_timestamp(EPOCH_2100).unwrap();
+        let epoch = UnixTime::from_unix_timestamp(EPOCH_2100).unwrap();
         let timestamp_result = get_current_timestamp(epoch);
         assert!(timestamp_result.is_err(), "Timestamp should be an error");
     }
 
     #[test]
     fn test_timestamp_increases_over_time() {
-        let epoch = OffsetDateTime::from_unix_timestamp(EPOCH_2024).unwrap();
+        let epoch = UnixTime::from_unix_timestamp(EPOCH_2024).unwrap();
         let timestamp1 = get_current_timestamp(epoch).unwrap();
         //thread::sleep(Duration::from_secs(9)); // Sleep for 9 seconds
-        let mut epoch = OffsetDateTime::from_unix_timestamp(EPOCH_2024).unwrap();
-        epoch -= time::Duration::seconds(9);
+        let epoch = UnixTime::from_unix_timestamp(EPOCH_2024).unwrap() - Duration::from_secs(9);
         let timestamp2 = get_current_timestamp(epoch).unwrap();
         assert!(
             timestamp2 > timestamp1,
@@ -64,8 +444,8 @@ mod tests {
 
     #[test]
     fn test_overflow_scenario() {
-        let current_time = OffsetDateTime::now_utc();
-        let epoch = current_time - time::Duration::seconds(0x3fffffff8);
+        let current_time = UnixTime::now_utc();
+        let epoch = current_time - Duration::from_secs(0x3fffffff8);
         // 0x7FFFFFFF * 8 = 0x3FFFFFFF8
         let timestamp = get_current_timestamp(epoch).unwrap();
         assert_eq!(
@@ -73,14 +453,14 @@ mod tests {
             "Max timestamp value should be 2147483647 (31bits)"
         );
 
-        let epoch = current_time - time::Duration::seconds(0x400000000);
+        let epoch = current_time - Duration::from_secs(0x400000000);
         let timestamp_result = get_current_timestamp(epoch);
         assert!(timestamp_result.is_err(), "Timestamp should be an error");
     }
 
     #[test]
     fn test_consistency_with_different_calls() {
-        let epoch = OffsetDateTime::from_unix_timestamp(EPOCH_2024).unwrap();
+        let epoch = UnixTime::from_unix_timestamp(EPOCH_2024).unwrap();
         let timestamp1 = get_current_timestamp(epoch).unwrap();
         let timestamp2 = get_current_timestamp(epoch).unwrap();
         assert_eq!(
@@ -88,4 +468,42 @@ mod tests {
             "Consecutive calls should return the same timestamp"
         );
     }
+
+    #[test]
+    fn test_custom_time_windows() {
+        let epoch = UnixTime::now_utc() - Duration::from_secs(100);
+        let one_second = timestamp_with_window(epoch, Duration::from_secs(1), u64::MAX);
+        let thirty_two = timestamp_with_window(epoch, Duration::from_secs(32), u64::MAX);
+        assert!((100..=101).contains(&one_second.unwrap()));
+        assert_eq!(thirty_two.unwrap(), 3);
+
+        let result = timestamp_with_window(epoch, Duration::from_secs(1), 99);
+        assert!(result.is_err(), "Timestamp should overflow");
+    }
+
+    #[test]
+    fn test_millisecond_time_windows() {
+        let epoch = UnixTime::now_utc() - Duration::from_secs(10);
+        let timestamp = timestamp_with_window(epoch, Duration::from_millis(1), u64::MAX);
+        assert!((10_000..11_000).contains(&timestamp.unwrap()));
+    }
+
+    #[test]
+    fn test_elapsed_since_epoch() {
+        let clock = crate::clock::tests::ManualClock::new(1719792001500);
+        let epoch = UnixTime::from_unix_timestamp(1719792000).unwrap();
+        let elapsed = elapsed_since_epoch(&clock, epoch).unwrap();
+        assert_eq!(elapsed, Duration::from_millis(1500));
+
+        clock.set(1719791999999);
+        assert!(elapsed_since_epoch(&clock, epoch).is_err());
+    }
+
+    #[test]
+    fn test_validate_time_window() {
+        assert!(validate_time_window(Duration::from_secs(1)).is_ok());
+        assert!(validate_time_window(Duration::from_millis(1500)).is_ok());
+        assert!(validate_time_window(Duration::ZERO).is_err());
+        assert!(validate_time_window(Duration::from_micros(1500)).is_err());
+    }
 }
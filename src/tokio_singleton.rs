@@ -1,37 +1,110 @@
-use once_cell::sync::Lazy;
-use tokio::sync::Mutex;
-
+//! Async front-end of the global SINTEFlake instance.
+//!
+//! It shares the instance of the sync functions. The instance is behind a blocking mutex:
+//! the critical sections are short and never held across an `.await`.
+//!
+//! With a rate limit, the functions generating IDs sleep until the limit allows
+//! the next ID, instead of blocking the thread or failing. Likewise, with the `Wait` clock
+//! rollback policy, updating the time sleeps until the clock is back to the last used window.
+use crate::block::IdBlock;
 use crate::error::SINTEFlakeError;
+use crate::retry::{self, RetryPolicy};
+use crate::singleton::{init_global, lock_global};
 use crate::sinteflake::SINTEFlake;
 
-static SINTEFLAKE: Lazy<Mutex<SINTEFlake>> =
-    Lazy::new(|| Mutex::new(SINTEFlake::new().expect("Failed to create SINTEFlake instance")));
+/// Installs a configured instance as the global SINTEFlake instance.
+///
+/// Same as [`init_global`], the instance is shared with the sync functions.
+/// Returns an error if the global instance already exists.
+pub async fn init_global_async(instance: SINTEFlake) -> Result<(), SINTEFlakeError> {
+    init_global(instance)
+}
 
-/// Sets the instance ID for the global async SINTEFlake instance.
-/// Returns an error if the ID is invalid.
+/// Sets the instance ID for the global SINTEFlake instance.
+/// Returns an error if the mutex is poisoned or if the ID is invalid.
 pub async fn set_instance_id_async(id: u16) -> Result<(), SINTEFlakeError> {
-    let mut instance = SINTEFLAKE.lock().await;
+    let mut instance = lock_global()?;
     instance.set_instance_id(id)
 }
 
-/// Update the time for the global async SINTEFlake instance.
-/// Returns an error if the time update fails.
+/// Sleeps until the clock is back to the last used time window of the global instance,
+/// with the `Wait` clock rollback policy, without locking it.
+async fn wait_for_clock() -> Result<(), SINTEFlakeError> {
+    loop {
+        let delay = lock_global()?.clock_rollback_delay();
+        if delay.is_zero() {
+            return Ok(());
+        }
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Update the time for the global SINTEFlake instance.
+/// The global instance is not locked while waiting for the clock after a rollback.
+/// Returns an error if the mutex is poisoned or if the time update fails.
 pub async fn update_time_async() -> Result<(), SINTEFlakeError> {
-    let mut instance = SINTEFLAKE.lock().await;
+    wait_for_clock().await?;
+    let mut instance = lock_global()?;
     instance.update_time()
 }
 
-/// Generates the next unique ID using the global async SINTEFlake instance.
-/// Returns an error if ID generation fails.
+/// Sleeps until the rate limit of the global instance allows the next ID, without locking it.
+async fn wait_for_rate_limit() -> Result<(), SINTEFlakeError> {
+    let delay = lock_global()?.rate_limit_delay();
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+    Ok(())
+}
+
+/// Generates the next unique ID using the global SINTEFlake instance.
+/// Returns an error if the mutex is poisoned or if ID generation fails.
 pub async fn next_id_async() -> Result<u64, SINTEFlakeError> {
-    let mut instance = SINTEFLAKE.lock().await;
+    wait_for_rate_limit().await?;
+    let mut instance = lock_global()?;
     instance.next_id()
 }
 
-/// Generates the next unique ID with a hash using the global async SINTEFlake instance.
-/// Returns an error if ID generation fails.
+/// Generates the next unique ID using the global SINTEFlake instance,
+/// retrying on retryable errors such as `CounterOverflow`.
+/// The global instance is not locked while sleeping until the next time window.
+/// Returns an error if the mutex is poisoned or if all the attempts failed.
+pub async fn next_id_with_retry_async(policy: RetryPolicy) -> Result<u64, SINTEFlakeError> {
+    let mut retries = 0;
+    loop {
+        if retries > 0 {
+            wait_for_clock().await?;
+        }
+        let delay = {
+            let mut instance = lock_global()?;
+            if retries > 0 {
+                retry::refresh(&mut instance)?;
+            }
+            match instance.next_id() {
+                Err(error) if error.is_retryable() && retries < policy.max_retries => {
+                    policy.delay(&instance)
+                }
+                result => return result,
+            }
+        };
+        retries += 1;
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Reserves a block of `n` IDs from the global SINTEFlake instance.
+/// The IDs of the block are consumed without locking the global instance.
+/// Returns an error if the mutex is poisoned or if the time window has less than `n` IDs left.
+pub async fn reserve_block_async(n: usize) -> Result<IdBlock, SINTEFlakeError> {
+    let mut instance = lock_global()?;
+    instance.reserve_block(n)
+}
+
+/// Generates the next unique ID with a hash using the global SINTEFlake instance.
+/// Returns an error if the mutex is poisoned or if ID generation fails.
 pub async fn next_id_with_hash_async(data: &[u8]) -> Result<u64, SINTEFlakeError> {
-    let mut instance = SINTEFLAKE.lock().await;
+    wait_for_rate_limit().await?;
+    let mut instance = lock_global()?;
     instance.next_id_with_hash(data)
 }
 
@@ -62,6 +135,36 @@ mod tests {
         assert_ne!(id_a, id_b);
     }
 
+    #[tokio::test]
+    async fn test_init_global_after_use() {
+        next_id_async().await.unwrap();
+        assert!(matches!(
+            init_global_async(SINTEFlake::new().unwrap()).await,
+            Err(SINTEFlakeError::AlreadyInitialized)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_shared_with_sync() {
+        // the instance created by the sync functions is the async one
+        crate::singleton::next_id().unwrap();
+        assert!(matches!(
+            init_global_async(SINTEFlake::new().unwrap()).await,
+            Err(SINTEFlakeError::AlreadyInitialized)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_next_id_with_retry() {
+        let id_a = next_id_with_retry_async(RetryPolicy::default())
+            .await
+            .unwrap();
+        let id_b = next_id_with_retry_async(RetryPolicy::default())
+            .await
+            .unwrap();
+        assert_ne!(id_a, id_b);
+    }
+
     #[tokio::test]
     async fn test_update_time() {
         update_time_async().await.unwrap();
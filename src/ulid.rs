@@ -0,0 +1,78 @@
+//! Conversions between SINTEFlake IDs and ULIDs, with the `ulid` feature.
+//!
+//! The time field of the ULID is the start of the time window of the ID, in milliseconds
+//! since the Unix epoch, and the 64 lowest bits of its randomness section hold the ID.
+//! The ULIDs of the IDs are sorted by time window, so systems storing ULIDs can keep the
+//! IDs of SINTEFlake-producing services, and get them back with [`from_ulid`].
+use ::ulid::Ulid;
+
+use crate::decode::{window_start_unix_ms, EncodingConfig};
+use crate::error::SINTEFlakeError;
+
+/// Converts an ID into a ULID, with the start of its time window as the ULID time.
+///
+/// # Errors
+/// Returns `InvalidId` if the ID doesn't fit in the layout of the settings,
+/// or `TimestampOverflow` if its time window starts before the Unix epoch
+/// or after the range of the ULID times.
+pub fn to_ulid(id: u64, config: &EncodingConfig) -> Result<Ulid, SINTEFlakeError> {
+    let timestamp_ms = window_start_unix_ms(id, config)?;
+    if timestamp_ms >> Ulid::TIME_BITS != 0 {
+        return Err(SINTEFlakeError::TimestampOverflow);
+    }
+    Ok(Ulid::from_parts(timestamp_ms, id as u128))
+}
+
+/// Converts a ULID made by [`to_ulid`] back into the ID.
+///
+/// # Errors
+/// Returns `InvalidId` if the randomness section doesn't hold an ID of the settings,
+/// or if the ULID time isn't the start of the time window of the ID.
+pub fn from_ulid(ulid: Ulid, config: &EncodingConfig) -> Result<u64, SINTEFlakeError> {
+    let random = ulid.random();
+    if random >> 64 != 0 {
+        return Err(SINTEFlakeError::InvalidId);
+    }
+    let id = random as u64;
+    match window_start_unix_ms(id, config) {
+        Ok(timestamp_ms) if timestamp_ms == ulid.timestamp_ms() => Ok(id),
+        _ => Err(SINTEFlakeError::InvalidId),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::decode;
+    use crate::sinteflake::SINTEFlake;
+    use crate::time::UnixTime;
+
+    #[test]
+    fn test_ulid_round_trip() {
+        let mut instance = SINTEFlake::builder()
+            .epoch(UnixTime::from_unix_timestamp(1719792000).unwrap())
+            .build()
+            .unwrap();
+        let config = instance.encoding_config();
+        let id = instance.next_id().unwrap();
+
+        let ulid = to_ulid(id, &config).unwrap();
+        let created_at = decode(id, &config).unwrap().created_at(&config);
+        assert_eq!(
+            ulid.timestamp_ms() as i128,
+            created_at.unix_timestamp_nanos() / 1_000_000
+        );
+        assert_eq!(from_ulid(ulid, &config).unwrap(), id);
+
+        let shifted = Ulid::from_parts(ulid.timestamp_ms() + 8000, ulid.random());
+        assert!(matches!(
+            from_ulid(shifted, &config),
+            Err(SINTEFlakeError::InvalidId)
+        ));
+        let wide = Ulid::from_parts(ulid.timestamp_ms(), ulid.random() | 1 << 70);
+        assert!(matches!(
+            from_ulid(wide, &config),
+            Err(SINTEFlakeError::InvalidId)
+        ));
+    }
+}
@@ -0,0 +1,143 @@
+//! Statistics on the distribution of the hashes over the buckets, see
+//! [`SINTEFlake::uniformity_report`](crate::sinteflake::SINTEFlake::uniformity_report).
+use alloc::vec;
+
+use crate::hash::IdHasher;
+use crate::layout::Layout;
+
+/// Occupancy of the hash buckets for synthetic keys, and a chi-square test of uniformity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UniformityReport {
+    /// Number of hashed keys.
+    pub samples: u64,
+
+    /// Number of buckets of the layout.
+    pub buckets: usize,
+
+    /// Keys expected per bucket with a uniform distribution.
+    pub expected: f64,
+
+    /// Fewest keys in a bucket.
+    pub min_occupancy: u64,
+
+    /// Most keys in a bucket.
+    pub max_occupancy: u64,
+
+    /// Buckets without any key.
+    pub empty_buckets: usize,
+
+    /// Pearson's chi-square statistic, with `buckets - 1` degrees of freedom.
+    pub chi_square: f64,
+
+    /// The chi-square statistic as a standard normal score, with the Wilson–Hilferty
+    /// approximation. Large positive values mean a clustered distribution.
+    pub z_score: f64,
+}
+
+impl UniformityReport {
+    /// Returns whether the distribution is compatible with a uniform one, when the
+    /// chi-square statistic is below the 99.99th percentile of its distribution.
+    pub fn is_uniform(&self) -> bool {
+        self.z_score < 3.719
+    }
+}
+
+/// Hashes the keys 0, 1, 2… as 8 big-endian bytes, and counts them per bucket.
+///
+/// The full 64-bit hashes are counted, so every bucket of the layout is measured, even
+/// the ones beyond the 4096 values of the truncated hashes.
+pub(crate) fn report(
+    algorithm: &dyn IdHasher,
+    hash_key: &[u8; 16],
+    layout: &Layout,
+    samples: u64,
+) -> UniformityReport {
+    let buckets = layout.buckets();
+    let mut counts = vec![0_u64; buckets];
+    for key in 0..samples {
+        let hash = algorithm.hash(hash_key, &key.to_be_bytes());
+        counts[(hash % buckets as u64) as usize] += 1;
+    }
+
+    let expected = samples as f64 / buckets as f64;
+    let chi_square = if samples == 0 {
+        0.0
+    } else {
+        counts
+            .iter()
+            .map(|&count| (count as f64 - expected).powi(2) / expected)
+            .sum()
+    };
+    let freedom = (buckets - 1).max(1) as f64;
+    let variance = 2.0 / (9.0 * freedom);
+    let z_score = ((chi_square / freedom).cbrt() - (1.0 - variance)) / variance.sqrt();
+
+    UniformityReport {
+        samples,
+        buckets,
+        expected,
+        min_occupancy: counts.iter().copied().min().unwrap_or_default(),
+        max_occupancy: counts.iter().copied().max().unwrap_or_default(),
+        empty_buckets: counts.iter().filter(|&&count| count == 0).count(),
+        chi_square,
+        z_score,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::{IdHasher, KeyedHasher, SipHash24};
+    use alloc::boxed::Box;
+    use core::hash::Hasher;
+
+    // keeps only the last byte, so most buckets stay empty
+    #[derive(Debug)]
+    struct LastByte;
+
+    struct LastByteHasher(u64);
+
+    impl Hasher for LastByteHasher {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            self.0 = bytes.last().copied().unwrap_or_default() as u64;
+        }
+    }
+
+    impl IdHasher for LastByte {
+        fn hash(&self, _key: &[u8; 16], data: &[u8]) -> u64 {
+            data.last().copied().unwrap_or_default() as u64
+        }
+
+        fn hasher(&self, _key: &[u8; 16]) -> KeyedHasher {
+            KeyedHasher::Custom(Box::new(LastByteHasher(0)))
+        }
+    }
+
+    #[test]
+    fn test_siphash_is_uniform() {
+        let report = report(&SipHash24, &[7; 16], &Layout::DEFAULT, 100_000);
+        assert_eq!(report.buckets, Layout::DEFAULT.buckets());
+        assert_eq!(report.samples, 100_000);
+        assert!(report.is_uniform(), "{:?}", report);
+        assert!(report.min_occupancy <= report.max_occupancy);
+    }
+
+    #[test]
+    fn test_all_buckets_are_reported() {
+        let layout = Layout::new(16, 31, 10, 5).unwrap();
+        let report = report(&SipHash24, &[7; 16], &layout, 1_000_000);
+        assert_eq!(report.buckets, 65536);
+        assert!(report.is_uniform(), "{:?}", report);
+    }
+
+    #[test]
+    fn test_bad_hash_is_not_uniform() {
+        let report = report(&LastByte, &[7; 16], &Layout::DEFAULT, 100_000);
+        assert!(!report.is_uniform());
+        assert_eq!(report.empty_buckets, Layout::DEFAULT.buckets() - 256);
+    }
+}
@@ -0,0 +1,103 @@
+//! Conversions of SINTEFlake IDs into UUIDv7, with the `uuid` feature.
+//!
+//! The timestamp of the UUID is the start of the time window of the ID, in milliseconds
+//! since the Unix epoch, and the ID fills the random bits around the version and variant.
+//! Databases can use the UUIDs as time-clustered 128-bit keys, while the IDs keep their
+//! compact 64-bit form on the wire, and [`from_uuid_v7`] gets them back.
+use ::uuid::Uuid;
+
+use crate::decode::{window_start_unix_ms, EncodingConfig};
+use crate::error::SINTEFlakeError;
+
+// the 2 highest bits of the ID go in rand_a, the others in rand_b
+const RAND_B_BITS: u32 = 62;
+const RAND_B_MASK: u64 = (1 << RAND_B_BITS) - 1;
+const VERSION_7: u128 = 0x7 << 76;
+const VARIANT_RFC_9562: u128 = 0b10 << 62;
+
+/// Expands an ID into a UUIDv7, with the start of its time window as the UUID timestamp.
+///
+/// # Errors
+/// Returns `InvalidId` if the ID doesn't fit in the layout of the settings,
+/// or `TimestampOverflow` if its time window starts before the Unix epoch
+/// or after the range of the 48-bit UUID timestamps.
+pub fn to_uuid_v7(id: u64, config: &EncodingConfig) -> Result<Uuid, SINTEFlakeError> {
+    let timestamp_ms = window_start_unix_ms(id, config)?;
+    if timestamp_ms >> 48 != 0 {
+        return Err(SINTEFlakeError::TimestampOverflow);
+    }
+    Ok(Uuid::from_u128(
+        (timestamp_ms as u128) << 80
+            | VERSION_7
+            | ((id >> RAND_B_BITS) as u128) << 64
+            | VARIANT_RFC_9562
+            | (id & RAND_B_MASK) as u128,
+    ))
+}
+
+/// Converts a UUID made by [`to_uuid_v7`] back into the ID.
+///
+/// # Errors
+/// Returns `InvalidId` if the UUID is not a UUIDv7 holding an ID of the settings,
+/// or if its timestamp isn't the start of the time window of the ID.
+pub fn from_uuid_v7(uuid: Uuid, config: &EncodingConfig) -> Result<u64, SINTEFlakeError> {
+    let value = uuid.as_u128();
+    if value & (0xF << 76) != VERSION_7 || value & (0b11 << 62) != VARIANT_RFC_9562 {
+        return Err(SINTEFlakeError::InvalidId);
+    }
+    let rand_a = (value >> 64) as u64 & 0xFFF;
+    if rand_a >> 2 != 0 {
+        return Err(SINTEFlakeError::InvalidId);
+    }
+    let id = rand_a << RAND_B_BITS | (value as u64 & RAND_B_MASK);
+    match window_start_unix_ms(id, config) {
+        Ok(timestamp_ms) if timestamp_ms == (value >> 80) as u64 => Ok(id),
+        _ => Err(SINTEFlakeError::InvalidId),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::decode;
+    use crate::sinteflake::SINTEFlake;
+    use crate::time::UnixTime;
+
+    #[test]
+    fn test_uuid_v7_round_trip() {
+        let mut instance = SINTEFlake::builder()
+            .epoch(UnixTime::from_unix_timestamp(1719792000).unwrap())
+            .build()
+            .unwrap();
+        let config = instance.encoding_config();
+        let created_at = UnixTime::from_unix_timestamp(1719792000).unwrap();
+
+        for id in [
+            instance.next_id().unwrap(),
+            config.layout.max_id() & !(0x7FFF_FFFF << 18),
+        ] {
+            let uuid = to_uuid_v7(id, &config).unwrap();
+            assert_eq!(uuid.get_version_num(), 7);
+            assert_eq!(uuid.get_variant(), ::uuid::Variant::RFC4122);
+            let start = decode(id, &config).unwrap().created_at(&config);
+            assert!(start >= created_at);
+            assert_eq!(
+                (uuid.as_u128() >> 80) as i128,
+                start.unix_timestamp_nanos() / 1_000_000
+            );
+            assert_eq!(from_uuid_v7(uuid, &config).unwrap(), id);
+        }
+
+        let uuid = to_uuid_v7(instance.next_id().unwrap(), &config).unwrap();
+        let shifted = Uuid::from_u128(uuid.as_u128() + (8000 << 80));
+        assert!(matches!(
+            from_uuid_v7(shifted, &config),
+            Err(SINTEFlakeError::InvalidId)
+        ));
+        let v4 = Uuid::from_u128(uuid.as_u128() ^ (0x3 << 76));
+        assert!(matches!(
+            from_uuid_v7(v4, &config),
+            Err(SINTEFlakeError::InvalidId)
+        ));
+    }
+}
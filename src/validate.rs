@@ -0,0 +1,436 @@
+//! Validation of IDs received from outside, to reject forged or foreign IDs at API boundaries.
+//!
+//! An ID is valid if it decodes with the settings of the deployment, its time window is
+//! not in the future, and it was generated by one of the allowed instances.
+//! [`unknown_instances`] finds the IDs of unknown instances among stored IDs, and
+//! [`detect_epoch`] the epoch an ID was likely generated with.
+//!
+//! ```rust
+//! use sinteflake::sinteflake::SINTEFlake;
+//! use sinteflake::validate::{validate, ValidationConfig, ValidationError};
+//!
+//! let mut instance = SINTEFlake::builder().instance_id(7).build()?;
+//! let config = ValidationConfig::new(instance.encoding_config()).allowed_instances([7, 8]);
+//!
+//! let id = instance.next_id()?;
+//! assert_eq!(validate(id, &config)?.instance_id, 7);
+//! assert_eq!(validate(1 << 63, &config), Err(ValidationError::ReservedBitSet));
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+use core::time::Duration;
+use thiserror::Error;
+
+use crate::decode::{decode, DecodedId, EncodingConfig};
+use crate::error::SINTEFlakeError;
+use crate::time::UnixTime;
+
+/// Why an ID is rejected by [`validate`].
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValidationError {
+    #[error("The most significant bit is set, it is reserved")]
+    ReservedBitSet,
+
+    #[error("The ID doesn't fit in the layout")]
+    OutsideLayout,
+
+    #[error("The time window of the ID is before {not_before}")]
+    TimestampTooOld { not_before: UnixTime },
+
+    #[error("The time window of the ID starts in the future, at {window_start}")]
+    TimestampInFuture { window_start: UnixTime },
+
+    #[error("Instance ID {instance_id} is not allowed")]
+    InstanceNotAllowed { instance_id: u16 },
+}
+
+impl From<ValidationError> for SINTEFlakeError {
+    fn from(_: ValidationError) -> Self {
+        SINTEFlakeError::InvalidId
+    }
+}
+
+/// Instance IDs and ranges of instance IDs allowed to generate IDs.
+///
+/// ```rust
+/// use sinteflake::validate::InstanceAllowlist;
+///
+/// let allowlist = InstanceAllowlist::new().with(7).with_range(100..=199);
+/// assert!(allowlist.contains(7));
+/// assert!(allowlist.contains(150));
+/// assert!(!allowlist.contains(8));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct InstanceAllowlist {
+    ranges: Vec<RangeInclusive<u16>>,
+}
+
+impl InstanceAllowlist {
+    /// Creates an empty allowlist, allowing no instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows an instance ID.
+    pub fn with(self, instance_id: u16) -> Self {
+        self.with_range(instance_id..=instance_id)
+    }
+
+    /// Allows a range of instance IDs, for example the IDs handed out by an allocator.
+    pub fn with_range(mut self, range: RangeInclusive<u16>) -> Self {
+        self.ranges.push(range);
+        self
+    }
+
+    /// Returns true if the instance ID is allowed.
+    pub fn contains(&self, instance_id: u16) -> bool {
+        self.ranges.iter().any(|range| range.contains(&instance_id))
+    }
+}
+
+impl FromIterator<u16> for InstanceAllowlist {
+    fn from_iter<I: IntoIterator<Item = u16>>(instance_ids: I) -> Self {
+        instance_ids
+            .into_iter()
+            .fold(InstanceAllowlist::new(), InstanceAllowlist::with)
+    }
+}
+
+/// The settings of the deployment the IDs must come from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationConfig {
+    pub(crate) encoding: EncodingConfig,
+    pub(crate) allowed_instances: Option<InstanceAllowlist>,
+    pub(crate) not_before: Option<UnixTime>,
+    pub(crate) max_clock_skew: Duration,
+}
+
+impl ValidationConfig {
+    /// Validates the IDs generated with the given settings, from any instance,
+    /// with up to one minute of clock skew between the instances.
+    pub fn new(encoding: EncodingConfig) -> Self {
+        ValidationConfig {
+            encoding,
+            allowed_instances: None,
+            not_before: None,
+            max_clock_skew: Duration::from_secs(60),
+        }
+    }
+
+    /// Accepts only the IDs generated by these instances.
+    pub fn allowed_instances(self, instance_ids: impl IntoIterator<Item = u16>) -> Self {
+        self.instance_allowlist(instance_ids.into_iter().collect())
+    }
+
+    /// Accepts only the IDs generated by the instances of the allowlist.
+    pub fn instance_allowlist(mut self, allowlist: InstanceAllowlist) -> Self {
+        self.allowed_instances = Some(allowlist);
+        self
+    }
+
+    /// Rejects the IDs of time windows starting before this time, for example the deployment
+    /// of the service, as the time windows can't be before the epoch anyway.
+    pub fn not_before(mut self, not_before: UnixTime) -> Self {
+        self.not_before = Some(not_before);
+        self
+    }
+
+    /// Sets how far in the future the start of the time window of an ID may be,
+    /// as the clocks of the instances may be ahead of the one of the validating service.
+    pub fn max_clock_skew(mut self, max_clock_skew: Duration) -> Self {
+        self.max_clock_skew = max_clock_skew;
+        self
+    }
+}
+
+/// Decodes an ID and checks that it could have been generated with the settings, now.
+///
+/// # Errors
+/// Returns the first failed check, see [`ValidationError`].
+#[cfg(feature = "std")]
+pub fn validate(id: u64, config: &ValidationConfig) -> Result<DecodedId, ValidationError> {
+    validate_at(id, config, UnixTime::now_utc())
+}
+
+/// Decodes an ID and checks that it could have been generated with the settings at a given
+/// time, for tests and without the `std` feature.
+///
+/// # Errors
+/// Returns the first failed check, see [`ValidationError`].
+pub fn validate_at(
+    id: u64,
+    config: &ValidationConfig,
+    now: UnixTime,
+) -> Result<DecodedId, ValidationError> {
+    if id >> 63 != 0 {
+        return Err(ValidationError::ReservedBitSet);
+    }
+    let decoded = decode(id, &config.encoding).map_err(|_| ValidationError::OutsideLayout)?;
+
+    let window_start = config.encoding.window_start(decoded.timestamp);
+    if let Some(not_before) = config.not_before {
+        // the time window containing not_before is accepted
+        if window_start + config.encoding.time_window <= not_before {
+            return Err(ValidationError::TimestampTooOld { not_before });
+        }
+    }
+    if window_start > now + config.max_clock_skew {
+        return Err(ValidationError::TimestampInFuture { window_start });
+    }
+
+    if let Some(allowed_instances) = &config.allowed_instances {
+        if !allowed_instances.contains(decoded.instance_id) {
+            return Err(ValidationError::InstanceNotAllowed {
+                instance_id: decoded.instance_id,
+            });
+        }
+    }
+    Ok(decoded)
+}
+
+/// Finds the IDs claiming to come from instances outside of the allowlist, for example in a
+/// table shared between services, to detect misconfigured instance IDs.
+///
+/// Returns the IDs with their instance ID, in order, skipping the IDs that don't decode.
+pub fn unknown_instances(
+    ids: impl IntoIterator<Item = u64>,
+    config: &EncodingConfig,
+    allowlist: &InstanceAllowlist,
+) -> Vec<(u64, u16)> {
+    ids.into_iter()
+        .filter_map(|id| Some((id, decode(id, config).ok()?.instance_id)))
+        .filter(|&(_, instance_id)| !allowlist.contains(instance_id))
+        .collect()
+}
+
+/// Scores the epochs a foreign ID may have been generated with, to diagnose services
+/// of a fleet configured with different epochs, whose IDs decode to nonsensical times.
+///
+/// The ID is taken as just received, see [`detect_epoch_at`].
+#[cfg(feature = "std")]
+pub fn detect_epoch(
+    id: u64,
+    candidate_epochs: &[UnixTime],
+    config: &EncodingConfig,
+) -> Vec<(UnixTime, f64)> {
+    detect_epoch_at(id, candidate_epochs, config, UnixTime::now_utc())
+}
+
+/// Scores the epochs a foreign ID may have been generated with, given the time the ID was
+/// first seen, like the time its row was written or the time of the log line it appeared in.
+///
+/// Returns the candidate epochs with a plausibility between 0 and 1, the most plausible first.
+/// With another epoch, the ID has the same time window, but a different creation time.
+/// An epoch is implausible, with 0, if the ID doesn't decode or would be created after it was
+/// seen. Otherwise, as IDs are seen soon after their creation, the plausibility decreases with
+/// the delay between the time window and the time the ID was seen: 1 within the window,
+/// 1/2 a window later, 1/3 two windows later, and so on.
+pub fn detect_epoch_at(
+    id: u64,
+    candidate_epochs: &[UnixTime],
+    config: &EncodingConfig,
+    seen_at: UnixTime,
+) -> Vec<(UnixTime, f64)> {
+    let window = config.time_window.as_nanos() as i128;
+    let mut scores: Vec<(UnixTime, f64)> = candidate_epochs
+        .iter()
+        .map(|&epoch| {
+            let config = EncodingConfig {
+                epoch,
+                ..config.clone()
+            };
+            let plausibility = match decode(id, &config) {
+                Ok(decoded) => {
+                    let window_start = config.window_start(decoded.timestamp);
+                    let delay =
+                        seen_at.unix_timestamp_nanos() - window_start.unix_timestamp_nanos();
+                    if delay < 0 {
+                        0.0
+                    } else {
+                        1.0 / (1.0 + (delay / window) as f64)
+                    }
+                }
+                Err(_) => 0.0,
+            };
+            (epoch, plausibility)
+        })
+        .collect();
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::encode;
+    use crate::layout::Layout;
+    use crate::sinteflake::SINTEFlake;
+
+    fn config() -> ValidationConfig {
+        let instance = SINTEFlake::builder().instance_id(3).build().unwrap();
+        ValidationConfig::new(instance.encoding_config())
+    }
+
+    #[test]
+    fn test_detect_epoch() {
+        let epoch = UnixTime::from_unix_timestamp(1719792000).unwrap();
+        let mut instance = SINTEFlake::builder().epoch(epoch).build().unwrap();
+        let config = instance.encoding_config();
+        let id = instance.next_id().unwrap();
+        let now = config.window_start(decode(id, &config).unwrap().timestamp);
+
+        let older = UnixTime::from_unix_timestamp(1600000000).unwrap();
+        let future = now + Duration::from_secs(3600);
+        let scores = detect_epoch_at(id, &[older, future, epoch], &config, now);
+        assert_eq!(
+            scores.iter().map(|s| s.0).collect::<Vec<_>>(),
+            [epoch, older, future]
+        );
+        assert_eq!(scores[0].1, 1.0);
+        assert!(scores[1].1 > 0.0 && scores[1].1 < 1.0);
+        assert_eq!(scores[2].1, 0.0);
+
+        // with a later epoch, the ID would be created in the future
+        let later = epoch + Duration::from_secs(86400);
+        assert_eq!(detect_epoch_at(id, &[later], &config, now), [(later, 0.0)]);
+    }
+
+    #[test]
+    fn test_detect_epoch_of_old_id() {
+        let epoch = UnixTime::from_unix_timestamp(1719792000).unwrap();
+        let clock = crate::clock::tests::ManualClock::new(1719792000000 + 86400 * 1000);
+        let mut instance = SINTEFlake::builder()
+            .epoch(epoch)
+            .clock(clock)
+            .build()
+            .unwrap();
+        let config = instance.encoding_config();
+        let id = instance.next_id().unwrap();
+        // stored a second after its creation, a day after the deployment
+        let seen_at =
+            config.window_start(decode(id, &config).unwrap().timestamp) + Duration::from_secs(1);
+
+        // the earlier epoch would make the ID a year older than the time it was stored
+        let earlier = epoch - Duration::from_secs(365 * 86400);
+        let scores = detect_epoch_at(id, &[earlier, epoch], &config, seen_at);
+        assert_eq!(scores[0], (epoch, 1.0));
+        assert!(scores[1].1 < 1e-5);
+    }
+
+    #[test]
+    fn test_validate() {
+        let config = config().allowed_instances([3]);
+        let decoded = DecodedId {
+            hash: 42,
+            timestamp: 1000,
+            instance_id: 3,
+            sequence: 7,
+        };
+        let id = encode(&decoded, &config.encoding).unwrap();
+        let window_start = config.encoding.window_start(1000);
+        assert_eq!(validate_at(id, &config, window_start), Ok(decoded));
+
+        assert_eq!(
+            validate_at(id | 1 << 63, &config, window_start),
+            Err(ValidationError::ReservedBitSet)
+        );
+
+        // a minute of skew is accepted by default
+        assert!(validate_at(id, &config, window_start - Duration::from_secs(60)).is_ok());
+        assert_eq!(
+            validate_at(id, &config, window_start - Duration::from_secs(61)),
+            Err(ValidationError::TimestampInFuture { window_start })
+        );
+
+        let other = encode(
+            &DecodedId {
+                instance_id: 4,
+                ..decoded
+            },
+            &config.encoding,
+        )
+        .unwrap();
+        assert_eq!(
+            validate_at(other, &config, window_start),
+            Err(ValidationError::InstanceNotAllowed { instance_id: 4 })
+        );
+    }
+
+    #[test]
+    fn test_instance_allowlist() {
+        let allowlist = InstanceAllowlist::new().with_range(10..=19).with(3);
+        assert!(allowlist.contains(3));
+        assert!(allowlist.contains(10));
+        assert!(allowlist.contains(19));
+        assert!(!allowlist.contains(20));
+        assert!(!InstanceAllowlist::new().contains(0));
+        assert!([1, 2]
+            .into_iter()
+            .collect::<InstanceAllowlist>()
+            .contains(2));
+
+        let config = config().instance_allowlist(allowlist.clone());
+        let id_of = |instance_id| {
+            let decoded = DecodedId {
+                hash: 1,
+                timestamp: 0,
+                instance_id,
+                sequence: 0,
+            };
+            encode(&decoded, &config.encoding).unwrap()
+        };
+        let now = config.encoding.window_start(0);
+        assert!(validate_at(id_of(15), &config, now).is_ok());
+        assert_eq!(
+            validate_at(id_of(20), &config, now),
+            Err(ValidationError::InstanceNotAllowed { instance_id: 20 })
+        );
+
+        let ids = [id_of(3), id_of(4), id_of(12), u64::MAX, id_of(20)];
+        assert_eq!(
+            unknown_instances(ids, &config.encoding, &allowlist),
+            [(id_of(4), 4), (id_of(20), 20)]
+        );
+    }
+
+    #[test]
+    fn test_not_before() {
+        let config = config();
+        let window_start = config.encoding.window_start(1000);
+        let config = config.not_before(window_start + Duration::from_secs(1));
+        let now = window_start + Duration::from_secs(3600);
+
+        let id_of = |timestamp| {
+            let decoded = DecodedId {
+                hash: 1,
+                timestamp,
+                instance_id: 0,
+                sequence: 0,
+            };
+            encode(&decoded, &config.encoding).unwrap()
+        };
+        // the window of not_before is accepted, not the one before it
+        assert!(validate_at(id_of(1000), &config, now).is_ok());
+        assert!(matches!(
+            validate_at(id_of(999), &config, now),
+            Err(ValidationError::TimestampTooOld { .. })
+        ));
+    }
+
+    #[test]
+    fn test_outside_layout() {
+        let mut encoding = config().encoding;
+        encoding.layout = Layout::JAVASCRIPT_SAFE;
+        let config = ValidationConfig::new(encoding);
+        assert_eq!(
+            validate_at(1 << 60, &config, UnixTime::now_utc()),
+            Err(ValidationError::OutsideLayout)
+        );
+        assert!(matches!(
+            SINTEFlakeError::from(ValidationError::OutsideLayout),
+            SINTEFlakeError::InvalidId
+        ));
+    }
+}
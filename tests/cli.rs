@@ -0,0 +1,94 @@
+#![cfg(feature = "cli")]
+
+use std::process::{Command, Output};
+
+fn sinteflake(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_sinteflake"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(args: &[&str]) -> String {
+    let output = sinteflake(args);
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_generate_and_decode() {
+    let ids = stdout(&["generate", "--count", "5", "--instance", "42"]);
+    let ids: Vec<&str> = ids.lines().collect();
+    assert_eq!(ids.len(), 5);
+
+    let decoded = stdout(&["decode", ids[0]]);
+    assert!(decoded.starts_with("hash=0x"), "{}", decoded);
+    assert!(decoded.contains(" instance=42 "), "{}", decoded);
+
+    let json = stdout(&["decode", ids[0], "--json"]);
+    assert!(json.contains(r#""instance_id":42"#), "{}", json);
+}
+
+#[test]
+fn test_formats() {
+    assert_eq!(
+        stdout(&["encode", "1234567890123", "--format", "base62"]),
+        "LjaL3EZ\n"
+    );
+    assert_eq!(
+        stdout(&["encode", "1234567890123", "--format", "hex"]),
+        "11f71fb04cb\n"
+    );
+
+    let id = stdout(&["generate", "--format", "hex"]);
+    let decoded = stdout(&["decode", id.trim(), "--format", "hex"]);
+    assert!(decoded.contains(" instance=0 "), "{}", decoded);
+}
+
+#[test]
+fn test_settings() {
+    let hash_key = "0102030405060708090a0b0c0d0e0f10";
+    let id = stdout(&["--hash-key", hash_key, "generate", "--instance", "7"]);
+    let decoded = stdout(&["--hash-key", hash_key, "decode", id.trim()]);
+    assert!(decoded.contains(" instance=7 "), "{}", decoded);
+
+    assert!(!sinteflake(&["--hash-key", "0102", "decode", "1"])
+        .status
+        .success());
+}
+
+#[test]
+fn test_invalid_id() {
+    let output = sinteflake(&["decode", "not-an-id"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid ID"));
+}
+
+#[test]
+fn test_analyze() {
+    let path = std::env::temp_dir().join(format!("sinteflake-analyze-{}", std::process::id()));
+    let ids = stdout(&["generate", "--count", "100", "--instance", "3"]);
+    std::fs::write(&path, format!("{}\n{}", ids, ids.lines().next().unwrap())).unwrap();
+
+    let output = sinteflake(&["analyze", path.to_str().unwrap()]);
+    assert!(!output.status.success());
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.contains("ids: 101\n"), "{}", report);
+    assert!(report.contains("duplicates: 1\n"), "{}", report);
+    assert!(report.contains("  instance 3: 101 IDs\n"), "{}", report);
+    assert!(!report.contains("warning"), "{}", report);
+
+    // the same data fills its bucket, and the next IDs cluster in the following buckets
+    let mut instance = sinteflake::sinteflake::SINTEFlake::new().unwrap();
+    let ids: Vec<String> = (0..300)
+        .map(|_| instance.next_id_with_hash(b"data").unwrap().to_string())
+        .collect();
+    std::fs::write(&path, ids.join("\n")).unwrap();
+
+    let report = stdout(&["analyze", path.to_str().unwrap()]);
+    assert!(report.contains("duplicates: 0\n"), "{}", report);
+    assert!(report.contains("is full"), "{}", report);
+    assert!(report.contains("warning: bucket"), "{}", report);
+
+    std::fs::remove_file(&path).unwrap();
+}
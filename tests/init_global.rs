@@ -0,0 +1,14 @@
+use sinteflake::sinteflake::SINTEFlake;
+use sinteflake::{init_global, next_id};
+
+// in its own test binary, so the global instance is not used before
+#[test]
+fn test_init_global() {
+    let instance = SINTEFlake::builder().instance_id(42).build().unwrap();
+    init_global(instance).unwrap();
+
+    let id = next_id().unwrap();
+    assert_eq!((id >> 8) & 0x3FF, 42);
+
+    assert!(init_global(SINTEFlake::new().unwrap()).is_err());
+}
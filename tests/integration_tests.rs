@@ -22,13 +22,13 @@ fn test_with_hash() {
 #[test]
 fn test_custom() {
     use sinteflake::sinteflake::SINTEFlake;
-    use time::OffsetDateTime;
+    use sinteflake::time::UnixTime;
 
     let mut instance = SINTEFlake::custom(
-        42,                                                       // instance_id
-        [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],  // hash_key
-        123,                                                      // counter hash key
-        OffsetDateTime::from_unix_timestamp(1719792000).unwrap(), // epoch
+        42,                                                      // instance_id
+        [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16], // hash_key
+        123,                                                     // counter hash key
+        UnixTime::from_unix_timestamp(1719792000).unwrap(),      // epoch
     )
     .unwrap();
 